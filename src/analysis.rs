@@ -0,0 +1,182 @@
+//! Where pieces were placed and sand was cleared over a run, and how each shape did, for the
+//! analysis after a game over.
+
+use std::collections::{HashMap, HashSet};
+
+use ndarray::Array2;
+
+use crate::constants::{Shape, SAND_BLOCK_SIZE};
+
+/// Which page of the analysis is shown over the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnalysisPage {
+    /// A heatmap of where pieces locked
+    Placements,
+    /// A heatmap of where sand was cleared
+    Clears,
+    /// How many of each shape were dealt and how many clears each took part in
+    Pieces,
+}
+
+impl AnalysisPage {
+    /// The page to show after this one, or `None` to go back to the game over screen
+    pub fn next(self) -> Option<Self> {
+        match self {
+            Self::Placements => Some(Self::Clears),
+            Self::Clears => Some(Self::Pieces),
+            Self::Pieces => None,
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::Placements => "PLACEMENTS",
+            Self::Clears => "CLEARS",
+            Self::Pieces => "PIECES",
+        }
+    }
+
+    /// The color of the hottest cells of the page's heatmap, if it has one. Colder cells are drawn
+    /// more transparent.
+    pub fn color(&self) -> Option<[f32; 4]> {
+        match self {
+            Self::Placements => Some([0.2, 0.4, 1.0, 0.8]),
+            Self::Clears => Some([1.0, 0.3, 0.1, 0.8]),
+            Self::Pieces => None,
+        }
+    }
+}
+
+/// A record of a whole run, with the board divided into block-sized cells
+#[derive(Debug, Clone)]
+pub struct RunAnalysis {
+    /// How many times a piece locked with a square in each cell
+    placements: Array2<u32>,
+    /// How many grains were cleared from each cell
+    clears: Array2<u32>,
+    pieces: usize,
+    clear_count: usize,
+    grains_cleared: usize,
+    /// How many pieces of each shape were dealt, by [`Shape::index`]
+    shapes_dealt: Vec<usize>,
+    /// How many clears took grains from a piece of each shape, by [`Shape::index`]
+    shape_clears: Vec<usize>,
+    /// The shape of each piece that's left sand on the board, by the number its grains are tagged
+    /// with
+    piece_shapes: HashMap<usize, Shape>,
+}
+
+impl RunAnalysis {
+    /// Start an analysis for a board with the given dimensions in grains
+    pub fn new(sand_dims: (usize, usize)) -> Self {
+        let dims = (
+            sand_dims.0.div_ceil(SAND_BLOCK_SIZE),
+            sand_dims.1.div_ceil(SAND_BLOCK_SIZE),
+        );
+        Self {
+            placements: Array2::zeros(dims),
+            clears: Array2::zeros(dims),
+            pieces: 0,
+            clear_count: 0,
+            grains_cleared: 0,
+            shapes_dealt: vec![0; Shape::all().count()],
+            shape_clears: vec![0; Shape::all().count()],
+            piece_shapes: HashMap::new(),
+        }
+    }
+
+    pub fn record_spawn(&mut self, shape: Shape) {
+        self.shapes_dealt[shape.index()] += 1;
+    }
+
+    /// Record the shape of a piece that turned into sand, by the number its grains are tagged
+    /// with, so clears can tell which shapes they took grains from
+    pub fn record_sand(&mut self, piece: usize, shape: Shape) {
+        self.piece_shapes.insert(piece, shape);
+    }
+
+    /// Record a piece locking, given the top left grain of each of its squares
+    pub fn record_lock(&mut self, squares: impl Iterator<Item = (usize, usize)>) {
+        self.pieces += 1;
+        for (x, y) in squares {
+            // Pieces move a grain at a time, so count each square in the cell its middle is in
+            let cell = (
+                (x + SAND_BLOCK_SIZE / 2) / SAND_BLOCK_SIZE,
+                (y + SAND_BLOCK_SIZE / 2) / SAND_BLOCK_SIZE,
+            );
+            if let Some(count) = self.placements.get_mut(cell) {
+                *count += 1;
+            }
+        }
+    }
+
+    /// Record a clear, given its grains and the numbers of the pieces they came from
+    pub fn record_clear(&mut self, grains: &[(usize, usize)], pieces: &HashSet<usize>) {
+        // A clear counts once for each shape it took grains from, however many pieces of it
+        let shapes = pieces
+            .iter()
+            .filter_map(|piece| self.piece_shapes.get(piece))
+            .map(Shape::index)
+            .collect::<HashSet<_>>();
+        for shape in shapes {
+            self.shape_clears[shape] += 1;
+        }
+        self.clear_count += 1;
+        self.grains_cleared += grains.len();
+        for (x, y) in grains {
+            self.clears[[x / SAND_BLOCK_SIZE, y / SAND_BLOCK_SIZE]] += 1;
+        }
+    }
+
+    /// Each cell's share of the hottest cell's count on a page's heatmap, indexed by `[x, y]` in
+    /// blocks, or `None` if the page doesn't have one
+    pub fn heatmap(&self, page: AnalysisPage) -> Option<Array2<f32>> {
+        let counts = match page {
+            AnalysisPage::Placements => &self.placements,
+            AnalysisPage::Clears => &self.clears,
+            AnalysisPage::Pieces => return None,
+        };
+        let max = counts.iter().copied().max().unwrap_or(0).max(1);
+        Some(counts.map(|count| *count as f32 / max as f32))
+    }
+
+    /// Each shape, with how many of it were dealt and how many clears took grains from it
+    pub fn shape_stats(&self) -> impl Iterator<Item = (Shape, usize, usize)> + '_ {
+        Shape::all().map(|shape| {
+            (
+                shape,
+                self.shapes_dealt[shape.index()],
+                self.shape_clears[shape.index()],
+            )
+        })
+    }
+
+    pub fn pieces(&self) -> usize {
+        self.pieces
+    }
+
+    pub fn clears(&self) -> usize {
+        self.clear_count
+    }
+
+    pub fn grains_cleared(&self) -> usize {
+        self.grains_cleared
+    }
+
+    /// Lines of stats about the run, to show along with a heatmap
+    pub fn stats(&self, score: usize, elapsed: f64) -> Vec<String> {
+        let pieces = self.pieces.max(1);
+        let seconds = elapsed as usize;
+        vec![
+            format!(
+                "{} PIECES IN {}M {:02}S",
+                self.pieces,
+                seconds / 60,
+                seconds % 60
+            ),
+            format!("{} CLEARS", self.clear_count),
+            format!("{} GRAINS PER PIECE", self.grains_cleared / pieces),
+            format!("{} POINTS PER PIECE", score / pieces),
+        ]
+    }
+}
@@ -0,0 +1,202 @@
+//! Loading the game's assets: the pixel font, the shape pack and the sand colors from the theme
+//! file. Each of them can come from a file the player picks, and one that can't be loaded is
+//! swapped for the one bundled with the game, so a broken file costs the player their custom look
+//! rather than the whole game. What went wrong is handed back to be shown once the window is up.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use image::io::Reader as ImageReader;
+use image::{GenericImageView, GrayImage, ImageFormat, SubImage};
+use ndarray::Array2;
+
+use crate::constants::{parse_shapes, Color, TETROMINO_SHAPES};
+use crate::error::Error;
+use crate::palette;
+use crate::settings::{Settings, ShapePack};
+
+const BUNDLED_FONT_IMAGE: &[u8] = include_bytes!("../assets/font.png");
+const BUNDLED_FONT_LAYOUT: &str = include_str!("../assets/font.txt");
+
+static FONT: OnceLock<Result<Font, Error>> = OnceLock::new();
+static SHAPES: OnceLock<Vec<Array2<bool>>> = OnceLock::new();
+
+/// Load the font, shape pack and theme the settings ask for, and put them to use. Any that can't
+/// be loaded are replaced with the bundled ones, and what went wrong with each is returned. This
+/// has to be called before anything is drawn or any shapes are used, and only does anything the
+/// first time it's called.
+pub fn load(settings: &Settings) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let custom_font = settings.font.as_deref().and_then(|path| {
+        load_font(path)
+            .map_err(|e| problems.push(format!("{e}, so the bundled font is used instead")))
+            .ok()
+    });
+    let font = custom_font.map_or_else(bundled_font, Ok);
+    let shapes = settings.shape_pack.load().unwrap_or_else(|e| {
+        problems.push(format!("{e}, so the tetromino pack is used instead"));
+        ShapePack::Tetromino.load().unwrap_or_default()
+    });
+    let palette = palette::load_palette().unwrap_or_else(|e| {
+        problems.push(format!("{e}, so the default colors are used instead"));
+        Color::DEFAULT_PALETTE
+    });
+
+    if FONT.set(font.map_err(Error::Font)).is_err() || SHAPES.set(shapes).is_err() {
+        problems.push("assets have already been loaded".to_string());
+    }
+    Color::set_palette(palette);
+    problems
+}
+
+/// The font text is drawn with, or why there isn't one
+pub fn font() -> Result<&'static Font, Error> {
+    FONT.get_or_init(|| bundled_font().map_err(Error::Font))
+        .as_ref()
+        .map_err(Clone::clone)
+}
+
+/// The shapes in the shape pack
+pub fn shapes() -> &'static [Array2<bool>] {
+    SHAPES.get_or_init(|| parse_shapes(TETROMINO_SHAPES).unwrap_or_default())
+}
+
+/// Where the glyphs are in a font's image, from the file that goes with it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FontLayout {
+    /// The image the glyphs are in, relative to the layout file
+    pub image: PathBuf,
+    /// How big each glyph is, in pixels
+    pub glyph_size: (u32, u32),
+    /// The characters on each row of glyphs, left to right and top to bottom
+    pub rows: Vec<Vec<char>>,
+}
+
+/// Parse a font's layout file, made of `key = value` lines where lines starting with `;` are
+/// comments
+pub fn parse_font_layout(source: &str) -> Result<FontLayout, String> {
+    let mut image = None;
+    let mut glyph_size = None;
+    let mut rows = Vec::new();
+    for (i, line) in source.lines().map(str::trim).enumerate() {
+        let error = |e: String| format!("line {}: {e}", i + 1);
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .map(|(k, v)| (k.trim(), v.trim()))
+            .ok_or_else(|| error("expected `key = value`".to_string()))?;
+        match key {
+            "image" => image = Some(PathBuf::from(value)),
+            "glyph" => {
+                let invalid = || error(format!("invalid glyph size: {value}"));
+                let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+                let size = (
+                    width.trim().parse().map_err(|_| invalid())?,
+                    height.trim().parse().map_err(|_| invalid())?,
+                );
+                if size.0 == 0 || size.1 == 0 {
+                    return Err(invalid());
+                }
+                glyph_size = Some(size);
+            }
+            "row" => rows.push(value.chars().filter(|c| !c.is_whitespace()).collect()),
+            _ => return Err(error(format!("unknown key: {key}"))),
+        }
+    }
+    Ok(FontLayout {
+        image: image.ok_or("no image")?,
+        glyph_size: glyph_size.ok_or("no glyph size")?,
+        rows,
+    })
+}
+
+/// A sheet of glyphs that are all the same size, and which character each one is
+#[derive(Debug, Clone)]
+pub struct Font {
+    image: GrayImage,
+    glyph_size: (u32, u32),
+    /// Each character's glyph, by its column and row on the sheet
+    glyphs: HashMap<char, (u32, u32)>,
+}
+
+impl Font {
+    pub fn new(image: GrayImage, layout: &FontLayout) -> Result<Self, String> {
+        let (width, height) = layout.glyph_size;
+        let columns = layout.rows.iter().map(Vec::len).max().unwrap_or(0) as u32;
+        if image.width() < columns * width || image.height() < layout.rows.len() as u32 * height {
+            return Err(format!(
+                "the image is {}x{} pixels, but the glyphs take up {}x{}",
+                image.width(),
+                image.height(),
+                columns * width,
+                layout.rows.len() as u32 * height
+            ));
+        }
+        let mut glyphs = HashMap::new();
+        for (y, row) in layout.rows.iter().enumerate() {
+            for (x, c) in row.iter().enumerate() {
+                if glyphs.insert(*c, (x as u32, y as u32)).is_some() {
+                    return Err(format!("{c:?} has more than one glyph"));
+                }
+            }
+        }
+        if glyphs.is_empty() {
+            return Err("no glyphs".to_string());
+        }
+        Ok(Self {
+            image,
+            glyph_size: layout.glyph_size,
+            glyphs,
+        })
+    }
+
+    /// How big each glyph is, in pixels
+    pub fn glyph_size(&self) -> (u32, u32) {
+        self.glyph_size
+    }
+
+    /// The glyph for a character. Letters the font only has in one case are drawn in that case.
+    pub fn glyph(&self, c: char) -> Option<SubImage<&GrayImage>> {
+        let (column, row) = self
+            .glyphs
+            .get(&c)
+            .or_else(|| self.glyphs.get(&c.to_uppercase().next()?))
+            .or_else(|| self.glyphs.get(&c.to_lowercase().next()?))?;
+        let (width, height) = self.glyph_size;
+        Some(self.image.view(column * width, row * height, width, height))
+    }
+}
+
+/// Load a font from its layout file, with the image it points to
+fn load_font(path: &Path) -> Result<Font, String> {
+    let invalid = |e: String| format!("invalid font {}: {e}", path.display());
+    let source = fs::read_to_string(path)
+        .map_err(|e| format!("could not read the font {}: {e}", path.display()))?;
+    let layout = parse_font_layout(&source).map_err(invalid)?;
+    let image_path = path.parent().unwrap_or(Path::new("")).join(&layout.image);
+    let bytes = fs::read(&image_path).map_err(|e| {
+        format!(
+            "could not read the font image {}: {e}",
+            image_path.display()
+        )
+    })?;
+    let image = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| e.to_string())
+        .and_then(|reader| reader.decode().map_err(|e| e.to_string()))
+        .map_err(|e| format!("invalid font image {}: {e}", image_path.display()))?;
+    Font::new(image.into_luma8(), &layout).map_err(invalid)
+}
+
+fn bundled_font() -> Result<Font, String> {
+    let image = ImageReader::with_format(Cursor::new(BUNDLED_FONT_IMAGE), ImageFormat::Png)
+        .decode()
+        .map_err(|e| e.to_string())?;
+    Font::new(image.into_luma8(), &parse_font_layout(BUNDLED_FONT_LAYOUT)?)
+}
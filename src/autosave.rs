@@ -0,0 +1,35 @@
+//! Saving the run in progress every so often, so that it can be picked up again after a crash or a
+//! power cut.
+
+use std::fs;
+use std::path::Path;
+
+use crate::disk;
+use crate::save::{self, GameSnapshot};
+
+/// Where the run in progress is saved. It's removed once the run ends normally.
+pub const AUTOSAVE_FILE: &str = "sandtris_autosave.json";
+
+/// Queue the run in progress to be saved, in the background
+pub fn save(snapshot: &GameSnapshot) -> Result<(), String> {
+    disk::write(AUTOSAVE_FILE, save::to_string(snapshot)?);
+    Ok(())
+}
+
+/// Remove the autosave, once there's no run left to recover
+pub fn discard() {
+    disk::remove(AUTOSAVE_FILE);
+}
+
+/// The run left behind by the last session, if it didn't end normally
+pub fn load_autosave() -> Option<Result<GameSnapshot, String>> {
+    let path = Path::new(AUTOSAVE_FILE);
+    if !path.exists() {
+        return None;
+    }
+    Some(
+        fs::read_to_string(path)
+            .map_err(|e| format!("could not read {AUTOSAVE_FILE}: {e}"))
+            .and_then(|source| save::from_str(&source)),
+    )
+}
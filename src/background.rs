@@ -0,0 +1,148 @@
+//! Work done on a thread of its own, for things the game only shows and can wait a frame or two
+//! for, like the hint. The game hands over the latest job and picks up the latest result whenever
+//! one is ready, without waiting for it. Jobs handed over while the thread is busy replace each
+//! other, so only the newest is ever worked on.
+//!
+//! Both hand-offs go through triple buffers, which the compositor uses to draw the board the same
+//! way.
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+/// Works through jobs on a background thread, one at a time, always taking the newest
+#[derive(Debug)]
+pub struct Background<J, R> {
+    jobs: Writer<Option<J>>,
+    results: Reader<Option<R>>,
+}
+
+impl<J: Send + 'static, R: Send + 'static> Background<J, R> {
+    /// Start the thread, which does `work` for each job. It stops once this is dropped.
+    pub fn new(mut work: impl FnMut(J) -> R + Send + 'static) -> Self {
+        let (jobs, mut job_reader) = triple_buffer::<Option<J>>();
+        let (mut result_writer, results) = triple_buffer::<Option<R>>();
+        thread::spawn(move || {
+            while let Some(job) = job_reader.wait() {
+                let Some(job) = job.take() else {
+                    continue;
+                };
+                *result_writer.back_mut() = Some(work(job));
+                result_writer.publish();
+            }
+        });
+        Self { jobs, results }
+    }
+
+    /// Queue a job, replacing any that hasn't been started yet
+    pub fn submit(&mut self, job: J) {
+        *self.jobs.back_mut() = Some(job);
+        self.jobs.publish();
+    }
+
+    /// The result of the most recently finished job, if one has finished since the last call
+    pub fn take_result(&mut self) -> Option<R> {
+        self.results.take_fresh().and_then(Option::take)
+    }
+}
+
+#[derive(Debug)]
+struct Shared<T> {
+    middle: Mutex<Middle<T>>,
+    published: Condvar,
+}
+
+#[derive(Debug)]
+struct Middle<T> {
+    buffer: T,
+    /// Whether the buffer has been published since the reader last took it
+    fresh: bool,
+    /// Whether the writer is gone, so nothing more will be published
+    closed: bool,
+}
+
+/// The writing side of a triple buffer. The writer fills its back buffer, then swaps it with the
+/// middle one to publish it.
+#[derive(Debug)]
+pub(crate) struct Writer<T> {
+    back: T,
+    shared: Arc<Shared<T>>,
+}
+
+/// The reading side of a triple buffer, which swaps the middle buffer into its front buffer
+/// whenever a new one has been published
+#[derive(Debug)]
+pub(crate) struct Reader<T> {
+    front: T,
+    shared: Arc<Shared<T>>,
+}
+
+pub(crate) fn triple_buffer<T: Default>() -> (Writer<T>, Reader<T>) {
+    let shared = Arc::new(Shared {
+        middle: Mutex::new(Middle {
+            buffer: T::default(),
+            fresh: false,
+            closed: false,
+        }),
+        published: Condvar::new(),
+    });
+    (
+        Writer {
+            back: T::default(),
+            shared: shared.clone(),
+        },
+        Reader {
+            front: T::default(),
+            shared,
+        },
+    )
+}
+
+impl<T> Writer<T> {
+    pub(crate) fn back_mut(&mut self) -> &mut T {
+        &mut self.back
+    }
+
+    pub(crate) fn publish(&mut self) {
+        let mut middle = self.shared.middle.lock().unwrap();
+        std::mem::swap(&mut middle.buffer, &mut self.back);
+        middle.fresh = true;
+        self.shared.published.notify_one();
+    }
+}
+
+impl<T> Reader<T> {
+    /// Take the newest published buffer, if one has been published since the last take
+    pub(crate) fn take_fresh(&mut self) -> Option<&mut T> {
+        let mut middle = self.shared.middle.lock().unwrap();
+        if !middle.fresh {
+            return None;
+        }
+        std::mem::swap(&mut middle.buffer, &mut self.front);
+        middle.fresh = false;
+        drop(middle);
+        Some(&mut self.front)
+    }
+
+    /// Wait for a buffer to be published and take it, or return `None` once the writer is gone
+    pub(crate) fn wait(&mut self) -> Option<&mut T> {
+        let mut middle = self.shared.middle.lock().unwrap();
+        while !middle.fresh {
+            if middle.closed {
+                return None;
+            }
+            middle = self.shared.published.wait(middle).unwrap();
+        }
+        std::mem::swap(&mut middle.buffer, &mut self.front);
+        middle.fresh = false;
+        drop(middle);
+        Some(&mut self.front)
+    }
+}
+
+impl<T> Drop for Writer<T> {
+    fn drop(&mut self) {
+        // Wake the reader so it can see that there's nothing more coming
+        self.shared.middle.lock().unwrap().closed = true;
+        self.shared.published.notify_all();
+    }
+}
@@ -0,0 +1,260 @@
+//! The sand on the board. Each thing known about a grain is kept in an array of its own, so the
+//! code that only needs one of them, like the physics and pathfinding that only look at colors,
+//! doesn't drag the rest along, and a new kind of per-grain state is one more array here rather
+//! than another array kept in step by hand.
+//!
+//! ```
+//! use sandtris::board::Board;
+//! use sandtris::constants::{Color, Grain};
+//!
+//! let mut board = Board::new((4, 4));
+//! let grain = Grain { color: Color::Red, landed: 0.0, piece: 1 };
+//! board.fill([0, 2, 4, 2], Some(grain));
+//! assert_eq!(board.get((3, 3)), Some(grain));
+//! assert!(board.is_clear([0, 0, 4, 2]));
+//! assert_eq!(board.grain_count(), 8);
+//! ```
+
+use ndarray::{s, Array2};
+
+use crate::constants::{Color, Grain};
+use crate::encoding::{apply_diff, board_checksum, board_hash, encode_diff};
+
+/// A board of sand, indexed by `(x, y)` with `y` growing downwards
+#[derive(Debug, Clone, PartialEq)]
+pub struct Board {
+    colors: Array2<Option<Color>>,
+    /// See [`Grain::landed`]
+    landed: Array2<f64>,
+    /// See [`Grain::piece`]
+    pieces: Array2<usize>,
+    /// How long the grain in each cell has been touching one it blends with, for the color mixing
+    /// modifier. It starts over whenever a grain moves into the cell.
+    mix_timers: Array2<f64>,
+    /// Grains the physics leaves where they are, like the grains of a clear that's still being
+    /// animated. A cell stops being pinned once something else is put in it.
+    pinned: Array2<bool>,
+}
+
+impl Board {
+    /// An empty board of the given width and height, in grains
+    pub fn new(dims: (usize, usize)) -> Self {
+        Self {
+            colors: Array2::default(dims),
+            landed: Array2::zeros(dims),
+            pieces: Array2::zeros(dims),
+            mix_timers: Array2::zeros(dims),
+            pinned: Array2::default(dims),
+        }
+    }
+
+    /// A board with grains of the given colors, which all landed at `landed` and didn't come from
+    /// any piece
+    pub fn from_colors(colors: Array2<Option<Color>>, landed: f64) -> Self {
+        let dims = colors.dim();
+        Self {
+            colors,
+            landed: Array2::from_elem(dims, landed),
+            pieces: Array2::zeros(dims),
+            mix_timers: Array2::zeros(dims),
+            pinned: Array2::default(dims),
+        }
+    }
+
+    pub fn dim(&self) -> (usize, usize) {
+        self.colors.dim()
+    }
+
+    /// The color of every cell, `None` where there's no grain
+    pub fn colors(&self) -> &Array2<Option<Color>> {
+        &self.colors
+    }
+
+    /// The grain in a cell, if there is one
+    pub fn get(&self, pos: (usize, usize)) -> Option<Grain> {
+        self.colors[pos].map(|color| Grain {
+            color,
+            landed: self.landed[pos],
+            piece: self.pieces[pos],
+        })
+    }
+
+    /// The color of the grain in a cell, or `None` if the cell is empty or off the board
+    pub fn color(&self, pos: (usize, usize)) -> Option<Color> {
+        self.colors.get(pos).copied().flatten()
+    }
+
+    pub fn is_filled(&self, pos: (usize, usize)) -> bool {
+        self.colors[pos].is_some()
+    }
+
+    /// Put a grain in a cell, or empty it
+    pub fn set(&mut self, pos: (usize, usize), grain: Option<Grain>) {
+        self.colors[pos] = grain.map(|grain| grain.color);
+        self.landed[pos] = grain.map_or(0.0, |grain| grain.landed);
+        self.pieces[pos] = grain.map_or(0, |grain| grain.piece);
+        self.mix_timers[pos] = 0.0;
+        self.pinned[pos] = false;
+    }
+
+    /// Change the color of a grain in place, keeping everything else about it
+    pub fn set_color(&mut self, pos: (usize, usize), color: Color) {
+        if let Some(cell) = &mut self.colors[pos] {
+            *cell = color;
+        }
+    }
+
+    /// Empty a cell, returning the grain that was in it
+    pub fn take(&mut self, pos: (usize, usize)) -> Option<Grain> {
+        let grain = self.get(pos);
+        self.set(pos, None);
+        grain
+    }
+
+    /// Move the grain in one cell to another, replacing whatever was there
+    pub fn move_grain(&mut self, from: (usize, usize), to: (usize, usize)) {
+        let grain = self.take(from);
+        self.set(to, grain);
+    }
+
+    /// The mix timer of a cell, see [`crate::mixing`]
+    pub fn mix_timer_mut(&mut self, pos: (usize, usize)) -> &mut f64 {
+        &mut self.mix_timers[pos]
+    }
+
+    /// Whether the physics has to leave the grain in a cell where it is
+    pub fn is_pinned(&self, pos: (usize, usize)) -> bool {
+        self.pinned[pos]
+    }
+
+    /// Pin the grains in every cell in a list, so the physics leaves them where they are
+    pub fn pin_all(&mut self, positions: &[(usize, usize)]) {
+        for pos in positions {
+            self.pinned[*pos] = true;
+        }
+    }
+
+    /// Fill a rectangle given as x, y, width and height with copies of a grain, or empty it
+    pub fn fill(&mut self, [x, y, width, height]: [usize; 4], grain: Option<Grain>) {
+        let region = s![x..x + width, y..y + height];
+        self.colors
+            .slice_mut(region)
+            .fill(grain.map(|grain| grain.color));
+        self.landed
+            .slice_mut(region)
+            .fill(grain.map_or(0.0, |grain| grain.landed));
+        self.pieces
+            .slice_mut(region)
+            .fill(grain.map_or(0, |grain| grain.piece));
+        self.mix_timers.slice_mut(region).fill(0.0);
+        self.pinned.slice_mut(region).fill(false);
+    }
+
+    /// Whether a rectangle given as x, y, width and height has no grains in it
+    pub fn is_clear(&self, [x, y, width, height]: [usize; 4]) -> bool {
+        self.colors
+            .slice(s![x..x + width, y..y + height])
+            .iter()
+            .all(Option::is_none)
+    }
+
+    /// Empty every cell
+    pub fn clear(&mut self) {
+        *self = Self::new(self.dim());
+    }
+
+    /// Every grain on the board, along with where it is
+    pub fn grains(&self) -> impl Iterator<Item = ((usize, usize), Grain)> + '_ {
+        self.colors
+            .indexed_iter()
+            .filter_map(|(pos, _)| self.get(pos).map(|grain| (pos, grain)))
+    }
+
+    /// Where every grain of a color is, from the top of the board down
+    pub fn positions_of(&self, color: Color) -> Vec<(usize, usize)> {
+        let mut positions = self
+            .colors
+            .indexed_iter()
+            .filter(|(_, cell)| **cell == Some(color))
+            .map(|(pos, _)| pos)
+            .collect::<Vec<_>>();
+        positions.sort_by_key(|(x, y)| (*y, *x));
+        positions
+    }
+
+    /// Where every grain in a band of rows is, `rows` being the first row and the one after the
+    /// last
+    pub fn positions_in_rows(&self, rows: (usize, usize)) -> Vec<(usize, usize)> {
+        let (top, bottom) = (rows.0.min(self.dim().1), rows.1.min(self.dim().1));
+        self.colors
+            .slice(s![.., top..bottom])
+            .indexed_iter()
+            .filter(|(_, cell)| cell.is_some())
+            .map(|((x, y), _)| (x, y + top))
+            .collect()
+    }
+
+    /// Where every grain within `radius` cells of a point is
+    pub fn positions_within(&self, (cx, cy): (usize, usize), radius: usize) -> Vec<(usize, usize)> {
+        self.colors
+            .indexed_iter()
+            .filter(|((x, y), cell)| {
+                cell.is_some() && x.abs_diff(cx).pow(2) + y.abs_diff(cy).pow(2) <= radius.pow(2)
+            })
+            .map(|(pos, _)| pos)
+            .collect()
+    }
+
+    /// Empty every cell in a list
+    pub fn remove_all(&mut self, positions: &[(usize, usize)]) {
+        for pos in positions {
+            self.set(*pos, None);
+        }
+    }
+
+    /// The changes to the colors on the board since it looked like `previous`, in the compact
+    /// [encoding](crate::encoding), or an error if `previous` is a different size
+    pub fn diff(&self, previous: &Board) -> Result<Vec<u8>, String> {
+        encode_diff(previous.colors(), &self.colors)
+    }
+
+    /// Apply a diff from [`Board::diff`]. Grains it adds are taken to have landed at `landed`, and
+    /// not to have come from any piece.
+    pub fn patch(&mut self, diff: &[u8], landed: f64) -> Result<(), String> {
+        let mut colors = self.colors.clone();
+        apply_diff(&mut colors, diff)?;
+        for (pos, color) in colors.indexed_iter() {
+            match (self.colors[pos], *color) {
+                (old, new) if old == new => {}
+                (Some(_), Some(new)) => self.set_color(pos, new),
+                (_, new) => self.set(
+                    pos,
+                    new.map(|color| Grain {
+                        color,
+                        landed,
+                        piece: 0,
+                    }),
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// A checksum of the colors on the board, see [`board_checksum`]
+    pub fn checksum(&self) -> u32 {
+        board_checksum(&self.colors)
+    }
+
+    /// A hash of the colors on the board, see [`board_hash`]
+    pub fn hash(&self) -> u64 {
+        board_hash(&self.colors)
+    }
+
+    pub fn grain_count(&self) -> usize {
+        self.colors.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.iter().all(Option::is_none)
+    }
+}
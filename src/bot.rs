@@ -0,0 +1,348 @@
+//! A greedy bot that picks where to drop a piece by trying every column and rotation and scoring
+//! where the piece would land. It can also look ahead to the next piece, and play a board through
+//! the same actions as a person, at one of a few skill levels.
+//!
+//! ```
+//! use sandtris::board::Board;
+//! use sandtris::bot::best_placement;
+//! use sandtris::constants::SAND_BLOCK_SIZE;
+//! use sandtris::game::Game;
+//! use sandtris::settings::Settings;
+//!
+//! let game = Game::headless(Settings::default());
+//! let sand = Board::new(game.sand().dim());
+//! let block = game.next_block();
+//! let placement = best_placement(&sand, &block).unwrap();
+//! // On an empty board, the piece lands on the floor
+//! assert!(placement.coords().any(|(_, y)| y + SAND_BLOCK_SIZE == sand.dim().1));
+//! ```
+
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use ndarray::{s, Array1};
+
+use crate::board::Board;
+use crate::constants::{
+    Color, Grain, BOT_DEPTH_WEIGHT, BOT_GAP_WEIGHT, BOT_MATCH_WEIGHT, BOT_MISMATCH_WEIGHT,
+    SAND_BLOCK_SIZE,
+};
+use crate::game::{Block, Game};
+use crate::input::Action;
+use crate::rng::{GameRng, WyRand};
+
+/// How well the bot plays
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Skill {
+    /// How long the bot waits after a piece spawns before it starts moving it, in seconds
+    pub thinking_delay: f64,
+    /// The chance of the bot dropping a piece a block to the side of where it meant to, from 0 to
+    /// 1
+    pub misdrop_rate: f64,
+    /// How many pieces the bot thinks about at once, counting the falling piece. It can see one
+    /// piece ahead, so anything past 2 plays like 2.
+    pub depth: usize,
+}
+
+/// The bot's skill levels, as picked from the menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BotLevel {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl BotLevel {
+    pub const ALL: [Self; 3] = [Self::Easy, Self::Normal, Self::Hard];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Easy => "EASY",
+            Self::Normal => "NORMAL",
+            Self::Hard => "HARD",
+        }
+    }
+
+    pub fn skill(&self) -> Skill {
+        match self {
+            Self::Easy => Skill {
+                thinking_delay: 1.0,
+                misdrop_rate: 0.3,
+                depth: 1,
+            },
+            Self::Normal => Skill {
+                thinking_delay: 0.5,
+                misdrop_rate: 0.1,
+                depth: 1,
+            },
+            Self::Hard => Skill {
+                thinking_delay: 0.15,
+                misdrop_rate: 0.0,
+                depth: 2,
+            },
+        }
+    }
+}
+
+impl FromStr for BotLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "easy" => Ok(Self::Easy),
+            "normal" => Ok(Self::Normal),
+            "hard" => Ok(Self::Hard),
+            other => Err(format!("invalid bot level: {other}")),
+        }
+    }
+}
+
+/// Plays a board by pressing the same actions a person would, one each update: it thinks for a
+/// moment once a piece spawns, then rotates it, moves it over and drops it
+#[derive(Debug, Clone)]
+pub struct Bot {
+    skill: Skill,
+    rng: WyRand,
+    /// Where the falling piece is headed, once the bot has thought about it
+    target: Option<Block>,
+    thinking: f64,
+    /// The falling piece as of the last update, to tell when a new one spawns or a move didn't
+    /// go anywhere
+    last: Option<Block>,
+    /// Whether the bot pressed anything last update
+    moved: bool,
+}
+
+impl Bot {
+    pub fn new(skill: Skill, seed: u64) -> Self {
+        Self {
+            skill,
+            rng: WyRand::new_seed(seed),
+            target: None,
+            thinking: 0.0,
+            last: None,
+            moved: false,
+        }
+    }
+
+    /// Take the bot's turn at the controls, `dt` seconds after the last one
+    pub fn update(&mut self, game: &mut Game, dt: f64) {
+        let block = game.falling_block().filter(|_| game.is_playing());
+        let last = std::mem::replace(&mut self.last, block);
+        let Some(block) = block else {
+            self.target = None;
+            return;
+        };
+        let stuck = std::mem::take(&mut self.moved) && last == Some(block);
+        // A new piece spawns at the top, so a piece that moved up or changed shape is a new one
+        if last.is_none_or(|last| block.y < last.y || block.shape != last.shape) {
+            self.target = None;
+            self.thinking = 0.0;
+        }
+
+        let Some(target) = self.target else {
+            self.thinking += dt;
+            if self.thinking >= self.skill.thinking_delay {
+                self.target = Some(self.plan(game, &block).unwrap_or(block));
+            }
+            return;
+        };
+        let action = if stuck {
+            // The piece couldn't get any closer, so make do with where it is
+            Action::Drop
+        } else if block.rotation != target.rotation {
+            Action::Rotate
+        } else if block.x < target.x {
+            Action::Right
+        } else if block.x > target.x {
+            Action::Left
+        } else {
+            Action::Drop
+        };
+        game.press(action);
+        game.release(action);
+        self.moved = true;
+    }
+
+    /// Where to drop a piece, sometimes a block off to one side
+    fn plan(&mut self, game: &Game, block: &Block) -> Option<Block> {
+        let lookahead = [game.next_block()];
+        let lookahead = &lookahead[..self.skill.depth.clamp(1, 2) - 1];
+        let mut target = best_placement_ahead(game.sand(), block, lookahead)?;
+        if self.rng.generate::<f64>() < self.skill.misdrop_rate {
+            let width = game.sand().dim().0 - target.width() * SAND_BLOCK_SIZE;
+            target.x = match self.rng.generate::<bool>() {
+                true => (target.x + SAND_BLOCK_SIZE).min(width),
+                false => target.x.saturating_sub(SAND_BLOCK_SIZE),
+            };
+        }
+        Some(target)
+    }
+}
+
+/// The best place to drop a piece, as the piece moved and rotated to where it would land, or
+/// `None` if it doesn't fit anywhere
+pub fn best_placement(sand: &Board, block: &Block) -> Option<Block> {
+    best_placement_ahead(sand, block, &[])
+}
+
+/// The best place to drop a piece, like [`best_placement`], but going with the best one found so
+/// far once `budget` runs out. At least one placement is always tried.
+pub fn best_placement_within(sand: &Board, block: &Block, budget: Duration) -> Option<Block> {
+    let start = Instant::now();
+    placements(sand, block)
+        .into_iter()
+        .enumerate()
+        .take_while(|(i, _)| *i == 0 || start.elapsed() < budget)
+        .map(|(_, block)| (evaluate(sand, &block), block))
+        .max_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, block)| block)
+}
+
+/// The best place to drop a piece, taking into account how well the pieces after it could be
+/// placed once it's down
+pub fn best_placement_ahead(sand: &Board, block: &Block, lookahead: &[Block]) -> Option<Block> {
+    placements(sand, block)
+        .into_iter()
+        .map(|block| (score_ahead(sand, &block, lookahead), block))
+        .max_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, block)| block)
+}
+
+/// How good a placement is, plus the best score the pieces after it could get. The sand isn't
+/// settled after each piece, which is close enough for choosing between spots.
+fn score_ahead(sand: &Board, block: &Block, lookahead: &[Block]) -> f64 {
+    let score = evaluate(sand, block);
+    let Some((next, rest)) = lookahead.split_first() else {
+        return score;
+    };
+    let placed = place(sand, block);
+    let best = placements(&placed, next)
+        .into_iter()
+        .map(|next| score_ahead(&placed, &next, rest))
+        .max_by(f64::total_cmp);
+    // With nowhere for the next piece to go, this is as bad as it gets
+    score + best.unwrap_or(f64::MIN / 2.0)
+}
+
+/// Every column and rotation a piece could be dropped in, each moved to where it would land
+fn placements(sand: &Board, block: &Block) -> Vec<Block> {
+    let surface = surface(sand);
+    let width = sand.dim().0;
+    (0..4)
+        .flat_map(|rotation| {
+            let piece_width = block.shape.rotated(rotation).dim().0 * SAND_BLOCK_SIZE;
+            (0..=width.saturating_sub(piece_width))
+                .step_by(SAND_BLOCK_SIZE)
+                .map(move |x| Block {
+                    x,
+                    rotation,
+                    ..*block
+                })
+        })
+        .filter_map(|block| landing(&surface, block))
+        .collect()
+}
+
+/// How good a spot is for a piece, higher being better. Pieces are rewarded for landing low and
+/// next to sand of their own color, and marked down for touching other colors and for leaving
+/// gaps under them.
+pub fn evaluate(sand: &Board, block: &Block) -> f64 {
+    let cells = block.coords().collect::<Vec<_>>();
+    let in_piece = |(x, y): (usize, usize)| {
+        cells.iter().any(|(cx, cy)| {
+            (*cx..cx + SAND_BLOCK_SIZE).contains(&x) && (*cy..cy + SAND_BLOCK_SIZE).contains(&y)
+        })
+    };
+
+    let height = sand.dim().1;
+    let (mut matches, mut mismatches, mut gaps) = (0, 0, 0);
+    for (cx, cy) in &cells {
+        // The grains just outside each edge of the cell
+        let left = cx.checked_sub(1).map(|x| (x, *cy..cy + SAND_BLOCK_SIZE));
+        let right = Some((cx + SAND_BLOCK_SIZE, *cy..cy + SAND_BLOCK_SIZE));
+        for (x, ys) in left.into_iter().chain(right) {
+            for y in ys {
+                tally(
+                    sand.color((x, y)),
+                    block.color,
+                    &mut matches,
+                    &mut mismatches,
+                );
+            }
+        }
+        let above = cy.checked_sub(1);
+        let below = Some(cy + SAND_BLOCK_SIZE);
+        for y in above.into_iter().chain(below) {
+            for x in *cx..cx + SAND_BLOCK_SIZE {
+                tally(
+                    sand.color((x, y)),
+                    block.color,
+                    &mut matches,
+                    &mut mismatches,
+                );
+            }
+        }
+
+        // Empty cells between the bottom of the cell and the sand below it
+        for x in *cx..cx + SAND_BLOCK_SIZE {
+            gaps += (cy + SAND_BLOCK_SIZE..height)
+                .take_while(|y| !sand.is_filled((x, *y)) && !in_piece((x, *y)))
+                .count();
+        }
+    }
+
+    let depth = cells.iter().map(|(_, y)| y).sum::<usize>() as f64 / SAND_BLOCK_SIZE as f64;
+    depth * BOT_DEPTH_WEIGHT + matches as f64 * BOT_MATCH_WEIGHT
+        - mismatches as f64 * BOT_MISMATCH_WEIGHT
+        - gaps as f64 * BOT_GAP_WEIGHT
+}
+
+/// The board with a piece's grains put where it is, without letting them settle
+fn place(sand: &Board, block: &Block) -> Board {
+    let mut placed = sand.clone();
+    let grain = Grain {
+        color: block.color,
+        landed: 0.0,
+        piece: 0,
+    };
+    for (x, y) in block.coords() {
+        placed.fill([x, y, SAND_BLOCK_SIZE, SAND_BLOCK_SIZE], Some(grain));
+    }
+    placed
+}
+
+/// Count a grain next to a piece as matching its color or not
+fn tally(grain: Option<Color>, piece: Color, matches: &mut usize, mismatches: &mut usize) {
+    match grain {
+        Some(color) if color == piece => *matches += 1,
+        Some(_) => *mismatches += 1,
+        None => {}
+    }
+}
+
+/// The first filled row in each column, or the height of the board for empty columns
+fn surface(sand: &Board) -> Array1<usize> {
+    let (width, height) = sand.dim();
+    (0..width)
+        .map(|x| {
+            (0..height)
+                .find(|y| sand.is_filled((x, *y)))
+                .unwrap_or(height)
+        })
+        .collect()
+}
+
+/// The piece dropped straight down from above the board onto the sand, or `None` if it doesn't
+/// fit on the board at all
+fn landing(surface: &Array1<usize>, block: Block) -> Option<Block> {
+    let lowest = block.shape.coords(block.rotation, block.x, 0).try_fold(
+        usize::MAX,
+        |lowest, (x, offset)| {
+            let floor = *surface.slice(s![x..x + SAND_BLOCK_SIZE]).iter().min()?;
+            let y = floor.checked_sub(offset + SAND_BLOCK_SIZE)?;
+            Some(lowest.min(y))
+        },
+    )?;
+    Some(Block { y: lowest, ..block })
+}
@@ -0,0 +1,125 @@
+//! What part of the board is on screen, and how big it's drawn.
+
+use std::str::FromStr;
+
+use piston_window::graphics::{self, Transformed};
+
+use crate::constants::{CAMERA_SNAP, CAMERA_SPEED};
+use crate::layout::Layout;
+
+/// How the camera decides what to show
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    /// Always show the whole board
+    Fit,
+    /// Zoom in on the falling piece, and back out to the whole board between pieces
+    Macro,
+}
+
+impl FromStr for CameraMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fit" => Ok(Self::Fit),
+            "macro" => Ok(Self::Macro),
+            other => Err(format!("invalid camera mode: {other}")),
+        }
+    }
+}
+
+/// Where the board is drawn in the window. Everything drawn in board coordinates goes through the
+/// camera, so a large board can be shrunk to fit the window, or the board zoomed in on one spot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    /// The point on the board at the top left of the view, in board pixels
+    pub offset: [f64; 2],
+    /// How many window pixels one board pixel takes up
+    pub zoom: f64,
+}
+
+impl Camera {
+    /// A camera showing the whole visible board
+    pub fn fit(layout: &Layout) -> Self {
+        Self {
+            offset: [0.0, layout.hidden_height() as f64],
+            zoom: layout.fit_zoom(),
+        }
+    }
+
+    /// A camera zoomed in `zoom` times closer than [`Camera::fit`], centered as close to `focus`
+    /// as it can be without showing anything past the edges of the board or the hidden rows
+    pub fn focus(layout: &Layout, focus: [f64; 2], zoom: f64) -> Self {
+        let zoom = layout.fit_zoom() * zoom;
+        let (view_width, view_height) = layout.view_size();
+        let (board_width, board_height) = layout.canvas_size();
+        let center = |focus: f64, view: u32, start: usize, end: usize| {
+            let visible = view as f64 / zoom;
+            let start = start as f64;
+            (focus - visible / 2.0).clamp(start, (end as f64 - visible).max(start))
+        };
+        Self {
+            offset: [
+                center(focus[0], view_width, 0, board_width),
+                center(focus[1], view_height, layout.hidden_height(), board_height),
+            ],
+            zoom,
+        }
+    }
+
+    /// Move part of the way towards another camera, so that the view glides rather than jumps.
+    /// Once it's close enough that the difference can't be seen, it snaps the rest of the way.
+    pub fn approach(&mut self, target: Self, dt: f64) {
+        let t = (dt * CAMERA_SPEED).min(1.0);
+        for (offset, target) in self.offset.iter_mut().zip(target.offset) {
+            *offset += (target - *offset) * t;
+        }
+        self.zoom += (target.zoom - self.zoom) * t;
+
+        let close = self
+            .offset
+            .iter()
+            .zip(target.offset)
+            .all(|(offset, target)| (offset - target).abs() < CAMERA_SNAP)
+            && (self.zoom - target.zoom).abs() < CAMERA_SNAP / 1000.0;
+        if close {
+            *self = target;
+        }
+    }
+
+    /// Transform a context from window coordinates to board coordinates
+    pub fn apply(&self, context: graphics::Context) -> graphics::Context {
+        context
+            .zoom(self.zoom)
+            .trans(-self.offset[0], -self.offset[1])
+    }
+
+    /// Convert a position in the window to board pixels
+    pub fn to_board(&self, pos: [f64; 2]) -> [f64; 2] {
+        [
+            pos[0] / self.zoom + self.offset[0],
+            pos[1] / self.zoom + self.offset[1],
+        ]
+    }
+}
+
+/// Clip a context to a rectangle of the given size in window pixels, starting from wherever the
+/// context's origin is in the window. Contexts without a viewport are left alone.
+pub fn clip(mut context: graphics::Context, (width, height): (u32, u32)) -> graphics::Context {
+    let Some(viewport) = context.viewport else {
+        return context;
+    };
+    // The transform ends in normalized device coordinates, which run from -1 to 1 with y up
+    let origin = [context.transform[0][2], context.transform[1][2]];
+    let [draw_width, draw_height] = viewport.draw_size.map(f64::from);
+    let scale = draw_width / viewport.window_size[0];
+    let x = (origin[0] + 1.0) / 2.0 * draw_width;
+    let y = (1.0 - origin[1]) / 2.0 * draw_height;
+    context.draw_state = context.draw_state.scissor([
+        x.max(0.0) as u32,
+        y.max(0.0) as u32,
+        (width as f64 * scale) as u32,
+        (height as f64 * scale) as u32,
+    ]);
+    context
+}
@@ -1,45 +1,75 @@
 use derivative::Derivative;
-use image::{Rgba, RgbaImage};
+use image::RgbaImage;
 use piston_window::graphics;
 use piston_window::prelude::*;
 
+use crate::error::Error;
+
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct Canvas {
+    /// `None` when there's no window to draw to
     #[derivative(Debug = "ignore")]
-    texture_context: G2dTextureContext,
+    texture_context: Option<G2dTextureContext>,
     dims: (u32, u32),
     buffer: image::RgbaImage,
+    /// The buffer as it was last uploaded, drawn again until the next upload
+    #[derivative(Debug = "ignore")]
+    uploaded: Option<G2dTexture>,
 }
 
 impl Canvas {
-    pub fn new(window: &mut PistonWindow) -> Self {
-        let dims = (window.size().width as u32, window.size().height as u32);
+    pub fn new(window: &mut PistonWindow, dims: (u32, u32)) -> Self {
         Self {
-            texture_context: window.create_texture_context(),
+            texture_context: Some(window.create_texture_context()),
             dims,
             buffer: RgbaImage::new(dims.0, dims.1),
+            uploaded: None,
         }
     }
 
-    pub fn clear(&mut self, color: Rgba<u8>) {
-        self.buffer = RgbaImage::from_pixel(self.dims.0, self.dims.1, color);
+    pub fn headless(dims: (u32, u32)) -> Self {
+        Self {
+            texture_context: None,
+            dims,
+            buffer: RgbaImage::new(dims.0, dims.1),
+            uploaded: None,
+        }
+    }
+
+    /// Start over with a blank buffer of a different size
+    pub fn resize(&mut self, dims: (u32, u32)) {
+        self.dims = dims;
+        self.buffer = RgbaImage::new(dims.0, dims.1);
+        self.uploaded = None;
     }
 
     pub fn image(&mut self) -> &mut RgbaImage {
         &mut self.buffer
     }
 
-    pub fn texture(&mut self) -> G2dTexture {
+    pub fn texture(&mut self) -> Result<G2dTexture, Error> {
         Texture::from_image(
-            &mut self.texture_context,
+            self.texture_context.as_mut().ok_or(Error::Headless)?,
             &self.buffer,
-            &TextureSettings::new(),
+            // The board is pixel art, so it's scaled up without smoothing on high DPI screens
+            &TextureSettings::new().filter(Filter::Nearest),
         )
-        .unwrap()
+        .map_err(|e| Error::Texture(format!("{e:?}")))
     }
 
-    pub fn render(&mut self, context: graphics::Context, g: &mut G2d) {
-        graphics::image(&self.texture(), context.transform, g);
+    /// Draw the canvas, uploading the buffer first if `upload` is set. Otherwise the last upload
+    /// is drawn again, which saves time when the buffer hasn't been redrawn.
+    pub fn render(
+        &mut self,
+        context: graphics::Context,
+        g: &mut G2d,
+        upload: bool,
+    ) -> Result<(), Error> {
+        if upload || self.uploaded.is_none() {
+            self.uploaded = Some(self.texture()?);
+        }
+        graphics::image(self.uploaded.as_ref().unwrap(), context.transform, g);
+        Ok(())
     }
 }
@@ -0,0 +1,156 @@
+//! Drawing the board on its own thread, so that a slow frame doesn't hold up input and physics.
+//!
+//! The game publishes a copy of everything the board drawing needs, the compositor thread draws it
+//! into an image, and the render loop uploads whichever image is newest. Both hand-offs go through
+//! triple buffers, so neither side ever waits for the other to finish.
+
+use std::collections::HashSet;
+use std::thread;
+
+use image::{Rgba, RgbaImage};
+use imageproc::drawing;
+use imageproc::rect::Rect;
+
+use crate::background::{triple_buffer, Reader, Writer};
+use crate::board::Board;
+use crate::constants::{CLEAR_PATH_COLOR, INVISIBLE_SAND_DELAY, INVISIBLE_SAND_FADE, SAND_SIZE};
+use crate::guides::Guides;
+
+/// Everything needed to draw the board at one moment
+#[derive(Debug, Clone)]
+pub struct BoardFrame {
+    pub sand: Board,
+    /// Grains that aren't drawn this frame, like while a clear flashes
+    pub hidden: Vec<(usize, usize)>,
+    /// Grains drawn partly faded and how visible they are from 0 to 1, like while a clear fades
+    /// out
+    pub fading: Option<(HashSet<(usize, usize)>, f64)>,
+    /// Grains drawn darker to draw the player's attention to them
+    pub highlight: HashSet<(usize, usize)>,
+    /// Grains outlined to show the path that set off a clear
+    pub clear_path: Vec<(usize, usize)>,
+    /// The game time, if grains fade out after landing because of the invisible sand modifier
+    pub fade_time: Option<f64>,
+    /// Whether fading grains are drawn partly faded, or snap between shown and hidden
+    pub smooth_fade: bool,
+    pub guides: Guides,
+    /// How many rows of grains at the top are hidden above the view
+    pub hidden_rows: usize,
+    /// Where the next piece will spawn, as x, y, width and height in grains
+    pub spawn_zone: Option<[usize; 4]>,
+    /// The row of grains the sand has to stay below to be sure of fitting the next piece
+    pub kill_line: Option<usize>,
+}
+
+impl BoardFrame {
+    fn draw(&self, image: &mut RgbaImage) {
+        image
+            .pixels_mut()
+            .for_each(|p| *p = Rgba([255, 255, 255, 255]));
+        let top = (self.hidden_rows * SAND_SIZE) as u32;
+        self.guides
+            .draw(image, top, self.spawn_zone, self.kill_line);
+        for ((x, y), grain) in self.sand.grains() {
+            if self.hidden.contains(&(x, y)) {
+                continue;
+            }
+
+            let mut visibility = self.fade_time.map_or(1.0, |time| {
+                1.0 - ((time - grain.landed - INVISIBLE_SAND_DELAY) / INVISIBLE_SAND_FADE)
+                    .clamp(0.0, 1.0)
+            });
+            if let Some((fading, fade)) = &self.fading {
+                if fading.contains(&(x, y)) {
+                    visibility *= fade;
+                }
+            }
+            if !self.smooth_fade {
+                visibility = visibility.round();
+            }
+            if visibility <= 0.0 {
+                continue;
+            }
+
+            drawing::draw_filled_rect_mut(
+                image,
+                Rect::at((x * SAND_SIZE) as i32, (y * SAND_SIZE) as i32)
+                    .of_size(SAND_SIZE as u32, SAND_SIZE as u32),
+                if self.highlight.contains(&(x, y)) {
+                    grain.color.highlight_pixel_color()
+                } else {
+                    grain.color.faded_pixel_color(visibility)
+                },
+            );
+        }
+        self.draw_clear_path(image);
+    }
+
+    /// Outline the clear path, by drawing a larger square behind each grain along it and then the
+    /// grains again on top
+    fn draw_clear_path(&self, image: &mut RgbaImage) {
+        let size = SAND_SIZE as i32;
+        for (x, y) in &self.clear_path {
+            drawing::draw_filled_rect_mut(
+                image,
+                Rect::at(*x as i32 * size - 1, *y as i32 * size - 1)
+                    .of_size(SAND_SIZE as u32 + 2, SAND_SIZE as u32 + 2),
+                CLEAR_PATH_COLOR,
+            );
+        }
+        for (x, y) in &self.clear_path {
+            let Some(color) = self
+                .sand
+                .color((*x, *y))
+                .filter(|_| !self.hidden.contains(&(*x, *y)))
+            else {
+                continue;
+            };
+            drawing::draw_filled_rect_mut(
+                image,
+                Rect::at(*x as i32 * size, *y as i32 * size)
+                    .of_size(SAND_SIZE as u32, SAND_SIZE as u32),
+                color.pixel_color(),
+            );
+        }
+    }
+}
+
+/// Draws board frames into images on a background thread
+#[derive(Debug)]
+pub struct Compositor {
+    frames: Writer<Option<BoardFrame>>,
+    images: Reader<Option<RgbaImage>>,
+}
+
+impl Compositor {
+    /// Start the compositor thread, drawing into images of the given size. It stops once the
+    /// compositor is dropped.
+    pub fn new(dims: (u32, u32)) -> Self {
+        let (frames, mut frame_reader) = triple_buffer::<Option<BoardFrame>>();
+        let (mut image_writer, images) = triple_buffer::<Option<RgbaImage>>();
+        thread::spawn(move || {
+            while let Some(frame) = frame_reader.wait() {
+                let Some(frame) = frame else {
+                    continue;
+                };
+                let image = image_writer
+                    .back_mut()
+                    .get_or_insert_with(|| RgbaImage::new(dims.0, dims.1));
+                frame.draw(image);
+                image_writer.publish();
+            }
+        });
+        Self { frames, images }
+    }
+
+    /// Queue a frame to be drawn, replacing any that hasn't been started yet
+    pub fn submit(&mut self, frame: BoardFrame) {
+        *self.frames.back_mut() = Some(frame);
+        self.frames.publish();
+    }
+
+    /// The most recently drawn image, if there's been one since the last call
+    pub fn latest(&mut self) -> Option<&RgbaImage> {
+        self.images.take_fresh().and_then(|image| image.as_ref())
+    }
+}
@@ -1,113 +1,498 @@
-use enum_map::{enum_map, Enum, EnumMap};
-use image::{
-    imageops, io::Reader as ImageReader, GenericImageView, GrayImage, ImageBuffer, ImageFormat,
-    Luma, Rgb, Rgba, RgbaImage, SubImage,
-};
-use lazy_static::lazy_static;
+//! Board dimensions, colors, shapes and the pixel font.
+
+use enum_map::{Enum, EnumMap};
+use image::{imageops, GrayImage, ImageBuffer, Luma, Rgb, Rgba, RgbaImage, SubImage};
 use lru::LruCache;
-use nanorand::{RandomGen, Rng};
-use ndarray::{Array2, ArrayView2};
-use piston_window::{G2dTexture, G2dTextureContext, PistonWindow, TextureSettings};
-use std::{io::Cursor, num::NonZeroUsize};
+use ndarray::{s, Array2, ArrayView2};
+use piston_window::graphics::{self, math::Matrix2d, DrawState, Transformed};
+use piston_window::{
+    Filter, G2d, G2dTexture, G2dTextureContext, ImageSize, PistonWindow, TextureSettings, Viewport,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{num::NonZeroUsize, str::FromStr, sync::RwLock, time::Duration};
+
+use crate::assets;
+use crate::error::Error;
+use crate::rng::{GameRng, Random};
 
-pub const WINDOW_SIZE: (u32, u32) = (600, 576);
-pub const BOARD_SIZE: (usize, usize) = (384, 576);
+/// The smallest and largest boards that can be set up, in blocks
+pub const MIN_BOARD_BLOCKS: (usize, usize) = (6, 10);
+pub const MAX_BOARD_BLOCKS: (usize, usize) = (40, 30);
+/// How many rows of blocks the board has above the top of the view by default, out of sight, for
+/// pieces to spawn in and sand to stack into before the game is over
+pub const HIDDEN_ROWS: usize = 2;
+/// The fewest and most hidden rows that can be set up, in blocks
+pub const HIDDEN_ROWS_RANGE: (usize, usize) = (2, 4);
+/// The size of the dashboard to the right of the board. The window is at least as tall as this, so
+/// short boards have some empty space under them. Like all of the layout, these are in window
+/// pixels, which can be several screen pixels each on a high DPI screen.
+pub const DASHBOARD_WIDTH: u32 = 216;
+pub const DASHBOARD_HEIGHT: u32 = 576;
+/// Where the first widget on the dashboard goes, from the top of the window
+pub const DASHBOARD_TOP: f64 = 30.0;
+/// The gap between widgets on the dashboard
+pub const DASHBOARD_PADDING: f64 = 12.0;
+/// The most room the board takes up in the window. Bigger boards are scaled down to fit.
+pub const MAX_VIEW_SIZE: (u32, u32) = (960, 720);
+/// How much closer the macro camera zooms in on the falling piece than the whole board view
+pub const MACRO_ZOOM: f64 = 2.0;
+/// How quickly the camera catches up with where it's headed, as the fraction of the way it moves
+/// per second
+pub const CAMERA_SPEED: f64 = 6.0;
+/// How close the camera has to get to where it's headed before it stops easing, in board pixels
+pub const CAMERA_SNAP: f64 = 0.01;
 pub const BLOCK_SIZE: usize = 32;
 pub const SAND_SIZE: usize = 4;
 pub const SAND_BLOCK_SIZE: usize = BLOCK_SIZE / SAND_SIZE;
 pub const CLEAR_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+pub const BOARD_BACKGROUND_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+/// The mark in the middle of each block of a powerup piece
+pub const POWERUP_MARK_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.8];
+/// How thick the frame around the board is, in pixels
+pub const GUIDE_FRAME_WIDTH: u32 = 2;
 pub const TEXT_COLOR: Rgba<u8> = Rgba([0, 0, 0, 255]);
 pub const UI_BACKGROUND_COLOR: [f32; 4] = [89.0 / 255.0, 92.0 / 255.0, 102.0 / 255.0, 1.0];
 pub const UI_ELEMENT_BG_COLOR: Rgba<u8> = Rgba([255, 255, 255, 255]);
+/// Part of a line picked out from the rest of it, like the points in the score feed
+pub const HIGHLIGHT_COLOR: Rgba<u8> = Rgba([0, 110, 200, 255]);
 pub const UI_ELEMENT_BG_COLOR_FLOAT: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
 pub const MOVE_DELAY: f64 = 1.0 / 6.0;
 pub const FIRST_INPUT_DELAY: f64 = 0.1;
 pub const INPUT_DELAY: f64 = 1.0 / 60.0;
 pub const MOVE_REPEAT: usize = 2;
+/// How much game time the frame advance key plays while paused, which is also how long each frame
+/// of an input script is unless it says otherwise
+pub const FRAME_STEP: f64 = 1.0 / 60.0;
+
+/// The window's title, which the mode and score are added to during a game
+pub const WINDOW_TITLE: &str = "sandtris_rs";
+/// The shortest time between changes to the window's title, in seconds, since changing it can be
+/// slow
+pub const TITLE_UPDATE_INTERVAL: f64 = 0.5;
+/// How soon after a new piece spawns a hard drop is ignored by default, so that a second press
+/// meant for the piece before doesn't drop this one too
+pub const DROP_LOCKOUT: f64 = 0.1;
+/// How long the drop key has to be held in hold to drop mode
+pub const DROP_HOLD_TIME: f64 = 0.3;
+/// How long a piece that has landed can still be nudged sideways before it locks, when nudging is
+/// on
+pub const LOCK_NUDGE_WINDOW: f64 = 0.15;
+/// How far in pixels a press has to travel downwards to count as a swipe rather than a tap
+pub const SWIPE_DISTANCE: f64 = 48.0;
 pub const PHYSICS_DELAY: f64 = 1.0 / 30.0;
-pub const FLASH_DELAY: f64 = 1.0 / 4.0;
+/// The most rounds of settling which grains move where that one row of sand gets in a physics tick
+pub const PHYSICS_MAX_ROUNDS: usize = 16;
+/// How many times faster the sand settles between pieces while the fast forward key is held
+pub const FAST_FORWARD_PASSES: usize = 4;
+/// The slowest and fastest the whole game can be set to run, as multiples of normal speed
+pub const MIN_GAME_SPEED: f64 = 0.5;
+pub const MAX_GAME_SPEED: f64 = 2.0;
+/// How long a clear's animation holds up the game by default, in seconds
+pub const CLEAR_DURATION: f64 = 1.0;
+/// The longest a clear's animation can be set to hold up the game, in seconds
+pub const MAX_CLEAR_DURATION: f64 = 5.0;
+/// How many times the grains in a clear flash before they're gone, with the flashing animation
+pub const CLEAR_FLASHES: usize = 2;
+/// How many rows above and below the path across the board a partial clear reaches
+pub const PARTIAL_CLEAR_BAND: usize = SAND_BLOCK_SIZE;
+/// The outline around the path through a clear that connects the walls, shown as the clear starts
+pub const CLEAR_PATH_COLOR: Rgba<u8> = Rgba([255, 0, 200, 255]);
+/// How many grains a clear needs to set off slow motion
+pub const SLOW_MOTION_GRAINS: usize = 1500;
+/// How fast the game runs during slow motion, as a fraction of normal speed
+pub const SLOW_MOTION_SCALE: f64 = 0.3;
+/// How long slow motion lasts, in real seconds
+pub const SLOW_MOTION_DURATION: f64 = 2.0;
+/// How far the board zooms in at the height of slow motion, as a fraction of its size
+pub const SLOW_MOTION_ZOOM: f64 = 0.15;
+/// How long the board stays still after a game over before it collapses, in seconds
+pub const COLLAPSE_DELAY: f64 = 1.0;
+/// How many rows the sand covering the dashboard falls each physics tick at the start of a game
+pub const INTRO_STEPS_PER_TICK: usize = 4;
+/// How many placements practice mode can undo
+pub const UNDO_HISTORY_LEN: usize = 32;
+
+pub const RISING_DELAY: f64 = 10.0;
+pub const RISING_ROWS: usize = SAND_BLOCK_SIZE;
+/// How many points each grain that spills off the top of the board costs, with the spill overflow
+/// setting
+pub const SPILL_PENALTY: usize = 2;
+pub const INVISIBLE_SAND_DELAY: f64 = 3.0;
+pub const INVISIBLE_SAND_FADE: f64 = 1.0;
+/// How long two grains have to touch before they blend with the color mixing modifier, in seconds
+pub const MIX_DELAY: f64 = 2.0;
+/// How many grains the stress test tries to drop onto the board each physics tick
+pub const STRESS_POUR_GRAINS: usize = 8;
+/// How often the stress test prints its timings, in seconds
+pub const STRESS_REPORT_DELAY: f64 = 1.0;
+/// How long a clear on one co-op board holds off the next row of rising sand on the other, in
+/// seconds
+pub const COOP_RISE_RELIEF: f64 = 3.0;
+/// How much sooner a clear on one versus board brings the next row of rising sand on the other, in
+/// seconds
+pub const VERSUS_RISE_PUSH: f64 = 2.0;
+/// How much faster pieces fall for every [`SCORE_GRAVITY_POINTS`] points scored, as a fraction of
+/// normal speed, in modes that speed up with the score
+pub const SCORE_GRAVITY_RAMP: f64 = 0.02;
+pub const SCORE_GRAVITY_POINTS: usize = 1000;
+/// The fastest the score can make pieces fall, as a multiple of normal speed
+pub const MAX_SCORE_GRAVITY: f64 = 2.0;
+/// How long a combo stays alive after a clear, in seconds, unless the mode says otherwise
+pub const COMBO_WINDOW: f64 = 4.0;
+/// Points for each group past the first that spans the board at the same time
+pub const MULTI_CLEAR_BONUS: usize = 1000;
+/// Clears built from fewer pieces than this earn an extra half of their points for each piece
+/// under it
+pub const EFFICIENT_CLEAR_PIECES: usize = 5;
+/// With the powerups modifier, one in this many pieces is dealt as a powerup
+pub const POWERUP_ODDS: u32 = 30;
+/// How many clears in a row earn a powerup for the next piece, with the powerups modifier
+pub const POWERUP_CHAIN: usize = 3;
+/// Points for each grain a color clear powerup sweeps away
+pub const COLOR_CLEAR_POINTS: usize = 2;
+/// How long a color clear takes to sweep down the board, in seconds
+pub const SWEEP_DURATION: f64 = 0.6;
+/// Points for each grain a laser powerup burns away
+pub const LASER_POINTS: usize = 1;
+/// How long a laser's beam shows before the band it hit is cleared, in seconds
+pub const BEAM_DURATION: f64 = 0.5;
+/// The laser's beam, which fades out over [`BEAM_DURATION`]
+pub const BEAM_COLOR: [f32; 4] = [1.0, 0.2, 0.2, 0.9];
+/// How far a bomb powerup's blast reaches from the middle of the piece, in grains
+pub const BOMB_RADIUS: usize = SAND_BLOCK_SIZE * 3;
+/// Points for each grain a bomb powerup blows away
+pub const BOMB_POINTS: usize = 1;
+/// How long a bomb's blast takes to spread out before the sand in it is cleared, in seconds
+pub const BLAST_DURATION: f64 = 0.4;
+/// The bomb's blast, which fades out as it spreads over [`BLAST_DURATION`]
+pub const BLAST_COLOR: [f32; 4] = [1.0, 0.7, 0.2, 0.8];
+/// How much charge fills the special meter
+pub const SPECIAL_CHARGE: usize = 100;
+/// Charge added to the special meter for each clear
+pub const SPECIAL_CLEAR_CHARGE: usize = 20;
+/// Charge added to the special meter for each step of a chain past the first clear
+pub const SPECIAL_CHAIN_CHARGE: usize = 15;
+/// How long the slow time special lasts, in seconds
+pub const SLOW_TIME_DURATION: f64 = 10.0;
+/// How much longer the falling piece takes to move down a step while time is slowed
+pub const SLOW_TIME_FACTOR: f64 = 3.0;
+/// How much the greedy bot values a piece landing one block lower
+pub const BOT_DEPTH_WEIGHT: f64 = 1.0;
+/// How much the greedy bot values each grain of the piece's own color touching it
+pub const BOT_MATCH_WEIGHT: f64 = 0.25;
+/// How much the greedy bot avoids each grain of another color touching the piece
+pub const BOT_MISMATCH_WEIGHT: f64 = 0.05;
+/// How much the greedy bot avoids each empty grain left under the piece
+pub const BOT_GAP_WEIGHT: f64 = 0.05;
+/// How often the best-placement hint is worked out again, in seconds
+pub const HINT_INTERVAL: f64 = 0.25;
+/// How long working out the hint may take each time, after which it goes with the best placement
+/// it's found
+pub const HINT_BUDGET: Duration = Duration::from_millis(50);
+/// The outline of where the hint suggests dropping the falling piece
+pub const HINT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.35];
+pub const HINT_BORDER_WIDTH: f64 = 1.0;
+/// How many physics ticks ahead the pile preview looks, at most
+pub const PILE_PREVIEW_TICKS: usize = 24;
+/// How long working out the pile preview may take each time
+pub const PILE_PREVIEW_BUDGET: Duration = Duration::from_millis(20);
+/// How many grains to either side of the piece the pile preview's copy of the board takes in
+pub const PILE_PREVIEW_MARGIN: usize = SAND_BLOCK_SIZE * 2;
+/// The pile preview draws from random numbers of its own, always the same ones, so it doesn't
+/// change how the game plays out or flicker between frames
+pub const PILE_PREVIEW_SEED: u64 = 0;
+pub const PILE_PREVIEW_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.5];
+/// How long the streak behind a hard-dropped piece takes to fade, in seconds
+pub const DROP_TRAIL_DURATION: f64 = 0.2;
+/// How opaque the streak behind a hard-dropped piece starts out
+pub const DROP_TRAIL_ALPHA: f32 = 0.4;
+/// How many specks of dust come out from under each column of a piece when it's hard dropped
+pub const DUST_PARTICLES: usize = 4;
+/// How long the dust from a hard drop lasts, in seconds
+pub const DUST_DURATION: f64 = 0.35;
+/// The fastest the dust from a hard drop is thrown out, in pixels per second
+pub const DUST_SPEED: f64 = 60.0;
+/// How fast the dust from a hard drop falls back down, in pixels per second squared
+pub const DUST_GRAVITY: f64 = 300.0;
+/// The width of each speck of dust, in pixels
+pub const DUST_PARTICLE_SIZE: f64 = 2.0;
+/// Points per minute that add one to a run's rating
+pub const GRADE_PACE_UNIT: f64 = 100.0;
+/// Points per piece that add one to a run's rating
+pub const GRADE_EFFICIENCY_UNIT: f64 = 10.0;
+/// How much each step of a run's longest combo or chain adds to its rating
+pub const GRADE_CHAIN_WEIGHT: f64 = 5.0;
 
-pub const SCORE_Y: u32 = 192;
 pub const SCORE_SCALE: usize = 4;
 pub const SCORE_LABEL_SCALE: usize = 3;
 pub const SCORE_DIGITS: usize = 6;
-pub const NEXT_BLOCK_Y: u32 = 48;
 pub const NEXT_BLOCK_DISPLAY_WIDTH: f64 = BLOCK_SIZE as f64 * 2.5;
 pub const NEXT_BLOCK_DISPLAY_HEIGHT: f64 = BLOCK_SIZE as f64 * 2.5;
 pub const NEXT_BLOCK_LABEL_SCALE: usize = 2;
+/// How much darker every other grain is when the next piece is shown as sand
+pub const SAND_PREVIEW_SHADE: f32 = 0.8;
+pub const GRADE_SCALE: usize = 2;
+/// Where the grade goes on the game over screen, under the message
+pub const RESULT_GRADE_Y: u32 = 340;
+/// The bars on the dashboard that fill up or run down, like the combo and special meters
+pub const METER_BAR_HEIGHT: f64 = 8.0;
+pub const METER_LABEL_SCALE: usize = 2;
+/// The special meter once it's full and ready to use
+pub const SPECIAL_READY_COLOR: [f32; 4] = [1.0, 0.85, 0.2, 1.0];
+pub const MISSION_TEXT_SCALE: usize = 2;
+pub const TUTORIAL_TEXT_Y: u32 = 16;
+pub const TUTORIAL_TEXT_SCALE: usize = 2;
+pub const ANALYSIS_TEXT_Y: u32 = 16;
+pub const ANALYSIS_TEXT_SCALE: usize = 2;
+/// How many shapes go on each row of the piece stats
+pub const PIECE_STATS_COLUMNS: usize = 3;
+pub const PIECE_STATS_CELL_WIDTH: f64 = 72.0;
+/// How tall the room for each shape's icon is, above its counts
+pub const PIECE_STATS_ICON_HEIGHT: f64 = 24.0;
+pub const PIECE_STATS_SCALE: usize = 2;
+
+pub const FEED_SCALE: usize = 2;
+pub const FEED_SPACING: u32 = 16;
+/// How many scoring events the feed shows at once
+pub const FEED_LENGTH: usize = 4;
+/// How long a scoring event stays in the feed, in seconds
+pub const FEED_DURATION: f64 = 3.0;
+/// How long a scoring event spends fading out at the end of that, in seconds
+pub const FEED_FADE: f64 = 1.0;
+/// The color of the points taken away by a penalty
+pub const FEED_PENALTY_COLOR: Rgba<u8> = Rgba([200, 0, 0, 255]);
+
+pub const TOAST_Y: u32 = 496;
+pub const TOAST_SCALE: usize = 2;
+/// The text color of toasts about something going wrong
+pub const TOAST_ERROR_COLOR: Rgba<u8> = Rgba([200, 0, 0, 255]);
+/// How long a toast stays on screen, in seconds
+pub const TOAST_DURATION: f64 = 4.0;
+/// How long a toast stays on screen while others are waiting to be shown, in seconds
+pub const TOAST_QUEUED_DURATION: f64 = 1.5;
+/// How long a toast spends sliding in at the start, in seconds
+pub const TOAST_SLIDE: f64 = 0.25;
+/// How far below where it rests a toast starts sliding in from, in pixels
+pub const TOAST_SLIDE_DISTANCE: f64 = 24.0;
+/// How long a toast spends fading out at the end, in seconds
+pub const TOAST_FADE: f64 = 0.5;
+/// The most toasts waiting to be shown, past which the oldest are dropped
+pub const TOAST_QUEUE_LENGTH: usize = 4;
+/// The longest line a toast shows, in characters
+pub const TOAST_LINE_LENGTH: usize = 30;
+/// How often to check whether the config file changed, in seconds
+pub const CONFIG_CHECK_DELAY: f64 = 0.5;
+/// How long drawing a frame should take at most, in seconds, before the render quality drops
+pub const RENDER_BUDGET: f64 = 1.0 / 120.0;
+/// The fraction of the render budget frames have to stay under for the quality to go back up
+pub const QUALITY_HEADROOM: f64 = 0.5;
+/// How many frames in a row have to be over budget before the render quality drops
+pub const QUALITY_WINDOW: usize = 60;
+
+pub const MENU_TITLE_Y: u32 = 96;
+pub const MENU_TITLE_SCALE: usize = 5;
+pub const MENU_ITEM_Y: u32 = 160;
+pub const MENU_ITEM_SCALE: usize = 3;
+pub const MENU_ITEM_SPACING: u32 = 28;
+
+/// How much one press changes a color channel on the palette screen
+pub const PALETTE_STEP: u8 = 5;
+pub const PALETTE_ROW_Y: u32 = 176;
+pub const PALETTE_ROW_SPACING: u32 = 44;
+pub const PALETTE_SWATCH_SIZE: u32 = 32;
+pub const PALETTE_CHANNEL_X: u32 = 64;
+pub const PALETTE_CHANNEL_SPACING: u32 = 72;
+pub const PALETTE_TEXT_SCALE: usize = 2;
+/// Where the preview pile of sand sits on the palette screen, and how tall it is
+pub const PALETTE_PREVIEW_Y: u32 = 360;
+pub const PALETTE_PREVIEW_HEIGHT: u32 = 96;
+pub const PALETTE_HINT_Y: u32 = 480;
+
+pub const KEYS_ROW_Y: u32 = 148;
+pub const KEYS_ROW_SPACING: u32 = 20;
+pub const KEYS_ACTION_X: u32 = 16;
+pub const KEYS_KEY_X: u32 = 176;
+pub const KEYS_TEXT_SCALE: usize = 2;
+/// The most characters of key names shown for each action on the key binding screen
+pub const KEYS_LINE_LENGTH: usize = 17;
+/// Where the prompt to press a key goes on the key binding screen
+pub const KEYS_PROMPT_Y: u32 = 482;
+pub const KEYS_HINT_Y: u32 = 512;
+
+pub const RULES_TEXT_Y: u32 = 160;
+pub const RULES_TEXT_SCALE: usize = 2;
+/// The longest line on a rules card, in characters
+pub const RULES_LINE_LENGTH: usize = 28;
+/// The gap left under each section of a rules card
+pub const RULES_SECTION_GAP: u32 = 12;
+pub const RULES_HINT_Y: u32 = 536;
+
+/// How tall the pictures of the final boards on the records screen are
+pub const THUMBNAIL_HEIGHT: u32 = 48;
+pub const RECORD_ROW_Y: u32 = 176;
+pub const RECORD_ROW_SPACING: u32 = 52;
+pub const RECORD_THUMBNAIL_X: u32 = 64;
+/// The gap between a record's picture and its text
+pub const RECORD_TEXT_GAP: u32 = 16;
+pub const RECORD_TEXT_SCALE: usize = 2;
+pub const RECORD_HINT_Y: u32 = 548;
+
+/// How often the run in progress is autosaved, in seconds, by default
+pub const AUTOSAVE_INTERVAL: f64 = 10.0;
+
+/// How long a game goes without any input before it pauses itself, in seconds, by default
+pub const IDLE_PAUSE_DELAY: f64 = 60.0;
+/// Drawn over everything while the game is paused for having been left alone
+pub const IDLE_DIM_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+pub const IDLE_TEXT_SCALE: usize = 3;
 
-#[rustfmt::skip]
-lazy_static! {
-    static ref SHAPES: EnumMap<Shape, Array2<bool>> = enum_map! {
-        Shape::T => Array2::from_shape_vec([2, 3], vec![
-            false, true, false,
-            true , true, true ,
-        ]).unwrap().reversed_axes(),
-        Shape::S => Array2::from_shape_vec([2, 3], vec![
-            false, true , true ,
-            true , true , false,
-        ]).unwrap().reversed_axes(),
-        Shape::Z => Array2::from_shape_vec([2, 3], vec![
-            true , true , false,
-            false, true , true ,
-        ]).unwrap().reversed_axes(),
-        Shape::I => Array2::from_shape_vec([4, 1], vec![
-            true , true , true , true ,
-        ]).unwrap(),
-        Shape::O => Array2::from_shape_vec([2, 2], vec![
-            true , true ,
-            true , true ,
-        ]).unwrap(),
-    };
-
-    static ref PIXEL_FONT_SPRITES: GrayImage = ImageReader::with_format(
-        Cursor::new(include_bytes!("../assets/font.png")),
-        ImageFormat::Png,
-    )
-        .decode()
-        .unwrap()
-        .into_luma8();
-
-    static ref ALPHA_CHARS: [SubImage<&'static GrayImage>; 26] =
-        [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25]
-            .map(|i| PIXEL_FONT_SPRITES.view(i * 5, 0, 5, 7));
-
-    static ref NUMERIC_CHARS: [SubImage<&'static GrayImage>; 10] =
-        [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]
-            .map(|i| PIXEL_FONT_SPRITES.view(i * 5, 7, 5, 7));
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
-pub enum Shape {
-    // I,
-    T,
-    S,
-    Z,
-    I,
-    O,
+/// How many pieces lock between snapshots for the time-lapse, by default
+pub const TIMELAPSE_INTERVAL: usize = 5;
+/// How many pixels across each grain is in exported time-lapses
+pub const TIMELAPSE_SCALE: u32 = 2;
+/// How many snapshots go on each row of the exported strip
+pub const TIMELAPSE_COLUMNS: usize = 10;
+pub const TIMELAPSE_GAP: u32 = 2;
+/// How long each snapshot shows for in the exported GIF
+pub const TIMELAPSE_FRAME_MS: u32 = 250;
+
+pub const TETROMINO_SHAPES: &str = include_str!("../assets/shapes/tetromino.txt");
+pub const PENTOMINO_SHAPES: &str = include_str!("../assets/shapes/pentomino.txt");
+/// The largest width or height, in blocks, that a shape in a shape pack can have
+pub const MAX_SHAPE_SIZE: usize = 5;
+
+/// The colors sand is drawn in, which can be changed from the palette screen while the game runs
+static PALETTE: RwLock<EnumMap<Color, [u8; 4]>> = RwLock::new(Color::DEFAULT_PALETTE);
+
+/// Parse a shape pack, where each shape is drawn with `#` for a block and `.` for empty space,
+/// shapes are separated by blank lines, and lines starting with `;` are comments
+pub fn parse_shapes(source: &str) -> Result<Vec<Array2<bool>>, String> {
+    let mut shapes = Vec::new();
+    let mut rows: Vec<&str> = Vec::new();
+    for line in source.lines().map(str::trim).chain(std::iter::once("")) {
+        if line.starts_with(';') {
+            continue;
+        }
+        if !line.is_empty() {
+            rows.push(line);
+            continue;
+        }
+        if rows.is_empty() {
+            continue;
+        }
+
+        let width = rows[0].len();
+        if rows.iter().any(|row| row.len() != width) {
+            return Err(format!(
+                "shape {} has rows of different lengths",
+                shapes.len() + 1
+            ));
+        }
+        if width > MAX_SHAPE_SIZE || rows.len() > MAX_SHAPE_SIZE {
+            return Err(format!(
+                "shape {} is larger than {MAX_SHAPE_SIZE}x{MAX_SHAPE_SIZE}",
+                shapes.len() + 1
+            ));
+        }
+        let cells = rows
+            .iter()
+            .flat_map(|row| row.chars())
+            .map(|c| match c {
+                '#' => Ok(true),
+                '.' => Ok(false),
+                c => Err(format!(
+                    "unexpected character {c:?} in shape {}",
+                    shapes.len() + 1
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if !cells.contains(&true) {
+            return Err(format!("shape {} is empty", shapes.len() + 1));
+        }
+        shapes.push(
+            Array2::from_shape_vec([rows.len(), width], cells)
+                .unwrap()
+                .reversed_axes(),
+        );
+        rows.clear();
+    }
+
+    if shapes.is_empty() {
+        return Err("shape pack contains no shapes".to_string());
+    }
+    Ok(shapes)
+}
+
+/// A shape from the active shape pack
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Shape(usize);
+
+/// Shapes are saved as their index in the shape pack, so they can only be loaded with the same pack
+impl Serialize for Shape {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Shape {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let index = usize::deserialize(deserializer)?;
+        Shape::all().nth(index).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "shape {index} isn't in the shape pack, which has {} shapes",
+                assets::shapes().len()
+            ))
+        })
+    }
 }
 
 impl Shape {
+    pub fn all() -> impl Iterator<Item = Shape> {
+        (0..assets::shapes().len()).map(Shape)
+    }
+
+    /// The shape's position in the shape pack
+    pub fn index(&self) -> usize {
+        self.0
+    }
+
     pub fn shape(&self) -> ArrayView2<'static, bool> {
-        SHAPES[*self].view()
+        assets::shapes()[self.0].view()
+    }
+
+    /// Get the shape rotated clockwise `rotation` times
+    pub fn rotated(&self, rotation: usize) -> Array2<bool> {
+        let mut shape = self.shape().to_owned();
+        for _ in 0..rotation % 4 {
+            shape = shape.t().slice(s![..;-1, ..]).to_owned();
+        }
+        shape
     }
 
-    pub fn coords(&self, x: usize, y: usize) -> impl Iterator<Item = (usize, usize)> {
-        SHAPES[*self]
+    pub fn coords(
+        &self,
+        rotation: usize,
+        x: usize,
+        y: usize,
+    ) -> impl Iterator<Item = (usize, usize)> {
+        self.rotated(rotation)
             .indexed_iter()
-            .filter_map(move |((px, py), v)| {
+            .filter_map(|((px, py), v)| {
                 v.then_some((x + (px * SAND_BLOCK_SIZE), y + (py * SAND_BLOCK_SIZE)))
             })
+            .collect::<Vec<_>>()
+            .into_iter()
     }
 }
 
-impl<Generator: Rng<OUTPUT>, const OUTPUT: usize> RandomGen<Generator, OUTPUT> for Shape {
-    fn random(rng: &mut Generator) -> Self {
-        [Shape::T, Shape::S, Shape::Z, Shape::I, Shape::O][rng.generate_range(0..5)]
+impl Random for Shape {
+    fn random(rng: &mut impl GameRng) -> Self {
+        Shape(rng.generate_range(0..assets::shapes().len()))
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Color {
     Red,
     Yellow,
@@ -116,28 +501,103 @@ pub enum Color {
 }
 
 impl Color {
-    const COLORS: EnumMap<Color, [u8; 4]> = EnumMap::from_array([
+    pub const ALL: [Color; 4] = [Color::Red, Color::Yellow, Color::Blue, Color::Green];
+
+    pub const DEFAULT_PALETTE: EnumMap<Color, [u8; 4]> = EnumMap::from_array([
         [204, 0, 0, 255],
         [241, 194, 50, 255],
         [61, 133, 198, 255],
         [106, 168, 79, 255],
     ]);
 
+    /// The colors every color is currently drawn in
+    pub fn palette() -> EnumMap<Color, [u8; 4]> {
+        *PALETTE.read().unwrap()
+    }
+
+    pub fn set_palette(palette: EnumMap<Color, [u8; 4]>) {
+        *PALETTE.write().unwrap() = palette;
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Color::Red => "RED",
+            Color::Yellow => "YELLOW",
+            Color::Blue => "BLUE",
+            Color::Green => "GREEN",
+        }
+    }
+
     pub fn pixel_color(&self) -> Rgba<u8> {
-        Rgba(Self::COLORS[*self])
+        Rgba(PALETTE.read().unwrap()[*self])
     }
 
     pub fn float_color(&self) -> [f32; 4] {
-        Self::COLORS[*self].map(|x| x as f32 / 255.0)
+        self.pixel_color().0.map(|x| x as f32 / 255.0)
+    }
+
+    /// Get a darker version of the pixel color, used to highlight grains
+    pub fn highlight_pixel_color(&self) -> Rgba<u8> {
+        let Rgba([r, g, b, a]) = self.pixel_color();
+        Rgba([r / 2, g / 2, b / 2, a])
+    }
+
+    /// Get the pixel color blended towards white, where a `visibility` of 0.0 is fully white
+    pub fn faded_pixel_color(&self, visibility: f64) -> Rgba<u8> {
+        let Rgba(color) = self.pixel_color();
+        Rgba(color.map(|x| (x as f64 * visibility + 255.0 * (1.0 - visibility)) as u8))
     }
 }
 
-impl<Generator: Rng<OUTPUT>, const OUTPUT: usize> RandomGen<Generator, OUTPUT> for Color {
-    fn random(rng: &mut Generator) -> Self {
-        [Color::Red, Color::Yellow, Color::Blue, Color::Green][rng.generate_range(0..4)]
+impl Random for Color {
+    fn random(rng: &mut impl GameRng) -> Self {
+        Color::ALL[rng.generate_range(0..Color::ALL.len())]
     }
 }
 
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "red" => Ok(Color::Red),
+            "yellow" => Ok(Color::Yellow),
+            "blue" => Ok(Color::Blue),
+            "green" => Ok(Color::Green),
+            _ => Err(format!("unknown color: {s}")),
+        }
+    }
+}
+
+/// Parse a color like `#rrggbb`, or `#rrggbbaa` with an alpha channel
+pub fn parse_hex_color(s: &str) -> Result<Rgba<u8>, String> {
+    let invalid = || format!("invalid color: {s}");
+    let hex = s.strip_prefix('#').ok_or_else(invalid)?;
+    if !matches!(hex.len(), 6 | 8) {
+        return Err(invalid());
+    }
+    let mut channels = [255; 4];
+    for (channel, i) in channels.iter_mut().zip((0..hex.len()).step_by(2)) {
+        *channel = hex
+            .get(i..i + 2)
+            .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+            .ok_or_else(invalid)?;
+    }
+    Ok(Rgba(channels))
+}
+
+/// A single grain of sand on the board
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Grain {
+    pub color: Color,
+    /// The game time at which the block this grain came from was locked
+    pub landed: f64,
+    /// Which piece of the run this grain came from, counting from 1. Sand that didn't come from a
+    /// piece, like garbage and mission boards, is piece 0.
+    #[serde(default)]
+    pub piece: usize,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, enum_map::Enum)]
 pub enum Direction {
     Left,
@@ -145,30 +605,111 @@ pub enum Direction {
     Down,
 }
 
+/// A line of text, rendered with as many screen pixels per window pixel as the screen has so that
+/// it stays sharp on high DPI screens. Its size is in window pixels, like everything else that's
+/// laid out.
+pub struct TextSprite {
+    texture: G2dTexture,
+    pixel_ratio: usize,
+}
+
+impl ImageSize for TextSprite {
+    fn get_size(&self) -> (u32, u32) {
+        let (width, height) = self.texture.get_size();
+        let ratio = self.pixel_ratio as u32;
+        (width / ratio, height / ratio)
+    }
+}
+
+impl TextSprite {
+    pub fn draw(&self, transform: Matrix2d, g: &mut G2d) {
+        self.draw_tinted([1.0, 1.0, 1.0, 1.0], &DrawState::default(), transform, g);
+    }
+
+    /// Draw the text multiplied by a color, like to fade it out
+    pub fn draw_tinted(
+        &self,
+        color: [f32; 4],
+        draw_state: &DrawState,
+        transform: Matrix2d,
+        g: &mut G2d,
+    ) {
+        let ratio = 1.0 / self.pixel_ratio as f64;
+        graphics::Image::new_color(color).draw(
+            &self.texture,
+            draw_state,
+            transform.scale(ratio, ratio),
+            g,
+        );
+    }
+}
+
+/// A run of text in one color, part of a line drawn with [`TextTextures::rich_texture`]
+pub type Segment<'a> = (&'a str, Rgba<u8>);
+
 pub struct TextTextures {
-    texture_context: G2dTextureContext,
-    cache: LruCache<(String, usize, Rgba<u8>, Option<Rgba<u8>>), G2dTexture>,
+    /// `None` when there's no window to create textures for
+    texture_context: Option<G2dTextureContext>,
+    /// How many screen pixels there are to each window pixel, rounded to a whole number so the
+    /// font's pixels all come out the same size
+    pixel_ratio: usize,
+    cache: LruCache<(Vec<(String, Rgba<u8>)>, usize, Option<Rgba<u8>>), TextSprite>,
 }
 
 impl TextTextures {
     pub fn new(window: &mut PistonWindow) -> Self {
         Self {
-            texture_context: window.create_texture_context(),
+            texture_context: Some(window.create_texture_context()),
+            pixel_ratio: 1,
             cache: LruCache::new(NonZeroUsize::new(64).unwrap()),
         }
     }
 
-    pub fn texture(&mut self, text: &str, scale: usize, color: Rgba<u8>) -> Option<&G2dTexture> {
-        self.cache
-            .try_get_or_insert((text.to_string(), scale, color, None), || {
-                G2dTexture::from_image(
-                    &mut self.texture_context,
-                    &Self::generate_text_sprite(text, scale, color, None).ok_or(())?,
-                    &TextureSettings::new(),
-                )
-                .map_err(|_| ())
-            })
-            .ok()
+    pub fn headless() -> Self {
+        Self {
+            texture_context: None,
+            pixel_ratio: 1,
+            cache: LruCache::new(NonZeroUsize::new(64).unwrap()),
+        }
+    }
+
+    /// Match the text to the screen a frame is being drawn to. Moving the window to a screen with
+    /// a different pixel ratio renders all the text again.
+    pub fn set_viewport(&mut self, viewport: &Viewport) {
+        let ratio = (viewport.draw_size[0] as f64 / viewport.window_size[0])
+            .round()
+            .max(1.0) as usize;
+        if ratio != self.pixel_ratio {
+            self.pixel_ratio = ratio;
+            self.cache.clear();
+        }
+    }
+
+    /// Upload an image to draw, like a board thumbnail. Unlike text, these aren't cached.
+    pub fn image_texture(&mut self, image: &RgbaImage) -> Result<G2dTexture, Error> {
+        G2dTexture::from_image(
+            self.texture_context.as_mut().ok_or(Error::Headless)?,
+            image,
+            &TextureSettings::new().filter(Filter::Nearest),
+        )
+        .map_err(|e| Error::Texture(format!("{e:?}")))
+    }
+
+    pub fn texture(
+        &mut self,
+        text: &str,
+        scale: usize,
+        color: Rgba<u8>,
+    ) -> Result<&TextSprite, Error> {
+        self.sprite(&[(text, color)], scale, None)
+    }
+
+    /// How tall a line of text drawn with a background is at a scale, in window pixels
+    pub fn line_height(scale: usize) -> f64 {
+        // A pixel of background above and below the glyphs. Without a font nothing is drawn, so
+        // the bundled font's height keeps the layout the same.
+        let glyph_height = assets::font().map_or(7, |font| font.glyph_size().1);
+        ((glyph_height + 2) as usize * scale) as f64
     }
 
     pub fn texture_with_background(
@@ -177,51 +718,122 @@ impl TextTextures {
         scale: usize,
         color: Rgba<u8>,
         background: Rgba<u8>,
-    ) -> Option<&G2dTexture> {
-        self.cache
-            .try_get_or_insert((text.to_string(), scale, color, Some(background)), || {
-                G2dTexture::from_image(
-                    &mut self.texture_context,
-                    &Self::generate_text_sprite(text, scale, color, Some(background)).ok_or(())?,
-                    &TextureSettings::new(),
-                )
-                .map_err(|_| ())
+    ) -> Result<&TextSprite, Error> {
+        self.sprite(&[(text, color)], scale, Some(background))
+    }
+
+    /// A line made of runs of text in different colors, like a label and a highlighted number,
+    /// drawn as one sprite
+    pub fn rich_texture(
+        &mut self,
+        segments: &[Segment],
+        scale: usize,
+    ) -> Result<&TextSprite, Error> {
+        self.sprite(segments, scale, None)
+    }
+
+    pub fn rich_texture_with_background(
+        &mut self,
+        segments: &[Segment],
+        scale: usize,
+        background: Rgba<u8>,
+    ) -> Result<&TextSprite, Error> {
+        self.sprite(segments, scale, Some(background))
+    }
+
+    fn sprite(
+        &mut self,
+        segments: &[Segment],
+        scale: usize,
+        background: Option<Rgba<u8>>,
+    ) -> Result<&TextSprite, Error> {
+        let pixel_ratio = self.pixel_ratio;
+        let key = segments
+            .iter()
+            .map(|(text, color)| (text.to_string(), *color))
+            .collect();
+        self.cache.try_get_or_insert((key, scale, background), || {
+            let texture = G2dTexture::from_image(
+                self.texture_context.as_mut().ok_or(Error::Headless)?,
+                &Self::generate_text_sprite(segments, scale * pixel_ratio, background)?,
+                &TextureSettings::new().filter(Filter::Nearest),
+            )
+            .map_err(|e| Error::Texture(format!("{e:?}")))?;
+            Ok(TextSprite {
+                texture,
+                pixel_ratio,
             })
-            .ok()
+        })
     }
 
-    fn get_sprite(c: char) -> Option<SubImage<&'static GrayImage>> {
-        match c {
-            'A'..='Z' => Some(ALPHA_CHARS[c as usize - 'A' as usize]),
-            'a'..='z' => Some(ALPHA_CHARS[c as usize - 'a' as usize]),
-            '0'..='9' => Some(NUMERIC_CHARS[c as usize - '0' as usize]),
-            _ => None,
+    /// Turn arbitrary text into lines that can be drawn, replacing characters the font doesn't
+    /// have with spaces and wrapping at word boundaries
+    pub fn wrap(text: &str, line_length: usize) -> Vec<String> {
+        let text = text
+            .chars()
+            .map(|c| if Self::get_sprite(c).is_ok() { c } else { ' ' })
+            .collect::<String>();
+        let mut lines = Vec::<String>::new();
+        for word in text.split_whitespace() {
+            match lines.last_mut() {
+                Some(line) if line.chars().count() + 1 + word.chars().count() <= line_length => {
+                    line.push(' ');
+                    line.push_str(word);
+                }
+                _ => lines.extend(
+                    word.chars()
+                        .collect::<Vec<_>>()
+                        .chunks(line_length)
+                        .map(|chunk| chunk.iter().collect()),
+                ),
+            }
         }
+        lines
+    }
+
+    fn get_sprite(c: char) -> Result<SubImage<&'static GrayImage>, Error> {
+        assets::font()?.glyph(c).ok_or(Error::UnsupportedChar(c))
     }
 
     fn generate_text_sprite(
-        text: &str,
+        segments: &[Segment],
         scale: usize,
-        color: Rgba<u8>,
         background: Option<Rgba<u8>>,
-    ) -> Option<RgbaImage> {
+    ) -> Result<RgbaImage, Error> {
+        let (glyph_width, glyph_height) = assets::font()?.glyph_size();
         let offset = if background.is_some() { 1 } else { 0 };
-        let width = (text.len() * 5 + text.len() - 1) as u32 + offset * 2;
-        let mut buffer = GrayImage::from_pixel(width, 7 + offset * 2, Luma([255]));
+        let text = segments.iter().map(|(text, _)| *text).collect::<String>();
+        // The color of each character
+        let colors = segments
+            .iter()
+            .flat_map(|(text, color)| text.chars().map(|_| *color))
+            .collect::<Vec<_>>();
+        let length = colors.len() as u32;
+        // A pixel between each glyph
+        let width = (length * (glyph_width + 1)).saturating_sub(1) + offset * 2;
+        let mut buffer = GrayImage::from_pixel(width, glyph_height + offset * 2, Luma([255]));
         for (i, c) in text.chars().enumerate() {
             if c != ' ' {
                 imageops::replace(
                     &mut buffer,
                     &*Self::get_sprite(c)?,
-                    i as i64 * 6 + offset as i64,
+                    (i as u32 * (glyph_width + 1) + offset) as i64,
                     offset as i64,
                 );
             }
         }
 
-        let Rgba([text_r, text_g, text_b, text_a]) = color;
         let Rgba([bg_r, bg_g, bg_b, bg_a]) = background.unwrap_or(Rgba([0, 0, 0, 0]));
-        let colored_buffer = imageproc::map::map_colors(&buffer, |Luma([scale])| {
+        let colored_buffer = RgbaImage::from_fn(buffer.width(), buffer.height(), |x, y| {
+            let Luma([scale]) = buffer[(x, y)];
+            // The gap after each glyph, and the background around the line, go with the glyph
+            // before them
+            let index =
+                (x.saturating_sub(offset) / (glyph_width + 1)).min(length.saturating_sub(1));
+            let Rgba([text_r, text_g, text_b, text_a]) = colors
+                .get(index as usize)
+                .copied()
+                .unwrap_or(Rgba([0, 0, 0, 0]));
             Rgba([
                 scale_subpixel(text_r, bg_r, scale),
                 scale_subpixel(text_g, bg_g, scale),
@@ -230,10 +842,10 @@ impl TextTextures {
             ])
         });
 
-        Some(imageops::resize(
+        Ok(imageops::resize(
             &colored_buffer,
             width * scale as u32,
-            (7 + offset * 2) * scale as u32,
+            (glyph_height + offset * 2) * scale as u32,
             imageops::FilterType::Nearest,
         ))
     }
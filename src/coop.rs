@@ -0,0 +1,150 @@
+//! Local co-op: two boards side by side, dealt from one piece queue, with a shared score.
+
+use piston_window::graphics;
+use piston_window::prelude::*;
+
+use crate::constants::{COOP_RISE_RELIEF, WINDOW_TITLE};
+use crate::game::{Game, SharedQueue};
+use crate::input::Action;
+use crate::mode::GameMode;
+use crate::rng::{GameRng, WyRand};
+use crate::settings::Settings;
+use crate::split::SplitScreen;
+
+/// The keys for the player on the left, who gets the letter keys
+fn left_player_action(key: Key) -> Option<Action> {
+    match key {
+        Key::A => Some(Action::Left),
+        Key::D => Some(Action::Right),
+        Key::S => Some(Action::Down),
+        Key::W => Some(Action::Rotate),
+        Key::Q => Some(Action::Hold),
+        Key::Space => Some(Action::Drop),
+        Key::E => Some(Action::Special),
+        _ => None,
+    }
+}
+
+/// The keys for the player on the right, who gets the arrow keys
+fn right_player_action(key: Key) -> Option<Action> {
+    match key {
+        Key::Left => Some(Action::Left),
+        Key::Right => Some(Action::Right),
+        Key::Down => Some(Action::Down),
+        Key::Up => Some(Action::Rotate),
+        Key::RShift => Some(Action::Hold),
+        Key::Return => Some(Action::Drop),
+        Key::RCtrl => Some(Action::Special),
+        _ => None,
+    }
+}
+
+/// Two games played at once. Pausing and restarting apply to both boards, and when either board
+/// fills up, the game is over for both.
+#[derive(Debug)]
+pub struct Coop {
+    rng: WyRand,
+    split: SplitScreen,
+}
+
+impl Coop {
+    pub fn new(window: &mut PistonWindow, settings: Settings) -> Self {
+        let mut coop = Self {
+            rng: WyRand::new(),
+            split: SplitScreen::new(window, [settings.clone(), settings]),
+        };
+        coop.restart();
+        coop
+    }
+
+    /// The window size that fits both boards side by side
+    pub fn window_size(settings: &Settings) -> (u32, u32) {
+        SplitScreen::window_size(GameMode::Coop, settings)
+    }
+
+    fn restart(&mut self) {
+        let queue = SharedQueue::new(self.split.settings(), self.rng.generate());
+        for board in &mut self.split.boards {
+            board.start_seeded(GameMode::Coop, self.rng.generate());
+            board.share_queue(queue.clone());
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        if let Some(Button::Keyboard(key)) = event.press_args() {
+            match key {
+                Key::P => self.split.press_both(Action::Pause),
+                _ => {
+                    for (board, action) in self.actions(key) {
+                        board.press(action);
+                    }
+                }
+            }
+        }
+        if let Some(Button::Keyboard(key)) = event.release_args() {
+            match key {
+                Key::R => self.restart(),
+                Key::P => self.split.release_both(Action::Pause),
+                _ => {
+                    for (board, action) in self.actions(key) {
+                        board.release(action);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Which boards a key does something on, and what it does
+    fn actions(&mut self, key: Key) -> Vec<(&mut Game, Action)> {
+        let [left, right] = &mut self.split.boards;
+        [
+            (left, left_player_action(key)),
+            (right, right_player_action(key)),
+        ]
+        .into_iter()
+        .filter_map(|(board, action)| Some((board, action?)))
+        .collect()
+    }
+
+    /// Whether both boards are idle, see [`Game::is_idle`]
+    pub fn is_idle(&self) -> bool {
+        self.split.is_idle()
+    }
+
+    /// What the window's title should say, with the score the two boards share
+    pub fn window_title(&self) -> String {
+        let [left, right] = &self.split.boards;
+        let status = if left.is_game_over() {
+            " (game over)"
+        } else {
+            ""
+        };
+        format!(
+            "{WINDOW_TITLE} - {} - {}{status}",
+            GameMode::Coop.name(),
+            left.score() + right.score()
+        )
+    }
+
+    pub fn max_fps(&self) -> Option<u64> {
+        self.split.max_fps()
+    }
+
+    pub fn update(&mut self, args: &UpdateArgs) {
+        // Clears on one board ease the pressure on the other
+        self.split
+            .update(args, |other| other.delay_rise(COOP_RISE_RELIEF));
+
+        let [left, right] = &mut self.split.boards;
+        left.set_partner_score(right.score());
+        right.set_partner_score(left.score());
+        if left.is_game_over() || right.is_game_over() {
+            left.end();
+            right.end();
+        }
+    }
+
+    pub fn render(&mut self, context: graphics::Context, g: &mut G2d) {
+        self.split.render(context, g);
+    }
+}
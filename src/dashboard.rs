@@ -0,0 +1,79 @@
+//! Laying out the dashboard beside the board. Widgets are stacked from the top down with the same
+//! gap between each, and centered across the dashboard, so adding one only takes knowing how tall
+//! it is rather than working out new positions for everything under it. The stack knows where the
+//! dashboard ends, so widgets that can go without are only drawn if there's room left for them.
+
+/// A rectangle on the dashboard, in pixels from its top left corner
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Slot {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Slot {
+    /// Where something `width` wide goes to be centered in the slot
+    pub fn center_x(&self, width: f64) -> f64 {
+        self.x + ((self.width - width) / 2.0).floor()
+    }
+}
+
+/// Widgets stacked top to bottom, each centered across the width
+#[derive(Debug, Clone)]
+pub struct Stack {
+    width: f64,
+    /// Where the next widget goes
+    y: f64,
+    /// Where the room for widgets ends
+    bottom: f64,
+    /// The gap left under each widget
+    padding: f64,
+}
+
+impl Stack {
+    pub fn new(width: f64, top: f64, bottom: f64, padding: f64) -> Self {
+        Self {
+            width,
+            y: top,
+            bottom,
+            padding,
+        }
+    }
+
+    /// Whether a widget this tall fits in the room left at the bottom of the stack
+    pub fn fits(&self, height: f64) -> bool {
+        self.y + height <= self.bottom
+    }
+
+    /// Take the room for the next widget down, centered across the stack
+    pub fn place(&mut self, width: f64, height: f64) -> Slot {
+        let slot = Slot {
+            x: ((self.width - width) / 2.0).floor(),
+            y: self.y,
+            width,
+            height,
+        };
+        self.y += height + self.padding;
+        slot
+    }
+
+    /// Take the room for the next widget down, across the whole width of the stack
+    pub fn place_row(&mut self, height: f64) -> Slot {
+        self.place(self.width, height)
+    }
+
+    /// Take the room for two widgets of the same size side by side, with the same gap between them
+    /// as under them, centered across the stack together
+    pub fn place_pair(&mut self, width: f64, height: f64) -> [Slot; 2] {
+        let row = self.place(width * 2.0 + self.padding, height);
+        [
+            Slot { width, ..row },
+            Slot {
+                x: row.x + width + self.padding,
+                width,
+                ..row
+            },
+        ]
+    }
+}
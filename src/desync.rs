@@ -0,0 +1,66 @@
+//! Telling when a game played back from its inputs has gone out of step with the one that was
+//! recorded. Recordings can carry a hash of the board on every frame, and the first frame whose
+//! hash doesn't match is written out along with the last one that did, to find what went wrong.
+
+use serde::Serialize;
+
+use crate::disk;
+use crate::game::Game;
+use crate::save::{self, GameSnapshot};
+
+/// Where the report on a desync is written
+pub const DESYNC_FILE: &str = "sandtris_desync.json";
+
+/// What the game looked like either side of where it went out of step
+#[derive(Debug, Clone, Serialize)]
+pub struct DesyncReport {
+    /// The first frame whose board didn't match, counting from 0
+    pub frame: usize,
+    /// The hash the recording has for the board just before the frame's update
+    pub expected: u64,
+    /// The hash of the board played back
+    pub actual: u64,
+    /// The position on the last frame that matched, if any did
+    pub last_in_step: Option<GameSnapshot>,
+    /// The position on the frame that didn't
+    pub diverged: GameSnapshot,
+}
+
+/// Checks the board hashes that come with each frame against the game playing them
+#[derive(Debug, Clone, Default)]
+pub struct DesyncCheck {
+    frame: usize,
+    last_in_step: Option<GameSnapshot>,
+    /// Set once the game has gone out of step, after which nothing more is checked
+    report: Option<DesyncReport>,
+}
+
+impl DesyncCheck {
+    /// Check the game against the hash recorded for the next frame, once the frame's inputs have
+    /// been given to it but before it's updated. Returns the report the first time the hashes
+    /// don't match.
+    pub fn check(&mut self, game: &Game, expected: Option<u64>) -> Option<&DesyncReport> {
+        let frame = self.frame;
+        self.frame += 1;
+        let expected = expected.filter(|_| self.report.is_none())?;
+        let actual = game.sand().hash();
+        if actual == expected {
+            self.last_in_step = Some(game.snapshot());
+            return None;
+        }
+        self.report = Some(DesyncReport {
+            frame,
+            expected,
+            actual,
+            last_in_step: self.last_in_step.take(),
+            diverged: game.snapshot(),
+        });
+        self.report.as_ref()
+    }
+}
+
+/// Queue a report to be written to [`DESYNC_FILE`]
+pub fn write_report(report: &DesyncReport) -> Result<(), String> {
+    disk::write(DESYNC_FILE, save::to_string(report)?);
+    Ok(())
+}
@@ -0,0 +1,135 @@
+//! Saving files on a background thread, so that a slow disk never holds up a frame. Everything the
+//! game writes goes through here, in the order it was queued. Errors come back through
+//! [`take_errors`] to be shown whenever the game gets to them.
+//!
+//! Files are only read when they're needed, like at startup or when a menu screen opens, so reads
+//! happen on the spot.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+
+use lazy_static::lazy_static;
+
+type Task = Box<dyn FnOnce() -> Result<(), String> + Send>;
+
+enum Job {
+    Task(Task),
+    /// Reply once every job before this one is done
+    Flush(Sender<()>),
+}
+
+struct Worker {
+    /// `None` once the worker has been shut down
+    jobs: Option<Sender<Job>>,
+    errors: Receiver<String>,
+    thread: Option<JoinHandle<()>>,
+}
+
+lazy_static! {
+    static ref WORKER: Mutex<Worker> = Mutex::new(Worker::start());
+}
+
+impl Worker {
+    /// Start the thread, which runs for as long as the game does
+    fn start() -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        let (error_sender, errors) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            for job in job_receiver {
+                match job {
+                    Job::Task(task) => {
+                        if let Err(e) = task() {
+                            let _ = error_sender.send(e);
+                        }
+                    }
+                    Job::Flush(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+        Self {
+            jobs: Some(jobs),
+            errors,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// Queue some saving work, like encoding and writing an image. After [`shutdown`], the work is
+/// done on the spot instead.
+pub fn run(task: impl FnOnce() -> Result<(), String> + Send + 'static) {
+    let worker = WORKER.lock().unwrap();
+    match &worker.jobs {
+        Some(jobs) => {
+            // The thread only stops once `jobs` is dropped, so sending can't fail
+            let _ = jobs.send(Job::Task(Box::new(task)));
+        }
+        None => {
+            drop(worker);
+            if let Err(e) = task() {
+                eprintln!("{e}");
+            }
+        }
+    }
+}
+
+/// Queue a file to be written. It's written beside where it goes and then moved into place, so a
+/// crash part way through leaves the old version rather than half of each.
+pub fn write(path: impl Into<PathBuf>, contents: impl Into<Vec<u8>>) {
+    let (path, contents) = (path.into(), contents.into());
+    run(move || write_atomically(&path, &contents));
+}
+
+/// Queue a file to be removed, if it exists
+pub fn remove(path: impl Into<PathBuf>) {
+    let path = path.into();
+    run(move || match fs::remove_file(&path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(format!("could not remove {}: {e}", path.display()))
+        }
+        _ => Ok(()),
+    });
+}
+
+/// Wait for everything queued so far to be saved, like before the game exits
+pub fn flush() {
+    let (done, wait) = mpsc::channel();
+    let sent = match &WORKER.lock().unwrap().jobs {
+        Some(jobs) => jobs.send(Job::Flush(done)).is_ok(),
+        None => false,
+    };
+    if sent {
+        let _ = wait.recv();
+    }
+}
+
+/// Finish everything queued and stop the thread, reporting any errors left over, since there's no
+/// game left to show them
+pub fn shutdown() {
+    let mut worker = WORKER.lock().unwrap();
+    // The thread works through what's left and stops once the queue is closed
+    worker.jobs = None;
+    if let Some(thread) = worker.thread.take() {
+        let _ = thread.join();
+    }
+    for e in worker.errors.try_iter() {
+        eprintln!("{e}");
+    }
+}
+
+/// The errors from saving since the last time this was called
+pub fn take_errors() -> Vec<String> {
+    WORKER.lock().unwrap().errors.try_iter().collect()
+}
+
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), String> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    fs::write(&temporary, contents)
+        .and_then(|_| fs::rename(&temporary, path))
+        .map_err(|e| format!("could not save {}: {e}", path.display()))
+}
@@ -0,0 +1,161 @@
+use std::fs;
+
+use crate::constants::{Color, Shape, BLOCK_SIZE};
+use crate::disk;
+use crate::layout::Layout;
+use crate::mission::{self, format_queue, format_row, Mission};
+
+/// Where the editor saves and loads its scenario
+pub const SCENARIO_FILE: &str = "sandtris_scenario.txt";
+
+/// A board editor for building scenarios. Sand is painted with the mouse one block-sized cell at a
+/// time, so that scenarios use the same format as mission boards.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Editor {
+    /// Every cell on the board, as rows from top to bottom
+    pub board: Vec<Vec<Option<Color>>>,
+    /// The color painted with the left mouse button
+    pub brush: Color,
+    /// The shape that will be added to the queue next
+    pub shape: Shape,
+    pub queue: Vec<(Shape, Color)>,
+    cursor: [f64; 2],
+    /// What the held mouse button paints, if one is held
+    painting: Option<Option<Color>>,
+}
+
+impl Editor {
+    pub fn new(layout: Layout) -> Self {
+        Self {
+            board: vec![vec![None; layout.columns]; layout.rows],
+            brush: Color::Red,
+            shape: Shape::all().next().unwrap(),
+            queue: Vec::new(),
+            cursor: [0.0, 0.0],
+            painting: None,
+        }
+    }
+
+    fn columns(&self) -> usize {
+        self.board[0].len()
+    }
+
+    /// Track the mouse position, in window coordinates. Returns whether the board changed.
+    pub fn move_cursor(&mut self, pos: [f64; 2]) -> bool {
+        self.cursor = pos;
+        self.paint()
+    }
+
+    /// Start painting with the brush color, or erasing if `erase` is set. Returns whether the
+    /// board changed.
+    pub fn start_painting(&mut self, erase: bool) -> bool {
+        self.painting = Some((!erase).then_some(self.brush));
+        self.paint()
+    }
+
+    pub fn stop_painting(&mut self) {
+        self.painting = None;
+    }
+
+    fn paint(&mut self) -> bool {
+        let (Some(value), Some((x, y))) = (self.painting, self.hovered_cell()) else {
+            return false;
+        };
+        let changed = self.board[y][x] != value;
+        self.board[y][x] = value;
+        changed
+    }
+
+    /// The board cell under the mouse, if it's over the board
+    fn hovered_cell(&self) -> Option<(usize, usize)> {
+        let [x, y] = self.cursor;
+        if x < 0.0 || y < 0.0 {
+            return None;
+        }
+        let (x, y) = (x as usize / BLOCK_SIZE, y as usize / BLOCK_SIZE);
+        (x < self.columns() && y < self.board.len()).then_some((x, y))
+    }
+
+    pub fn cycle_brush(&mut self, forwards: bool) {
+        let index = Color::ALL.iter().position(|c| *c == self.brush).unwrap();
+        let len = Color::ALL.len();
+        self.brush = Color::ALL[if forwards {
+            (index + 1) % len
+        } else {
+            (index + len - 1) % len
+        }];
+    }
+
+    pub fn cycle_shape(&mut self) {
+        self.shape = Shape::all()
+            .nth(self.shape.index() + 1)
+            .or_else(|| Shape::all().next())
+            .unwrap();
+    }
+
+    /// Add the selected shape to the end of the queue, in the brush color
+    pub fn push_piece(&mut self) {
+        self.queue.push((self.shape, self.brush));
+    }
+
+    pub fn pop_piece(&mut self) {
+        self.queue.pop();
+    }
+
+    /// The scenario as a mission with no objective, to be played with the normal rules
+    pub fn to_mission(&self) -> Mission {
+        let first_row = self
+            .board
+            .iter()
+            .position(|row| row.iter().any(Option::is_some))
+            .unwrap_or(self.board.len());
+        Mission {
+            name: "SCENARIO".to_string(),
+            objective: mission::Objective::Score(usize::MAX),
+            gravity: 1.0,
+            modifiers: Default::default(),
+            board: self.board[first_row..].to_vec(),
+            queue: self.queue.clone(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let mission = self.to_mission();
+        let mut source = format!(
+            "; Saved by the sandtris board editor\nname = {}\n",
+            mission.name
+        );
+        for row in &mission.board {
+            source += &format!("row = {}\n", format_row(row));
+        }
+        if !mission.queue.is_empty() {
+            source += &format!("queue = {}\n", format_queue(&mission.queue));
+        }
+        disk::write(SCENARIO_FILE, source);
+        Ok(())
+    }
+
+    /// Load a scenario saved by the editor, or any single mission
+    pub fn load(&mut self) -> Result<(), String> {
+        let source = fs::read_to_string(SCENARIO_FILE)
+            .map_err(|e| format!("could not read {SCENARIO_FILE}: {e}"))?;
+        let mission = mission::parse_missions(&source)
+            .map_err(|e| format!("invalid scenario {SCENARIO_FILE}: {e}"))?
+            .remove(0);
+        let (columns, rows) = (self.columns(), self.board.len());
+        if mission.board.len() > rows {
+            return Err(format!("{SCENARIO_FILE} has more rows than the board"));
+        }
+        if mission.board.iter().any(|row| row.len() > columns) {
+            return Err(format!("{SCENARIO_FILE} is wider than the board"));
+        }
+
+        self.board = vec![vec![None; columns]; rows - mission.board.len()];
+        self.board.extend(mission.board.into_iter().map(|mut row| {
+            row.resize(columns, None);
+            row
+        }));
+        self.queue = mission.queue;
+        Ok(())
+    }
+}
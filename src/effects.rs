@@ -0,0 +1,115 @@
+//! Effects drawn over the board that are only there for show, like the streak behind a hard-dropped
+//! piece and the dust it kicks up. They play out apart from the game itself, with randomness of
+//! their own, so they're left out of saves and never change how a replay plays out.
+
+use piston_window::graphics;
+use piston_window::prelude::*;
+
+use crate::constants::{
+    DROP_TRAIL_ALPHA, DROP_TRAIL_DURATION, DUST_DURATION, DUST_GRAVITY, DUST_PARTICLES,
+    DUST_PARTICLE_SIZE, DUST_SPEED, SAND_BLOCK_SIZE, SAND_SIZE,
+};
+use crate::game::Block;
+use crate::quality::Quality;
+use crate::rng::{GameRng, WyRand};
+
+/// A streak down one column of a dropped piece, in pixels
+#[derive(Debug, Clone)]
+struct Trail {
+    rect: [f64; 4],
+    color: [f32; 4],
+    age: f64,
+}
+
+/// A speck of dust thrown up where a piece landed, in pixels
+#[derive(Debug, Clone)]
+struct Particle {
+    pos: [f64; 2],
+    velocity: [f64; 2],
+    color: [f32; 4],
+    age: f64,
+}
+
+#[derive(Debug, Default)]
+pub struct Effects {
+    rng: WyRand,
+    trails: Vec<Trail>,
+    particles: Vec<Particle>,
+}
+
+impl Effects {
+    /// A piece was hard dropped from `from_y` to where it is now: leave a streak down the column
+    /// under each of its cells that fell, and a puff of dust where each lands
+    pub fn hard_drop(&mut self, block: &Block, from_y: usize) {
+        let fell = (block.y - from_y) * SAND_SIZE;
+        let size = (SAND_BLOCK_SIZE * SAND_SIZE) as f64;
+        let coords = block.coords().collect::<Vec<_>>();
+        for (x, y) in &coords {
+            let (left, top) = ((x * SAND_SIZE) as f64, (y * SAND_SIZE) as f64);
+            // The streak goes behind the topmost cell in each column, and the dust comes out from
+            // under the bottom one
+            if !coords.iter().any(|(cx, cy)| cx == x && cy < y) {
+                self.trails.push(Trail {
+                    rect: [left, top - fell as f64, size, fell as f64],
+                    color: block.color.float_color(),
+                    age: 0.0,
+                });
+            }
+            if !coords.iter().any(|(cx, cy)| cx == x && cy > y) {
+                for _ in 0..DUST_PARTICLES {
+                    let spread = self.rng.generate::<f64>() * 2.0 - 1.0;
+                    self.particles.push(Particle {
+                        pos: [left + size * (spread + 1.0) / 2.0, top + size],
+                        velocity: [
+                            spread * DUST_SPEED,
+                            -self.rng.generate::<f64>() * DUST_SPEED,
+                        ],
+                        color: block.color.float_color(),
+                        age: 0.0,
+                    });
+                }
+            }
+        }
+    }
+
+    pub fn tick(&mut self, dt: f64) {
+        for trail in &mut self.trails {
+            trail.age += dt;
+        }
+        self.trails.retain(|trail| trail.age < DROP_TRAIL_DURATION);
+        for particle in &mut self.particles {
+            particle.age += dt;
+            particle.velocity[1] += DUST_GRAVITY * dt;
+            particle.pos[0] += particle.velocity[0] * dt;
+            particle.pos[1] += particle.velocity[1] * dt;
+        }
+        self.particles
+            .retain(|particle| particle.age < DUST_DURATION);
+    }
+
+    pub fn clear(&mut self) {
+        self.trails.clear();
+        self.particles.clear();
+    }
+
+    /// Draw the effects, leaving out the ones `quality` has no room for
+    pub fn render(&self, quality: Quality, context: graphics::Context, g: &mut G2d) {
+        for trail in self.trails.iter().filter(|_| quality.trails()) {
+            let mut color = trail.color;
+            color[3] *= DROP_TRAIL_ALPHA * (1.0 - trail.age / DROP_TRAIL_DURATION) as f32;
+            graphics::rectangle(color, trail.rect, context.transform, g);
+        }
+        for particle in self.particles.iter().filter(|_| quality.particles()) {
+            let mut color = particle.color;
+            color[3] *= (1.0 - particle.age / DUST_DURATION) as f32;
+            let [x, y] = particle.pos;
+            let size = DUST_PARTICLE_SIZE;
+            graphics::rectangle(
+                color,
+                [x - size / 2.0, y - size / 2.0, size, size],
+                context.transform,
+                g,
+            );
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! A compact binary encoding for boards, used by saves and the network protocol to keep snapshots
+//! small.
+//!
+//! Each cell takes 3 bits: 0 for empty, or 1 to 4 for a color. Boards are usually mostly empty
+//! space and tall columns of one color, so they're also run-length encoded, and whichever of the
+//! two is shorter is used. The encoding starts with a tag byte saying which it is, followed by the
+//! width and height as little-endian `u16`s.
+//!
+//! A diff between two boards of the same size is run-length encoded the same way, with a code of
+//! its own for cells that haven't changed. It's for keeping a copy of a board up to date, with a
+//! checksum to tell when the copy has drifted and needs the whole board again. A longer hash, cheap
+//! enough to take every frame, tells when a game played back from its inputs has gone out of step.
+//!
+//! ```
+//! use ndarray::Array2;
+//! use sandtris::constants::Color;
+//! use sandtris::encoding::{apply_diff, decode_board, encode_board, encode_diff};
+//!
+//! let mut board = Array2::default([96, 144]);
+//! board[[0, 143]] = Some(Color::Red);
+//! let bytes = encode_board(&board);
+//! assert!(bytes.len() < 96 * 144 / 8);
+//! assert_eq!(decode_board(&bytes), Ok(board.clone()));
+//!
+//! let mut copy = board.clone();
+//! board[[0, 142]] = Some(Color::Blue);
+//! let diff = encode_diff(&copy, &board).unwrap();
+//! apply_diff(&mut copy, &diff).unwrap();
+//! assert_eq!(copy, board);
+//! ```
+
+use ndarray::Array2;
+
+use crate::constants::Color;
+
+const TAG_PACKED: u8 = 0;
+const TAG_RUNS: u8 = 1;
+const TAG_DIFF: u8 = 2;
+const HEADER_LEN: usize = 5;
+const BITS_PER_CELL: usize = 3;
+/// The longest run that fits in one byte, alongside the cell value
+const MAX_RUN: usize = 32;
+/// The code for a cell that's the same as before, in a diff
+const UNCHANGED: u8 = 7;
+
+/// Encode a board, with cells in column order
+pub fn encode_board(board: &Array2<Option<Color>>) -> Vec<u8> {
+    let (width, height) = board.dim();
+    let mut header = vec![0];
+    header.extend((width as u16).to_le_bytes());
+    header.extend((height as u16).to_le_bytes());
+
+    let codes = board.iter().map(|cell| encode_cell(*cell));
+    let packed = pack(codes.clone());
+    let runs = run_length_encode(codes);
+    if runs.len() < packed.len() {
+        header[0] = TAG_RUNS;
+        header.extend(runs);
+    } else {
+        header[0] = TAG_PACKED;
+        header.extend(packed);
+    }
+    header
+}
+
+/// Decode a board written by [`encode_board`]
+pub fn decode_board(bytes: &[u8]) -> Result<Array2<Option<Color>>, String> {
+    if bytes.len() < HEADER_LEN {
+        return Err("encoded board is too short".to_string());
+    }
+    let width = u16::from_le_bytes([bytes[1], bytes[2]]) as usize;
+    let height = u16::from_le_bytes([bytes[3], bytes[4]]) as usize;
+    let len = width * height;
+    let data = &bytes[HEADER_LEN..];
+
+    let codes = match bytes[0] {
+        TAG_PACKED => {
+            if data.len() != (len * BITS_PER_CELL).div_ceil(8) {
+                return Err(format!(
+                    "packed board has the wrong length for {width}x{height}"
+                ));
+            }
+            unpack(data, len)
+        }
+        TAG_RUNS => {
+            let codes = data
+                .iter()
+                .flat_map(|byte| std::iter::repeat_n(byte >> 5, (byte & 0x1f) as usize + 1))
+                .collect::<Vec<_>>();
+            if codes.len() != len {
+                return Err(format!("runs don't fill a {width}x{height} board"));
+            }
+            codes
+        }
+        tag => return Err(format!("unknown board encoding: {tag}")),
+    };
+    let cells = codes
+        .into_iter()
+        .map(decode_cell)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Array2::from_shape_vec((width, height), cells).unwrap())
+}
+
+/// Encode the changes from one board to another, which must be the same size
+pub fn encode_diff(
+    old: &Array2<Option<Color>>,
+    new: &Array2<Option<Color>>,
+) -> Result<Vec<u8>, String> {
+    let (width, height) = new.dim();
+    if old.dim() != new.dim() {
+        return Err(format!(
+            "can't diff a {}x{} board against a {width}x{height} one",
+            old.dim().0,
+            old.dim().1
+        ));
+    }
+    let mut bytes = vec![TAG_DIFF];
+    bytes.extend((width as u16).to_le_bytes());
+    bytes.extend((height as u16).to_le_bytes());
+    let codes = old.iter().zip(new).map(|(old, new)| {
+        if old == new {
+            UNCHANGED
+        } else {
+            encode_cell(*new)
+        }
+    });
+    bytes.extend(run_length_encode(codes));
+    Ok(bytes)
+}
+
+/// Apply a diff written by [`encode_diff`] to the board it was made from. The board is left as it
+/// was if the diff is invalid.
+pub fn apply_diff(board: &mut Array2<Option<Color>>, bytes: &[u8]) -> Result<(), String> {
+    if bytes.len() < HEADER_LEN || bytes[0] != TAG_DIFF {
+        return Err("not a board diff".to_string());
+    }
+    let width = u16::from_le_bytes([bytes[1], bytes[2]]) as usize;
+    let height = u16::from_le_bytes([bytes[3], bytes[4]]) as usize;
+    if (width, height) != board.dim() {
+        return Err(format!(
+            "diff is for a {width}x{height} board, but the board is {}x{}",
+            board.dim().0,
+            board.dim().1
+        ));
+    }
+    let codes = bytes[HEADER_LEN..]
+        .iter()
+        .flat_map(|byte| std::iter::repeat_n(byte >> 5, (byte & 0x1f) as usize + 1))
+        .collect::<Vec<_>>();
+    if codes.len() != width * height {
+        return Err(format!("diff doesn't cover a {width}x{height} board"));
+    }
+    let cells = codes
+        .into_iter()
+        .map(|code| match code {
+            UNCHANGED => Ok(None),
+            code => decode_cell(code).map(Some),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    for (cell, change) in board.iter_mut().zip(cells) {
+        if let Some(new) = change {
+            *cell = new;
+        }
+    }
+    Ok(())
+}
+
+/// A quick checksum of a board's colors, for checking that two copies of a board still match
+pub fn board_checksum(board: &Array2<Option<Color>>) -> u32 {
+    // FNV-1a over the cell codes
+    board.iter().fold(0x811c9dc5, |hash, cell| {
+        (hash ^ encode_cell(*cell) as u32).wrapping_mul(0x01000193)
+    })
+}
+
+/// A 64-bit hash of a board, quick enough to take on every frame. It's FxHash over the encoded
+/// board, which is mostly runs, so there isn't much to go through.
+pub fn board_hash(board: &Array2<Option<Color>>) -> u64 {
+    const SEED: u64 = 0x517cc1b727220a95;
+    encode_board(board).chunks(8).fold(0, |hash, chunk| {
+        let mut word = [0; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        (hash.rotate_left(5) ^ u64::from_le_bytes(word)).wrapping_mul(SEED)
+    })
+}
+
+fn encode_cell(cell: Option<Color>) -> u8 {
+    cell.map_or(0, |color| {
+        Color::ALL.iter().position(|c| *c == color).unwrap() as u8 + 1
+    })
+}
+
+fn decode_cell(code: u8) -> Result<Option<Color>, String> {
+    match code {
+        0 => Ok(None),
+        _ => Color::ALL
+            .get(code as usize - 1)
+            .map(|color| Some(*color))
+            .ok_or_else(|| format!("invalid cell in encoded board: {code}")),
+    }
+}
+
+/// Pack 3-bit codes into bytes, least significant bit first
+fn pack(codes: impl Iterator<Item = u8>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for (i, code) in codes.enumerate() {
+        for bit in 0..BITS_PER_CELL {
+            let index = i * BITS_PER_CELL + bit;
+            if index.is_multiple_of(8) {
+                bytes.push(0);
+            }
+            *bytes.last_mut().unwrap() |= ((code >> bit) & 1) << (index % 8);
+        }
+    }
+    bytes
+}
+
+fn unpack(bytes: &[u8], len: usize) -> Vec<u8> {
+    (0..len)
+        .map(|i| {
+            (0..BITS_PER_CELL).fold(0, |code, bit| {
+                let index = i * BITS_PER_CELL + bit;
+                code | ((bytes[index / 8] >> (index % 8)) & 1) << bit
+            })
+        })
+        .collect()
+}
+
+/// One byte per run, with the code in the top 3 bits and the run length minus one in the rest
+fn run_length_encode(codes: impl Iterator<Item = u8>) -> Vec<u8> {
+    let mut runs: Vec<(u8, usize)> = Vec::new();
+    for code in codes {
+        match runs.last_mut() {
+            Some((value, count)) if *value == code && *count < MAX_RUN => *count += 1,
+            _ => runs.push((code, 1)),
+        }
+    }
+    runs.into_iter()
+        .map(|(code, count)| code << 5 | (count - 1) as u8)
+        .collect()
+}
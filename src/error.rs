@@ -0,0 +1,47 @@
+//! Errors from starting up and drawing the game.
+
+use std::fmt;
+
+/// Something that went wrong outside of the game rules, like creating the window or drawing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The settings, or a file they point to, are invalid
+    Config(String),
+    /// A replay to watch couldn't be loaded
+    Replay(String),
+    /// An input script to play couldn't be loaded
+    Script(String),
+    /// A replay didn't play out to the score it claims
+    Verification(String),
+    /// The history of runs couldn't be exported
+    Stats(String),
+    /// The window couldn't be created
+    Window(String),
+    /// An image couldn't be uploaded to the GPU
+    Texture(String),
+    /// The bundled font couldn't be decoded
+    Font(String),
+    /// Text contained a character that isn't in the font
+    UnsupportedChar(char),
+    /// Something was drawn by a game created without a window
+    Headless,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Config(e) => write!(f, "invalid settings: {e}"),
+            Self::Replay(e) => write!(f, "could not load the replay: {e}"),
+            Self::Script(e) => write!(f, "could not load the input script: {e}"),
+            Self::Verification(e) => write!(f, "the replay does not check out: {e}"),
+            Self::Stats(e) => write!(f, "could not export the stats: {e}"),
+            Self::Window(e) => write!(f, "could not create the window: {e}"),
+            Self::Texture(e) => write!(f, "could not create a texture: {e}"),
+            Self::Font(e) => write!(f, "could not load the font, text will not be shown: {e}"),
+            Self::UnsupportedChar(c) => write!(f, "the font has no character {c:?}"),
+            Self::Headless => write!(f, "there is no window to draw to"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
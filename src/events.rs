@@ -0,0 +1,110 @@
+//! Events emitted by the game as it's played, for logging or driving other systems.
+
+use std::fmt::Debug;
+
+use crate::constants::{Color, Shape};
+
+/// Something that happened in the game that other systems might want to react to
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameEvent {
+    PieceSpawned {
+        shape: Shape,
+        color: Color,
+    },
+    PieceLocked {
+        shape: Shape,
+        color: Color,
+        x: usize,
+        y: usize,
+    },
+    SandCleared {
+        pixels: usize,
+        color: Color,
+        combo: usize,
+        points: usize,
+    },
+    /// A clear that followed from an earlier one without another piece locking, with how many
+    /// clears the last lock has led to
+    Chain {
+        chain: usize,
+    },
+    /// Several groups that spanned the board at once, cleared together
+    MultiClear {
+        groups: usize,
+        points: usize,
+    },
+    /// A clear built from only a few pieces
+    EfficientClear {
+        pieces: usize,
+        points: usize,
+    },
+    /// Every grain of a color swept off the board by a powerup
+    ColorCleared {
+        color: Color,
+        grains: usize,
+        points: usize,
+    },
+    /// Every grain in a band of rows burned away by a powerup
+    LaserCleared {
+        grains: usize,
+        points: usize,
+    },
+    /// Every grain near where it landed blown away by a powerup
+    BombExploded {
+        grains: usize,
+        points: usize,
+    },
+    /// Grains pushed off the top of the board by rising sand, and the points they cost
+    SandSpilled {
+        grains: usize,
+        points: usize,
+    },
+    MissionComplete {
+        index: usize,
+    },
+    GameOver {
+        score: usize,
+    },
+}
+
+pub trait Subscriber: Debug {
+    fn on_event(&mut self, event: &GameEvent);
+}
+
+/// Queues up events as the game emits them, and delivers them to subscribers once per update
+#[derive(Debug, Default)]
+pub struct EventBus {
+    pending: Vec<GameEvent>,
+    subscribers: Vec<Box<dyn Subscriber>>,
+}
+
+impl EventBus {
+    pub fn emit(&mut self, event: GameEvent) {
+        self.pending.push(event);
+    }
+
+    pub fn subscribe(&mut self, subscriber: Box<dyn Subscriber>) {
+        self.subscribers.push(subscriber);
+    }
+
+    /// Deliver all pending events to the subscribers, and return them so the game can also handle
+    /// them
+    pub fn dispatch(&mut self) -> Vec<GameEvent> {
+        for event in &self.pending {
+            for subscriber in &mut self.subscribers {
+                subscriber.on_event(event);
+            }
+        }
+        std::mem::take(&mut self.pending)
+    }
+}
+
+/// Prints every event, for debugging
+#[derive(Debug, Default)]
+pub struct EventLogger;
+
+impl Subscriber for EventLogger {
+    fn on_event(&mut self, event: &GameEvent) {
+        println!("{event:?}");
+    }
+}
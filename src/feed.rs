@@ -0,0 +1,100 @@
+//! A feed of recent scoring events, shown on the dashboard.
+
+use std::collections::VecDeque;
+
+use image::Rgba;
+
+use crate::constants::{
+    FEED_DURATION, FEED_FADE, FEED_LENGTH, FEED_PENALTY_COLOR, HIGHLIGHT_COLOR, TEXT_COLOR,
+};
+use crate::events::{GameEvent, Subscriber};
+
+/// A feed entry: what happened in the text color, then the points it was worth in another
+#[derive(Debug, Clone)]
+struct Entry {
+    segments: [(String, Rgba<u8>); 2],
+    /// How much longer it's shown, in seconds
+    remaining: f64,
+}
+
+/// The last few scoring events, newest first, each fading out once it's been shown for a while
+#[derive(Debug, Default)]
+pub struct ScoreFeed {
+    entries: VecDeque<Entry>,
+}
+
+impl ScoreFeed {
+    pub fn tick(&mut self, dt: f64) {
+        for entry in &mut self.entries {
+            entry.remaining -= dt;
+        }
+        self.entries.retain(|entry| entry.remaining > 0.0);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Each entry's text, in colored segments, and its opacity, newest first
+    pub fn entries(&self) -> impl Iterator<Item = (&[(String, Rgba<u8>)], f64)> {
+        self.entries.iter().map(|entry| {
+            let opacity = (entry.remaining / FEED_FADE).min(1.0);
+            (entry.segments.as_slice(), opacity)
+        })
+    }
+
+    /// Add an entry for an event, with its points or combo after it
+    fn push(&mut self, label: String, points: String) {
+        self.push_colored(label, points, HIGHLIGHT_COLOR);
+    }
+
+    fn push_colored(&mut self, label: String, points: String, color: Rgba<u8>) {
+        self.entries.push_front(Entry {
+            segments: [(format!("{label} "), TEXT_COLOR), (points, color)],
+            remaining: FEED_DURATION,
+        });
+        self.entries.truncate(FEED_LENGTH);
+    }
+}
+
+impl Subscriber for ScoreFeed {
+    fn on_event(&mut self, event: &GameEvent) {
+        // The pixel font has no plus sign, so bonuses are listed by their points alone
+        match event {
+            GameEvent::SandCleared { pixels, combo, .. } => {
+                self.push(format!("CLEAR {pixels}"), format!("X{combo}"))
+            }
+            GameEvent::Chain { chain } => self.push("CHAIN".to_string(), format!("X{chain}")),
+            GameEvent::MultiClear { groups, points } => {
+                let name = match groups {
+                    2 => "DOUBLE",
+                    3 => "TRIPLE",
+                    _ => "MULTI",
+                };
+                self.push(name.to_string(), points.to_string())
+            }
+            GameEvent::EfficientClear { pieces, points } => {
+                self.push(format!("{pieces} PIECES"), points.to_string())
+            }
+            GameEvent::ColorCleared { points, .. } => {
+                self.push("SWEEP".to_string(), points.to_string())
+            }
+            GameEvent::LaserCleared { points, .. } => {
+                self.push("LASER".to_string(), points.to_string())
+            }
+            GameEvent::BombExploded { points, .. } => {
+                self.push("BOMB".to_string(), points.to_string())
+            }
+            GameEvent::SandSpilled { points, .. } => self.push_colored(
+                "SPILLED".to_string(),
+                points.to_string(),
+                FEED_PENALTY_COLOR,
+            ),
+            _ => {}
+        }
+    }
+}
@@ -1,8 +1,50 @@
+//! The game itself: board state, the rules, and rendering.
+
+use crate::analysis::{AnalysisPage, RunAnalysis};
+use crate::autosave;
+use crate::background::Background;
+use crate::board::Board;
+use crate::bot::{self, BotLevel};
+use crate::camera::{self, Camera, CameraMode};
 use crate::canvas::Canvas;
+use crate::compositor::{BoardFrame, Compositor};
 use crate::constants::*;
+use crate::dashboard::{Slot, Stack};
+use crate::disk;
+use crate::editor::Editor;
+use crate::effects::Effects;
+use crate::error::Error;
+use crate::events::{EventBus, EventLogger, GameEvent, Subscriber};
+use crate::feed::ScoreFeed;
+use crate::grade::{self, Grade, GradeTable, Grader};
+use crate::guides::Guides;
+use crate::input::{
+    Action, ControlScheme, Gesture, IdleTimer, InputState, KeyCapture, Pointer, RepeatScheduler,
+};
+use crate::keymap::{key_name, KeymapEditor};
+use crate::layout::Layout;
+use crate::mission::{self, parse_row, Mission, MissionTracker, ObjectiveTracker, MISSIONS};
+use crate::mixing::run_mixing;
+use crate::mode::{GameMode, Modifiers, RulesCard};
+use crate::palette::{PaletteEditor, CHANNELS};
 use crate::pathfinding::find_connected_sand;
-use crate::pathfinding::find_spanning_group;
-use crate::physics::run_rng_physics;
+use crate::physics::{run_rng_physics, run_sticky_physics, shift_up, Emitter, StickyGrid, Subgrid};
+use crate::powerup::Powerup;
+use crate::quality::{FrameTimer, Quality};
+use crate::randomizer::{weighted_index, Randomizer};
+use crate::replay::{Frame, Input, Replay, REPLAY_FILE};
+use crate::rng::{GameRng, WyRand};
+use crate::rules::ClearRules;
+use crate::save::GameSnapshot;
+use crate::settings::{ClearAnimation, ClearStyle, Overflow, Settings};
+use crate::special::SpecialMeter;
+use crate::stats::{self, RunRecord};
+use crate::stress::StressTest;
+use crate::timelapse::TimeLapse;
+use crate::toast::{ToastKind, Toasts};
+use crate::transition::Transition;
+use crate::tutorial::{Goal, Tutorial};
+use crate::watcher::FileWatcher;
 use derivative::Derivative;
 use enum_map::EnumMap;
 use graphics::ImageSize;
@@ -10,509 +52,3870 @@ use graphics::Transformed;
 use image::GenericImage;
 use image::GenericImageView;
 use image::Rgba;
-use imageproc::drawing;
-use imageproc::rect::Rect;
-use nanorand::RandomGen;
-use nanorand::Rng;
-use nanorand::WyRand;
-use ndarray::s;
-use ndarray::Array;
-use ndarray::Array2;
 use piston_window::graphics;
 use piston_window::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{HashSet, VecDeque};
+use std::f64::consts::PI;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Instant;
+
+/// A planner thread that's started the first time it's needed, taking jobs of `J` and giving back
+/// results of `R`
+type PlannerHandle<J, R> = Option<Background<J, R>>;
+
+/// What the hint planner is asked for: when the piece spawned, the sand and the piece
+type HintJob = (f64, Board, Block);
+
+/// Where the falling piece would land, along with the grains of the pile it would slump into
+type PilePreview = (Block, HashSet<(usize, usize)>);
 
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct Game {
     rng: WyRand,
+    settings: Settings,
+    /// Watches the config files so that changes apply without restarting
+    config_watcher: FileWatcher,
+    next_config_check: f64,
+    /// A short message shown over the board, with how many seconds it has left
+    toasts: Toasts,
+    feed: ScoreFeed,
+    effects: Effects,
+    /// The best grade reached in this mode, once the game is over
+    best_grade: Option<Grade>,
+    /// Whether the game speed has been turned down or hints shown during this run. Assisted runs
+    /// don't count towards the best grades.
+    assisted: bool,
+    /// Where the greedy bot would drop the falling piece, with hints on, along with when that
+    /// piece spawned. Like the camera, it isn't part of the state.
+    hint: Option<(f64, Block)>,
+    next_hint: f64,
+    /// When the piece the hint was last asked for spawned
+    hint_requested: Option<f64>,
+    /// Works out the hint off the main thread, from when the piece spawned, the sand and the
+    /// piece. It's started the first time hints are on.
+    hint_planner: PlannerHandle<HintJob, (f64, Option<Block>)>,
+    /// Where the falling piece would land, with the pile preview on, along with the grains of the
+    /// pile it would slump into
+    pile_preview: Option<PilePreview>,
+    next_pile_preview: f64,
+    /// Where the piece would land when the pile preview was last asked for
+    pile_requested: Option<Block>,
+    /// Works out the pile preview off the main thread. It's started the first time the pile
+    /// preview is on.
+    pile_planner: PlannerHandle<(Block, PileGrid), PilePreview>,
+    /// The page of analysis shown over the board after a game over, instead of the game over
+    /// message
+    analysis_page: Option<AnalysisPage>,
+    /// Where pieces come from when this board shares its queue with another, instead of
+    /// `piece_queue`
+    shared_queue: Option<SharedQueue>,
+    /// The other board's score, when this board is on a team
+    partner_score: Option<usize>,
+    /// Rendering errors that have already been printed
+    reported_errors: HashSet<String>,
+    input: InputState,
+    pointer: Pointer,
+    events: EventBus,
     #[derivative(Debug = "ignore")]
     text_textures: TextTextures,
     canvas: Canvas,
-    sand: Array2<Option<Color>>,
+    /// Draws the board in the background. Started on the first render, so games that are never
+    /// drawn don't start a thread.
+    compositor: Option<Compositor>,
+    frame_timer: FrameTimer,
+    camera: Camera,
+    /// The size of the board, which is the mode's own if it has one or the board size setting if
+    /// not. It stays as it was for the last mode started until another one starts.
+    layout: Layout,
+    /// How many frames have been drawn, for redrawing the board only every few frames
+    frames_drawn: usize,
+    sand: Board,
     animation: Option<(f64, Animation)>,
+    /// Bullet time after a big clear, which slows down the whole game for a moment
+    slow_motion: Option<SlowMotion>,
     play_mode: PlayMode,
+    mode: GameMode,
+    modifiers: Modifiers,
+    gravity: f64,
+    /// How much faster the falling piece falls for the score, see [`GameMode::score_gravity`]. It's
+    /// worked out as each piece spawns, so a piece falls at one speed all the way down.
+    score_gravity: f64,
+    mission: Option<MissionTracker>,
+    next_mission: usize,
+    /// How close the game is to its mode's goal, in modes that have one
+    goal: Option<ObjectiveTracker>,
+    /// When the game ends, in modes with a time limit
+    deadline: Option<f64>,
+    /// Whether the game ended by reaching its goal or running out of time, rather than topping out
+    finished: bool,
+    tutorial: Option<Tutorial>,
+    /// Kept between visits to the editor, so a scenario can be tried out and then tweaked
+    editor: Option<Editor>,
+    /// A board and queue from the editor that marathon games start with
+    scenario: Option<Mission>,
+    /// A sand animation playing over the start or end of a game
+    transition: Option<Transition>,
+    /// Timings for the stress test, while it's running
+    stress: Option<StressTest>,
+    /// Sources of sand pouring onto the board, stepped along with the physics
+    emitters: Vec<Emitter>,
+    next_stress_report: f64,
+    /// Grains drawn darker to draw the player's attention to them
+    highlight: HashSet<(usize, usize)>,
+    menu_selection: usize,
+    /// The palette screen's state, while it's open
+    palette_editor: Option<PaletteEditor>,
+    /// The key binding screen's state, while it's open
+    keymap_editor: Option<KeymapEditor>,
+    /// The rules card on screen, and the menu item it was opened for
+    rules_card: Option<(MenuItem, RulesCard)>,
+    /// Catches the key pressed to bind on the key binding screen, or to wake the game up
+    key_capture: KeyCapture,
+    idle: IdleTimer,
+    /// Whether the game pauses itself when left alone, see [`Game::enable_idle_pause`]
+    idle_pause: bool,
+    /// Set while the game is paused because it was left alone, until a key is pressed
+    away: bool,
+    /// The best grade in each mode, while the records screen is open
+    #[derivative(Debug = "ignore")]
+    records: Vec<Record>,
+    /// Snapshots taken before each piece spawned, most recent last. Only recorded in practice
+    /// mode.
+    #[derivative(Debug = "ignore")]
+    history: VecDeque<SimState>,
+    /// The current game's inputs so far, saved when it ends. Only games that play out the same
+    /// from the same seed and inputs are recorded.
+    #[derivative(Debug = "ignore")]
+    recording: Option<Replay>,
+    /// Inputs since the last update, which go into the recording with it
+    recorded_inputs: Vec<Input>,
     elapsed_time: f64,
     next_move: f64,
     next_physics_update: f64,
-    control_updates: EnumMap<Direction, Option<f64>>,
+    next_rise: f64,
+    repeat: RepeatScheduler,
     queue_drop: bool,
+    /// The falling piece has landed, and is about to lock once it can't be nudged any more
+    locking: Option<Locking>,
+    /// When the drop key was pressed, if it's down
+    drop_pressed_at: Option<f64>,
+    /// When the last piece from the queue spawned, for the drop lockout
+    spawned_at: f64,
     falling_block: Option<Block>,
-    next_block: Block,
+    piece_queue: PieceQueue,
+    held_block: Option<Block>,
+    hold_used: bool,
+    last_block_x: Option<usize>,
     score: usize,
     combo: usize,
+    /// How much longer the combo stays alive without another clear, in seconds
+    combo_timer: f64,
+    /// How many clears have followed from the last piece that locked
+    chain: usize,
+    grader: Grader,
+    /// The grade the run is on so far, if the mode is graded
+    grade: Option<Grade>,
+    analysis: RunAnalysis,
+    /// Snapshots of the board over the run. Like the history, they aren't part of the state.
+    timelapse: TimeLapse,
+    /// Whose turn it is in hot seat mode, as the player that controls the falling or next piece
+    turn: usize,
+    /// The player that placed the last piece to lock, who gets the points for any clears it sets
+    /// off
+    last_lock_player: usize,
+    /// Each player's share of the score in hot seat mode
+    player_scores: [usize; 2],
+    /// How many pieces have locked this run, which numbers the grains of each one
+    pieces_locked: usize,
+    /// Set by a long enough chain of clears, to make the next piece a powerup
+    powerup_earned: bool,
+    special_meter: SpecialMeter,
+    /// How much longer the slow time special lasts, in seconds
+    time_slowed: f64,
+    /// Whether to save the run in progress every so often
+    autosave: bool,
+    next_autosave: f64,
+    /// A run the last session left behind, offered on the menu
+    recoverable: Option<GameSnapshot>,
+    /// Set when closing the window has to be confirmed first, because a run is in progress
+    confirm_quit: bool,
+    /// Set once the game is ready for the window to close
+    quitting: bool,
+    /// How well the bot plays in versus, as picked on the menu
+    bot_level: BotLevel,
+    /// Set when versus is picked on the menu, for whatever runs the game to start it
+    versus_request: Option<BotLevel>,
 }
 
 impl Game {
-    pub fn new(window: &mut PistonWindow) -> Self {
+    pub fn new(window: &mut PistonWindow, settings: Settings) -> Self {
+        let (width, height) = settings.board.canvas_size();
+        let canvas = Canvas::new(window, (width as u32, height as u32));
+        Self::with_graphics(TextTextures::new(window), canvas, settings)
+    }
+
+    /// Create a game without a window. It plays the same way, but can't be rendered, which is
+    /// useful for driving the simulation from a bot or a test.
+    pub fn headless(settings: Settings) -> Self {
+        let (width, height) = settings.board.canvas_size();
+        Self::with_graphics(
+            TextTextures::headless(),
+            Canvas::headless((width as u32, height as u32)),
+            settings,
+        )
+    }
+
+    fn with_graphics(text_textures: TextTextures, canvas: Canvas, settings: Settings) -> Self {
         let mut rng = WyRand::new();
-        let next_block = rng.generate();
+        let piece_queue = PieceQueue::new(&settings, None, &mut rng);
+        let modifiers = settings.modifiers;
+        let frame_timer = FrameTimer::new(settings.render_quality);
+        let layout = settings.board;
+        let sand_dims = layout.sand_dims();
+        let camera = Camera::fit(&layout);
+        let config_watcher = FileWatcher::new(settings.config_files());
+        let mut events = EventBus::default();
+        if settings.log_events {
+            events.subscribe(Box::new(EventLogger));
+        }
         Self {
             rng,
-            text_textures: TextTextures::new(window),
-            canvas: Canvas::new(window),
-            sand: Array2::default([BOARD_SIZE.0 / SAND_SIZE, BOARD_SIZE.1 / SAND_SIZE]),
+            settings,
+            config_watcher,
+            next_config_check: CONFIG_CHECK_DELAY,
+            toasts: Toasts::default(),
+            feed: ScoreFeed::default(),
+            effects: Effects::default(),
+            best_grade: None,
+            assisted: false,
+            hint: None,
+            next_hint: 0.0,
+            hint_requested: None,
+            hint_planner: None,
+            pile_preview: None,
+            next_pile_preview: 0.0,
+            pile_requested: None,
+            pile_planner: None,
+            analysis_page: None,
+            shared_queue: None,
+            partner_score: None,
+            reported_errors: HashSet::new(),
+            input: InputState::default(),
+            pointer: Pointer::default(),
+            events,
+            text_textures,
+            canvas,
+            compositor: None,
+            frame_timer,
+            camera,
+            layout,
+            frames_drawn: 0,
+            sand: Board::new(sand_dims),
             animation: None,
-            play_mode: PlayMode::Playing,
+            slow_motion: None,
+            play_mode: PlayMode::Menu,
+            mode: GameMode::Marathon,
+            modifiers,
+            gravity: 1.0,
+            score_gravity: 1.0,
+            mission: None,
+            next_mission: mission::load_progress().min(MISSIONS.len() - 1),
+            goal: None,
+            deadline: None,
+            finished: false,
+            tutorial: None,
+            editor: None,
+            scenario: None,
+            transition: None,
+            stress: None,
+            emitters: Vec::new(),
+            next_stress_report: 0.0,
+            highlight: HashSet::new(),
+            menu_selection: 0,
+            palette_editor: None,
+            keymap_editor: None,
+            rules_card: None,
+            key_capture: KeyCapture::default(),
+            idle: IdleTimer::default(),
+            idle_pause: false,
+            away: false,
+            records: Vec::new(),
+            history: VecDeque::new(),
+            recording: None,
+            recorded_inputs: Vec::new(),
             elapsed_time: 0.0,
             next_move: MOVE_DELAY,
             next_physics_update: 0.0,
-            control_updates: Default::default(),
+            next_rise: RISING_DELAY,
+            repeat: RepeatScheduler::default(),
             queue_drop: false,
+            locking: None,
+            drop_pressed_at: None,
+            spawned_at: 0.0,
             falling_block: None,
-            next_block,
+            piece_queue,
+            held_block: None,
+            hold_used: false,
+            last_block_x: None,
             score: 0,
             combo: 0,
+            combo_timer: 0.0,
+            chain: 0,
+            grader: Grader::default(),
+            grade: None,
+            analysis: RunAnalysis::new(sand_dims),
+            timelapse: TimeLapse::default(),
+            turn: 0,
+            last_lock_player: 0,
+            player_scores: [0; 2],
+            pieces_locked: 0,
+            powerup_earned: false,
+            special_meter: SpecialMeter::default(),
+            time_slowed: 0.0,
+            autosave: false,
+            next_autosave: 0.0,
+            recoverable: None,
+            confirm_quit: false,
+            quitting: false,
+            bot_level: BotLevel::Normal,
+            versus_request: None,
         }
     }
 
-    fn reset(&mut self) {
-        self.sand.assign(&Array::from_elem(1, None));
-        self.animation = None;
-        self.play_mode = PlayMode::Playing;
-        self.next_move = self.elapsed_time + MOVE_DELAY;
-        self.next_physics_update = self.elapsed_time;
-        self.queue_drop = false;
-        self.falling_block = None;
-        self.next_block = self.rng.generate();
-        self.score = 0;
-        self.combo = 1;
+    /// Save runs in progress every so often, and offer to recover one that the last session left
+    /// behind
+    pub fn enable_autosave(&mut self) {
+        match autosave::load_autosave() {
+            Some(Ok(snapshot)) => self.recoverable = Some(snapshot),
+            Some(Err(e)) => self.show_error(&e),
+            None => {}
+        }
+        self.autosave = true;
     }
 
-    pub fn handle_event(&mut self, event: &Event) {
-        if let Some(button) = event.press_args() {
-            match button {
-                Button::Keyboard(key) => match key {
-                    Key::Left => {
-                        if self.play_mode == PlayMode::Playing {
-                            self.move_block(Direction::Left);
-                        }
-                        self.control_updates[Direction::Left] =
-                            Some(self.elapsed_time + FIRST_INPUT_DELAY);
-                    }
-                    Key::Right => {
-                        if self.play_mode == PlayMode::Playing {
-                            self.move_block(Direction::Right);
-                        }
-                        self.control_updates[Direction::Right] =
-                            Some(self.elapsed_time + FIRST_INPUT_DELAY);
-                    }
-                    Key::Down => {
-                        if self.play_mode == PlayMode::Playing {
-                            self.move_block(Direction::Down);
-                        }
-                        self.control_updates[Direction::Down] =
-                            Some(self.elapsed_time + FIRST_INPUT_DELAY);
-                    }
-                    _ => {}
-                },
-                _ => {}
-            }
+    /// Pause games that go without any input for a while, see [`Settings::idle_pause`]. Only for
+    /// games played at the keyboard, since the inputs of a replay come in on their own.
+    pub fn enable_idle_pause(&mut self) {
+        self.idle_pause = true;
+    }
+
+    /// Ask to close the window. A run in progress is paused and has to be given up by pressing
+    /// confirm or closing the window again; otherwise the game is ready to close straight away.
+    pub fn request_quit(&mut self) {
+        // Keys go to the quit prompt rather than waking the game up
+        if self.away {
+            self.away = false;
+            self.key_capture.cancel();
         }
-        if let Some(button) = event.release_args() {
-            match button {
-                Button::Keyboard(key) => match key {
-                    Key::Left => {
-                        self.control_updates[Direction::Left] = None;
-                    }
-                    Key::Right => {
-                        self.control_updates[Direction::Right] = None;
-                    }
-                    Key::Down => {
-                        self.control_updates[Direction::Down] = None;
-                    }
-                    Key::Space => {
-                        self.queue_drop = true;
-                    }
-                    Key::P => {
-                        self.play_mode = self.play_mode.toggle_pause();
-                    }
-                    Key::R => {
-                        self.reset();
-                    }
-                    _ => {}
-                },
-                _ => {}
-            }
+        let in_run = matches!(self.play_mode, PlayMode::Playing | PlayMode::Paused);
+        if !in_run || self.confirm_quit {
+            self.quitting = true;
+            return;
         }
+        if self.play_mode == PlayMode::Playing {
+            // Pausing goes through the same inputs as the key, so a replay of the run pauses here
+            // too
+            self.press(Action::Pause);
+            self.release(Action::Pause);
+        }
+        self.confirm_quit = true;
     }
 
-    fn move_block(&mut self, direction: Direction) {
-        for _ in 0..MOVE_REPEAT {
-            match direction {
-                Direction::Left => {
-                    if let Some(block) = self
-                        .falling_block
-                        .filter(|_| self.can_move(Direction::Left))
-                    {
-                        self.falling_block = Some(block.dec_x());
-                    }
-                }
-                Direction::Right => {
-                    if let Some(block) = self
-                        .falling_block
-                        .filter(|_| self.can_move(Direction::Right))
-                    {
-                        self.falling_block = Some(block.inc_x());
-                    }
-                }
-                Direction::Down => {
-                    if let Some(block) = self.falling_block {
-                        if self.can_move(Direction::Down) {
-                            self.falling_block = Some(block.inc_y())
-                        } else {
-                            self.add_sand_block();
-                            self.falling_block = None;
-                            self.combo = 0;
-                            break;
-                        }
-                    }
-                }
+    /// Whether the window should close, see [`Game::request_quit`]
+    pub fn should_quit(&self) -> bool {
+        self.quitting
+    }
+
+    /// Save anything that still needs saving before the game goes away. The run in progress is
+    /// autosaved straight away, so it can be recovered next time.
+    pub fn shutdown(&mut self) {
+        if self.can_autosave() {
+            if let Err(e) = autosave::save(&self.snapshot()) {
+                eprintln!("{e}");
             }
         }
+        for e in disk::take_errors() {
+            eprintln!("{e}");
+        }
     }
 
-    pub fn update(&mut self, event: &UpdateArgs) {
-        if self.play_mode != PlayMode::Playing {
-            return;
-        }
+    /// Start a new game in the given mode
+    pub fn start(&mut self, mode: GameMode) {
+        self.mode = mode;
+        self.scenario = None;
+        self.restart();
+    }
 
-        if self.run_animation(event.dt) {
-            // If we are in the middle of an animation, let run_animation() handle it, the game is
-            // effectively frozen
+    /// Start a new game from a given seed, which plays out the same as any other game started from
+    /// that seed with the same settings and inputs. It isn't recorded, since it's usually a replay.
+    pub fn start_seeded(&mut self, mode: GameMode, seed: u64) {
+        self.mode = mode;
+        self.scenario = None;
+        self.reseed(seed);
+        self.recording = None;
+    }
+
+    /// Start the current mode over from a fresh seed, recording it if possible
+    fn restart(&mut self) {
+        let seed = self.rng.generate();
+        self.reseed(seed);
+        let recordable = self.settings.controls == ControlScheme::Keyboard
+            && self.scenario.is_none()
+            && !matches!(
+                self.mode,
+                GameMode::Missions | GameMode::Tutorial | GameMode::Editor
+            );
+        self.recording = recordable.then(|| Replay {
+            rules: self.settings.rules().to_vec(),
+            ..Replay::new(self.mode, seed, self.settings.game_speed)
+        });
+        self.recorded_inputs.clear();
+    }
+
+    fn reseed(&mut self, seed: u64) {
+        self.rng = WyRand::new_seed(seed);
+        // Timers are set relative to the game time, and keys held from before would repeat out of
+        // step, so both start from scratch for the game to be reproducible
+        self.elapsed_time = 0.0;
+        self.input = InputState::default();
+        self.repeat = RepeatScheduler::default();
+        self.reset();
+    }
+
+    /// Size the board and everything drawn from it for a layout, for a mode with a board size of
+    /// its own
+    fn set_layout(&mut self, layout: Layout) {
+        if layout == self.layout {
             return;
-        } else if let Some((_, animation)) = self.animation.take() {
-            // The animation is complete, do whatever needs to be done now that the animation's
-            // finished
-            match animation {
-                Animation::RemoveLine {
-                    affected_pixels, ..
-                } => {
-                    self.combo += 1;
-                    self.score += affected_pixels.len() * self.combo;
-                    for (px, py) in affected_pixels {
-                        self.sand[[px, py]] = None;
-                    }
-                }
-            }
         }
+        self.layout = layout;
+        self.sand = Board::new(layout.sand_dims());
+        let (width, height) = layout.canvas_size();
+        self.canvas.resize((width as u32, height as u32));
+        // The compositor is sized for the board, so a new one is started on the next render
+        self.compositor = None;
+        self.camera = Camera::fit(&layout);
+        // Anything the planners are still working on is for the old board
+        self.hint_planner = None;
+        self.hint_requested = None;
+        self.hint = None;
+        self.pile_planner = None;
+        self.pile_requested = None;
+        self.pile_preview = None;
+    }
 
-        self.elapsed_time += event.dt;
+    /// The size the window needs to be for the board and dashboard, which changes when a mode
+    /// with a board size of its own starts
+    pub fn window_size(&self) -> (u32, u32) {
+        self.layout.window_size()
+    }
 
-        self.control_updates = self.control_updates.map(|input, update| {
-            if let Some(update) = update.filter(|update| self.elapsed_time >= *update) {
-                self.move_block(input);
-                Some(update + INPUT_DELAY)
-            } else {
-                update
-            }
-        });
+    /// The sand on the board
+    pub fn sand(&self) -> &Board {
+        &self.sand
+    }
 
-        if self.queue_drop {
-            while self.falling_block.is_some() {
-                self.move_block(Direction::Down);
-            }
-            self.queue_drop = false;
-        }
+    /// The piece the player is controlling, if one is falling
+    pub fn falling_block(&self) -> Option<Block> {
+        self.falling_block
+    }
 
-        if self.elapsed_time >= self.next_physics_update {
-            self.run_sand_physics();
-            self.next_physics_update += PHYSICS_DELAY;
+    pub fn next_block(&self) -> Block {
+        match &self.shared_queue {
+            Some(queue) => queue.peek(),
+            None => self.piece_queue.peek(),
         }
+    }
 
-        if let Some((x, y)) = find_spanning_group(&self.sand) {
-            self.animation = Some((
-                0.0,
-                Animation::RemoveLine {
-                    flash_state: false,
-                    affected_pixels: find_connected_sand(&self.sand, x, y),
-                },
-            ));
-        }
+    pub fn held_block(&self) -> Option<Block> {
+        self.held_block
+    }
 
-        if self.elapsed_time >= self.next_move {
-            if self.falling_block.is_some() {
-                if self.control_updates[Direction::Down].is_none() {
-                    self.move_block(Direction::Down);
-                }
-            } else {
-                self.falling_block = Some({
-                    self.next_block.with_pos(
-                        self.sand.dim().0 / 2 - self.next_block.width() * SAND_BLOCK_SIZE / 2,
-                        0,
-                    )
-                });
-                self.next_block = self.rng.generate();
-                if !self.can_move(Direction::Down) {
-                    self.play_mode = PlayMode::GameOver
-                }
-            }
-            self.next_move += MOVE_DELAY;
-        }
+    pub fn score(&self) -> usize {
+        self.score
     }
 
-    fn run_animation(&mut self, delta: f64) -> bool {
-        let Some((animation_ts, animation)) = &mut self.animation else {
-            return false;
-        };
+    /// The replay being recorded of the current game, if it's being recorded. Recording stops once
+    /// the game is over.
+    pub fn recording(&self) -> Option<&Replay> {
+        self.recording.as_ref()
+    }
 
-        *animation_ts += delta;
+    /// Whether nothing on screen is moving or counting down, so there's no need to update or draw
+    /// the game until there's some input. That's only ever the case on the menu or while paused.
+    pub fn is_idle(&self) -> bool {
+        matches!(
+            self.play_mode,
+            PlayMode::Menu
+                | PlayMode::Paused
+                | PlayMode::Palette
+                | PlayMode::Keys
+                | PlayMode::Records
+                | PlayMode::Rules
+        ) && self.transition.is_none()
+            && self.toasts.is_empty()
+            && self.feed.is_empty()
+            && self.camera == Camera::fit(&self.layout)
+    }
 
-        match animation {
-            Animation::RemoveLine { flash_state, .. } => {
-                *flash_state = if (..FLASH_DELAY).contains(animation_ts)
-                    || (FLASH_DELAY * 2.0..FLASH_DELAY * 3.0).contains(animation_ts)
-                {
-                    false
-                } else if *animation_ts <= FLASH_DELAY * 4.0 {
-                    true
-                } else {
-                    return false;
-                };
-            }
-        };
+    /// Whether the key binding screen is waiting for a key to bind, and so wants every key pressed
+    pub fn is_capturing_key(&self) -> bool {
+        self.key_capture.is_waiting()
+    }
 
-        return true;
+    /// Change the game speed setting, like to match the speed a replay was recorded at
+    pub fn set_speed(&mut self, speed: f64) {
+        self.settings.game_speed = speed;
     }
 
-    fn can_move(&self, direction: Direction) -> bool {
-        if let Some(block) = self.falling_block {
-            match direction {
-                Direction::Left => {
-                    // TODO: Check sand
-                    block.x > 0
-                        && block.coords().all(|(px, py)| {
-                            self.sand
-                                .slice(s![px - 1, py..py + SAND_BLOCK_SIZE])
-                                .iter()
-                                .all(Option::is_none)
-                        })
-                }
-                Direction::Right => {
-                    // TODO: Check sand
-                    block.x < self.sand.dim().0 - (SAND_BLOCK_SIZE * block.width())
-                        && block.coords().all(|(px, py)| {
-                            self.sand
-                                .slice(s![px + SAND_BLOCK_SIZE, py..py + SAND_BLOCK_SIZE])
-                                .iter()
-                                .all(Option::is_none)
-                        })
-                }
-                Direction::Down => {
-                    block.y < self.sand.dim().1 - (SAND_BLOCK_SIZE * block.height())
-                        && block.coords().all(|(px, py)| {
-                            self.sand
-                                .slice(s![px..px + SAND_BLOCK_SIZE, py + SAND_BLOCK_SIZE])
-                                .iter()
-                                .all(Option::is_none)
-                        })
-                }
-            }
-        } else {
-            false
-        }
+    /// The most frames to draw each second, or `None` for as many as possible
+    pub fn max_fps(&self) -> Option<u64> {
+        self.settings.max_fps
     }
 
-    fn add_sand_block(&mut self) {
-        if let Some(block) = self.falling_block {
-            for (px, py) in block.coords() {
-                self.sand
-                    .slice_mut(s![px..px + SAND_BLOCK_SIZE, py..py + SAND_BLOCK_SIZE])
-                    .assign(&Array::from_elem(1, Some(block.color)));
-            }
-        }
+    pub fn is_game_over(&self) -> bool {
+        self.play_mode == PlayMode::GameOver
     }
 
-    fn run_sand_physics(&mut self) {
-        // let last_sand = self.sand.clone();
-        // The bottom line will not move so we can skip it, and not worry about the bottom edge
-        // case
-        // for y in (0..self.sand.dim().1 - 1).rev() {
-        //     for x in 0..self.sand.dim().0 {
-        //         if self.sand[[x, y]].is_none() {
-        //             continue;
-        //         }
-        //
-        //         // Check directly below
-        //         if self.sand[[x, y + 1]].is_none() {
-        //             self.sand[[x, y + 1]] = self.sand[[x, y]];
-        //             self.sand[[x, y]] = None;
-        //             continue;
-        //         }
-        //     }
-        //
-        //     for x in 0..self.sand.dim().0 {
-        //         if self.sand[[x, y]].is_none() {
-        //             continue;
-        //         }
-        //
-        //         // TODO: Make which direction the sand actually goes randomly decided
-        //
-        //         // Check bottom left
-        //         if x > 0 && self.sand[[x - 1, y + 1]].is_none() {
-        //             self.sand[[x - 1, y + 1]] = self.sand[[x, y]];
-        //             self.sand[[x, y]] = None;
-        //             continue;
-        //         }
-        //
-        //         // Check bottom right
-        //         if x < self.sand.dim().0 - 1 && self.sand[[x + 1, y + 1]].is_none() {
-        //             self.sand[[x + 1, y + 1]] = self.sand[[x, y]];
-        //             self.sand[[x, y]] = None;
-        //             continue;
-        //         }
-        //     }
-        // }
-        run_rng_physics(&mut self.rng, self.sand.view_mut());
+    /// Whether a game is being played, and isn't paused or over
+    pub fn is_playing(&self) -> bool {
+        self.play_mode == PlayMode::Playing
     }
 
-    fn center_texture(
-        width: u32,
-        height: u32,
-        context: graphics::Context,
-        texture: &G2dTexture,
-    ) -> graphics::Context {
-        Self::center_texture_x(width, context, texture)
-            .trans(0.0, (height / 2 - texture.get_height() / 2) as f64)
+    /// What the window's title should say: the mode and score during a game, or just the name of
+    /// the game on the menu and its screens
+    pub fn window_title(&self) -> String {
+        let status = match self.play_mode {
+            PlayMode::Playing => "",
+            PlayMode::Paused => " (paused)",
+            PlayMode::GameOver => " (game over)",
+            PlayMode::MissionComplete => " (complete)",
+            PlayMode::Menu
+            | PlayMode::Palette
+            | PlayMode::Keys
+            | PlayMode::Records
+            | PlayMode::Rules => return WINDOW_TITLE.to_string(),
+        };
+        format!(
+            "{WINDOW_TITLE} - {} - {}{status}",
+            self.mode.name(),
+            self.score
+        )
     }
 
-    fn center_texture_x(
-        width: u32,
-        context: graphics::Context,
-        texture: &G2dTexture,
-    ) -> graphics::Context {
-        context.trans((width / 2 - texture.get_width() / 2) as f64, 0.0)
+    /// The bot level versus was picked with on the menu, if it was picked since the last call.
+    /// Versus needs a window with room for two boards, so starting it is left to whatever runs the
+    /// game.
+    pub fn take_versus_request(&mut self) -> Option<BotLevel> {
+        self.versus_request.take()
     }
 
-    fn draw_dashboard(&mut self, context: graphics::Context, g: &mut G2d) {
-        let ui_width = WINDOW_SIZE.0 - BOARD_SIZE.0 as u32;
-        let ui_height = WINDOW_SIZE.1 as u32;
+    /// Capture the current position, for saving
+    pub fn snapshot(&self) -> GameSnapshot {
+        GameSnapshot {
+            mode: self.mode,
+            sand: self.sand.colors().clone(),
+            hidden_rows: self.layout.hidden_sand_rows(),
+            falling_block: self.falling_block,
+            next_block: self.next_block(),
+            held_block: self.held_block,
+            hold_used: self.hold_used,
+            score: self.score,
+            combo: self.combo,
+        }
+    }
 
-        let context = context.trans(BOARD_SIZE.0 as f64, 0.0);
+    /// Start a new game in the snapshot's mode, from the snapshot's position
+    pub fn restore(&mut self, snapshot: GameSnapshot) -> Result<(), String> {
+        let layout = snapshot.mode.layout(&self.settings);
+        let snapshot = snapshot.with_hidden_rows(layout.hidden_sand_rows())?;
+        if snapshot.sand.dim() != layout.sand_dims() {
+            return Err(format!(
+                "saved board is {:?} grains, but the board is {:?}",
+                snapshot.sand.dim(),
+                layout.sand_dims()
+            ));
+        }
+        if matches!(
+            snapshot.mode,
+            GameMode::Missions | GameMode::Tutorial | GameMode::Editor | GameMode::Stress
+        ) {
+            return Err(format!("{} games can't be saved", snapshot.mode.name()));
+        }
+        let sand = Board::from_colors(snapshot.sand, self.elapsed_time);
+        // The falling piece has to be on the board and clear of the sand
+        if snapshot
+            .falling_block
+            .is_some_and(|block| !block.fits(&sand))
+        {
+            return Err("saved piece overlaps the sand or the walls".to_string());
+        }
+        self.start(snapshot.mode);
+        self.sand = sand;
+        self.falling_block = snapshot.falling_block;
+        let next = snapshot.next_block;
+        self.piece_queue.set_preset(&[(next.shape, next.color)]);
+        self.held_block = snapshot.held_block;
+        self.hold_used = snapshot.hold_used;
+        self.score = snapshot.score;
+        self.combo = snapshot.combo;
+        // Snapshots don't keep timers, so a combo gets a whole window to carry on from
+        if self.combo > 0 {
+            self.combo_timer = self.mode.combo_window();
+        }
+        Ok(())
+    }
 
-        // Draw background
-        graphics::rectangle_from_to(
-            UI_BACKGROUND_COLOR,
-            [0.0, 0.0],
-            [ui_width as f64, ui_height as f64],
-            context.transform,
-            g,
-        );
+    fn reset(&mut self) {
+        self.set_layout(self.mode.layout(&self.settings));
+        self.sand.clear();
+        self.emitters.clear();
+        self.animation = None;
+        self.slow_motion = None;
+        self.play_mode = PlayMode::Playing;
+        self.modifiers = self.mode.modifiers(&self.settings);
+        self.gravity = self.mode.def().gravity;
+        self.score_gravity = 1.0;
+        self.mission = None;
+        self.goal = self
+            .mode
+            .def()
+            .goal
+            .map(|goal| ObjectiveTracker::new(goal, self.elapsed_time));
+        self.deadline = self
+            .mode
+            .def()
+            .time_limit
+            .map(|seconds| self.elapsed_time + seconds);
+        self.finished = false;
+        self.tutorial = None;
+        self.highlight.clear();
+        self.history.clear();
+        self.next_move = self.elapsed_time + self.move_delay();
+        self.next_physics_update = self.elapsed_time;
+        self.next_rise = self.elapsed_time + RISING_DELAY;
+        self.queue_drop = false;
+        self.locking = None;
+        self.drop_pressed_at = None;
+        self.spawned_at = self.elapsed_time;
+        self.falling_block = None;
+        self.piece_queue =
+            PieceQueue::new(&self.settings, self.mode.color_override(), &mut self.rng);
+        self.held_block = None;
+        self.hold_used = false;
+        self.last_block_x = None;
+        self.score = 0;
+        self.combo = 0;
+        self.combo_timer = 0.0;
+        self.chain = 0;
+        self.feed.clear();
+        self.effects.clear();
+        self.grader = Grader::default();
+        self.grade = None;
+        self.best_grade = None;
+        self.assisted = false;
+        self.analysis = RunAnalysis::new(self.sand.dim());
+        self.timelapse = TimeLapse::default();
+        self.turn = 0;
+        self.last_lock_player = 0;
+        self.player_scores = [0; 2];
+        self.pieces_locked = 0;
+        self.powerup_earned = false;
+        self.special_meter = SpecialMeter::default();
+        self.time_slowed = 0.0;
+        self.next_autosave = self.elapsed_time + self.settings.autosave_interval;
+        self.analysis_page = None;
 
-        // Draw score
-        let score_texture = self
-            .text_textures
-            .texture_with_background(
-                &format!("{:0width$}", self.score, width = SCORE_DIGITS),
-                SCORE_SCALE,
-                TEXT_COLOR,
-                UI_ELEMENT_BG_COLOR,
-            )
-            .unwrap();
+        for _ in 0..self.mode.def().garbage {
+            self.raise_floor();
+        }
+
+        if self.mode == GameMode::Missions {
+            let tracker = MissionTracker::new(self.next_mission, self.elapsed_time);
+            let mission = tracker.mission();
+            self.modifiers = mission.modifiers;
+            self.gravity = mission.gravity;
+            self.next_move = self.elapsed_time + self.move_delay();
+            self.load_board(&mission.board);
+            self.piece_queue.set_preset(&mission.queue);
+            self.mission = Some(tracker);
+        }
 
-        let score_context =
-            Self::center_texture_x(ui_width, context, score_texture).trans(0.0, SCORE_Y as f64);
+        if let Some(scenario) = self.scenario.clone() {
+            self.load_board(&scenario.board);
+            self.piece_queue.set_preset(&scenario.queue);
+        }
 
-        graphics::image(score_texture, score_context.transform, g);
+        if self.mode == GameMode::Editor {
+            let layout = self.layout;
+            self.editor.get_or_insert_with(|| Editor::new(layout));
+            self.show_editor_board();
+        }
 
-        let score_label_texture = self
-            .text_textures
-            .texture_with_background("SCORE", SCORE_LABEL_SCALE, TEXT_COLOR, UI_ELEMENT_BG_COLOR)
-            .unwrap();
+        if self.mode == GameMode::Tutorial {
+            self.tutorial = Some(Tutorial::default());
+            self.start_tutorial_step();
+        }
+
+        self.stress = (self.mode == GameMode::Stress).then(StressTest::default);
+        if self.stress.is_some() {
+            self.emitters.push(StressTest::emitter(self.sand.dim().0));
+        }
+        self.transition = (self.mode != GameMode::Editor).then(|| Transition::intro(self.layout));
+        self.next_stress_report = self.elapsed_time + STRESS_REPORT_DELAY;
+    }
+
+    fn show_editor_board(&mut self) {
+        let Some(editor) = &self.editor else {
+            return;
+        };
+        let board = editor.board.clone();
+        self.sand.clear();
+        self.load_board(&board);
+    }
+
+    /// Set up the board and pieces for the current tutorial step
+    fn start_tutorial_step(&mut self) {
+        let Some(tutorial) = &self.tutorial else {
+            return;
+        };
+        let step = tutorial.step();
+        let board = step
+            .board
+            .iter()
+            .map(|row| parse_row(row).unwrap())
+            .collect::<Vec<_>>();
+        self.sand.clear();
+        self.load_board(&board);
+        self.piece_queue.set_color_override(step.color);
+        self.falling_block = None;
+        self.held_block = None;
+        self.update_highlight();
+    }
+
+    /// During the tutorial's clearing lesson, highlight the group connected to the left wall that
+    /// the player needs to extend to the right wall
+    fn update_highlight(&mut self) {
+        self.highlight.clear();
+        let Some(color) = self
+            .tutorial
+            .as_ref()
+            .map(Tutorial::step)
+            .filter(|step| step.goal == Goal::Clear)
+            .and_then(|step| step.color)
+        else {
+            return;
+        };
+        if let Some(y) = (0..self.sand.dim().1).find(|y| self.sand.color((0, *y)) == Some(color)) {
+            self.highlight.extend(find_connected_sand(
+                &self.sand,
+                0,
+                y,
+                self.clear_rules().connectivity(),
+            ));
+        }
+    }
+
+    /// The rules for what sand clears, from the mode with the current settings and modifiers
+    fn clear_rules(&self) -> Box<dyn ClearRules> {
+        self.mode.clear_rules(&self.settings, &self.modifiers)
+    }
+
+    fn move_delay(&self) -> f64 {
+        let delay = MOVE_DELAY / (self.gravity * self.score_gravity);
+        if self.time_slowed > 0.0 {
+            delay * SLOW_TIME_FACTOR
+        } else {
+            delay
+        }
+    }
+
+    /// Fill the bottom of the board with rows of block-sized cells, given from top to bottom.
+    /// Missions are made for the standard board, so whatever doesn't fit on a smaller one is cut
+    /// off at the top and right. Nothing is put in the hidden rows.
+    fn load_board(&mut self, rows: &[Vec<Option<Color>>]) {
+        let (width, height) = self.sand.dim();
+        for (i, row) in rows.iter().rev().take(self.layout.rows).enumerate() {
+            let y = height - (i + 1) * SAND_BLOCK_SIZE;
+            for (column, color) in row.iter().take(width / SAND_BLOCK_SIZE).enumerate() {
+                let grain = color.map(|color| Grain {
+                    color,
+                    landed: self.elapsed_time,
+                    piece: 0,
+                });
+                self.sand.fill(
+                    [
+                        column * SAND_BLOCK_SIZE,
+                        y,
+                        SAND_BLOCK_SIZE,
+                        SAND_BLOCK_SIZE,
+                    ],
+                    grain,
+                );
+            }
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        if self.mode == GameMode::Editor && self.play_mode == PlayMode::Playing {
+            self.handle_editor_mouse(event);
+        } else if self.settings.controls == ControlScheme::Pointer {
+            self.handle_pointer(event);
+        }
+        if event.press_args().is_some() || event.mouse_cursor_args().is_some() {
+            self.idle.input();
+        }
+        if let Some(Button::Keyboard(key)) = event.press_args() {
+            if let Some(key) = self.key_capture.press(key) {
+                if self.away {
+                    self.wake_up();
+                } else {
+                    self.bind_key(key);
+                }
+            } else if let Some(action) = self.settings.keymap.action(key) {
+                self.press(action);
+            }
+        }
+        if let Some(Button::Keyboard(key)) = event.release_args() {
+            if self.key_capture.release(key) {
+                return;
+            }
+            if let Some(action) = self.settings.keymap.action(key) {
+                self.release(action);
+            }
+        }
+    }
+
+    /// The settings the game is playing with, as they were last loaded or changed in game
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Handle an action starting, like a key being pressed
+    pub fn press(&mut self, action: Action) {
+        // The quit prompt only watches for presses, and otherwise lets them through to the paused
+        // game, so that recordings don't depend on it
+        if self.confirm_quit {
+            self.confirm_quit = false;
+            self.quitting = action == Action::Confirm;
+        }
+        if self.recording.is_some() && self.play_mode != PlayMode::Menu {
+            self.recorded_inputs.push(Input::Press(action));
+        }
+        self.input.press(action);
+        if self.play_mode == PlayMode::Menu {
+            self.menu_action(action);
+            return;
+        }
+        if self.play_mode == PlayMode::Palette {
+            self.palette_action(action);
+            return;
+        }
+        if self.play_mode == PlayMode::Keys {
+            self.keys_action(action);
+            return;
+        }
+        if self.play_mode == PlayMode::Rules {
+            self.rules_action(action);
+            return;
+        }
+        if self.play_mode == PlayMode::Records {
+            if matches!(action, Action::Confirm | Action::Menu) {
+                self.records.clear();
+                self.play_mode = PlayMode::Menu;
+            }
+            return;
+        }
+        if self.tutorial.as_ref().is_some_and(|t| !t.allows(action)) {
+            return;
+        }
+        if self.mode == GameMode::Editor && self.play_mode == PlayMode::Playing {
+            self.editor_action(action);
+            return;
+        }
+        match action {
+            Action::Left | Action::Right | Action::Down => {
+                let direction = action.direction().unwrap();
+                if self.play_mode == PlayMode::Playing {
+                    self.move_block(direction);
+                }
+                self.repeat.press(direction);
+            }
+            Action::Rotate if self.play_mode == PlayMode::Playing => {
+                self.rotate_block();
+            }
+            Action::Hold if self.play_mode == PlayMode::Playing => {
+                self.hold_block();
+            }
+            Action::Special if self.play_mode == PlayMode::Playing => {
+                self.use_special();
+            }
+            Action::Advance if self.play_mode == PlayMode::Paused => {
+                self.advance_frame();
+            }
+            Action::Drop => {
+                self.drop_pressed_at = Some(self.elapsed_time);
+            }
+            Action::Undo
+                if self.mode.def().undo
+                    && matches!(self.play_mode, PlayMode::Playing | PlayMode::GameOver) =>
+            {
+                self.undo();
+            }
+            _ => {}
+        }
+    }
+
+    /// Handle an action ending, like a key being released
+    pub fn release(&mut self, action: Action) {
+        if self.recording.is_some() && self.play_mode != PlayMode::Menu {
+            self.recorded_inputs.push(Input::Release(action));
+        }
+        self.input.release(action);
+        // The palette, key binding, records and rules screens do everything on presses
+        if matches!(
+            self.play_mode,
+            PlayMode::Palette | PlayMode::Keys | PlayMode::Records | PlayMode::Rules
+        ) {
+            return;
+        }
+        if let Some(tutorial) = &mut self.tutorial {
+            if tutorial.allows(action) {
+                tutorial.on_action(action);
+            } else if action.direction().is_none() {
+                return;
+            }
+        }
+        match action {
+            Action::Left | Action::Right | Action::Down => {
+                self.repeat.release(action.direction().unwrap());
+            }
+            Action::Drop if self.play_mode != PlayMode::Menu => {
+                // In hold to drop mode, the drop happens in `step` once the key has been held
+                // long enough, and letting go early cancels it
+                let pressed_at = self.drop_pressed_at.take();
+                if !self.settings.hold_to_drop && pressed_at.is_some_and(|t| self.can_drop(t)) {
+                    self.queue_drop = true;
+                }
+            }
+            Action::Pause => {
+                self.play_mode = self.play_mode.toggle_pause();
+            }
+            Action::Restart if self.play_mode != PlayMode::Menu => {
+                self.restart();
+            }
+            Action::Confirm if self.play_mode == PlayMode::MissionComplete => {
+                self.reset();
+            }
+            Action::Confirm if self.play_mode == PlayMode::GameOver => {
+                self.analysis_page = match self.analysis_page {
+                    Some(page) => page.next(),
+                    None => Some(AnalysisPage::Placements),
+                };
+            }
+            Action::Save if self.play_mode == PlayMode::GameOver && !self.timelapse.is_empty() => {
+                match self.timelapse.export() {
+                    Ok(()) => self.show_toast("SAVING TIME LAPSE"),
+                    Err(e) => self.show_error(&e),
+                }
+            }
+            Action::Menu => {
+                self.play_mode = PlayMode::Menu;
+                self.recording = None;
+                if self.autosave {
+                    autosave::discard();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_editor_mouse(&mut self, event: &Event) {
+        let Some(editor) = &mut self.editor else {
+            return;
+        };
+        let mut changed = false;
+        if let Some(pos) = event.mouse_cursor_args() {
+            // The editor's board starts below the hidden rows
+            let [x, y] = self.camera.to_board(pos);
+            let hidden = self.layout.hidden_height() as f64;
+            changed |= editor.move_cursor([x, y - hidden]);
+        }
+        match event.press_args() {
+            Some(Button::Mouse(MouseButton::Left)) => changed |= editor.start_painting(false),
+            Some(Button::Mouse(MouseButton::Right)) => changed |= editor.start_painting(true),
+            _ => {}
+        }
+        if let Some(Button::Mouse(_)) = event.release_args() {
+            editor.stop_painting();
+        }
+        if changed {
+            self.show_editor_board();
+        }
+    }
+
+    fn handle_pointer(&mut self, event: &Event) {
+        if let Some(pos) = event.mouse_cursor_args() {
+            self.pointer.move_to(pos);
+        }
+        let mut gesture = None;
+        if let Some(Button::Mouse(MouseButton::Left)) = event.press_args() {
+            self.pointer.press();
+        }
+        if let Some(Button::Mouse(MouseButton::Left)) = event.release_args() {
+            gesture = self.pointer.release();
+        }
+        if let Some(touch) = event.touch_args() {
+            // Touch positions are normalized to the window
+            let [x, y] = touch.position();
+            let (width, height) = self.layout.window_size();
+            self.pointer.move_to([x * width as f64, y * height as f64]);
+            match touch.touch {
+                Touch::Start => self.pointer.press(),
+                Touch::Move => {}
+                Touch::End => gesture = self.pointer.release(),
+                Touch::Cancel => self.pointer.cancel(),
+            }
+        }
+
+        if self.play_mode != PlayMode::Playing {
+            return;
+        }
+        match gesture {
+            Some(Gesture::Tap) => self.rotate_block(),
+            Some(Gesture::SwipeDown) => self.queue_drop = true,
+            None => {}
+        }
+    }
+
+    /// Move the falling block towards the pointer, so that it ends up centered under it
+    fn follow_pointer(&mut self) {
+        let (Some(pos), Some(block)) = (self.pointer.position, self.falling_block) else {
+            return;
+        };
+        let [x, _] = self.camera.to_board(pos);
+        let target =
+            (x / SAND_SIZE as f64) as isize - (block.width() * SAND_BLOCK_SIZE / 2) as isize;
+        let offset = target - block.x as isize;
+        if offset <= -(MOVE_REPEAT as isize) {
+            self.move_block(Direction::Left);
+        } else if offset >= MOVE_REPEAT as isize {
+            self.move_block(Direction::Right);
+        }
+    }
+
+    fn editor_action(&mut self, action: Action) {
+        let Some(editor) = &mut self.editor else {
+            return;
+        };
+        match action {
+            Action::Left => editor.cycle_brush(false),
+            Action::Right => editor.cycle_brush(true),
+            Action::Rotate | Action::Down => editor.cycle_shape(),
+            Action::Hold => editor.push_piece(),
+            Action::Undo => editor.pop_piece(),
+            Action::Save => {
+                if let Err(e) = editor.save() {
+                    self.show_error(&e);
+                }
+            }
+            Action::Load => {
+                if let Err(e) = editor.load() {
+                    self.show_error(&e);
+                }
+                self.show_editor_board();
+            }
+            Action::Confirm => {
+                // Try out the scenario
+                self.scenario = Some(editor.to_mission());
+                self.mode = GameMode::Marathon;
+                self.reset();
+            }
+            _ => {}
+        }
+    }
+
+    /// The items on the menu, with a way back into the last session's run if it left one
+    fn menu_items(&self) -> Vec<MenuItem> {
+        self.recoverable
+            .iter()
+            .map(|_| MenuItem::Recover)
+            .chain(MenuItem::all(self.bot_level))
+            .collect()
+    }
+
+    fn menu_action(&mut self, action: Action) {
+        let items = self.menu_items();
+        match action {
+            Action::Rotate => {
+                self.menu_selection = (self.menu_selection + items.len() - 1) % items.len();
+            }
+            Action::Down => {
+                self.menu_selection = (self.menu_selection + 1) % items.len();
+            }
+            // Left and right pick the bot level on the versus item
+            Action::Left | Action::Right => {
+                if let MenuItem::Versus(level) = items[self.menu_selection] {
+                    let levels = BotLevel::ALL.len();
+                    let index = BotLevel::ALL.iter().position(|l| *l == level).unwrap();
+                    let offset = if action == Action::Left {
+                        levels - 1
+                    } else {
+                        1
+                    };
+                    self.bot_level = BotLevel::ALL[(index + offset) % levels];
+                }
+            }
+            Action::Confirm => match items[self.menu_selection] {
+                MenuItem::Recover => {
+                    let snapshot = self.recoverable.take().unwrap();
+                    self.menu_selection = 0;
+                    if let Err(e) = self.restore(snapshot) {
+                        self.show_error(&e);
+                    }
+                }
+                item @ (MenuItem::Mode(_) | MenuItem::Versus(_)) => {
+                    if self.settings.rules_cards {
+                        let mode = match item {
+                            MenuItem::Mode(mode) => mode,
+                            _ => GameMode::Versus,
+                        };
+                        self.rules_card = Some((item, mode.rules_card(&self.settings)));
+                        self.play_mode = PlayMode::Rules;
+                    } else {
+                        self.play(item);
+                    }
+                }
+                MenuItem::Palette => {
+                    self.palette_editor = Some(PaletteEditor::new());
+                    self.play_mode = PlayMode::Palette;
+                }
+                MenuItem::Keys => {
+                    self.keymap_editor = Some(KeymapEditor::new(
+                        self.settings.keymap.clone(),
+                        self.settings.default_keymap.clone(),
+                    ));
+                    self.play_mode = PlayMode::Keys;
+                }
+                MenuItem::Records => {
+                    self.load_records();
+                    self.play_mode = PlayMode::Records;
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Load the best grade in each mode for the records screen, in the order the modes are listed
+    /// on the menu
+    fn load_records(&mut self) {
+        let grades = grade::load_best_grades();
+        self.records = GameMode::all()
+            .filter_map(|mode| {
+                let name = mode.name().to_lowercase();
+                let grade = *grades.get(&name)?;
+                let thumbnail = grade::load_thumbnail(&name)
+                    .and_then(|image| self.text_textures.image_texture(&image).ok());
+                Some(Record {
+                    mode,
+                    grade,
+                    thumbnail,
+                })
+            })
+            .collect();
+    }
+
+    fn palette_action(&mut self, action: Action) {
+        let Some(editor) = &mut self.palette_editor else {
+            return;
+        };
+        match action {
+            Action::Rotate => editor.cycle_color(false),
+            Action::Down => editor.cycle_color(true),
+            Action::Hold => editor.cycle_channel(),
+            Action::Left => editor.adjust(false),
+            Action::Right => editor.adjust(true),
+            Action::Undo => editor.reset_color(),
+            Action::Confirm => {
+                if let Err(e) = editor.save() {
+                    self.show_error(&e);
+                }
+                self.palette_editor = None;
+                self.play_mode = PlayMode::Menu;
+            }
+            Action::Menu => {
+                editor.cancel();
+                self.palette_editor = None;
+                self.play_mode = PlayMode::Menu;
+            }
+            _ => {}
+        }
+    }
+
+    /// Start the mode a menu item is for
+    fn play(&mut self, item: MenuItem) {
+        match item {
+            MenuItem::Mode(mode) => self.start(mode),
+            MenuItem::Versus(level) => {
+                self.versus_request = Some(level);
+                self.play_mode = PlayMode::Menu;
+            }
+            _ => {}
+        }
+    }
+
+    fn rules_action(&mut self, action: Action) {
+        let Some((item, _)) = self.rules_card else {
+            return;
+        };
+        match action {
+            Action::Confirm => {
+                self.rules_card = None;
+                self.play(item);
+            }
+            Action::Menu => {
+                self.rules_card = None;
+                self.play_mode = PlayMode::Menu;
+            }
+            _ => {}
+        }
+    }
+
+    fn keys_action(&mut self, action: Action) {
+        let Some(editor) = &mut self.keymap_editor else {
+            return;
+        };
+        match action {
+            Action::Rotate => editor.cycle_action(false),
+            Action::Down => editor.cycle_action(true),
+            Action::Confirm => self.key_capture.start(),
+            Action::Undo => {
+                editor.reset_action();
+                self.save_keymap();
+            }
+            Action::Restart => {
+                editor.reset_all();
+                self.save_keymap();
+            }
+            Action::Menu => {
+                self.keymap_editor = None;
+                self.play_mode = PlayMode::Menu;
+            }
+            _ => {}
+        }
+    }
+
+    /// Bind the key caught on the key binding screen to the selected action. Escape gives up on
+    /// binding, and a key that does something else has to be pressed again to take it.
+    fn bind_key(&mut self, key: Key) {
+        let Some(editor) = &mut self.keymap_editor else {
+            return;
+        };
+        if key == Key::Escape {
+            editor.conflict = None;
+            return;
+        }
+        match editor.capture(key) {
+            Ok(true) => self.save_keymap(),
+            Ok(false) => self.key_capture.start(),
+            Err(e) => self.show_error(&e),
+        }
+    }
+
+    /// Use the keys from the key binding screen, and save them for next time
+    fn save_keymap(&mut self) {
+        let Some(editor) = &self.keymap_editor else {
+            return;
+        };
+        self.settings.keymap = editor.keymap.clone();
+        if let Err(e) = editor.save() {
+            self.show_error(&e);
+        }
+    }
+
+    fn move_block(&mut self, direction: Direction) {
+        for _ in 0..MOVE_REPEAT {
+            match direction {
+                Direction::Left => {
+                    if let Some(block) = self.falling_block.filter(|block| {
+                        self.can_move(Direction::Left) && self.can_nudge_to(block.x - 1)
+                    }) {
+                        self.falling_block = Some(block.dec_x());
+                    }
+                }
+                Direction::Right => {
+                    if let Some(block) = self.falling_block.filter(|block| {
+                        self.can_move(Direction::Right) && self.can_nudge_to(block.x + 1)
+                    }) {
+                        self.falling_block = Some(block.inc_x());
+                    }
+                }
+                Direction::Down => {
+                    if let Some(block) = self.falling_block {
+                        if self.can_move(Direction::Down) {
+                            self.falling_block = Some(block.inc_y())
+                        } else if self.settings.lock_nudge && self.locking.is_none() {
+                            self.locking = Some(Locking {
+                                deadline: self.elapsed_time + LOCK_NUDGE_WINDOW,
+                                x: block.x,
+                            });
+                            break;
+                        } else if self
+                            .locking
+                            .is_some_and(|locking| self.elapsed_time < locking.deadline)
+                        {
+                            break;
+                        } else {
+                            self.locking = None;
+                            match block.powerup {
+                                Some(powerup) => self.use_powerup(powerup, block),
+                                None => self.add_sand_block(),
+                            }
+                            self.events.emit(GameEvent::PieceLocked {
+                                shape: block.shape,
+                                color: block.color,
+                                x: block.x,
+                                y: block.y,
+                            });
+                            self.analysis.record_lock(block.coords());
+                            self.timelapse
+                                .on_piece_locked(&self.sand, self.settings.timelapse_interval);
+                            self.last_lock_player = self.turn;
+                            if self.mode == GameMode::HotSeat {
+                                self.turn = 1 - self.turn;
+                            }
+                            self.last_block_x = Some(block.x);
+                            self.falling_block = None;
+                            self.hold_used = false;
+                            self.chain = 0;
+                            // Lock out: the piece came to rest without reaching the visible board
+                            let bottom = block.y + block.height() * SAND_BLOCK_SIZE;
+                            if bottom <= self.layout.hidden_sand_rows() {
+                                self.top_out();
+                            }
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        // A piece nudged off a ledge falls again
+        if direction != Direction::Down && self.locking.is_some() && self.can_move(Direction::Down)
+        {
+            self.locking = None;
+        }
+    }
+
+    /// Whether the falling piece can move to a column. Once it's landed, it can only be nudged a
+    /// cell away from where it landed.
+    fn can_nudge_to(&self, x: usize) -> bool {
+        self.locking
+            .is_none_or(|locking| x.abs_diff(locking.x) <= SAND_BLOCK_SIZE)
+    }
+
+    pub fn update(&mut self, event: &UpdateArgs) {
+        if let Some(recording) = &mut self.recording {
+            recording.frames.push(Frame {
+                dt: event.dt,
+                inputs: std::mem::take(&mut self.recorded_inputs),
+                hash: self.settings.replay_hashes.then(|| self.sand.hash()),
+            });
+        }
+        self.update_config(event.dt);
+        self.update_camera(event.dt);
+        self.feed.tick(event.dt);
+        self.effects.tick(event.dt);
+        self.check_idle(event.dt);
+        // Held keys repeat at the game's speed, so they keep pace with gravity
+        self.repeat.tick(event.dt * self.settings.game_speed);
+        if self.play_mode == PlayMode::Playing
+            && (self.settings.game_speed < 1.0 || self.settings.hint || self.settings.pile_preview)
+        {
+            self.assisted = true;
+        }
+
+        // Slow motion counts down in real time, but everything in the game runs slower during it
+        let game_time = UpdateArgs {
+            dt: event.dt * self.time_scale(),
+        };
+        if let Some(slow_motion) = &mut self.slow_motion {
+            slow_motion.remaining -= event.dt;
+            if slow_motion.remaining <= 0.0 {
+                self.slow_motion = None;
+            }
+        }
+
+        self.step(&game_time);
+        self.autosave();
+        for e in disk::take_errors() {
+            self.show_error(&e);
+        }
+        if let Some(transition) = &mut self.transition {
+            if !transition.update(game_time.dt, &mut self.sand) {
+                self.transition = None;
+            }
+        }
+        // Whatever step() didn't get to, because the game is paused or animating, waits for it
+        self.repeat.hold_overdue();
+        self.dispatch_events();
+        if self.play_mode == PlayMode::Playing {
+            self.update_grade();
+        }
+        self.update_hint();
+        self.update_pile_preview();
+    }
+
+    /// Work out where to drop the falling piece again, with hints on. It's asked for as soon as a
+    /// piece spawns, and then only every so often as the sand settles, and shows up once the
+    /// planner thread has worked it out.
+    fn update_hint(&mut self) {
+        let Some(block) = self
+            .falling_block
+            .filter(|_| self.settings.hint && self.play_mode == PlayMode::Playing)
+        else {
+            self.hint = None;
+            return;
+        };
+        if let Some((spawned, hint)) = self.hint_planner.as_mut().and_then(Background::take_result)
+        {
+            self.hint = hint.map(|hint| (spawned, hint));
+        }
+        // The hint for a piece that's already down is no use for this one
+        if self
+            .hint
+            .is_some_and(|(spawned, _)| spawned != self.spawned_at)
+        {
+            self.hint = None;
+        }
+        if self.hint_requested == Some(self.spawned_at) && self.elapsed_time < self.next_hint {
+            return;
+        }
+        self.hint_requested = Some(self.spawned_at);
+        self.next_hint = self.elapsed_time + HINT_INTERVAL;
+        let planner = self.hint_planner.get_or_insert_with(|| {
+            Background::new(|(spawned, sand, block): HintJob| {
+                (
+                    spawned,
+                    bot::best_placement_within(&sand, &block, HINT_BUDGET),
+                )
+            })
+        });
+        planner.submit((self.spawned_at, self.sand.clone(), block));
+    }
+
+    /// Work out the pile the falling piece would make if it were dropped now, with the pile preview
+    /// on. It's asked for again whenever the piece would land somewhere else, and every so often
+    /// as the sand under it settles, and shows up once the planner thread has worked it out with
+    /// the same physics as the game.
+    fn update_pile_preview(&mut self) {
+        let Some(block) = self
+            .falling_block
+            .filter(|_| self.settings.pile_preview && self.play_mode == PlayMode::Playing)
+        else {
+            self.pile_preview = None;
+            return;
+        };
+        let landed = self.landing(block);
+        if let Some(preview) = self.pile_planner.as_mut().and_then(Background::take_result) {
+            self.pile_preview = Some(preview);
+        }
+        // The pile from somewhere else the piece could have landed is no use here
+        if self
+            .pile_preview
+            .as_ref()
+            .is_some_and(|(cached, _)| *cached != landed)
+        {
+            self.pile_preview = None;
+        }
+        if self.pile_requested == Some(landed) && self.elapsed_time < self.next_pile_preview {
+            return;
+        }
+        self.pile_requested = Some(landed);
+        self.next_pile_preview = self.elapsed_time + HINT_INTERVAL;
+
+        let grains = landed.coords().flat_map(|(px, py)| {
+            (0..SAND_BLOCK_SIZE)
+                .flat_map(move |gx| (0..SAND_BLOCK_SIZE).map(move |gy| (px + gx, py + gy)))
+        });
+        let grid = if self.modifiers.sticky_sand {
+            let mut grid = StickyGrid::new(self.sand.clone());
+            grid.add_grains(grains, landed.color);
+            PileGrid::Sticky(Box::new(grid))
+        } else {
+            // Only the columns the grains could reach are copied, from the top of the piece down
+            let left = landed.x.saturating_sub(PILE_PREVIEW_MARGIN);
+            let right = landed.x + landed.width() * SAND_BLOCK_SIZE + PILE_PREVIEW_MARGIN;
+            let mut grid = Subgrid::new(self.sand.colors().view(), left..right, landed.y);
+            grid.add_grains(grains);
+            PileGrid::Loose(grid)
+        };
+        let planner = self
+            .pile_planner
+            .get_or_insert_with(|| Background::new(PileGrid::settle));
+        planner.submit((landed, grid));
+    }
+
+    /// Where a piece would land if it were dropped straight down from where it is
+    fn landing(&self, block: Block) -> Block {
+        let mut landed = block;
+        while landed.inc_y().fits(&self.sand) {
+            landed = landed.inc_y();
+        }
+        landed
+    }
+
+    /// Save the run in progress if it's been long enough since the last time
+    fn autosave(&mut self) {
+        let interval = self.settings.autosave_interval;
+        if interval <= 0.0
+            || self.play_mode != PlayMode::Playing
+            || self.elapsed_time < self.next_autosave
+            || !self.can_autosave()
+        {
+            return;
+        }
+        self.next_autosave = self.elapsed_time + interval;
+        if let Err(e) = autosave::save(&self.snapshot()) {
+            self.show_error(&e);
+        }
+    }
+
+    /// Whether there's a run in progress to autosave. Only runs that can be restored from a
+    /// snapshot are saved.
+    fn can_autosave(&self) -> bool {
+        self.autosave
+            && matches!(self.play_mode, PlayMode::Playing | PlayMode::Paused)
+            && self.scenario.is_none()
+            && !matches!(
+                self.mode,
+                GameMode::Missions
+                    | GameMode::Tutorial
+                    | GameMode::Editor
+                    | GameMode::Stress
+                    | GameMode::Coop
+                    | GameMode::Versus
+            )
+    }
+
+    /// Ease the camera towards the falling piece in macro mode, or towards the whole board. This
+    /// runs on real time, so the camera keeps up during slow motion.
+    fn update_camera(&mut self, dt: f64) {
+        let layout = self.layout;
+        let target = match (self.settings.camera, self.falling_block) {
+            (CameraMode::Macro, Some(block)) if self.play_mode == PlayMode::Playing => {
+                let middle = |pos: usize, size: usize| {
+                    ((pos + size * SAND_BLOCK_SIZE / 2) * SAND_SIZE) as f64
+                };
+                let focus = [
+                    middle(block.x, block.width()),
+                    middle(block.y, block.height()),
+                ];
+                Camera::focus(&layout, focus, MACRO_ZOOM)
+            }
+            _ => Camera::fit(&layout),
+        };
+        self.camera.approach(target, dt);
+    }
+
+    /// How fast the game runs compared to real time
+    fn time_scale(&self) -> f64 {
+        let slow_motion = match self.slow_motion {
+            Some(_) => SLOW_MOTION_SCALE,
+            None => 1.0,
+        };
+        self.settings.game_speed * slow_motion
+    }
+
+    /// Pause a game that's gone too long without any input, dimming the screen until a key is
+    /// pressed
+    fn check_idle(&mut self, dt: f64) {
+        if self.play_mode != PlayMode::Playing {
+            if self.away && self.play_mode != PlayMode::Paused {
+                self.away = false;
+                self.key_capture.cancel();
+            }
+            return;
+        }
+        self.idle.tick(dt);
+        let delay = self.settings.idle_pause;
+        if self.idle_pause && delay > 0.0 && self.idle.idle_for() >= delay {
+            // Pausing goes through the same inputs as the key, so a replay of the run pauses here
+            // too
+            self.press(Action::Pause);
+            self.release(Action::Pause);
+            self.away = true;
+            self.key_capture.start();
+        }
+    }
+
+    /// Carry on with a game that paused itself, once the player is back
+    fn wake_up(&mut self) {
+        self.away = false;
+        if self.play_mode == PlayMode::Paused {
+            self.press(Action::Pause);
+            self.release(Action::Pause);
+        }
+    }
+
+    fn update_grade(&mut self) {
+        let rating = self.grader.rating(self.score, self.elapsed_time);
+        self.grade = GradeTable::for_mode(self.mode).map(|table| table.grade(rating));
+    }
+
+    /// Reload the settings if a config file changed, and count down the toasts. This runs on real
+    /// time, so it keeps working while the game is paused or in a menu.
+    fn update_config(&mut self, dt: f64) {
+        self.toasts.tick(dt);
+
+        self.next_config_check -= dt;
+        if self.next_config_check > 0.0 {
+            return;
+        }
+        self.next_config_check = CONFIG_CHECK_DELAY;
+        if !self.config_watcher.poll() {
+            return;
+        }
+
+        match self.settings.reload() {
+            Ok(settings) => {
+                if self.mission.is_none() {
+                    self.modifiers = self.mode.modifiers(&settings);
+                }
+                self.piece_queue.apply_settings(&settings);
+                // The replay can't say the rules or the speed changed partway through, so it
+                // stops here
+                if settings.rules() != self.settings.rules()
+                    || settings.game_speed != self.settings.game_speed
+                {
+                    self.recording = None;
+                }
+                if settings.render_quality != self.settings.render_quality {
+                    self.frame_timer = FrameTimer::new(settings.render_quality);
+                }
+                self.settings = settings;
+                self.show_toast("SETTINGS RELOADED");
+            }
+            Err(e) => {
+                eprintln!("{e}");
+                self.show_error(&e);
+            }
+        }
+    }
+
+    /// Deal pieces from a queue shared with other boards, rather than this board's own queue
+    pub fn share_queue(&mut self, queue: SharedQueue) {
+        self.shared_queue = Some(queue);
+    }
+
+    /// Show a teammate's score added to this board's on the dashboard
+    pub fn set_partner_score(&mut self, score: usize) {
+        self.partner_score = Some(score);
+    }
+
+    /// Push back the next row of rising sand
+    pub fn delay_rise(&mut self, seconds: f64) {
+        self.next_rise += seconds;
+    }
+
+    /// Bring the next row of rising sand closer, like when an opponent clears
+    pub fn hasten_rise(&mut self, seconds: f64) {
+        self.next_rise -= seconds;
+    }
+
+    /// End the game as if the board had filled up, like when a teammate's board does
+    pub fn end(&mut self) {
+        if self.play_mode == PlayMode::Playing {
+            self.game_over();
+        }
+    }
+
+    /// Show a short message over the board for a few seconds, after any that are already showing
+    pub fn show_toast(&mut self, message: &str) {
+        self.toasts.push(message, ToastKind::Info);
+    }
+
+    /// Show that something went wrong, like a file that couldn't be saved, the same way as
+    /// [`Game::show_toast`]
+    pub fn show_error(&mut self, message: &str) {
+        self.toasts.push(message, ToastKind::Error);
+    }
+
+    fn dispatch_events(&mut self) {
+        for event in self.events.dispatch() {
+            self.feed.on_event(&event);
+            self.grader.on_event(&event);
+            self.special_meter.on_event(&event);
+            if let Some(tracker) = &mut self.mission {
+                tracker.on_event(&event);
+            }
+            if let Some(goal) = &mut self.goal {
+                goal.on_event(&event);
+            }
+            if let Some(tutorial) = &mut self.tutorial {
+                tutorial.on_event(&event);
+            }
+        }
+
+        if let Some(tutorial) = self.tutorial.as_mut().filter(|t| t.is_step_complete()) {
+            if tutorial.advance() {
+                self.start_tutorial_step();
+            } else {
+                self.tutorial = None;
+                self.play_mode = PlayMode::Menu;
+            }
+        }
+
+        if let Some(tracker) = self.mission.as_ref().filter(|t| t.is_complete()) {
+            if self.play_mode == PlayMode::Playing {
+                let completed = tracker.index + 1;
+                mission::save_progress(completed.max(mission::load_progress()));
+                self.next_mission = completed % MISSIONS.len();
+                self.play_mode = PlayMode::MissionComplete;
+                self.events.emit(GameEvent::MissionComplete {
+                    index: tracker.index,
+                });
+            }
+        }
+
+        if self.goal.as_ref().is_some_and(|goal| goal.is_complete())
+            && self.play_mode == PlayMode::Playing
+        {
+            self.finish();
+        }
+    }
+
+    /// End the game because it reached its mode's goal or ran out of time
+    fn finish(&mut self) {
+        self.finished = true;
+        self.game_over();
+    }
+
+    /// The board filled up: the game is over, unless the mode never ends, in which case the board
+    /// is emptied to carry on with
+    fn top_out(&mut self) {
+        if self.mode.def().endless {
+            self.sand.clear();
+            self.effects.clear();
+        } else {
+            self.game_over();
+        }
+    }
+
+    fn game_over(&mut self) {
+        self.play_mode = PlayMode::GameOver;
+        if self.autosave {
+            autosave::discard();
+        }
+        // Seeded without advancing the game's random state
+        let seed = self.rng.clone().generate();
+        self.transition = Some(Transition::collapse(seed));
+        self.events.emit(GameEvent::GameOver { score: self.score });
+        if self.settings.timelapse_interval > 0 {
+            self.timelapse.capture(&self.sand);
+        }
+        self.update_grade();
+        // Runs from the editor's scenarios start from a custom board, so they don't count
+        self.best_grade = self
+            .grade
+            .filter(|_| self.scenario.is_none() && !self.assisted)
+            .map(|grade| {
+                grade::record_grade(self.mode, grade, &grade::board_thumbnail(&self.sand))
+            });
+        if self.scenario.is_none() {
+            stats::record_run(&RunRecord {
+                mode: self.mode.name().to_lowercase(),
+                score: self.score,
+                pieces: self.analysis.pieces(),
+                clears: self.analysis.clears(),
+                grains_cleared: self.analysis.grains_cleared(),
+                seconds: self.elapsed_time,
+                grade: self.grade.map(|grade| grade.to_string()),
+                finished: self.finished,
+                assisted: self.assisted,
+            });
+        }
+        // The recording stops where the game ended, even if practice lets the last piece be undone
+        if let Some(mut recording) = self.recording.take() {
+            recording.score = Some(self.score);
+            if let Err(e) = recording.save(Path::new(REPLAY_FILE)) {
+                self.show_error(&e);
+            }
+        }
+    }
+
+    fn step(&mut self, event: &UpdateArgs) {
+        if self.play_mode != PlayMode::Playing {
+            return;
+        }
+
+        // Nothing moves while editing
+        if self.mode == GameMode::Editor {
+            return;
+        }
+
+        // Tutorial steps that are just text freeze the game until the player continues
+        if self
+            .tutorial
+            .as_ref()
+            .is_some_and(|t| t.step().goal == Goal::Confirm)
+        {
+            return;
+        }
+
+        if self.run_animation(event.dt) {
+            // Play carries on while a clear is animated, with its grains pinned in place until
+            // they're taken away. The game is frozen for any other animation.
+            if !self.is_clearing() {
+                return;
+            }
+        } else if let Some((_, animation)) = self.animation.take() {
+            self.finish_animation(animation);
+        }
+
+        self.elapsed_time += event.dt;
+
+        self.time_slowed = (self.time_slowed - event.dt).max(0.0);
+
+        if self.combo_timer > 0.0 {
+            self.combo_timer -= event.dt;
+            if self.combo_timer <= 0.0 {
+                self.combo_timer = 0.0;
+                self.combo = 0;
+            }
+        }
+
+        if self.stress.is_some() {
+            self.step_stress();
+            return;
+        }
+
+        for (direction, count) in self.repeat.take_due() {
+            for _ in 0..count {
+                self.move_block(direction);
+            }
+        }
+
+        if self.settings.controls == ControlScheme::Pointer {
+            self.follow_pointer();
+        }
+
+        if let Some(pressed_at) = self.drop_pressed_at {
+            if self.settings.hold_to_drop
+                && self.elapsed_time - pressed_at >= DROP_HOLD_TIME
+                && self.can_drop(pressed_at)
+            {
+                self.queue_drop = true;
+                self.drop_pressed_at = None;
+            }
+        }
+
+        if self.queue_drop {
+            let start = self.falling_block;
+            let mut landed = start;
+            while let Some(block) = self.falling_block.filter(|_| self.locking.is_none()) {
+                landed = Some(block);
+                self.move_block(Direction::Down);
+            }
+            self.queue_drop = false;
+            if let (Some(start), Some(landed)) = (start, landed) {
+                if landed.y > start.y && !self.settings.reduce_motion {
+                    self.effects.hard_drop(&landed, start.y);
+                }
+            }
+        }
+
+        if self
+            .locking
+            .is_some_and(|locking| self.elapsed_time >= locking.deadline)
+        {
+            self.move_block(Direction::Down);
+        }
+
+        if self.elapsed_time >= self.next_physics_update {
+            // Fast forwarding runs several passes of the physics at once, so only the sand speeds
+            // up, and only while it settles between pieces
+            let passes = if self.input.is_held(Action::FastForward) && self.falling_block.is_none()
+            {
+                FAST_FORWARD_PASSES
+            } else {
+                1
+            };
+            self.run_emitters(PHYSICS_DELAY);
+            for _ in 0..passes {
+                self.run_sand_physics();
+            }
+            if self.modifiers.color_mixing {
+                run_mixing(&mut self.sand, PHYSICS_DELAY);
+            }
+            self.next_physics_update += PHYSICS_DELAY;
+            if self.tutorial.is_some() {
+                self.update_highlight();
+            }
+        }
+
+        if let Some(tracker) = &mut self.mission {
+            tracker.record_time(self.elapsed_time, self.score);
+        }
+        if let Some(goal) = &mut self.goal {
+            goal.record_time(self.elapsed_time, self.score);
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| self.elapsed_time >= deadline)
+        {
+            self.finish();
+            return;
+        }
+
+        // Rising sand would carry a clear's grains away from where it's going to take them, so it
+        // waits for the clear to finish
+        if self.mode.rises() && self.elapsed_time >= self.next_rise && !self.is_clearing() {
+            self.raise_floor();
+            self.next_rise += RISING_DELAY;
+            if self.play_mode != PlayMode::Playing {
+                return;
+            }
+        }
+
+        // The grains of a clear in progress would be found again, so new clears wait for it
+        let clears = match self.is_clearing() {
+            true => Vec::new(),
+            false => self.clear_rules().find_clears(&self.sand),
+        };
+        if !clears.is_empty() {
+            let affected_pixels = clears
+                .iter()
+                .flat_map(|clear| clear.grains.iter().copied())
+                .collect::<Vec<_>>();
+            if affected_pixels.len() >= SLOW_MOTION_GRAINS {
+                self.slow_motion = Some(SlowMotion::new(&affected_pixels));
+            }
+            let style = self.mode.clear_animation(&self.settings);
+            self.sand.pin_all(&affected_pixels);
+            // Play goes on while the clear animates, so a piece can lock before it's scored
+            self.chain += 1;
+            let animation = Animation::RemoveLine {
+                style,
+                affected_pixels,
+                groups: clears.len(),
+                path: clears.into_iter().flat_map(|clear| clear.path).collect(),
+                player: self.last_lock_player,
+                chain: self.chain,
+            };
+            // Instant clears take their grains straight away
+            if style.duration() > 0.0 {
+                self.animation = Some((0.0, animation));
+            } else {
+                self.finish_animation(animation);
+            }
+        }
+
+        if self.elapsed_time >= self.next_move {
+            if self.falling_block.is_some() {
+                if !self.repeat.is_held(Direction::Down) {
+                    self.move_block(Direction::Down);
+                }
+            } else {
+                if self.mode.def().undo {
+                    self.save_snapshot();
+                }
+                let mut block = match &self.shared_queue {
+                    Some(queue) => queue.pop(),
+                    None => self.piece_queue.pop(&mut self.rng),
+                };
+                if self.modifiers.powerups {
+                    block.powerup = if std::mem::take(&mut self.powerup_earned) {
+                        Some(Powerup::random(&mut self.rng))
+                    } else {
+                        Powerup::roll(&mut self.rng)
+                    };
+                }
+                self.spawned_at = self.elapsed_time;
+                self.analysis.record_spawn(block.shape);
+                self.spawn_block(block);
+            }
+            self.next_move += self.move_delay();
+        }
+    }
+
+    /// Score points for a clear, crediting them to whoever set it off
+    fn add_points(&mut self, points: usize) {
+        self.add_points_for(self.last_lock_player, points);
+    }
+
+    /// Score points for a clear, crediting them to a particular hot seat player
+    fn add_points_for(&mut self, player: usize, points: usize) {
+        self.score += points;
+        self.player_scores[player] += points;
+    }
+
+    /// Pour more sand and run the physics, timing it
+    fn step_stress(&mut self) {
+        if self.elapsed_time >= self.next_physics_update {
+            self.run_emitters(PHYSICS_DELAY);
+        }
+        let Some(stress) = &mut self.stress else {
+            return;
+        };
+        if self.elapsed_time >= self.next_physics_update {
+            let start = Instant::now();
+            let resolution = run_rng_physics(&mut self.rng, &mut self.sand);
+            stress.record(start.elapsed(), self.sand.grain_count(), resolution);
+            self.next_physics_update += PHYSICS_DELAY;
+        }
+        if self.elapsed_time >= self.next_stress_report {
+            println!("{}", stress.report());
+            self.next_stress_report += STRESS_REPORT_DELAY;
+        }
+    }
+
+    /// Pour in the sand from every emitter that's due after `dt` seconds, and drop the ones that
+    /// have run dry
+    fn run_emitters(&mut self, dt: f64) {
+        for emitter in &mut self.emitters {
+            emitter.step(&mut self.rng, dt, &mut self.sand, self.elapsed_time);
+        }
+        self.emitters.retain(|emitter| !emitter.is_dry());
+    }
+
+    /// Add a source of sand pouring onto the board, until the run ends or it runs dry
+    pub fn add_emitter(&mut self, emitter: Emitter) {
+        self.emitters.push(emitter);
+    }
+
+    fn save_snapshot(&mut self) {
+        if self.history.len() >= UNDO_HISTORY_LEN {
+            self.history.pop_front();
+        }
+        let state = self.save_state();
+        self.history.push_back(state);
+    }
+
+    /// Rewind to just before the last placed piece spawned, so it comes up again
+    fn undo(&mut self) {
+        // The most recent snapshot belongs to the piece that's still falling (or that just topped
+        // out), so undoing the last placement means going back one further
+        let current = usize::from(self.falling_block.is_some());
+        if self.history.len() <= current {
+            return;
+        }
+        self.history.truncate(self.history.len() - current);
+        let state = self.history.pop_back().unwrap();
+
+        // The keys are still held as they are now, not as they were back then
+        let input = self.input.clone();
+        let repeat = self.repeat.clone();
+        self.load_state(&state);
+        self.input = input;
+        self.repeat = repeat;
+    }
+
+    /// Save everything that decides how the game plays out from here: the board, the random state,
+    /// the timers, the piece queue and the held keys. Progress through a tutorial isn't included.
+    /// This is just a few copies, so it's cheap enough to do every frame, as rollback needs.
+    pub fn save_state(&self) -> SimState {
+        SimState {
+            rng: self.rng.clone(),
+            sand: self.sand.clone(),
+            animation: self.animation.clone(),
+            slow_motion: self.slow_motion,
+            play_mode: self.play_mode,
+            mode: self.mode,
+            modifiers: self.modifiers,
+            gravity: self.gravity,
+            score_gravity: self.score_gravity,
+            mission: self.mission.clone(),
+            goal: self.goal.clone(),
+            deadline: self.deadline,
+            finished: self.finished,
+            elapsed_time: self.elapsed_time,
+            next_move: self.next_move,
+            next_physics_update: self.next_physics_update,
+            next_rise: self.next_rise,
+            input: self.input.clone(),
+            repeat: self.repeat.clone(),
+            queue_drop: self.queue_drop,
+            locking: self.locking,
+            drop_pressed_at: self.drop_pressed_at,
+            spawned_at: self.spawned_at,
+            falling_block: self.falling_block,
+            piece_queue: self.piece_queue.clone(),
+            held_block: self.held_block,
+            hold_used: self.hold_used,
+            last_block_x: self.last_block_x,
+            score: self.score,
+            combo: self.combo,
+            combo_timer: self.combo_timer,
+            chain: self.chain,
+            grader: self.grader.clone(),
+            grade: self.grade,
+            analysis: self.analysis.clone(),
+            turn: self.turn,
+            last_lock_player: self.last_lock_player,
+            player_scores: self.player_scores,
+            pieces_locked: self.pieces_locked,
+            powerup_earned: self.powerup_earned,
+            special_meter: self.special_meter.clone(),
+            time_slowed: self.time_slowed,
+            emitters: self.emitters.clone(),
+            transition: self.transition.clone(),
+        }
+    }
+
+    /// Go back to a state from [`Game::save_state`]. Given the same inputs, the game then plays out
+    /// exactly as it did after the state was saved.
+    pub fn load_state(&mut self, state: &SimState) {
+        let state = state.clone();
+        self.set_layout(state.mode.layout(&self.settings));
+        self.rng = state.rng;
+        self.sand = state.sand;
+        self.animation = state.animation;
+        self.slow_motion = state.slow_motion;
+        self.play_mode = state.play_mode;
+        self.mode = state.mode;
+        self.modifiers = state.modifiers;
+        self.gravity = state.gravity;
+        self.score_gravity = state.score_gravity;
+        self.mission = state.mission;
+        self.goal = state.goal;
+        self.deadline = state.deadline;
+        self.finished = state.finished;
+        self.elapsed_time = state.elapsed_time;
+        self.next_move = state.next_move;
+        self.next_physics_update = state.next_physics_update;
+        self.next_rise = state.next_rise;
+        self.input = state.input;
+        self.repeat = state.repeat;
+        self.queue_drop = state.queue_drop;
+        self.locking = state.locking;
+        self.drop_pressed_at = state.drop_pressed_at;
+        self.spawned_at = state.spawned_at;
+        self.falling_block = state.falling_block;
+        self.piece_queue = state.piece_queue;
+        self.held_block = state.held_block;
+        self.hold_used = state.hold_used;
+        self.last_block_x = state.last_block_x;
+        self.score = state.score;
+        self.combo = state.combo;
+        self.combo_timer = state.combo_timer;
+        self.chain = state.chain;
+        self.grader = state.grader;
+        self.grade = state.grade;
+        self.analysis = state.analysis;
+        self.turn = state.turn;
+        self.last_lock_player = state.last_lock_player;
+        self.player_scores = state.player_scores;
+        self.pieces_locked = state.pieces_locked;
+        self.powerup_earned = state.powerup_earned;
+        self.special_meter = state.special_meter;
+        self.time_slowed = state.time_slowed;
+        self.emitters = state.emitters.clone();
+        self.transition = state.transition;
+    }
+
+    /// Do whatever needs to be done once an animation has finished, like taking away the grains it
+    /// cleared
+    fn finish_animation(&mut self, animation: Animation) {
+        match animation {
+            Animation::RemoveLine {
+                affected_pixels,
+                groups,
+                player,
+                chain,
+                ..
+            } => {
+                self.combo += 1;
+                self.combo_timer = self.mode.combo_window();
+                let points = affected_pixels.len() * self.combo;
+                self.add_points_for(player, points);
+                if let Some(grain) = affected_pixels.first().and_then(|p| self.sand.get(*p)) {
+                    self.events.emit(GameEvent::SandCleared {
+                        pixels: affected_pixels.len(),
+                        color: grain.color,
+                        combo: self.combo,
+                        points,
+                    });
+                }
+                if groups > 1 {
+                    let points = MULTI_CLEAR_BONUS * (groups - 1);
+                    self.add_points_for(player, points);
+                    self.events.emit(GameEvent::MultiClear { groups, points });
+                }
+                if let Some(pieces) = self.pieces_in(&affected_pixels) {
+                    let under = EFFICIENT_CLEAR_PIECES.saturating_sub(pieces);
+                    if under > 0 {
+                        let points = points * under / 2;
+                        self.add_points_for(player, points);
+                        self.events
+                            .emit(GameEvent::EfficientClear { pieces, points });
+                    }
+                }
+                self.record_clear(&affected_pixels);
+                for (px, py) in affected_pixels {
+                    self.sand.set((px, py), None);
+                }
+
+                if chain >= POWERUP_CHAIN {
+                    self.powerup_earned = true;
+                }
+                if chain > 1 {
+                    self.events.emit(GameEvent::Chain { chain });
+                }
+            }
+            Animation::Sweep { color, pixels } => {
+                let points = pixels.len() * COLOR_CLEAR_POINTS;
+                self.add_points(points);
+                self.events.emit(GameEvent::ColorCleared {
+                    color,
+                    grains: pixels.len(),
+                    points,
+                });
+                self.record_clear(&pixels);
+                self.sand.remove_all(&pixels);
+            }
+            // Laser clears don't count towards combos or chains, they're a way out of trouble
+            // rather than a way to score
+            Animation::Beam { pixels, .. } => {
+                let points = pixels.len() * LASER_POINTS;
+                self.add_points(points);
+                self.events.emit(GameEvent::LaserCleared {
+                    grains: pixels.len(),
+                    points,
+                });
+                self.record_clear(&pixels);
+                self.sand.remove_all(&pixels);
+            }
+            // Like lasers, bombs are for getting out of trouble
+            Animation::Blast { pixels, .. } => {
+                let points = pixels.len() * BOMB_POINTS;
+                self.add_points(points);
+                self.events.emit(GameEvent::BombExploded {
+                    grains: pixels.len(),
+                    points,
+                });
+                self.record_clear(&pixels);
+                self.sand.remove_all(&pixels);
+            }
+        }
+    }
+
+    /// Whether the grains of a clear are being animated away
+    fn is_clearing(&self) -> bool {
+        matches!(self.animation, Some((_, Animation::RemoveLine { .. })))
+    }
+
+    fn run_animation(&mut self, delta: f64) -> bool {
+        let Some((animation_ts, animation)) = &mut self.animation else {
+            return false;
+        };
+
+        *animation_ts += delta;
+
+        match animation {
+            Animation::Sweep { .. } => {
+                if *animation_ts >= SWEEP_DURATION {
+                    return false;
+                }
+            }
+            Animation::Beam { .. } => {
+                if *animation_ts >= BEAM_DURATION {
+                    return false;
+                }
+            }
+            Animation::Blast { .. } => {
+                if *animation_ts >= BLAST_DURATION {
+                    return false;
+                }
+            }
+            Animation::RemoveLine { style, .. } => {
+                if *animation_ts > style.duration() {
+                    return false;
+                }
+            }
+        };
+
+        return true;
+    }
+
+    fn spawn_block(&mut self, block: Block) {
+        self.locking = None;
+        self.score_gravity = self.mode.score_gravity(self.score);
+        self.falling_block = Some(block.with_pos(
+            self.settings.spawn_position.spawn_x(
+                self.sand.dim().0,
+                block.width(),
+                self.last_block_x,
+            ),
+            self.spawn_y(block.height()),
+        ));
+
+        // Initial actions: a rotate or hold that is held down when the piece appears is applied
+        // immediately
+        if self.input.is_held(Action::Hold) && !self.hold_used {
+            self.hold_block();
+            return;
+        }
+        if self.input.is_held(Action::Rotate) {
+            self.rotate_block();
+        }
+
+        self.events.emit(GameEvent::PieceSpawned {
+            shape: block.shape,
+            color: block.color,
+        });
+        // Block out: the piece appeared on top of sand
+        if self.falling_block.is_some_and(|block| !self.fits(&block)) {
+            self.top_out();
+        }
+    }
+
+    /// Where a piece `height` blocks tall spawns, in sand pixels: resting on the top of the visible
+    /// board, or at the very top if it's too tall for the hidden rows
+    fn spawn_y(&self, height: usize) -> usize {
+        self.settings
+            .board
+            .hidden_sand_rows()
+            .saturating_sub(height * SAND_BLOCK_SIZE)
+    }
+
+    /// Whether a drop pressed at the given time goes through. Presses from before the piece
+    /// spawned, or from just after, are taken to be meant for the piece before.
+    fn can_drop(&self, pressed_at: f64) -> bool {
+        pressed_at >= self.spawned_at + self.settings.drop_lockout
+    }
+
+    fn rotate_block(&mut self) {
+        let Some(block) = self.falling_block.filter(|_| self.locking.is_none()) else {
+            return;
+        };
+        let rotated = block.rotate();
+        // Keep the block centered on the same spot, then try nudging it one block to either side
+        // if it doesn't fit
+        let center_x = (block.x + block.width() * SAND_BLOCK_SIZE / 2) as isize
+            - (rotated.width() * SAND_BLOCK_SIZE / 2) as isize;
+        for offset in [0, -(SAND_BLOCK_SIZE as isize), SAND_BLOCK_SIZE as isize] {
+            let x = center_x + offset;
+            if x >= 0 && self.fits(&rotated.with_pos(x as usize, block.y)) {
+                self.falling_block = Some(rotated.with_pos(x as usize, block.y));
+                return;
+            }
+        }
+    }
+
+    fn hold_block(&mut self) {
+        let Some(block) = self.falling_block.take() else {
+            return;
+        };
+        self.locking = None;
+        self.hold_used = true;
+        if let Some(held) = self.held_block.replace(block.with_rotation(0)) {
+            self.spawn_block(held);
+        }
+    }
+
+    fn fits(&self, block: &Block) -> bool {
+        block.fits(&self.sand)
+    }
+
+    fn can_move(&self, direction: Direction) -> bool {
+        if let Some(block) = self.falling_block {
+            match direction {
+                Direction::Left => {
+                    // TODO: Check sand
+                    block.x > 0
+                        && block
+                            .coords()
+                            .all(|(px, py)| self.sand.is_clear([px - 1, py, 1, SAND_BLOCK_SIZE]))
+                }
+                Direction::Right => {
+                    // TODO: Check sand
+                    block.x < self.sand.dim().0 - (SAND_BLOCK_SIZE * block.width())
+                        && block.coords().all(|(px, py)| {
+                            self.sand
+                                .is_clear([px + SAND_BLOCK_SIZE, py, 1, SAND_BLOCK_SIZE])
+                        })
+                }
+                Direction::Down => {
+                    block.y < self.sand.dim().1 - (SAND_BLOCK_SIZE * block.height())
+                        && block.coords().all(|(px, py)| {
+                            self.sand
+                                .is_clear([px, py + SAND_BLOCK_SIZE, SAND_BLOCK_SIZE, 1])
+                        })
+                }
+            }
+        } else {
+            false
+        }
+    }
+
+    /// Push all the sand up and fill the bottom with a row of garbage. The row has a gap so it
+    /// can never clear on its own. Sand pushed off the top either ends the game or spills, as the
+    /// overflow setting says.
+    fn raise_floor(&mut self) {
+        let spilled = shift_up(&mut self.sand, RISING_ROWS);
+        if spilled > 0 {
+            match self.settings.overflow {
+                Overflow::GameOver => {
+                    self.top_out();
+                    return;
+                }
+                Overflow::Spill => {
+                    let points = (spilled * SPILL_PENALTY).min(self.score);
+                    self.score -= points;
+                    self.events.emit(GameEvent::SandSpilled {
+                        grains: spilled,
+                        points,
+                    });
+                }
+            }
+        }
+
+        let (width, height) = self.sand.dim();
+        let columns = width / SAND_BLOCK_SIZE;
+        let gap = self.rng.generate_range(0..columns);
+        for column in (0..columns).filter(|c| *c != gap) {
+            let grain = Grain {
+                color: self.rng.generate(),
+                landed: self.elapsed_time,
+                piece: 0,
+            };
+            self.sand.fill(
+                [
+                    column * SAND_BLOCK_SIZE,
+                    height - RISING_ROWS,
+                    SAND_BLOCK_SIZE,
+                    RISING_ROWS,
+                ],
+                Some(grain),
+            );
+        }
+
+        // The falling block rides up with the sand if it's in the way, and tops out if it can't
+        if let Some(block) = self.falling_block.filter(|block| !self.fits(block)) {
+            if block.y >= RISING_ROWS && self.fits(&block.with_pos(block.x, block.y - RISING_ROWS))
+            {
+                self.falling_block = Some(block.with_pos(block.x, block.y - RISING_ROWS));
+            } else {
+                self.top_out();
+            }
+        }
+    }
+
+    /// Record a clear for the analysis, along with the pieces its grains came from, before they're
+    /// taken off the board
+    fn record_clear(&mut self, pixels: &[(usize, usize)]) {
+        let pieces = pixels
+            .iter()
+            .filter_map(|p| self.sand.get(*p))
+            .map(|grain| grain.piece)
+            .filter(|piece| *piece != 0)
+            .collect();
+        self.analysis.record_clear(pixels, &pieces);
+    }
+
+    /// How many different pieces the grains at these cells came from, or `None` if any of them
+    /// didn't come from a piece at all
+    fn pieces_in(&self, pixels: &[(usize, usize)]) -> Option<usize> {
+        let mut pieces = HashSet::new();
+        for p in pixels {
+            match self.sand.get(*p) {
+                Some(Grain { piece: 0, .. }) => return None,
+                Some(grain) => {
+                    pieces.insert(grain.piece);
+                }
+                None => {}
+            }
+        }
+        Some(pieces.len()).filter(|n| *n > 0)
+    }
+
+    /// Set off a powerup piece as it locks, in place of its sand
+    fn use_powerup(&mut self, powerup: Powerup, block: Block) {
+        // A clear still being animated is cut short, so its grains are gone before the powerup
+        // looks for what to take
+        if let Some((_, clear)) = self.animation.take() {
+            self.finish_animation(clear);
+        }
+        match powerup {
+            Powerup::ColorClear => {
+                let pixels = self.sand.positions_of(block.color);
+                if !pixels.is_empty() {
+                    self.animation = Some((
+                        0.0,
+                        Animation::Sweep {
+                            color: block.color,
+                            pixels,
+                        },
+                    ));
+                }
+            }
+            Powerup::Laser => {
+                let rows = (block.y, block.y + block.height() * SAND_BLOCK_SIZE);
+                let pixels = self.sand.positions_in_rows(rows);
+                self.animation = Some((0.0, Animation::Beam { rows, pixels }));
+            }
+            Powerup::Bomb => {
+                let center = (
+                    block.x + block.width() * SAND_BLOCK_SIZE / 2,
+                    block.y + block.height() * SAND_BLOCK_SIZE / 2,
+                );
+                let pixels = self.sand.positions_within(center, BOMB_RADIUS);
+                self.animation = Some((0.0, Animation::Blast { center, pixels }));
+            }
+        }
+    }
+
+    /// Play one frame while paused, to watch what happens a step at a time. It's played from the
+    /// key press, so replays step at the same moment.
+    fn advance_frame(&mut self) {
+        self.play_mode = PlayMode::Playing;
+        self.step(&UpdateArgs { dt: FRAME_STEP });
+        if self.play_mode == PlayMode::Playing {
+            self.play_mode = PlayMode::Paused;
+        }
+    }
+
+    /// Set off the chosen special, if the special meter is full. The specials that make the
+    /// falling piece a powerup wait for there to be one.
+    fn use_special(&mut self) {
+        let Some(special) = self.settings.special else {
+            return;
+        };
+        if !self.special_meter.is_full() {
+            return;
+        }
+        match special.powerup() {
+            Some(powerup) => match &mut self.falling_block {
+                Some(block) => block.powerup = Some(powerup),
+                None => return,
+            },
+            None => self.time_slowed = SLOW_TIME_DURATION,
+        }
+        self.special_meter.drain();
+    }
+
+    fn add_sand_block(&mut self) {
+        if let Some(block) = self.falling_block {
+            self.pieces_locked += 1;
+            self.analysis.record_sand(self.pieces_locked, block.shape);
+            for (px, py) in block.coords() {
+                self.sand.fill(
+                    [px, py, SAND_BLOCK_SIZE, SAND_BLOCK_SIZE],
+                    Some(Grain {
+                        color: block.color,
+                        landed: self.elapsed_time,
+                        piece: self.pieces_locked,
+                    }),
+                );
+            }
+        }
+    }
+
+    fn run_sand_physics(&mut self) {
+        // let last_sand = self.sand.clone();
+        // The bottom line will not move so we can skip it, and not worry about the bottom edge
+        // case
+        // for y in (0..self.sand.dim().1 - 1).rev() {
+        //     for x in 0..self.sand.dim().0 {
+        //         if self.sand[[x, y]].is_none() {
+        //             continue;
+        //         }
+        //
+        //         // Check directly below
+        //         if self.sand[[x, y + 1]].is_none() {
+        //             self.sand[[x, y + 1]] = self.sand[[x, y]];
+        //             self.sand[[x, y]] = None;
+        //             continue;
+        //         }
+        //     }
+        //
+        //     for x in 0..self.sand.dim().0 {
+        //         if self.sand[[x, y]].is_none() {
+        //             continue;
+        //         }
+        //
+        //         // TODO: Make which direction the sand actually goes randomly decided
+        //
+        //         // Check bottom left
+        //         if x > 0 && self.sand[[x - 1, y + 1]].is_none() {
+        //             self.sand[[x - 1, y + 1]] = self.sand[[x, y]];
+        //             self.sand[[x, y]] = None;
+        //             continue;
+        //         }
+        //
+        //         // Check bottom right
+        //         if x < self.sand.dim().0 - 1 && self.sand[[x + 1, y + 1]].is_none() {
+        //             self.sand[[x + 1, y + 1]] = self.sand[[x, y]];
+        //             self.sand[[x, y]] = None;
+        //             continue;
+        //         }
+        //     }
+        // }
+        if self.modifiers.sticky_sand {
+            run_sticky_physics(&mut self.sand);
+            return;
+        }
+        run_rng_physics(&mut self.rng, &mut self.sand);
+    }
+
+    fn center_texture(
+        width: u32,
+        height: u32,
+        context: graphics::Context,
+        texture: &TextSprite,
+    ) -> graphics::Context {
+        Self::center_texture_x(width, context, texture).trans(
+            0.0,
+            (height / 2).saturating_sub(texture.get_height() / 2) as f64,
+        )
+    }
+
+    /// Center a texture across `width`, or line it up with the left edge if it's wider than that,
+    /// as titles are on the narrowest boards
+    fn center_texture_x(
+        width: u32,
+        context: graphics::Context,
+        texture: &TextSprite,
+    ) -> graphics::Context {
+        context.trans(
+            (width / 2).saturating_sub(texture.get_width() / 2) as f64,
+            0.0,
+        )
+    }
+
+    fn draw_dashboard(&mut self, context: graphics::Context, g: &mut G2d) -> Result<(), Error> {
+        let ui_width = DASHBOARD_WIDTH;
+        let ui_height = self.layout.window_size().1;
+
+        let context = context.trans(self.layout.view_size().0 as f64, 0.0);
+
+        // Draw background
+        graphics::rectangle_from_to(
+            UI_BACKGROUND_COLOR,
+            [0.0, 0.0],
+            [ui_width as f64, ui_height as f64],
+            context.transform,
+            g,
+        );
+
+        let mut stack = Stack::new(
+            ui_width as f64,
+            DASHBOARD_TOP,
+            ui_height as f64,
+            DASHBOARD_PADDING,
+        );
+
+        if let Some(editor) = self
+            .editor
+            .as_ref()
+            .filter(|_| self.mode == GameMode::Editor)
+        {
+            // The editor shows the piece that will be queued next and the last one queued
+            let brush = Block::new(editor.shape, editor.brush);
+            let last = editor
+                .queue
+                .last()
+                .map(|(shape, color)| Block::new(*shape, *color));
+            let lines = [format!("{} PIECES", editor.queue.len())];
+            return [
+                self.draw_block_display(context, g, &mut stack, "BRUSH", Some(brush), false),
+                self.draw_score(context, g, &mut stack),
+                self.draw_block_display(context, g, &mut stack, "LAST", last, false),
+                self.draw_info(context, g, &mut stack, "QUEUE", &lines),
+            ]
+            .into_iter()
+            .collect();
+        }
+
+        if let Some(lines) = self.stress.as_ref().map(StressTest::lines) {
+            return [
+                self.draw_score(context, g, &mut stack),
+                self.draw_info(context, g, &mut stack, "PHYSICS", &lines),
+            ]
+            .into_iter()
+            .collect();
+        }
+
+        let next_block = Some(self.next_block());
+        // The same piece again as the grains it turns into, for players still getting the hang of
+        // how blocks become sand. It goes beside the next piece so it doesn't push the rest of the
+        // dashboard down.
+        let (next, next_sand) = match self.settings.sand_preview {
+            true => {
+                let [next_slot, sand_slot] =
+                    stack.place_pair(NEXT_BLOCK_DISPLAY_WIDTH, Self::block_display_height());
+                (
+                    self.draw_block_display_in(context, g, next_slot, "NEXT", next_block, false),
+                    self.draw_block_display_in(context, g, sand_slot, "AS SAND", next_block, true),
+                )
+            }
+            false => (
+                self.draw_block_display(context, g, &mut stack, "NEXT", next_block, false),
+                Ok(()),
+            ),
+        };
+        let special = self.draw_special(context, g, &mut stack);
+        let score = self.draw_score(context, g, &mut stack);
+        let grade = self.draw_grade(context, g, &mut stack);
+        let held_block = self.held_block;
+        let hold = self.draw_block_display(context, g, &mut stack, "HOLD", held_block, false);
+        let combo = self.draw_combo(context, g, &mut stack);
+
+        // Draw the mission or mode's goal, the time left, or whose turn it is in hot seat mode
+        let goal = match (&self.mission, &self.goal) {
+            (Some(tracker), _) => {
+                let lines = [tracker.mission().objective.describe(), tracker.progress()];
+                self.draw_info(context, g, &mut stack, "GOAL", &lines)
+            }
+            (None, Some(goal)) => {
+                let lines = [goal.objective.describe(), goal.progress()];
+                self.draw_info(context, g, &mut stack, "GOAL", &lines)
+            }
+            (None, None) if self.deadline.is_some() => {
+                let left = (self.deadline.unwrap() - self.elapsed_time).max(0.0);
+                let lines = [format!("{:.0}S", left.ceil())];
+                self.draw_info(context, g, &mut stack, "TIME LEFT", &lines)
+            }
+            (None, None) if self.mode == GameMode::HotSeat => {
+                // The score of whoever's turn it is stands out
+                let lines = (0..2)
+                    .map(|player| {
+                        let score = format!(
+                            "{:0width$}",
+                            self.player_scores[player],
+                            width = SCORE_DIGITS
+                        );
+                        let color = match player == self.turn {
+                            true => HIGHLIGHT_COLOR,
+                            false => TEXT_COLOR,
+                        };
+                        vec![(format!("P{} ", player + 1), TEXT_COLOR), (score, color)]
+                    })
+                    .collect::<Vec<_>>();
+                let label = format!("PLAYER {} TURN", self.turn + 1);
+                self.draw_rich_info(context, g, &mut stack, &label, &lines)
+            }
+            (None, None) => Ok(()),
+        };
+
+        // The piece stats and the feed are the first things to go when the dashboard is full. The
+        // stats take the feed's place if they fit, and it stays otherwise.
+        let feed = if self.settings.piece_stats && stack.fits(Self::piece_stats_height()) {
+            let slot = stack.place_row(Self::piece_stats_height());
+            self.draw_piece_stats(context, g, slot)
+        } else if stack.fits(Self::feed_height()) {
+            self.draw_feed(context, g, &mut stack)
+        } else {
+            Ok(())
+        };
+
+        // Keep drawing the rest of the dashboard if one part fails
+        [
+            next, next_sand, special, score, grade, hold, combo, goal, feed,
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    /// Draw a line of text centered in a slot on the dashboard, `y` pixels down from its top
+    fn draw_slot_text(
+        &mut self,
+        context: graphics::Context,
+        g: &mut G2d,
+        slot: Slot,
+        y: f64,
+        text: &str,
+        scale: usize,
+    ) -> Result<(), Error> {
+        self.draw_slot_rich_text(context, g, slot, y, &[(text, TEXT_COLOR)], scale)
+    }
+
+    /// Draw a line made of runs of text in different colors, like [`Game::draw_slot_text`]
+    fn draw_slot_rich_text(
+        &mut self,
+        context: graphics::Context,
+        g: &mut G2d,
+        slot: Slot,
+        y: f64,
+        segments: &[Segment],
+        scale: usize,
+    ) -> Result<(), Error> {
+        let texture = self.text_textures.rich_texture_with_background(
+            segments,
+            scale,
+            UI_ELEMENT_BG_COLOR,
+        )?;
+        texture.draw(
+            context
+                .trans(slot.center_x(texture.get_width() as f64), slot.y + y)
+                .transform,
+            g,
+        );
+        Ok(())
+    }
+
+    /// Draw the grade the run is on. Its room is kept before there is one, so the dashboard doesn't
+    /// shift once it shows up.
+    fn draw_grade(
+        &mut self,
+        context: graphics::Context,
+        g: &mut G2d,
+        stack: &mut Stack,
+    ) -> Result<(), Error> {
+        let slot = stack.place_row(TextTextures::line_height(GRADE_SCALE));
+        let Some(grade) = self.grade else {
+            return Ok(());
+        };
+        self.draw_slot_text(
+            context,
+            g,
+            slot,
+            0.0,
+            &format!("GRADE {grade}"),
+            GRADE_SCALE,
+        )
+    }
+
+    /// Draw the recent scoring events, fading out as they get old
+    fn draw_feed(
+        &mut self,
+        context: graphics::Context,
+        g: &mut G2d,
+        stack: &mut Stack,
+    ) -> Result<(), Error> {
+        let slot = stack.place_row(Self::feed_height());
+        let entries = self
+            .feed
+            .entries()
+            .map(|(segments, opacity)| (segments.to_vec(), opacity))
+            .collect::<Vec<_>>();
+        for (i, (segments, opacity)) in entries.into_iter().enumerate() {
+            let segments = segments
+                .iter()
+                .map(|(text, color)| (text.as_str(), *color))
+                .collect::<Vec<_>>();
+            let texture = self.text_textures.rich_texture_with_background(
+                &segments,
+                FEED_SCALE,
+                UI_ELEMENT_BG_COLOR,
+            )?;
+            let y = slot.y + (i as u32 * FEED_SPACING) as f64;
+            texture.draw_tinted(
+                [1.0, 1.0, 1.0, opacity as f32],
+                &context.draw_state,
+                context
+                    .trans(slot.center_x(texture.get_width() as f64), y)
+                    .transform,
+                g,
+            );
+        }
+        Ok(())
+    }
+
+    fn feed_height() -> f64 {
+        FEED_SPACING as f64 * FEED_LENGTH as f64
+    }
+
+    /// How tall the piece stats are, with a heading over a row of cells for every
+    /// [`PIECE_STATS_COLUMNS`] shapes
+    fn piece_stats_height() -> f64 {
+        let rows = Shape::all().count().div_ceil(PIECE_STATS_COLUMNS);
+        let line_height = TextTextures::line_height(PIECE_STATS_SCALE);
+        line_height + rows as f64 * (PIECE_STATS_ICON_HEIGHT + line_height)
+    }
+
+    /// Draw each shape with how many of it have been dealt and, in another color, how many clears
+    /// took grains from it, in rows centered in the slot
+    fn draw_piece_stats(
+        &mut self,
+        context: graphics::Context,
+        g: &mut G2d,
+        slot: Slot,
+    ) -> Result<(), Error> {
+        let line_height = TextTextures::line_height(PIECE_STATS_SCALE);
+        let heading = [("DEALT ", TEXT_COLOR), ("CLEARS", HIGHLIGHT_COLOR)];
+        self.draw_slot_rich_text(context, g, slot, 0.0, &heading, PIECE_STATS_SCALE)?;
+
+        let stats = self.analysis.shape_stats().collect::<Vec<_>>();
+        for (row, shapes) in stats.chunks(PIECE_STATS_COLUMNS).enumerate() {
+            let y = slot.y + line_height + row as f64 * (PIECE_STATS_ICON_HEIGHT + line_height);
+            // A short last row is centered like the others
+            let left = slot.center_x(PIECE_STATS_CELL_WIDTH * shapes.len() as f64);
+            for (i, (shape, dealt, clears)) in shapes.iter().enumerate() {
+                let x = left + i as f64 * PIECE_STATS_CELL_WIDTH;
+                let block = Block::new(*shape, Color::ALL[shape.index() % Color::ALL.len()]);
+                let (width, height) = (
+                    (block.width() * BLOCK_SIZE) as f64,
+                    (block.height() * BLOCK_SIZE) as f64,
+                );
+                let scale = (PIECE_STATS_CELL_WIDTH / width).min(PIECE_STATS_ICON_HEIGHT / height);
+                block.render_origin(
+                    context
+                        .trans(
+                            x + (PIECE_STATS_CELL_WIDTH - width * scale) / 2.0,
+                            y + (PIECE_STATS_ICON_HEIGHT - height * scale) / 2.0,
+                        )
+                        .scale(scale, scale),
+                    g,
+                );
+                let counts = [
+                    (format!("{dealt} "), TEXT_COLOR),
+                    (clears.to_string(), HIGHLIGHT_COLOR),
+                ];
+                let counts = counts
+                    .iter()
+                    .map(|(text, color)| (text.as_str(), *color))
+                    .collect::<Vec<_>>();
+                let cell = Slot {
+                    x,
+                    y,
+                    width: PIECE_STATS_CELL_WIDTH,
+                    height: PIECE_STATS_ICON_HEIGHT + line_height,
+                };
+                self.draw_slot_rich_text(
+                    context,
+                    g,
+                    cell,
+                    PIECE_STATS_ICON_HEIGHT,
+                    &counts,
+                    PIECE_STATS_SCALE,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Draw the special meter with the name of the special over it. While time is slowed, the bar
+    /// shows how much of it is left instead.
+    fn draw_special(
+        &mut self,
+        context: graphics::Context,
+        g: &mut G2d,
+        stack: &mut Stack,
+    ) -> Result<(), Error> {
+        let Some(special) = self.settings.special else {
+            return Ok(());
+        };
+
+        let (fill, color) = if self.time_slowed > 0.0 {
+            (self.time_slowed / SLOW_TIME_DURATION, SPECIAL_READY_COLOR)
+        } else if self.special_meter.is_full() {
+            (1.0, SPECIAL_READY_COLOR)
+        } else {
+            (self.special_meter.fill(), CLEAR_COLOR)
+        };
+        self.draw_meter(context, g, stack, special.name(), fill, color)
+    }
+
+    /// Draw the combo and a bar that empties as its window runs out, while a combo is alive. Its
+    /// room is kept the rest of the time.
+    fn draw_combo(
+        &mut self,
+        context: graphics::Context,
+        g: &mut G2d,
+        stack: &mut Stack,
+    ) -> Result<(), Error> {
+        if self.combo_timer <= 0.0 {
+            stack.place(NEXT_BLOCK_DISPLAY_WIDTH, Self::meter_height());
+            return Ok(());
+        }
+
+        let fill = (self.combo_timer / self.mode.combo_window()).clamp(0.0, 1.0);
+        let label = format!("COMBO X{}", self.combo);
+        self.draw_meter(context, g, stack, &label, fill, CLEAR_COLOR)
+    }
+
+    /// How tall a meter is, label and all, see [`Game::draw_meter`]
+    fn meter_height() -> f64 {
+        TextTextures::line_height(METER_LABEL_SCALE) + METER_BAR_HEIGHT
+    }
+
+    /// Draw a bar filled part of the way, with a label over it
+    fn draw_meter(
+        &mut self,
+        context: graphics::Context,
+        g: &mut G2d,
+        stack: &mut Stack,
+        label: &str,
+        fill: f64,
+        color: [f32; 4],
+    ) -> Result<(), Error> {
+        let slot = stack.place(NEXT_BLOCK_DISPLAY_WIDTH, Self::meter_height());
+        let bar_context = context.trans(
+            slot.x,
+            slot.y + TextTextures::line_height(METER_LABEL_SCALE),
+        );
+        graphics::rectangle_from_to(
+            UI_ELEMENT_BG_COLOR_FLOAT,
+            [0.0, 0.0],
+            [slot.width, METER_BAR_HEIGHT],
+            bar_context.transform,
+            g,
+        );
+        graphics::rectangle_from_to(
+            color,
+            [0.0, 0.0],
+            [slot.width * fill, METER_BAR_HEIGHT],
+            bar_context.transform,
+            g,
+        );
+
+        self.draw_slot_text(context, g, slot, 0.0, label, METER_LABEL_SCALE)
+    }
+
+    fn draw_score(
+        &mut self,
+        context: graphics::Context,
+        g: &mut G2d,
+        stack: &mut Stack,
+    ) -> Result<(), Error> {
+        let (score, label) = match self.partner_score {
+            Some(partner_score) => (self.score + partner_score, "TEAM SCORE"),
+            None => (self.score, "SCORE"),
+        };
+        let label_height = TextTextures::line_height(SCORE_LABEL_SCALE);
+        let slot = stack.place_row(label_height + TextTextures::line_height(SCORE_SCALE));
+        let digits = format!("{score:0width$}", width = SCORE_DIGITS);
+        self.draw_slot_text(context, g, slot, label_height, &digits, SCORE_SCALE)?;
+        self.draw_slot_text(context, g, slot, 0.0, label, SCORE_LABEL_SCALE)
+    }
+
+    /// Draw a label with a few lines of text under it
+    fn draw_info(
+        &mut self,
+        context: graphics::Context,
+        g: &mut G2d,
+        stack: &mut Stack,
+        label: &str,
+        lines: &[String],
+    ) -> Result<(), Error> {
+        let lines = lines
+            .iter()
+            .map(|line| vec![(line.clone(), TEXT_COLOR)])
+            .collect::<Vec<_>>();
+        self.draw_rich_info(context, g, stack, label, &lines)
+    }
+
+    /// Draw a label with a few lines of text under it, each made of runs of text in different
+    /// colors
+    fn draw_rich_info(
+        &mut self,
+        context: graphics::Context,
+        g: &mut G2d,
+        stack: &mut Stack,
+        label: &str,
+        lines: &[Vec<(String, Rgba<u8>)>],
+    ) -> Result<(), Error> {
+        let line_height = TextTextures::line_height(MISSION_TEXT_SCALE);
+        let slot = stack.place_row(line_height * (lines.len() + 1) as f64);
+        self.draw_slot_text(context, g, slot, 0.0, label, MISSION_TEXT_SCALE)?;
+        for (i, line) in lines.iter().enumerate() {
+            let y = line_height * (i + 1) as f64;
+            let segments = line
+                .iter()
+                .map(|(text, color)| (text.as_str(), *color))
+                .collect::<Vec<_>>();
+            self.draw_slot_rich_text(context, g, slot, y, &segments, MISSION_TEXT_SCALE)?;
+        }
+        Ok(())
+    }
+
+    fn draw_block_display(
+        &mut self,
+        context: graphics::Context,
+        g: &mut G2d,
+        stack: &mut Stack,
+        label: &str,
+        block: Option<Block>,
+        grains: bool,
+    ) -> Result<(), Error> {
+        let slot = stack.place(NEXT_BLOCK_DISPLAY_WIDTH, Self::block_display_height());
+        self.draw_block_display_in(context, g, slot, label, block, grains)
+    }
+
+    fn block_display_height() -> f64 {
+        TextTextures::line_height(NEXT_BLOCK_LABEL_SCALE) + NEXT_BLOCK_DISPLAY_HEIGHT
+    }
+
+    /// Draw a piece in a box with a label over it, in a slot [`Game::block_display_height`] tall.
+    /// With `grains`, it's drawn as the sand it turns into.
+    fn draw_block_display_in(
+        &mut self,
+        context: graphics::Context,
+        g: &mut G2d,
+        slot: Slot,
+        label: &str,
+        block: Option<Block>,
+        grains: bool,
+    ) -> Result<(), Error> {
+        let label_height = TextTextures::line_height(NEXT_BLOCK_LABEL_SCALE);
+        let display_context = context.trans(slot.x, slot.y + label_height);
+
+        graphics::rectangle_from_to(
+            UI_ELEMENT_BG_COLOR_FLOAT,
+            [0.0, 0.0],
+            [NEXT_BLOCK_DISPLAY_WIDTH, NEXT_BLOCK_DISPLAY_HEIGHT],
+            display_context.transform,
+            g,
+        );
+
+        if let Some(block) = block {
+            // Draw at half size, or smaller if that's what it takes for a large shape to fit
+            let (width, height) = (
+                (block.width() * BLOCK_SIZE) as f64,
+                (block.height() * BLOCK_SIZE) as f64,
+            );
+            let scale = (NEXT_BLOCK_DISPLAY_WIDTH / width)
+                .min(NEXT_BLOCK_DISPLAY_HEIGHT / height)
+                .min(0.5);
+            let shape_context = display_context
+                .trans(
+                    NEXT_BLOCK_DISPLAY_WIDTH / 2.0 - width * scale / 2.0,
+                    NEXT_BLOCK_DISPLAY_HEIGHT / 2.0 - height * scale / 2.0,
+                )
+                .scale(scale, scale);
+
+            if grains {
+                block.render_grains_origin(shape_context, g);
+            } else {
+                block.render_origin(shape_context, g);
+            }
+        }
+
+        // The label goes last, so the display still shows up if text can't be drawn. It lines up
+        // with the left edge of the display.
+        let label_texture = self.text_textures.texture_with_background(
+            label,
+            NEXT_BLOCK_LABEL_SCALE,
+            TEXT_COLOR,
+            UI_ELEMENT_BG_COLOR,
+        )?;
+        label_texture.draw(context.trans(slot.x, slot.y).transform, g);
+        Ok(())
+    }
+
+    fn draw_menu(&mut self, context: graphics::Context, g: &mut G2d) -> Result<(), Error> {
+        let board_width = self.layout.view_size().0;
+        graphics::rectangle_from_to(
+            UI_ELEMENT_BG_COLOR_FLOAT,
+            [0.0, 0.0],
+            [board_width as f64, self.layout.view_size().1 as f64],
+            context.transform,
+            g,
+        );
+
+        let title_texture =
+            self.text_textures
+                .texture("SELECT MODE", MENU_TITLE_SCALE, TEXT_COLOR)?;
+        title_texture.draw(
+            Self::center_texture_x(board_width, context, title_texture)
+                .trans(0.0, MENU_TITLE_Y as f64)
+                .transform,
+            g,
+        );
+
+        // Items are squeezed closer together when there are too many to fit at the usual spacing
+        let items = self.menu_items();
+        let room = self.layout.view_size().1 as f64
+            - MENU_ITEM_Y as f64
+            - TextTextures::line_height(MENU_ITEM_SCALE);
+        let spacing = (MENU_ITEM_SPACING as f64).min(room / (items.len() - 1).max(1) as f64);
+        for (i, item) in items.into_iter().enumerate() {
+            let texture = if i == self.menu_selection {
+                self.text_textures.texture_with_background(
+                    &item.name(),
+                    MENU_ITEM_SCALE,
+                    UI_ELEMENT_BG_COLOR,
+                    TEXT_COLOR,
+                )
+            } else {
+                self.text_textures
+                    .texture(&item.name(), MENU_ITEM_SCALE, TEXT_COLOR)
+            }?;
+            texture.draw(
+                Self::center_texture_x(board_width, context, texture)
+                    .trans(0.0, (MENU_ITEM_Y as f64 + i as f64 * spacing).floor())
+                    .transform,
+                g,
+            );
+        }
+        Ok(())
+    }
+
+    /// Draw the palette screen: a swatch and the red, green and blue values of each color, and a
+    /// pile of sand in the colors being picked
+    fn draw_palette(&mut self, context: graphics::Context, g: &mut G2d) -> Result<(), Error> {
+        let Some(editor) = self.palette_editor.clone() else {
+            return Ok(());
+        };
+        let (view_width, view_height) = self.layout.view_size();
+        graphics::rectangle_from_to(
+            UI_ELEMENT_BG_COLOR_FLOAT,
+            [0.0, 0.0],
+            [view_width as f64, view_height as f64],
+            context.transform,
+            g,
+        );
+
+        let title_texture = self
+            .text_textures
+            .texture("COLORS", MENU_TITLE_SCALE, TEXT_COLOR)?;
+        title_texture.draw(
+            Self::center_texture_x(view_width, context, title_texture)
+                .trans(0.0, MENU_TITLE_Y as f64)
+                .transform,
+            g,
+        );
+
+        let palette = Color::palette();
+        for (i, color) in Color::ALL.into_iter().enumerate() {
+            let y = (PALETTE_ROW_Y + i as u32 * PALETTE_ROW_SPACING) as f64;
+            let swatch = PALETTE_SWATCH_SIZE as f64;
+            graphics::rectangle(
+                color.float_color(),
+                [swatch / 2.0, y, swatch, swatch],
+                context.transform,
+                g,
+            );
+            for (channel, name) in CHANNELS.into_iter().enumerate() {
+                let text = format!("{name} {}", palette[color][channel]);
+                let texture = if color == editor.color && channel == editor.channel {
+                    self.text_textures.texture_with_background(
+                        &text,
+                        PALETTE_TEXT_SCALE,
+                        UI_ELEMENT_BG_COLOR,
+                        TEXT_COLOR,
+                    )
+                } else {
+                    self.text_textures
+                        .texture(&text, PALETTE_TEXT_SCALE, TEXT_COLOR)
+                }?;
+                let x = PALETTE_CHANNEL_X + channel as u32 * PALETTE_CHANNEL_SPACING;
+                let text_y = y + (swatch - texture.get_height() as f64) / 2.0;
+                texture.draw(context.trans(x as f64, text_y).transform, g);
+            }
+        }
+
+        // A heap of sand in bands of each color, like it would settle on the board
+        let size = SAND_SIZE as f64;
+        let columns = view_width as usize / SAND_SIZE;
+        let rows = PALETTE_PREVIEW_HEIGHT as usize / SAND_SIZE;
+        for x in 0..columns {
+            let distance = x.abs_diff(columns / 2);
+            let height = rows.saturating_sub(distance * rows / (columns / 2).max(1) / 2);
+            for y in rows - height..rows {
+                let color = Color::ALL[((x + y) / SAND_BLOCK_SIZE) % Color::ALL.len()];
+                graphics::rectangle(
+                    color.float_color(),
+                    [
+                        x as f64 * size,
+                        PALETTE_PREVIEW_Y as f64 + y as f64 * size,
+                        size,
+                        size,
+                    ],
+                    context.transform,
+                    g,
+                );
+            }
+        }
+
+        let hints = [
+            "LEFT RIGHT CHANGE",
+            "C NEXT CHANNEL  U RESET",
+            "ENTER SAVE  M CANCEL",
+        ];
+        self.draw_text_lines(context, g, &hints, PALETTE_HINT_Y, PALETTE_TEXT_SCALE)
+    }
+
+    /// Draw the rules card for the mode picked on the menu, with each section's lines wrapped to
+    /// fit the board
+    fn draw_rules(&mut self, context: graphics::Context, g: &mut G2d) -> Result<(), Error> {
+        let Some((_, card)) = self.rules_card.clone() else {
+            return Ok(());
+        };
+        let (view_width, view_height) = self.layout.view_size();
+        graphics::rectangle_from_to(
+            UI_ELEMENT_BG_COLOR_FLOAT,
+            [0.0, 0.0],
+            [view_width as f64, view_height as f64],
+            context.transform,
+            g,
+        );
+
+        let title_texture =
+            self.text_textures
+                .texture(&card.title, MENU_TITLE_SCALE, TEXT_COLOR)?;
+        title_texture.draw(
+            Self::center_texture_x(view_width, context, title_texture)
+                .trans(0.0, MENU_TITLE_Y as f64)
+                .transform,
+            g,
+        );
+
+        let line_height = TextTextures::line_height(RULES_TEXT_SCALE) as u32;
+        let mut y = RULES_TEXT_Y;
+        for (heading, lines) in &card.sections {
+            let texture = self.text_textures.texture_with_background(
+                heading,
+                RULES_TEXT_SCALE,
+                UI_ELEMENT_BG_COLOR,
+                TEXT_COLOR,
+            )?;
+            texture.draw(
+                Self::center_texture_x(view_width, context, texture)
+                    .trans(0.0, y as f64)
+                    .transform,
+                g,
+            );
+            y += line_height;
+            let lines = lines
+                .iter()
+                .flat_map(|line| TextTextures::wrap(&line.to_uppercase(), RULES_LINE_LENGTH))
+                .collect::<Vec<_>>();
+            self.draw_text_lines(context, g, &lines, y, RULES_TEXT_SCALE)?;
+            y += lines.len() as u32 * line_height + RULES_SECTION_GAP;
+        }
+
+        let hints = [self
+            .settings
+            .keymap
+            .hint(&[(Action::Confirm, "START"), (Action::Menu, "BACK")])];
+        self.draw_text_lines(context, g, &hints, RULES_HINT_Y, RULES_TEXT_SCALE)
+    }
+
+    /// Draw the key binding screen: every action with the keys bound to it, and what to press to
+    /// change them
+    fn draw_keys(&mut self, context: graphics::Context, g: &mut G2d) -> Result<(), Error> {
+        let Some(editor) = self.keymap_editor.clone() else {
+            return Ok(());
+        };
+        let (view_width, view_height) = self.layout.view_size();
+        graphics::rectangle_from_to(
+            UI_ELEMENT_BG_COLOR_FLOAT,
+            [0.0, 0.0],
+            [view_width as f64, view_height as f64],
+            context.transform,
+            g,
+        );
+
+        let title_texture = self
+            .text_textures
+            .texture("KEYS", MENU_TITLE_SCALE, TEXT_COLOR)?;
+        title_texture.draw(
+            Self::center_texture_x(view_width, context, title_texture)
+                .trans(0.0, MENU_TITLE_Y as f64)
+                .transform,
+            g,
+        );
+
+        for (i, action) in Action::ALL.into_iter().enumerate() {
+            let y = (KEYS_ROW_Y + i as u32 * KEYS_ROW_SPACING) as f64;
+            let name = action.name().to_uppercase().replace('_', " ");
+            let texture = if action == editor.action {
+                self.text_textures.texture_with_background(
+                    &name,
+                    KEYS_TEXT_SCALE,
+                    UI_ELEMENT_BG_COLOR,
+                    TEXT_COLOR,
+                )
+            } else {
+                self.text_textures
+                    .texture(&name, KEYS_TEXT_SCALE, TEXT_COLOR)
+            }?;
+            texture.draw(context.trans(KEYS_ACTION_X as f64, y).transform, g);
+
+            // As many of the keys as fit
+            let mut keys = String::new();
+            for key in editor
+                .keymap
+                .keys(action)
+                .iter()
+                .filter_map(|k| key_name(*k))
+            {
+                let name = key.to_uppercase().replace('_', " ");
+                if keys.len() + name.len() + 1 > KEYS_LINE_LENGTH {
+                    break;
+                }
+                keys = format!("{keys} {name}");
+            }
+            if !keys.trim().is_empty() {
+                let texture =
+                    self.text_textures
+                        .texture(keys.trim(), KEYS_TEXT_SCALE, TEXT_COLOR)?;
+                texture.draw(context.trans(KEYS_KEY_X as f64, y).transform, g);
+            }
+        }
+
+        let prompt = match editor.conflict {
+            Some((key, other)) => format!(
+                "{} IS ON {}  AGAIN TO TAKE",
+                key_name(key).unwrap_or_default(),
+                other.name()
+            ),
+            None if self.key_capture.is_waiting() => "PRESS A KEY  ESC CANCELS".to_string(),
+            None => String::new(),
+        };
+        let prompt = prompt.to_uppercase().replace('_', " ");
+        self.draw_text_lines(context, g, &[prompt], KEYS_PROMPT_Y, KEYS_TEXT_SCALE)?;
+
+        let keymap = &self.settings.keymap;
+        let hints = [
+            keymap.hint(&[(Action::Confirm, "BIND"), (Action::Undo, "RESET")]),
+            keymap.hint(&[(Action::Restart, "RESET ALL"), (Action::Menu, "BACK")]),
+        ];
+        self.draw_text_lines(context, g, &hints, KEYS_HINT_Y, KEYS_TEXT_SCALE)
+    }
+
+    /// Draw the records screen: the best grade reached in each mode, next to the board the run
+    /// ended on
+    fn draw_records(&mut self, context: graphics::Context, g: &mut G2d) -> Result<(), Error> {
+        let (view_width, view_height) = self.layout.view_size();
+        graphics::rectangle_from_to(
+            UI_ELEMENT_BG_COLOR_FLOAT,
+            [0.0, 0.0],
+            [view_width as f64, view_height as f64],
+            context.transform,
+            g,
+        );
+
+        let title_texture = self
+            .text_textures
+            .texture("RECORDS", MENU_TITLE_SCALE, TEXT_COLOR)?;
+        title_texture.draw(
+            Self::center_texture_x(view_width, context, title_texture)
+                .trans(0.0, MENU_TITLE_Y as f64)
+                .transform,
+            g,
+        );
+
+        if self.records.is_empty() {
+            self.draw_text_lines(
+                context,
+                g,
+                &["NO RECORDS YET"],
+                RECORD_ROW_Y,
+                RECORD_TEXT_SCALE,
+            )?;
+        }
+        // Only as many rows as fit above the hint are shown, with the last one saying how many
+        // more there are if they don't all fit
+        let rows = ((RECORD_HINT_Y - RECORD_ROW_Y) / RECORD_ROW_SPACING) as usize;
+        let shown = match self.records.len() > rows {
+            true => rows - 1,
+            false => rows,
+        };
+        if self.records.len() > shown {
+            self.draw_text_lines(
+                context,
+                g,
+                &[format!("AND {} MORE", self.records.len() - shown)],
+                RECORD_ROW_Y + shown as u32 * RECORD_ROW_SPACING,
+                RECORD_TEXT_SCALE,
+            )?;
+        }
+        for (i, record) in self.records.iter().take(shown).enumerate() {
+            let y = (RECORD_ROW_Y + i as u32 * RECORD_ROW_SPACING) as f64;
+            let mut x = RECORD_THUMBNAIL_X as f64;
+            if let Some(thumbnail) = &record.thumbnail {
+                graphics::image(thumbnail, context.trans(x, y).transform, g);
+                x += thumbnail.get_width() as f64;
+            }
+            x += RECORD_TEXT_GAP as f64;
+            let text = format!("{} {}", record.mode.name(), record.grade);
+            let texture = self
+                .text_textures
+                .texture(&text, RECORD_TEXT_SCALE, TEXT_COLOR)?;
+            let text_y = y + (THUMBNAIL_HEIGHT as f64 - texture.get_height() as f64) / 2.0;
+            texture.draw(context.trans(x, text_y).transform, g);
+        }
+
+        self.draw_text_lines(
+            context,
+            g,
+            &["ENTER BACK"],
+            RECORD_HINT_Y,
+            RECORD_TEXT_SCALE,
+        )
+    }
+
+    /// Draw lines of text centered on the board, one under the other starting at `y`
+    fn draw_text_lines(
+        &mut self,
+        context: graphics::Context,
+        g: &mut G2d,
+        lines: &[impl AsRef<str>],
+        y: u32,
+        scale: usize,
+    ) -> Result<(), Error> {
+        let mut y = y as f64;
+        for line in lines.iter().map(AsRef::as_ref) {
+            if !line.is_empty() {
+                let texture = self.text_textures.texture_with_background(
+                    line,
+                    scale,
+                    TEXT_COLOR,
+                    UI_ELEMENT_BG_COLOR,
+                )?;
+                texture.draw(
+                    Self::center_texture_x(self.layout.view_size().0, context, texture)
+                        .trans(0.0, y)
+                        .transform,
+                    g,
+                );
+            }
+            y += ((7 + 2) * scale) as f64;
+        }
+        Ok(())
+    }
 
-        graphics::image(
-            score_label_texture,
-            score_context
-                .trans(0.0, -(score_label_texture.get_height() as f64))
-                .transform,
+    /// Draw a large title with a smaller line of text under it, centered on the board
+    fn draw_message(
+        &mut self,
+        context: graphics::Context,
+        g: &mut G2d,
+        title: &str,
+        subtitle: &str,
+    ) -> Result<(), Error> {
+        let texture = self.text_textures.texture(title, 6, TEXT_COLOR)?;
+        texture.draw(
+            Self::center_texture(
+                self.layout.view_size().0,
+                self.layout.view_size().1,
+                context,
+                texture,
+            )
+            .trans(0.0, texture.get_height() as f64 / (-7.0 / 4.0))
+            .transform,
             g,
         );
-
-        // Draw next block display
-        let next_block_context = context.trans(
-            ui_width as f64 / 2.0 - NEXT_BLOCK_DISPLAY_WIDTH / 2.0,
-            NEXT_BLOCK_Y as f64,
-        );
-
-        let next_block_label_texture = self
-            .text_textures
-            .texture_with_background(
-                "NEXT",
-                NEXT_BLOCK_LABEL_SCALE,
-                TEXT_COLOR,
-                UI_ELEMENT_BG_COLOR,
+        let subtitle_texture = self.text_textures.texture(subtitle, 3, TEXT_COLOR)?;
+        subtitle_texture.draw(
+            Self::center_texture(
+                self.layout.view_size().0,
+                self.layout.view_size().1,
+                context,
+                subtitle_texture,
             )
-            .unwrap();
-        graphics::image(
-            next_block_label_texture,
-            next_block_context
-                .trans(0.0, -(next_block_label_texture.get_height() as f64))
-                .transform,
+            .trans(0.0, subtitle_texture.get_height() as f64 / (7.0 / 4.0))
+            .transform,
             g,
         );
+        Ok(())
+    }
 
-        graphics::rectangle_from_to(
-            UI_ELEMENT_BG_COLOR_FLOAT,
-            [0.0, 0.0],
-            [NEXT_BLOCK_DISPLAY_WIDTH, NEXT_BLOCK_DISPLAY_HEIGHT],
-            next_block_context.transform,
+    pub fn render(&mut self, context: graphics::Context, g: &mut G2d) {
+        let start = Instant::now();
+        if let Some(viewport) = &context.viewport {
+            self.text_textures.set_viewport(viewport);
+        }
+        let quality = self.frame_timer.quality;
+        let redraw_board = self.frames_drawn.is_multiple_of(quality.board_interval());
+        self.frames_drawn += 1;
+        let frame = redraw_board.then(|| self.board_frame(quality));
+        let (width, height) = self.layout.canvas_size();
+        let compositor = self
+            .compositor
+            .get_or_insert_with(|| Compositor::new((width as u32, height as u32)));
+        if let Some(frame) = frame {
+            compositor.submit(frame);
+        }
+        // The compositor runs behind, so the board shows whatever it last finished
+        let upload = match compositor.latest() {
+            Some(image) => {
+                self.canvas.image().clone_from(image);
+                true
+            }
+            None => false,
+        };
+        // The board can't spill out of its view, however far the camera zooms in
+        let (view_width, view_height) = self.layout.view_size();
+        let window_height = self.layout.window_size().1;
+        graphics::rectangle(
+            BOARD_BACKGROUND_COLOR,
+            [0.0, 0.0, view_width as f64, window_height as f64],
+            context.transform,
             g,
         );
+        let mut board_context = self
+            .camera
+            .apply(camera::clip(context, (view_width, view_height)));
+        if let Some(slow_motion) = &self.slow_motion {
+            board_context = slow_motion.zoom_in(board_context);
+        }
+        let board = self.canvas.render(board_context, g, upload);
+        self.effects.render(quality, board_context, g);
+
+        if let Some((_, hint)) = self.hint {
+            hint.render_outline(HINT_COLOR, board_context, g);
+        }
+        if let Some((_, pile)) = &self.pile_preview {
+            Self::draw_pile_outline(pile, board_context, g);
+        }
+        if let Some(block) = self.falling_block {
+            block.render(board_context, g);
+        }
+        match &self.animation {
+            Some((time, Animation::Beam { rows, .. })) => {
+                self.draw_beam(board_context, g, *rows, *time)
+            }
+            Some((time, Animation::Blast { center, .. })) => {
+                self.draw_blast(board_context, g, *center, *time)
+            }
+            _ => {}
+        }
 
-        let shape_context = next_block_context.trans(
-            NEXT_BLOCK_DISPLAY_WIDTH / 2.0 - (self.next_block.width() * BLOCK_SIZE) as f64 / 4.0,
-            NEXT_BLOCK_DISPLAY_HEIGHT / 2.0 - (self.next_block.height() * BLOCK_SIZE) as f64 / 4.0,
-        ).scale(0.5, 0.5);
+        let dashboard = self.draw_dashboard(context, g);
+        self.draw_curtain(context, g);
+        let overlays = self.draw_overlays(context, g);
 
-        self.next_block.render_origin(shape_context, g);
+        // Whatever couldn't be drawn is skipped, the game can still be played without it
+        for result in [board, dashboard, overlays] {
+            if let Err(e) = result {
+                self.report_error(e);
+            }
+        }
+        self.frame_timer.record(start.elapsed().as_secs_f64());
     }
 
-    pub fn render(&mut self, context: graphics::Context, g: &mut G2d) {
-        self.canvas.clear(Rgba([255, 255, 255, 255]));
-        let buffer = self.canvas.image();
-
-        // graphics::clear(CLEAR_COLOR, g);
-        for ((x, y), color) in self
-            .sand
-            .indexed_iter()
-            .filter_map(|(pos, pixel)| pixel.map(|p| (pos, p)))
-        {
-            // TODO: Put this into the filter expression, maybe?
-            if let Some((
-                _,
+    /// A copy of what's needed to draw the board, for the compositor
+    fn board_frame(&self, quality: Quality) -> BoardFrame {
+        let hidden = match &self.animation {
+            Some((
+                time,
                 Animation::RemoveLine {
-                    flash_state: false,
+                    style,
                     affected_pixels,
+                    ..
                 },
-            )) = &self.animation
-            {
-                if affected_pixels.contains(&(x, y)) {
-                    continue;
-                }
+            )) => match style.style {
+                ClearStyle::Fade => Vec::new(),
+                ClearStyle::Dissolve => affected_pixels
+                    .iter()
+                    .copied()
+                    .filter(|pos| style.is_dissolved(*time, *pos))
+                    .collect(),
+                _ if style.visibility(*time) <= 0.0 => affected_pixels.clone(),
+                _ => Vec::new(),
+            },
+            // The sweep takes the grains from the top of the board down
+            Some((time, Animation::Sweep { pixels, .. })) => {
+                let (top, bottom) = (pixels[0].1, pixels[pixels.len() - 1].1);
+                let front = top as f64 + (bottom + 1 - top) as f64 * time / SWEEP_DURATION;
+                pixels
+                    .iter()
+                    .copied()
+                    .filter(|(_, y)| (*y as f64) < front)
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+        let fading = match &self.animation {
+            Some((
+                time,
+                Animation::RemoveLine {
+                    style,
+                    affected_pixels,
+                    ..
+                },
+            )) if style.style == ClearStyle::Fade => Some((
+                affected_pixels.iter().copied().collect(),
+                style.visibility(*time),
+            )),
+            _ => None,
+        };
+        // The path is only outlined at the start, to show what set the clear off
+        let clear_path = match &self.animation {
+            Some((time, Animation::RemoveLine { style, path, .. })) if style.shows_path(*time) => {
+                path.clone()
             }
+            _ => Vec::new(),
+        };
+        // The spawn zone and kill line only mean something while there are pieces falling
+        let in_play = matches!(self.play_mode, PlayMode::Playing | PlayMode::Paused)
+            && !matches!(self.mode, GameMode::Editor | GameMode::Stress);
+        BoardFrame {
+            sand: self.sand.clone(),
+            hidden,
+            fading,
+            highlight: self.highlight.clone(),
+            clear_path,
+            // With invisible sand, grains fade out a while after landing, but the whole board is
+            // revealed once the game is over
+            fade_time: (self.modifiers.invisible_sand && self.play_mode != PlayMode::GameOver)
+                .then_some(self.elapsed_time),
+            smooth_fade: quality.smooth_fade(),
+            guides: Guides {
+                grid: self
+                    .settings
+                    .guides
+                    .grid
+                    .filter(|_| quality.background_grid()),
+                ..self.settings.guides
+            },
+            hidden_rows: self.layout.hidden_sand_rows(),
+            spawn_zone: in_play.then(|| {
+                let next = self.next_block();
+                let x = self.settings.spawn_position.spawn_x(
+                    self.sand.dim().0,
+                    next.width(),
+                    self.last_block_x,
+                );
+                [
+                    x,
+                    self.spawn_y(next.height()),
+                    next.width() * SAND_BLOCK_SIZE,
+                    next.height() * SAND_BLOCK_SIZE,
+                ]
+            }),
+            // Any piece in any rotation fits above the line where it spawns
+            kill_line: in_play
+                .then(|| {
+                    Shape::all()
+                        .map(|shape| {
+                            let (width, height) = shape.shape().dim();
+                            self.spawn_y(height) + width.max(height) * SAND_BLOCK_SIZE
+                        })
+                        .max()
+                })
+                .flatten(),
+        }
+    }
+
+    /// Draw a laser's beam across a band of rows, fading out as it goes
+    fn draw_beam(
+        &self,
+        context: graphics::Context,
+        g: &mut G2d,
+        (top, bottom): (usize, usize),
+        time: f64,
+    ) {
+        let mut color = BEAM_COLOR;
+        color[3] *= (1.0 - time / BEAM_DURATION).clamp(0.0, 1.0) as f32;
+        let width = (self.sand.dim().0 * SAND_SIZE) as f64;
+        let (y, height) = (
+            (top * SAND_SIZE) as f64,
+            ((bottom - top) * SAND_SIZE) as f64,
+        );
+        graphics::rectangle(color, [0.0, y, width, height], context.transform, g);
+    }
 
-            drawing::draw_filled_rect_mut(
-                buffer,
-                Rect::at((x * SAND_SIZE) as i32, (y * SAND_SIZE) as i32)
-                    .of_size(SAND_SIZE as u32, SAND_SIZE as u32),
-                color.pixel_color(),
+    /// Draw a bomb's blast as a circle that grows to its full reach while fading out
+    fn draw_blast(
+        &self,
+        context: graphics::Context,
+        g: &mut G2d,
+        (x, y): (usize, usize),
+        time: f64,
+    ) {
+        let progress = (time / BLAST_DURATION).clamp(0.0, 1.0);
+        let mut color = BLAST_COLOR;
+        color[3] *= (1.0 - progress) as f32;
+        let radius = (BOMB_RADIUS * SAND_SIZE) as f64 * progress;
+        let (x, y) = ((x * SAND_SIZE) as f64, (y * SAND_SIZE) as f64);
+        graphics::ellipse(
+            color,
+            graphics::ellipse::circle(x, y, radius),
+            context.transform,
+            g,
+        );
+    }
+
+    /// Draw the sand covering the dashboard at the start of a game
+    fn draw_curtain(&self, context: graphics::Context, g: &mut G2d) {
+        let Some(curtain) = self.transition.as_ref().and_then(Transition::curtain) else {
+            return;
+        };
+        let context = context.trans(self.layout.view_size().0 as f64, 0.0);
+        let size = SAND_SIZE as f64;
+        for ((x, y), color) in curtain
+            .indexed_iter()
+            .filter_map(|(pos, color)| color.map(|c| (pos, c)))
+        {
+            graphics::rectangle(
+                color.float_color(),
+                [x as f64 * size, y as f64 * size, size, size],
+                context.transform,
+                g,
             );
         }
-        self.canvas.render(context, g);
+    }
 
-        if let Some(block) = self.falling_block {
-            block.render(context, g);
+    /// Draw the edges of a pile of grains, wherever a grain in it has no neighbor in it
+    fn draw_pile_outline(pile: &HashSet<(usize, usize)>, context: graphics::Context, g: &mut G2d) {
+        let size = SAND_SIZE as f64;
+        let width = HINT_BORDER_WIDTH;
+        for &(x, y) in pile {
+            let (left, top) = ((x * SAND_SIZE) as f64, (y * SAND_SIZE) as f64);
+            let outside = |dx: isize, dy: isize| {
+                x.checked_add_signed(dx)
+                    .zip(y.checked_add_signed(dy))
+                    .is_none_or(|neighbor| !pile.contains(&neighbor))
+            };
+            let edges = [
+                (outside(-1, 0), [left, top, width, size]),
+                (outside(1, 0), [left + size - width, top, width, size]),
+                (outside(0, -1), [left, top, size, width]),
+                (outside(0, 1), [left, top + size - width, size, width]),
+            ];
+            for (_, rect) in edges.into_iter().filter(|(edge, _)| *edge) {
+                graphics::rectangle(PILE_PREVIEW_COLOR, rect, context.transform, g);
+            }
+        }
+    }
+
+    /// Draw the text and menus that go over the board
+    fn draw_overlays(&mut self, context: graphics::Context, g: &mut G2d) -> Result<(), Error> {
+        if let Some(tutorial) = &self.tutorial {
+            let lines = tutorial.step().text;
+            self.draw_text_lines(context, g, lines, TUTORIAL_TEXT_Y, TUTORIAL_TEXT_SCALE)?;
         }
 
-        self.draw_dashboard(context, g);
+        if self.play_mode == PlayMode::Menu {
+            self.draw_menu(context, g)?;
+        }
 
-        // Render paused text
-        if self.play_mode == PlayMode::Paused {
-            let texture = self.text_textures.texture("PAUSED", 6, TEXT_COLOR).unwrap();
-            graphics::image(
-                texture,
+        if self.play_mode == PlayMode::Palette {
+            self.draw_palette(context, g)?;
+        }
+
+        if self.play_mode == PlayMode::Keys {
+            self.draw_keys(context, g)?;
+        }
+
+        if self.play_mode == PlayMode::Rules {
+            self.draw_rules(context, g)?;
+        }
+
+        if self.play_mode == PlayMode::Records {
+            self.draw_records(context, g)?;
+        }
+
+        // Render paused text, or the quit prompt
+        if self.play_mode == PlayMode::Paused && self.confirm_quit {
+            self.draw_message(context, g, "QUIT", "PRESS ENTER TO QUIT")?;
+        } else if self.play_mode == PlayMode::Paused && self.away {
+            let (width, height) = self.layout.window_size();
+            graphics::rectangle(
+                IDLE_DIM_COLOR,
+                [0.0, 0.0, width as f64, height as f64],
+                context.transform,
+                g,
+            );
+            let lines = ["PAUSED", "PRESS ANY KEY"];
+            let y =
+                self.layout.view_size().1 / 2 - TextTextures::line_height(IDLE_TEXT_SCALE) as u32;
+            self.draw_text_lines(context, g, &lines, y, IDLE_TEXT_SCALE)?;
+        } else if self.play_mode == PlayMode::Paused {
+            let texture = self.text_textures.texture("PAUSED", 6, TEXT_COLOR)?;
+            texture.draw(
                 Self::center_texture(
-                    (self.sand.dim().0 * SAND_SIZE) as u32,
-                    (self.sand.dim().1 * SAND_SIZE) as u32,
+                    self.layout.view_size().0,
+                    self.layout.view_size().1,
                     context,
                     texture,
                 )
@@ -523,57 +3926,197 @@ impl Game {
 
         // Render game over text
         if self.play_mode == PlayMode::GameOver {
-            let texture = self
-                .text_textures
-                .texture("GAME OVER", 6, TEXT_COLOR)
-                .unwrap();
-            graphics::image(
-                texture,
-                Self::center_texture(
-                    (self.sand.dim().0 * SAND_SIZE) as u32,
-                    (self.sand.dim().1 * SAND_SIZE) as u32,
-                    context,
-                    texture,
-                )
-                .trans(0.0, texture.get_height() as f64 / (-7.0 / 4.0))
-                .transform,
+            if let Some(page) = self.analysis_page {
+                self.draw_analysis(context, g, page)?;
+            } else {
+                let title = match self.finished {
+                    true => "FINISHED",
+                    false => "GAME OVER",
+                };
+                self.draw_message(context, g, title, "PRESS R TO RESTART")?;
+                let mut lines = Vec::new();
+                if let Some(goal) = self.goal.as_ref().filter(|_| self.finished) {
+                    lines.push(format!("TIME {:.0}S", goal.elapsed()));
+                }
+                if self.mode == GameMode::HotSeat {
+                    let [first, second] = self.player_scores;
+                    lines.push(match first.cmp(&second) {
+                        Ordering::Greater => "PLAYER 1 WINS".to_string(),
+                        Ordering::Less => "PLAYER 2 WINS".to_string(),
+                        Ordering::Equal => "TIE GAME".to_string(),
+                    });
+                }
+                if let Some(grade) = self.grade {
+                    lines.push(format!("GRADE {grade}"));
+                    lines.extend(self.best_grade.map(|best| format!("BEST {best}")));
+                }
+                if self.assisted {
+                    lines.push("ASSISTED".to_string());
+                }
+                lines.push("ENTER FOR ANALYSIS".to_string());
+                if !self.timelapse.is_empty() {
+                    lines.push("S SAVES TIME LAPSE".to_string());
+                }
+                self.draw_text_lines(context, g, &lines, RESULT_GRADE_Y, 3)?;
+            }
+        }
+
+        if self.play_mode == PlayMode::MissionComplete {
+            self.draw_message(context, g, "COMPLETE", "PRESS R TO CONTINUE")?;
+        }
+
+        // Toasts go over everything else, menus included
+        self.draw_toast(context, g)
+    }
+
+    fn draw_toast(&mut self, context: graphics::Context, g: &mut G2d) -> Result<(), Error> {
+        let Some(toast) = self.toasts.current() else {
+            return Ok(());
+        };
+        let color = match toast.kind {
+            ToastKind::Info => TEXT_COLOR,
+            ToastKind::Error => TOAST_ERROR_COLOR,
+        };
+        let mut y = TOAST_Y as f64 + toast.offset;
+        for line in toast.lines.iter().filter(|line| !line.is_empty()) {
+            let texture = self.text_textures.texture_with_background(
+                line,
+                TOAST_SCALE,
+                color,
+                UI_ELEMENT_BG_COLOR,
+            )?;
+            texture.draw_tinted(
+                [1.0, 1.0, 1.0, toast.opacity],
+                &context.draw_state,
+                Self::center_texture_x(self.layout.view_size().0, context, texture)
+                    .trans(0.0, y)
+                    .transform,
                 g,
             );
-            let restart_texture = self
-                .text_textures
-                .texture("PRESS R TO RESTART", 3, TEXT_COLOR)
-                .unwrap();
-            graphics::image(
-                restart_texture,
-                Self::center_texture(
-                    (self.sand.dim().0 * SAND_SIZE) as u32,
-                    (self.sand.dim().1 * SAND_SIZE) as u32,
-                    context,
-                    restart_texture,
-                )
-                .trans(0.0, restart_texture.get_height() as f64 / (7.0 / 4.0))
-                .transform,
-                g,
-            )
+            y += TextTextures::line_height(TOAST_SCALE);
+        }
+        Ok(())
+    }
+
+    /// Draw a page of the analysis of the run over the board, with the run's stats at the top
+    fn draw_analysis(
+        &mut self,
+        context: graphics::Context,
+        g: &mut G2d,
+        page: AnalysisPage,
+    ) -> Result<(), Error> {
+        let size = BLOCK_SIZE as f64;
+        let board_context = self.camera.apply(context);
+        if let (Some(color), Some(heatmap)) = (page.color(), self.analysis.heatmap(page)) {
+            for ((x, y), heat) in heatmap.indexed_iter() {
+                if *heat > 0.0 {
+                    graphics::rectangle(
+                        [color[0], color[1], color[2], color[3] * (0.2 + 0.8 * heat)],
+                        [x as f64 * size, y as f64 * size, size, size],
+                        board_context.transform,
+                        g,
+                    );
+                }
+            }
+        }
+
+        let mut lines = vec![page.title().to_string()];
+        lines.extend(self.analysis.stats(self.score, self.elapsed_time));
+        lines.push("ENTER FOR MORE".to_string());
+        self.draw_text_lines(context, g, &lines, ANALYSIS_TEXT_Y, ANALYSIS_TEXT_SCALE)?;
+
+        if page == AnalysisPage::Pieces {
+            // The shapes go under the stats, across the middle of the board
+            let (view_width, view_height) = self.layout.view_size();
+            let mut stack = Stack::new(
+                view_width as f64,
+                view_height as f64 / 2.0,
+                view_height as f64,
+                0.0,
+            );
+            let slot = stack.place_row(Self::piece_stats_height());
+            self.draw_piece_stats(context, g, slot)?;
+        }
+        Ok(())
+    }
+
+    /// Print an error to stderr the first time it happens, rather than on every frame
+    fn report_error(&mut self, error: Error) {
+        let message = error.to_string();
+        if !self.reported_errors.contains(&message) {
+            eprintln!("{message}");
+            self.reported_errors.insert(message);
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-struct Block {
-    x: usize,
-    y: usize,
-    shape: Shape,
-    color: Color,
+/// The sand the pile preview lets a piece's grains settle in, with the physics the game is using
+#[derive(Debug)]
+enum PileGrid {
+    Loose(Subgrid),
+    Sticky(Box<StickyGrid>),
+}
+
+impl PileGrid {
+    /// Let the grains settle for a while, and return where the piece landed along with where its
+    /// grains ended up
+    fn settle((landed, grid): (Block, Self)) -> PilePreview {
+        let pile = match grid {
+            Self::Loose(mut grid) => {
+                let mut rng = WyRand::new_seed(PILE_PREVIEW_SEED);
+                grid.simulate(&mut rng, PILE_PREVIEW_TICKS, PILE_PREVIEW_BUDGET);
+                grid.followed().collect()
+            }
+            Self::Sticky(mut grid) => {
+                grid.simulate(PILE_PREVIEW_TICKS, PILE_PREVIEW_BUDGET);
+                grid.followed().collect()
+            }
+        };
+        (landed, pile)
+    }
+}
+
+/// A piece, made of a shape and a color. Its position is the top left corner of the rotated
+/// shape, in sand pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Block {
+    pub x: usize,
+    pub y: usize,
+    /// How many times the shape is rotated clockwise
+    pub rotation: usize,
+    pub shape: Shape,
+    pub color: Color,
+    #[serde(default)]
+    pub powerup: Option<Powerup>,
 }
 
 impl Block {
+    fn new(shape: Shape, color: Color) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            rotation: 0,
+            shape,
+            color,
+            powerup: None,
+        }
+    }
+
     fn with_pos(mut self, x: usize, y: usize) -> Self {
         self.x = x;
         self.y = y;
         self
     }
 
+    fn with_rotation(mut self, rotation: usize) -> Self {
+        self.rotation = rotation % 4;
+        self
+    }
+
+    fn rotate(self) -> Self {
+        self.with_rotation(self.rotation + 1)
+    }
+
     fn inc_x(mut self) -> Self {
         self.x += 1;
         self
@@ -590,15 +4133,24 @@ impl Block {
     }
 
     pub fn coords(&self) -> impl Iterator<Item = (usize, usize)> {
-        self.shape.coords(self.x, self.y)
+        self.shape.coords(self.rotation, self.x, self.y)
+    }
+
+    /// Whether the block is inside the board and clear of the sand
+    fn fits(&self, sand: &Board) -> bool {
+        self.x + self.width() * SAND_BLOCK_SIZE <= sand.dim().0
+            && self.y + self.height() * SAND_BLOCK_SIZE <= sand.dim().1
+            && self
+                .coords()
+                .all(|(px, py)| sand.is_clear([px, py, SAND_BLOCK_SIZE, SAND_BLOCK_SIZE]))
     }
 
-    fn width(&self) -> usize {
-        self.shape.shape().dim().0
+    pub fn width(&self) -> usize {
+        self.shape.rotated(self.rotation).dim().0
     }
 
-    fn height(&self) -> usize {
-        self.shape.shape().dim().1
+    pub fn height(&self) -> usize {
+        self.shape.rotated(self.rotation).dim().1
     }
 
     fn render(&self, context: graphics::Context, g: &mut G2d) {
@@ -611,41 +4163,380 @@ impl Block {
                 context.transform,
                 g,
             );
+            self.render_powerup_mark([x, y], context, g);
         }
     }
 
     fn render_origin(&self, context: graphics::Context, g: &mut G2d) {
-        for (px, py) in self.shape.coords(0, 0) {
+        for (px, py) in self.shape.coords(self.rotation, 0, 0) {
+            let (x, y) = ((px * SAND_SIZE) as f64, (py * SAND_SIZE) as f64);
+            graphics::rectangle_from_to(
+                self.color.float_color(),
+                [x, y],
+                [x + BLOCK_SIZE as f64, y + BLOCK_SIZE as f64],
+                context.transform,
+                g,
+            );
+            self.render_powerup_mark([x, y], context, g);
+        }
+    }
+
+    /// Draw the piece at the origin as the grains of sand each of its cells turns into when it
+    /// lands, with every other grain shaded so they can be told apart
+    fn render_grains_origin(&self, context: graphics::Context, g: &mut G2d) {
+        let color = self.color.float_color();
+        let shaded = [
+            color[0] * SAND_PREVIEW_SHADE,
+            color[1] * SAND_PREVIEW_SHADE,
+            color[2] * SAND_PREVIEW_SHADE,
+            color[3],
+        ];
+        for (px, py) in self.shape.coords(self.rotation, 0, 0) {
+            for (gx, gy) in
+                (0..SAND_BLOCK_SIZE).flat_map(|gx| (0..SAND_BLOCK_SIZE).map(move |gy| (gx, gy)))
+            {
+                let color = if (gx + gy) % 2 == 0 { color } else { shaded };
+                let (x, y) = (
+                    ((px + gx) * SAND_SIZE) as f64,
+                    ((py + gy) * SAND_SIZE) as f64,
+                );
+                graphics::rectangle(
+                    color,
+                    [x, y, SAND_SIZE as f64, SAND_SIZE as f64],
+                    context.transform,
+                    g,
+                );
+            }
+        }
+    }
+
+    /// Draw just the edges of each cell, for marking a spot without covering the sand under it
+    fn render_outline(&self, color: [f32; 4], context: graphics::Context, g: &mut G2d) {
+        let border = graphics::Rectangle::new_border(color, HINT_BORDER_WIDTH);
+        for (px, py) in self.coords() {
             let (x, y) = ((px * SAND_SIZE) as f64, (py * SAND_SIZE) as f64);
-            graphics::rectangle_from_to(self.color.float_color(), [x, y], [x + BLOCK_SIZE as f64, y + BLOCK_SIZE as f64], context.transform, g);
+            let inset = HINT_BORDER_WIDTH;
+            let size = BLOCK_SIZE as f64 - inset * 2.0;
+            border.draw(
+                [x + inset, y + inset, size, size],
+                &context.draw_state,
+                context.transform,
+                g,
+            );
+        }
+    }
+
+    /// Powerups have a light square in the middle of each block, to tell them apart
+    fn render_powerup_mark(&self, [x, y]: [f64; 2], context: graphics::Context, g: &mut G2d) {
+        if self.powerup.is_some() {
+            let inset = BLOCK_SIZE as f64 / 3.0;
+            graphics::rectangle_from_to(
+                POWERUP_MARK_COLOR,
+                [x + inset, y + inset],
+                [x + BLOCK_SIZE as f64 - inset, y + BLOCK_SIZE as f64 - inset],
+                context.transform,
+                g,
+            );
         }
     }
 }
 
-impl<Generator: Rng<OUTPUT>, const OUTPUT: usize> RandomGen<Generator, OUTPUT> for Block {
-    fn random(rng: &mut Generator) -> Self {
+/// The upcoming pieces, with shapes chosen by a pluggable [`Randomizer`] and colors chosen by
+/// weight
+#[derive(Debug, Clone)]
+struct PieceQueue {
+    randomizer: Box<dyn Randomizer>,
+    color_weights: EnumMap<Color, u32>,
+    color_override: Option<Color>,
+    /// Pieces dealt before the randomizer takes over, for boards set up with a fixed start
+    preset: VecDeque<Block>,
+    next: Block,
+}
+
+impl PieceQueue {
+    fn new(settings: &Settings, color_override: Option<Color>, rng: &mut WyRand) -> Self {
+        let mut randomizer = settings.randomizer.build(settings.shape_weights());
+        let next = Self::generate(
+            randomizer.as_mut(),
+            &settings.color_weights,
+            color_override,
+            rng,
+        );
         Self {
-            x: 0,
-            y: 0,
-            shape: rng.generate(),
-            color: rng.generate(),
+            randomizer,
+            color_weights: settings.color_weights,
+            color_override,
+            preset: VecDeque::new(),
+            next,
+        }
+    }
+
+    fn generate(
+        randomizer: &mut dyn Randomizer,
+        color_weights: &EnumMap<Color, u32>,
+        color_override: Option<Color>,
+        rng: &mut WyRand,
+    ) -> Block {
+        Block::new(
+            randomizer.next_shape(rng),
+            color_override
+                .unwrap_or_else(|| Color::ALL[weighted_index(rng, color_weights.as_slice())]),
+        )
+    }
+
+    fn set_color_override(&mut self, color_override: Option<Color>) {
+        self.color_override = color_override;
+        if let Some(color) = color_override {
+            self.next.color = color;
+        }
+    }
+
+    /// Switch to a new randomizer and color weights, keeping the piece that's already shown as next
+    fn apply_settings(&mut self, settings: &Settings) {
+        self.randomizer = settings.randomizer.build(settings.shape_weights());
+        self.color_weights = settings.color_weights;
+    }
+
+    fn set_preset(&mut self, pieces: &[(Shape, Color)]) {
+        self.preset = pieces
+            .iter()
+            .map(|(shape, color)| Block::new(*shape, *color))
+            .collect();
+        if let Some(first) = self.preset.pop_front() {
+            self.next = first;
         }
     }
+
+    fn peek(&self) -> Block {
+        self.next
+    }
+
+    fn pop(&mut self, rng: &mut WyRand) -> Block {
+        let next = self.preset.pop_front().unwrap_or_else(|| {
+            Self::generate(
+                self.randomizer.as_mut(),
+                &self.color_weights,
+                self.color_override,
+                rng,
+            )
+        });
+        std::mem::replace(&mut self.next, next)
+    }
+}
+
+/// A piece queue that several boards deal from, so that each piece goes to whichever board spawns
+/// a piece first. Clones share the same queue.
+#[derive(Debug, Clone)]
+pub struct SharedQueue(Rc<RefCell<(PieceQueue, WyRand)>>);
+
+impl SharedQueue {
+    pub fn new(settings: &Settings, seed: u64) -> Self {
+        let mut rng = WyRand::new_seed(seed);
+        let queue = PieceQueue::new(settings, None, &mut rng);
+        Self(Rc::new(RefCell::new((queue, rng))))
+    }
+
+    pub fn peek(&self) -> Block {
+        self.0.borrow().0.peek()
+    }
+
+    pub fn pop(&self) -> Block {
+        let (queue, rng) = &mut *self.0.borrow_mut();
+        queue.pop(rng)
+    }
+}
+
+/// The simulation's state at one moment, from [`Game::save_state`]
+#[derive(Clone)]
+pub struct SimState {
+    rng: WyRand,
+    sand: Board,
+    animation: Option<(f64, Animation)>,
+    slow_motion: Option<SlowMotion>,
+    play_mode: PlayMode,
+    mode: GameMode,
+    modifiers: Modifiers,
+    gravity: f64,
+    score_gravity: f64,
+    mission: Option<MissionTracker>,
+    goal: Option<ObjectiveTracker>,
+    deadline: Option<f64>,
+    finished: bool,
+    elapsed_time: f64,
+    next_move: f64,
+    next_physics_update: f64,
+    next_rise: f64,
+    input: InputState,
+    repeat: RepeatScheduler,
+    queue_drop: bool,
+    locking: Option<Locking>,
+    drop_pressed_at: Option<f64>,
+    spawned_at: f64,
+    falling_block: Option<Block>,
+    piece_queue: PieceQueue,
+    held_block: Option<Block>,
+    hold_used: bool,
+    last_block_x: Option<usize>,
+    score: usize,
+    combo: usize,
+    combo_timer: f64,
+    chain: usize,
+    grader: Grader,
+    grade: Option<Grade>,
+    analysis: RunAnalysis,
+    turn: usize,
+    last_lock_player: usize,
+    player_scores: [usize; 2],
+    pieces_locked: usize,
+    powerup_earned: bool,
+    special_meter: SpecialMeter,
+    time_slowed: f64,
+    emitters: Vec<Emitter>,
+    /// The collapse after a game over changes the board, so it's part of the state too
+    transition: Option<Transition>,
 }
 
 #[derive(Debug, Clone)]
 enum Animation {
     RemoveLine {
-        flash_state: bool,
+        style: ClearAnimation,
         affected_pixels: Vec<(usize, usize)>,
+        /// How many separate groups spanned the board
+        groups: usize,
+        /// A path from wall to wall through each group
+        path: Vec<(usize, usize)>,
+        /// The hot seat player the clear is credited to, as of when it was found
+        player: usize,
+        /// How many clears the last lock had led to, counting this one, as of when it was found
+        chain: usize,
     },
+    /// A color clear powerup sweeping every grain of a color away, from the top down
+    Sweep {
+        color: Color,
+        /// Sorted from top to bottom
+        pixels: Vec<(usize, usize)>,
+    },
+    /// A laser powerup's beam across a band of rows, which clears every grain in it once the beam
+    /// fades
+    Beam {
+        /// The first row of the band and the one after the last
+        rows: (usize, usize),
+        pixels: Vec<(usize, usize)>,
+    },
+    /// A bomb powerup's blast spreading out from where it landed, which clears every grain it
+    /// reaches once it's done
+    Blast {
+        center: (usize, usize),
+        pixels: Vec<(usize, usize)>,
+    },
+}
+
+/// A piece that has landed but not locked yet, see [`Settings::lock_nudge`]
+#[derive(Debug, Clone, Copy)]
+struct Locking {
+    /// When the piece locks, in game time
+    deadline: f64,
+    /// Where the piece landed
+    x: usize,
+}
+
+/// Slow motion after a big clear, with the board zooming in on the clear and back out
+#[derive(Debug, Clone, Copy)]
+struct SlowMotion {
+    /// How much longer it lasts, in real seconds
+    remaining: f64,
+    /// The middle of the clear in pixels, which the board zooms in on
+    focus: [f64; 2],
+}
+
+impl SlowMotion {
+    fn new(affected_pixels: &[(usize, usize)]) -> Self {
+        let count = affected_pixels.len().max(1) as f64;
+        let (x, y) = affected_pixels.iter().fold((0.0, 0.0), |(x, y), (px, py)| {
+            (x + *px as f64, y + *py as f64)
+        });
+        let size = SAND_SIZE as f64;
+        Self {
+            remaining: SLOW_MOTION_DURATION,
+            focus: [(x / count + 0.5) * size, (y / count + 0.5) * size],
+        }
+    }
+
+    /// Zoom a context in on the clear, by an amount that swells and then eases back off as the
+    /// slow motion plays out
+    fn zoom_in(&self, context: graphics::Context) -> graphics::Context {
+        let progress = 1.0 - self.remaining / SLOW_MOTION_DURATION;
+        let zoom = 1.0 + SLOW_MOTION_ZOOM * (progress.clamp(0.0, 1.0) * PI).sin();
+        let [x, y] = self.focus;
+        context.trans(x, y).zoom(zoom).trans(-x, -y)
+    }
+}
+
+/// A mode's best grade, as shown on the records screen
+struct Record {
+    mode: GameMode,
+    grade: Grade,
+    /// The board the run ended on, if a picture of it was saved
+    thumbnail: Option<G2dTexture>,
+}
+
+/// An entry on the main menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MenuItem {
+    /// Pick up the run the last session left behind, see [`Game::enable_autosave`]
+    Recover,
+    Mode(GameMode),
+    /// A game against the bot on a board of its own, at the level picked with left and right
+    Versus(BotLevel),
+    /// The palette screen, for picking the colors of the sand
+    Palette,
+    /// The key binding screen, for choosing which keys do what
+    Keys,
+    /// The records screen, with the best grade in each mode
+    Records,
+}
+
+impl MenuItem {
+    /// Every item, in the order they're listed
+    fn all(bot_level: BotLevel) -> Vec<Self> {
+        GameMode::all()
+            .map(Self::Mode)
+            .chain([
+                Self::Versus(bot_level),
+                Self::Palette,
+                Self::Keys,
+                Self::Records,
+            ])
+            .collect()
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Self::Recover => "RECOVER LAST RUN".to_string(),
+            Self::Mode(mode) => mode.name(),
+            Self::Versus(level) => format!("VS BOT {}", level.name()),
+            Self::Palette => "COLORS".to_string(),
+            Self::Keys => "KEYS".to_string(),
+            Self::Records => "RECORDS".to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum PlayMode {
+    Menu,
+    /// The palette screen, which is opened from the menu
+    Palette,
+    /// The key binding screen, which is opened from the menu
+    Keys,
+    /// The rules card for a mode picked on the menu, shown before it starts
+    Rules,
+    /// The records screen, which is opened from the menu
+    Records,
     Playing,
     Paused,
     GameOver,
+    MissionComplete,
 }
 
 impl PlayMode {
@@ -654,6 +4545,12 @@ impl PlayMode {
             Self::Playing => Self::Paused,
             Self::Paused => Self::Playing,
             Self::GameOver => Self::GameOver,
+            Self::Menu => Self::Menu,
+            Self::Palette => Self::Palette,
+            Self::Keys => Self::Keys,
+            Self::Rules => Self::Rules,
+            Self::Records => Self::Records,
+            Self::MissionComplete => Self::MissionComplete,
         }
     }
 }
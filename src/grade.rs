@@ -0,0 +1,248 @@
+//! Grades for how well a run went, with thresholds for each mode loaded from a text file.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::board::Board;
+use crate::constants::{
+    GRADE_CHAIN_WEIGHT, GRADE_EFFICIENCY_UNIT, GRADE_PACE_UNIT, THUMBNAIL_HEIGHT,
+};
+use crate::disk;
+use crate::events::{GameEvent, Subscriber};
+use crate::mode::GameMode;
+use image::{imageops, Rgba, RgbaImage};
+use lazy_static::lazy_static;
+
+/// Where the best grade reached in each mode is saved
+pub const BEST_GRADES_FILE: &str = "sandtris_grades.txt";
+
+lazy_static! {
+    pub static ref GRADE_TABLES: Vec<GradeTable> =
+        parse_grades(include_str!("../assets/grades.txt")).unwrap();
+}
+
+/// A rank letter for a run, from worst to best
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Grade {
+    D,
+    C,
+    B,
+    A,
+    S,
+    GM,
+}
+
+impl Grade {
+    pub const ALL: [Self; 6] = [Self::D, Self::C, Self::B, Self::A, Self::S, Self::GM];
+}
+
+impl fmt::Display for Grade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl FromStr for Grade {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|grade| grade.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown grade: {s}"))
+    }
+}
+
+/// The thresholds for some modes, as the rating each grade needs
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradeTable {
+    /// Lowercase mode names
+    pub modes: Vec<String>,
+    pub thresholds: HashMap<Grade, f64>,
+}
+
+impl GradeTable {
+    /// The table for a mode, or `None` if the mode isn't graded
+    pub fn for_mode(mode: GameMode) -> Option<&'static Self> {
+        let name = mode.name().to_lowercase();
+        GRADE_TABLES
+            .iter()
+            .find(|table| table.modes.contains(&name))
+    }
+
+    /// The best grade a rating reaches
+    pub fn grade(&self, rating: f64) -> Grade {
+        Grade::ALL
+            .into_iter()
+            .rev()
+            .find(|grade| rating >= self.thresholds[grade])
+            .unwrap_or(Grade::D)
+    }
+}
+
+/// Parse a grades file, where each block of `key = value` lines lists some modes and a threshold
+/// for every grade, blocks are separated by blank lines, and lines starting with `;` are comments
+pub fn parse_grades(source: &str) -> Result<Vec<GradeTable>, String> {
+    let mut tables = Vec::new();
+    let mut table: Option<GradeTable> = None;
+    let mut finish = |table: Option<GradeTable>| -> Result<(), String> {
+        let Some(table) = table else {
+            return Ok(());
+        };
+        if table.modes.is_empty() {
+            return Err("grade thresholds without any modes".to_string());
+        }
+        let mut last = f64::NEG_INFINITY;
+        for grade in Grade::ALL {
+            let threshold = *table
+                .thresholds
+                .get(&grade)
+                .ok_or_else(|| format!("no threshold for {grade} in {}", table.modes[0]))?;
+            if threshold <= last {
+                return Err(format!(
+                    "the threshold for {grade} in {} has to be higher than the grade below",
+                    table.modes[0]
+                ));
+            }
+            last = threshold;
+        }
+        tables.push(table);
+        Ok(())
+    };
+
+    for (i, line) in source.lines().map(str::trim).enumerate() {
+        let error = |e: String| format!("line {}: {e}", i + 1);
+        if line.starts_with(';') {
+            continue;
+        }
+        if line.is_empty() {
+            finish(table.take())?;
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .map(|(k, v)| (k.trim(), v.trim()))
+            .ok_or_else(|| error("expected `key = value`".to_string()))?;
+        let table = table.get_or_insert_with(|| GradeTable {
+            modes: Vec::new(),
+            thresholds: HashMap::new(),
+        });
+        match key {
+            "modes" => {
+                table.modes = value
+                    .split(',')
+                    .map(|mode| mode.trim().to_lowercase())
+                    .collect()
+            }
+            grade => {
+                let grade = grade.parse().map_err(error)?;
+                let threshold = value
+                    .parse()
+                    .map_err(|_| error(format!("invalid threshold: {value}")))?;
+                table.thresholds.insert(grade, threshold);
+            }
+        }
+    }
+    finish(table)?;
+
+    if tables.is_empty() {
+        return Err("no grade thresholds".to_string());
+    }
+    Ok(tables)
+}
+
+/// Keeps track of what goes into a run's rating, other than the score and time
+#[derive(Debug, Clone, Default)]
+pub struct Grader {
+    pieces: usize,
+    best_chain: usize,
+}
+
+impl Grader {
+    /// How well a run is going, adding up the points scored per minute, the points per piece
+    /// locked and the longest combo or chain. The pace counts at least a minute, so that one early
+    /// clear doesn't top the charts.
+    pub fn rating(&self, score: usize, elapsed: f64) -> f64 {
+        let pace = score as f64 / (elapsed / 60.0).max(1.0);
+        let efficiency = score as f64 / self.pieces.max(1) as f64;
+        pace / GRADE_PACE_UNIT
+            + efficiency / GRADE_EFFICIENCY_UNIT
+            + self.best_chain as f64 * GRADE_CHAIN_WEIGHT
+    }
+}
+
+impl Subscriber for Grader {
+    fn on_event(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::PieceLocked { .. } => self.pieces += 1,
+            GameEvent::SandCleared { combo, .. } => self.best_chain = self.best_chain.max(*combo),
+            GameEvent::Chain { chain } => self.best_chain = self.best_chain.max(*chain),
+            _ => {}
+        }
+    }
+}
+
+/// The best grade reached in each mode, by lowercase mode name
+pub fn load_best_grades() -> HashMap<String, Grade> {
+    fs::read_to_string(BEST_GRADES_FILE)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let (mode, grade) = line.split_once('=')?;
+            Some((mode.trim().to_string(), grade.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// Save a grade reached in a mode if it beats the best one so far, along with a picture of the
+/// board the run ended on, and return the best one
+pub fn record_grade(mode: GameMode, grade: Grade, thumbnail: &RgbaImage) -> Grade {
+    let mut grades = load_best_grades();
+    let name = mode.name().to_lowercase();
+    if let Some(best) = grades.get(&name).filter(|best| **best >= grade) {
+        return *best;
+    }
+    let (thumbnail, path) = (thumbnail.clone(), thumbnail_path(&name));
+    disk::run(move || {
+        thumbnail
+            .save(&path)
+            .map_err(|e| format!("could not save {}: {e}", path.display()))
+    });
+    grades.insert(name, grade);
+    let mut lines = grades
+        .iter()
+        .map(|(mode, grade)| format!("{mode} = {grade}\n"))
+        .collect::<Vec<_>>();
+    lines.sort();
+    disk::write(BEST_GRADES_FILE, lines.concat());
+    grade
+}
+
+/// Where the picture of the final board of the best run in a mode is saved, by lowercase mode name
+pub fn thumbnail_path(mode: &str) -> PathBuf {
+    PathBuf::from(format!("sandtris_best_{}.png", mode.replace(' ', "_")))
+}
+
+/// The picture of the final board of the best run in a mode, if one was saved
+pub fn load_thumbnail(mode: &str) -> Option<RgbaImage> {
+    image::open(thumbnail_path(mode))
+        .ok()
+        .map(|image| image.to_rgba8())
+}
+
+/// A small picture of the sand on a board, [`THUMBNAIL_HEIGHT`] pixels tall
+pub fn board_thumbnail(sand: &Board) -> RgbaImage {
+    let (width, height) = sand.dim();
+    let board = RgbaImage::from_fn(width as u32, height as u32, |x, y| {
+        match sand.color((x as usize, y as usize)) {
+            Some(color) => color.pixel_color(),
+            None => Rgba([255, 255, 255, 255]),
+        }
+    });
+    let thumbnail_width = (width as u32 * THUMBNAIL_HEIGHT / height as u32).max(1);
+    imageops::thumbnail(&board, thumbnail_width, THUMBNAIL_HEIGHT)
+}
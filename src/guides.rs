@@ -0,0 +1,93 @@
+//! Guides drawn on the board under the sand, to help judge columns and heights on a board without
+//! cells: a frame around the edge, grid lines between blocks, where the next piece will spawn and
+//! how high the sand can pile up.
+
+use image::{Pixel, Rgba, RgbaImage};
+
+use crate::constants::{parse_hex_color, BLOCK_SIZE, GUIDE_FRAME_WIDTH, SAND_SIZE};
+
+/// The color of each guide, or `None` for guides that aren't drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Guides {
+    pub frame: Option<Rgba<u8>>,
+    pub grid: Option<Rgba<u8>>,
+    pub spawn_zone: Option<Rgba<u8>>,
+    pub kill_line: Option<Rgba<u8>>,
+}
+
+impl Guides {
+    /// Every guide, in colors faint enough to stay out of the way of the sand
+    pub fn standard() -> Self {
+        Self {
+            frame: Some(Rgba([89, 92, 102, 255])),
+            grid: Some(Rgba([0, 0, 0, 20])),
+            spawn_zone: Some(Rgba([255, 220, 0, 48])),
+            kill_line: Some(Rgba([220, 40, 40, 128])),
+        }
+    }
+
+    /// Draw the guides into an image of the board, before the sand goes on top of them.
+    /// `top` is the first row of pixels below the hidden rows, `spawn_zone` is where the next
+    /// piece will appear and `kill_line` is the row the sand mustn't reach, both in grains.
+    pub fn draw(
+        &self,
+        image: &mut RgbaImage,
+        top: u32,
+        spawn_zone: Option<[usize; 4]>,
+        kill_line: Option<usize>,
+    ) {
+        let (width, height) = image.dimensions();
+        if let (Some(color), Some([x, y, w, h])) = (self.spawn_zone, spawn_zone) {
+            let [x, y, w, h] = [x, y, w, h].map(|n| (n * SAND_SIZE) as u32);
+            blend_rect(image, [x, y, w, h], color);
+        }
+        if let Some(color) = self.grid {
+            let block = BLOCK_SIZE as u32;
+            for x in (block..width).step_by(BLOCK_SIZE) {
+                blend_rect(image, [x, 0, 1, height], color);
+            }
+            for y in (block..height).step_by(BLOCK_SIZE) {
+                blend_rect(image, [0, y, width, 1], color);
+            }
+        }
+        if let (Some(color), Some(row)) = (self.kill_line, kill_line) {
+            blend_rect(image, [0, (row * SAND_SIZE) as u32, width, 2], color);
+        }
+        if let Some(color) = self.frame {
+            let thickness = GUIDE_FRAME_WIDTH;
+            let visible = height - top;
+            blend_rect(image, [0, top, width, thickness], color);
+            blend_rect(
+                image,
+                [0, height.saturating_sub(thickness), width, thickness],
+                color,
+            );
+            blend_rect(image, [0, top, thickness, visible], color);
+            blend_rect(
+                image,
+                [width.saturating_sub(thickness), top, thickness, visible],
+                color,
+            );
+        }
+    }
+}
+
+/// Blend a color over a rectangle of an image, given as x, y, width and height. Anything past the
+/// edge of the image is left off.
+fn blend_rect(image: &mut RgbaImage, [x, y, width, height]: [u32; 4], color: Rgba<u8>) {
+    let right = (x + width).min(image.width());
+    let bottom = (y + height).min(image.height());
+    for py in y..bottom {
+        for px in x..right {
+            image.get_pixel_mut(px, py).blend(&color);
+        }
+    }
+}
+
+/// Parse a guide color like `#rrggbb` or `#rrggbbaa`, or `off` for a guide that isn't drawn
+pub fn parse_guide_color(s: &str) -> Result<Option<Rgba<u8>>, String> {
+    match s {
+        "off" => Ok(None),
+        s => parse_hex_color(s).map(Some),
+    }
+}
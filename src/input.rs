@@ -0,0 +1,296 @@
+//! Mapping keys and pointer gestures to game actions, and repeating held directions.
+
+use std::str::FromStr;
+
+use enum_map::{Enum, EnumMap};
+use piston_window::Key;
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{Direction, FIRST_INPUT_DELAY, INPUT_DELAY, SWIPE_DISTANCE};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum, Serialize, Deserialize)]
+pub enum Action {
+    Left,
+    Right,
+    Down,
+    Rotate,
+    Hold,
+    Drop,
+    Pause,
+    Restart,
+    Confirm,
+    Menu,
+    Undo,
+    Save,
+    Load,
+    /// Held to make the sand settle faster while no piece is falling
+    FastForward,
+    /// Set off the special effect once the special meter is full
+    Special,
+    /// Play one frame of the game while it's paused
+    Advance,
+}
+
+impl Action {
+    /// Every action, in the order they're listed on the key binding screen
+    pub const ALL: [Self; 16] = [
+        Self::Left,
+        Self::Right,
+        Self::Down,
+        Self::Rotate,
+        Self::Hold,
+        Self::Drop,
+        Self::Special,
+        Self::Pause,
+        Self::Restart,
+        Self::Confirm,
+        Self::Menu,
+        Self::Undo,
+        Self::Save,
+        Self::Load,
+        Self::FastForward,
+        Self::Advance,
+    ];
+
+    /// The name used for the action in the config file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Left => "left",
+            Self::Right => "right",
+            Self::Down => "down",
+            Self::Rotate => "rotate",
+            Self::Hold => "hold",
+            Self::Drop => "drop",
+            Self::Pause => "pause",
+            Self::Restart => "restart",
+            Self::Confirm => "confirm",
+            Self::Menu => "menu",
+            Self::Undo => "undo",
+            Self::Save => "save",
+            Self::Load => "load",
+            Self::FastForward => "fast_forward",
+            Self::Special => "special",
+            Self::Advance => "advance",
+        }
+    }
+
+    pub fn direction(&self) -> Option<Direction> {
+        match self {
+            Self::Left => Some(Direction::Left),
+            Self::Right => Some(Direction::Right),
+            Self::Down => Some(Direction::Down),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|action| action.name() == s)
+            .ok_or_else(|| format!("invalid action: {s}"))
+    }
+}
+
+/// Tracks which actions are currently held down, so that code outside of the event handler (like
+/// piece spawning) can react to held inputs rather than only to presses and releases
+#[derive(Debug, Clone, Default)]
+pub struct InputState {
+    held: EnumMap<Action, bool>,
+}
+
+/// Takes the next key pressed for itself, before the keymap turns it into an action, for the key
+/// binding screen and for waking the game up after it paused itself
+#[derive(Debug, Clone, Default)]
+pub struct KeyCapture {
+    waiting: bool,
+    /// Keys that were captured and are still held, whose releases are kept from the game too
+    held: Vec<Key>,
+}
+
+impl KeyCapture {
+    /// Capture the next key pressed
+    pub fn start(&mut self) {
+        self.waiting = true;
+    }
+
+    /// Stop waiting for a key, if it was
+    pub fn cancel(&mut self) {
+        self.waiting = false;
+    }
+
+    pub fn is_waiting(&self) -> bool {
+        self.waiting
+    }
+
+    /// A key was pressed. Returns it if it was captured, and so shouldn't do anything else.
+    pub fn press(&mut self, key: Key) -> Option<Key> {
+        if !self.waiting {
+            return None;
+        }
+        self.waiting = false;
+        self.held.push(key);
+        Some(key)
+    }
+
+    /// A key was released. Returns whether its press was captured, in which case the release is
+    /// too.
+    pub fn release(&mut self, key: Key) -> bool {
+        let captured = self.held.contains(&key);
+        self.held.retain(|held| *held != key);
+        captured
+    }
+}
+
+/// How long it's been since the player last pressed anything or moved the mouse, in real time
+#[derive(Debug, Clone, Default)]
+pub struct IdleTimer {
+    idle: f64,
+}
+
+impl IdleTimer {
+    /// The player did something
+    pub fn input(&mut self) {
+        self.idle = 0.0;
+    }
+
+    pub fn tick(&mut self, dt: f64) {
+        self.idle += dt;
+    }
+
+    /// How long there's been no input, in seconds
+    pub fn idle_for(&self) -> f64 {
+        self.idle
+    }
+}
+
+impl InputState {
+    pub fn press(&mut self, action: Action) {
+        self.held[action] = true;
+    }
+
+    pub fn release(&mut self, action: Action) {
+        self.held[action] = false;
+    }
+
+    pub fn is_held(&self, action: Action) -> bool {
+        self.held[action]
+    }
+}
+
+/// Schedules auto-repeat for held movement keys. It runs on its own clock rather than the game's,
+/// which stops during pauses and animations, so that held keys repeat at the same rate no matter
+/// what the game or the framerate is doing.
+#[derive(Debug, Clone, Default)]
+pub struct RepeatScheduler {
+    clock: f64,
+    /// When each held direction next repeats
+    next: EnumMap<Direction, Option<f64>>,
+}
+
+impl RepeatScheduler {
+    pub fn press(&mut self, direction: Direction) {
+        self.next[direction] = Some(self.clock + FIRST_INPUT_DELAY);
+    }
+
+    pub fn release(&mut self, direction: Direction) {
+        self.next[direction] = None;
+    }
+
+    pub fn is_held(&self, direction: Direction) -> bool {
+        self.next[direction].is_some()
+    }
+
+    pub fn tick(&mut self, dt: f64) {
+        self.clock += dt;
+    }
+
+    /// Take the repeats that are due, as how many times each direction repeated since the last
+    /// call. A slow frame gets several repeats rather than slowing down movement.
+    pub fn take_due(&mut self) -> EnumMap<Direction, usize> {
+        let clock = self.clock;
+        EnumMap::from_fn(|direction| {
+            let Some(next) = &mut self.next[direction] else {
+                return 0;
+            };
+            let mut count = 0;
+            while *next <= clock {
+                *next += INPUT_DELAY;
+                count += 1;
+            }
+            count
+        })
+    }
+
+    /// Push back repeats that are overdue because nothing took them, like while the game is paused,
+    /// so that they don't all fire at once when it resumes
+    pub fn hold_overdue(&mut self) {
+        for next in self.next.values_mut().flatten() {
+            *next = next.max(self.clock);
+        }
+    }
+}
+
+/// How the falling piece can be controlled, on top of the keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlScheme {
+    Keyboard,
+    /// The piece follows the mouse or a finger horizontally, a click or tap rotates it and a swipe
+    /// down drops it
+    Pointer,
+}
+
+impl FromStr for ControlScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keyboard" => Ok(Self::Keyboard),
+            "mouse" | "touch" | "pointer" => Ok(Self::Pointer),
+            other => Err(format!("invalid control scheme: {other}")),
+        }
+    }
+}
+
+/// What a press and release of the mouse or a touch amounted to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gesture {
+    Tap,
+    SwipeDown,
+}
+
+/// Tracks the mouse or a touch for the pointer control scheme, in window coordinates
+#[derive(Debug, Default)]
+pub struct Pointer {
+    pub position: Option<[f64; 2]>,
+    /// Where the current press or touch started
+    pressed_at: Option<[f64; 2]>,
+}
+
+impl Pointer {
+    pub fn move_to(&mut self, position: [f64; 2]) {
+        self.position = Some(position);
+    }
+
+    pub fn press(&mut self) {
+        self.pressed_at = self.position;
+    }
+
+    pub fn cancel(&mut self) {
+        self.pressed_at = None;
+    }
+
+    pub fn release(&mut self) -> Option<Gesture> {
+        let [start_x, start_y] = self.pressed_at.take()?;
+        let [x, y] = self.position?;
+        let (dx, dy) = (x - start_x, y - start_y);
+        if dy >= SWIPE_DISTANCE && dy > dx.abs() {
+            Some(Gesture::SwipeDown)
+        } else {
+            Some(Gesture::Tap)
+        }
+    }
+}
@@ -0,0 +1,430 @@
+//! Which keys do what. A keymap starts from one of the built-in presets, and any action can then be
+//! rebound to one or more keys of its own, in the config file or on the key binding screen.
+
+use std::str::FromStr;
+
+use enum_map::EnumMap;
+use piston_window::Key;
+
+use crate::disk;
+use crate::input::Action;
+
+/// Where keys rebound on the key binding screen are saved, as `bind_` lines like in the config
+/// file. It's read after the config file, so it only lists the actions whose keys differ from
+/// there.
+pub const KEYS_FILE: &str = "sandtris_keys.cfg";
+
+/// The built-in key layouts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyPreset {
+    /// The arrow keys, with letters for everything else
+    Standard,
+    /// W, A, S and D to move and rotate, with the arrow keys still working too
+    Wasd,
+    /// Everything within reach of the left hand, for playing with the other hand on the mouse or
+    /// not at all
+    LeftHand,
+    /// The number pad, for keyboards that have one
+    Numpad,
+    /// H, J, K and L like in vi, for keyboards without a separate arrow cluster
+    Vi,
+}
+
+impl FromStr for KeyPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "standard" | "arrows" => Ok(Self::Standard),
+            "wasd" => Ok(Self::Wasd),
+            "left_hand" | "one_hand" => Ok(Self::LeftHand),
+            "numpad" => Ok(Self::Numpad),
+            "vi" => Ok(Self::Vi),
+            other => Err(format!("invalid key preset: {other}")),
+        }
+    }
+}
+
+/// The keys bound to each action. An action can have any number of keys, but each key only does
+/// one thing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: EnumMap<Action, Vec<Key>>,
+}
+
+impl Keymap {
+    pub fn preset(preset: KeyPreset) -> Self {
+        use Action::*;
+        let bindings: &[(Action, &[Key])] = match preset {
+            KeyPreset::Standard => &[
+                (Left, &[Key::Left]),
+                (Right, &[Key::Right]),
+                (Down, &[Key::Down]),
+                (Rotate, &[Key::Up, Key::X]),
+                (Hold, &[Key::C, Key::LShift]),
+                (Drop, &[Key::Space]),
+                (Pause, &[Key::P, Key::Escape]),
+                (Restart, &[Key::R]),
+                (Confirm, &[Key::Return]),
+                (Menu, &[Key::M]),
+                (Undo, &[Key::U]),
+                (Save, &[Key::S]),
+                (Load, &[Key::L]),
+                (FastForward, &[Key::F]),
+                (Special, &[Key::V]),
+                (Advance, &[Key::Period]),
+            ],
+            KeyPreset::Wasd => &[
+                (Left, &[Key::A, Key::Left]),
+                (Right, &[Key::D, Key::Right]),
+                (Down, &[Key::S, Key::Down]),
+                (Rotate, &[Key::W, Key::Up]),
+                (Hold, &[Key::Q, Key::LShift]),
+                (Drop, &[Key::Space]),
+                (Pause, &[Key::P, Key::Escape]),
+                (Restart, &[Key::R]),
+                (Confirm, &[Key::Return, Key::E]),
+                (Menu, &[Key::M]),
+                (Undo, &[Key::U, Key::Z]),
+                (Save, &[Key::F5]),
+                (Load, &[Key::F9]),
+                (FastForward, &[Key::F]),
+                (Special, &[Key::X]),
+                (Advance, &[Key::Period]),
+            ],
+            KeyPreset::LeftHand => &[
+                (Left, &[Key::A]),
+                (Right, &[Key::D]),
+                (Down, &[Key::S]),
+                (Rotate, &[Key::W]),
+                (Hold, &[Key::Q, Key::LShift]),
+                (Drop, &[Key::Space]),
+                (Pause, &[Key::Escape, Key::Tab]),
+                (Restart, &[Key::R]),
+                (Confirm, &[Key::E]),
+                (Menu, &[Key::Backquote]),
+                (Undo, &[Key::Z]),
+                (Save, &[Key::F1]),
+                (Load, &[Key::F2]),
+                (FastForward, &[Key::F]),
+                (Special, &[Key::X]),
+                (Advance, &[Key::G]),
+            ],
+            KeyPreset::Numpad => &[
+                (Left, &[Key::NumPad4]),
+                (Right, &[Key::NumPad6]),
+                (Down, &[Key::NumPad5, Key::NumPad2]),
+                (Rotate, &[Key::NumPad8]),
+                (Hold, &[Key::NumPad0]),
+                (Drop, &[Key::NumPadPlus]),
+                (Pause, &[Key::NumPadMultiply, Key::Escape]),
+                (Restart, &[Key::NumPadMinus]),
+                (Confirm, &[Key::NumPadEnter, Key::Return]),
+                (Menu, &[Key::NumPadDivide]),
+                (Undo, &[Key::NumPadPeriod]),
+                (Save, &[Key::F5]),
+                (Load, &[Key::F9]),
+                (FastForward, &[Key::NumPad3]),
+                (Special, &[Key::NumPad1]),
+                (Advance, &[Key::NumPad9]),
+            ],
+            KeyPreset::Vi => &[
+                (Left, &[Key::H]),
+                (Right, &[Key::L]),
+                (Down, &[Key::J]),
+                (Rotate, &[Key::K]),
+                (Hold, &[Key::I]),
+                (Drop, &[Key::Space]),
+                (Pause, &[Key::P, Key::Escape]),
+                (Restart, &[Key::R]),
+                (Confirm, &[Key::Return]),
+                (Menu, &[Key::M]),
+                (Undo, &[Key::U]),
+                (Save, &[Key::F5]),
+                (Load, &[Key::F9]),
+                (FastForward, &[Key::F]),
+                (Special, &[Key::O]),
+                (Advance, &[Key::Period]),
+            ],
+        };
+        let mut keymap = Self {
+            bindings: EnumMap::default(),
+        };
+        for (action, keys) in bindings {
+            keymap.bindings[*action] = keys.to_vec();
+        }
+        keymap
+    }
+
+    /// What a key does, if anything
+    pub fn action(&self, key: Key) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(_, keys)| keys.contains(&key))
+            .map(|(action, _)| action)
+    }
+
+    pub fn keys(&self, action: Action) -> &[Key] {
+        &self.bindings[action]
+    }
+
+    /// A line saying what to press on a screen, like `RETURN BIND  U RESET`, naming the first key
+    /// bound to each action. Actions without a key are left out.
+    pub fn hint(&self, hints: &[(Action, &str)]) -> String {
+        hints
+            .iter()
+            .filter_map(|(action, does)| {
+                let key = self.keys(*action).iter().find_map(|key| key_name(*key))?;
+                Some(format!("{} {does}", key.to_uppercase().replace('_', " ")))
+            })
+            .collect::<Vec<_>>()
+            .join("  ")
+    }
+
+    /// Replace the keys bound to an action. The keys are taken away from any other action they
+    /// were bound to.
+    pub fn bind(&mut self, action: Action, keys: Vec<Key>) {
+        for bound in self.bindings.values_mut() {
+            bound.retain(|key| !keys.contains(key));
+        }
+        self.bindings[action] = keys;
+    }
+
+    /// The `bind_` lines that turn `base` into this keymap, one for each action whose keys differ
+    pub fn to_config(&self, base: &Keymap) -> String {
+        Action::ALL
+            .into_iter()
+            .filter(|action| self.keys(*action) != base.keys(*action))
+            .map(|action| {
+                let keys = match self.keys(action) {
+                    [] => "none".to_string(),
+                    keys => keys
+                        .iter()
+                        .filter_map(|key| key_name(*key))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                };
+                format!("bind_{} = {keys}\n", action.name())
+            })
+            .collect()
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::preset(KeyPreset::Standard)
+    }
+}
+
+/// Save the keys that differ from `base` to [`KEYS_FILE`]
+pub fn save_keymap(keymap: &Keymap, base: &Keymap) -> Result<(), String> {
+    let source = format!(
+        "# Keys rebound on the key binding screen, which take the place of the config file's\n{}",
+        keymap.to_config(base)
+    );
+    disk::write(KEYS_FILE, source);
+    Ok(())
+}
+
+/// The key binding screen. An action is picked from the list and then given whichever key is
+/// pressed next, in place of the keys it had. Changes take effect and are saved straight away.
+#[derive(Debug, Clone)]
+pub struct KeymapEditor {
+    /// The keymap from the config file, which resetting goes back to
+    defaults: Keymap,
+    pub keymap: Keymap,
+    /// The selected action
+    pub action: Action,
+    /// A key that's already bound to another action, and that other action. Pressing it again
+    /// takes it.
+    pub conflict: Option<(Key, Action)>,
+}
+
+impl KeymapEditor {
+    pub fn new(keymap: Keymap, defaults: Keymap) -> Self {
+        Self {
+            defaults,
+            keymap,
+            action: Action::ALL[0],
+            conflict: None,
+        }
+    }
+
+    /// Select the next action down the list, or up it if `forward` isn't set
+    pub fn cycle_action(&mut self, forward: bool) {
+        let index = Action::ALL.iter().position(|a| *a == self.action).unwrap();
+        let count = Action::ALL.len();
+        let offset = if forward { 1 } else { count - 1 };
+        self.action = Action::ALL[(index + offset) % count];
+        self.conflict = None;
+    }
+
+    /// Bind a key to the selected action in place of its keys. A key that's bound to another
+    /// action has to be pressed twice to take it, and returns false the first time.
+    pub fn capture(&mut self, key: Key) -> Result<bool, String> {
+        if key_name(key).is_none() {
+            return Err("that key can't be bound".to_string());
+        }
+        let other = self
+            .keymap
+            .action(key)
+            .filter(|other| *other != self.action);
+        if let Some(other) = other {
+            if self.conflict != Some((key, other)) {
+                self.conflict = Some((key, other));
+                return Ok(false);
+            }
+        }
+        self.conflict = None;
+        self.assign(self.action, vec![key]);
+        Ok(true)
+    }
+
+    /// Put the selected action's keys back to how the config file has them
+    pub fn reset_action(&mut self) {
+        self.conflict = None;
+        self.assign(self.action, self.defaults.keys(self.action).to_vec());
+    }
+
+    /// Put every key back to how the config file has them
+    pub fn reset_all(&mut self) {
+        self.conflict = None;
+        self.keymap = self.defaults.clone();
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        save_keymap(&self.keymap, &self.defaults)
+    }
+
+    /// Bind keys to an action. An action the keys were all taken from is given the keys this one
+    /// had, so taking another action's only key swaps the two rather than leaving it with none.
+    fn assign(&mut self, action: Action, keys: Vec<Key>) {
+        let replaced = self.keymap.keys(action).to_vec();
+        let emptied = Action::ALL.into_iter().find(|other| {
+            let bound = self.keymap.keys(*other);
+            *other != action && !bound.is_empty() && bound.iter().all(|key| keys.contains(key))
+        });
+        let freed = replaced
+            .into_iter()
+            .filter(|key| !keys.contains(key))
+            .collect();
+        self.keymap.bind(action, keys);
+        if let Some(other) = emptied {
+            self.keymap.bind(other, freed);
+        }
+    }
+}
+
+/// Parse a comma separated list of key names, like `a, left, numpad4`, or `none` for no keys
+pub fn parse_keys(s: &str) -> Result<Vec<Key>, String> {
+    if s == "none" {
+        return Ok(Vec::new());
+    }
+    s.split(',').map(|name| parse_key(name.trim())).collect()
+}
+
+/// The name of each key that has one, other than letters, digits, F keys and number pad digits.
+/// Where a key has more than one name, the first is the one it's saved as.
+const KEY_NAMES: &[(&str, Key)] = &[
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("space", Key::Space),
+    ("return", Key::Return),
+    ("enter", Key::Return),
+    ("escape", Key::Escape),
+    ("esc", Key::Escape),
+    ("tab", Key::Tab),
+    ("backspace", Key::Backspace),
+    ("backquote", Key::Backquote),
+    ("comma", Key::Comma),
+    ("period", Key::Period),
+    ("slash", Key::Slash),
+    ("semicolon", Key::Semicolon),
+    ("lshift", Key::LShift),
+    ("rshift", Key::RShift),
+    ("lctrl", Key::LCtrl),
+    ("rctrl", Key::RCtrl),
+    ("lalt", Key::LAlt),
+    ("ralt", Key::RAlt),
+    ("numpad_plus", Key::NumPadPlus),
+    ("numpad_minus", Key::NumPadMinus),
+    ("numpad_multiply", Key::NumPadMultiply),
+    ("numpad_divide", Key::NumPadDivide),
+    ("numpad_enter", Key::NumPadEnter),
+    ("numpad_period", Key::NumPadPeriod),
+];
+
+/// The F keys, numbered from 1
+const F_KEYS: [Key; 13] = [
+    Key::Unknown,
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+    Key::F10,
+    Key::F11,
+    Key::F12,
+];
+
+const NUMPAD_KEYS: [Key; 10] = [
+    Key::NumPad0,
+    Key::NumPad1,
+    Key::NumPad2,
+    Key::NumPad3,
+    Key::NumPad4,
+    Key::NumPad5,
+    Key::NumPad6,
+    Key::NumPad7,
+    Key::NumPad8,
+    Key::NumPad9,
+];
+
+/// The name a key is saved as, which [`parse_key`] reads back, or `None` for keys that can't be
+/// bound
+pub fn key_name(key: Key) -> Option<String> {
+    if key == Key::Unknown {
+        return None;
+    }
+    let c = char::from_u32(u32::from(key)).unwrap_or_default();
+    if c.is_ascii_lowercase() || c.is_ascii_digit() {
+        return Some(c.to_string());
+    }
+    if let Some((name, _)) = KEY_NAMES.iter().find(|(_, named)| *named == key) {
+        return Some(name.to_string());
+    }
+    let numbered = |prefix: &str, keys: &[Key]| {
+        let n = keys.iter().position(|numbered| *numbered == key)?;
+        Some(format!("{prefix}{n}"))
+    };
+    numbered("numpad", &NUMPAD_KEYS).or_else(|| numbered("f", &F_KEYS))
+}
+
+/// Parse a key name: a letter or digit, or the name of a key like `space` or `f5`
+pub fn parse_key(name: &str) -> Result<Key, String> {
+    let name = name.to_lowercase();
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        // Letter and digit keys are numbered after their lowercase characters
+        if c.is_ascii_lowercase() || c.is_ascii_digit() {
+            return Ok(Key::from(c as u32));
+        }
+    }
+    if let Some((_, key)) = KEY_NAMES.iter().find(|(named, _)| *named == name) {
+        return Ok(*key);
+    }
+    let numbered = |prefix: &str, keys: &[Key]| {
+        let n: usize = name.strip_prefix(prefix)?.parse().ok()?;
+        keys.get(n).copied()
+    };
+    numbered("numpad", &NUMPAD_KEYS)
+        .or_else(|| numbered("f", &F_KEYS))
+        .filter(|key| *key != Key::Unknown)
+        .ok_or_else(|| format!("invalid key: {name}"))
+}
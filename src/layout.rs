@@ -0,0 +1,124 @@
+//! The board's size, and where the board and dashboard go in the window.
+
+use std::str::FromStr;
+
+use crate::constants::{
+    BLOCK_SIZE, DASHBOARD_HEIGHT, DASHBOARD_WIDTH, HIDDEN_ROWS, HIDDEN_ROWS_RANGE,
+    MAX_BOARD_BLOCKS, MAX_VIEW_SIZE, MIN_BOARD_BLOCKS, SAND_SIZE,
+};
+
+/// The board's size in blocks, and the window layout that follows from it. The board's view sits
+/// at the top left of the window with the dashboard to its right, and the window is always tall
+/// enough for the whole dashboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub columns: usize,
+    pub rows: usize,
+    /// How many more rows there are above the top of the view, which pieces spawn in
+    pub hidden_rows: usize,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            columns: 12,
+            rows: 18,
+            hidden_rows: HIDDEN_ROWS,
+        }
+    }
+}
+
+impl Layout {
+    pub fn new(columns: usize, rows: usize) -> Result<Self, String> {
+        let (min, max) = (MIN_BOARD_BLOCKS, MAX_BOARD_BLOCKS);
+        if !(min.0..=max.0).contains(&columns) || !(min.1..=max.1).contains(&rows) {
+            return Err(format!(
+                "the board has to be between {}x{} and {}x{} blocks, not {columns}x{rows}",
+                min.0, min.1, max.0, max.1
+            ));
+        }
+        Ok(Self {
+            columns,
+            rows,
+            hidden_rows: HIDDEN_ROWS,
+        })
+    }
+
+    /// The same board with a different number of hidden rows
+    pub fn with_hidden_rows(self, hidden_rows: usize) -> Result<Self, String> {
+        let (min, max) = HIDDEN_ROWS_RANGE;
+        if !(min..=max).contains(&hidden_rows) {
+            return Err(format!(
+                "the board has to have between {min} and {max} hidden rows, not {hidden_rows}"
+            ));
+        }
+        Ok(Self {
+            hidden_rows,
+            ..self
+        })
+    }
+
+    /// The size of the visible part of the board in pixels
+    pub fn board_size(&self) -> (usize, usize) {
+        (self.columns * BLOCK_SIZE, self.rows * BLOCK_SIZE)
+    }
+
+    /// The height of the rows hidden above the top of the view, in pixels
+    pub fn hidden_height(&self) -> usize {
+        self.hidden_rows * BLOCK_SIZE
+    }
+
+    /// The whole board's size in pixels, including the hidden rows
+    pub fn canvas_size(&self) -> (usize, usize) {
+        let (width, height) = self.board_size();
+        (width, height + self.hidden_height())
+    }
+
+    /// The whole board's size in grains of sand, including the hidden rows
+    pub fn sand_dims(&self) -> (usize, usize) {
+        let (width, height) = self.canvas_size();
+        (width / SAND_SIZE, height / SAND_SIZE)
+    }
+
+    /// How many rows of grains are hidden above the top of the view
+    pub fn hidden_sand_rows(&self) -> usize {
+        self.hidden_height() / SAND_SIZE
+    }
+
+    /// How much the board is scaled to fit in the window, which is only ever scaled down
+    pub fn fit_zoom(&self) -> f64 {
+        let (width, height) = self.board_size();
+        (MAX_VIEW_SIZE.0 as f64 / width as f64)
+            .min(MAX_VIEW_SIZE.1 as f64 / height as f64)
+            .min(1.0)
+    }
+
+    /// The size of the part of the window the board is drawn in, in window pixels
+    pub fn view_size(&self) -> (u32, u32) {
+        let (width, height) = self.board_size();
+        let zoom = self.fit_zoom();
+        (
+            (width as f64 * zoom).round() as u32,
+            (height as f64 * zoom).round() as u32,
+        )
+    }
+
+    pub fn window_size(&self) -> (u32, u32) {
+        let (width, height) = self.view_size();
+        (width + DASHBOARD_WIDTH, height.max(DASHBOARD_HEIGHT))
+    }
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    /// Parse a board size like `12x18`, in blocks, with the default number of hidden rows
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid board size: {s}");
+        let (columns, rows) = s.split_once('x').ok_or_else(invalid)?;
+        Self::new(
+            columns.trim().parse().map_err(|_| invalid())?,
+            rows.trim().parse().map_err(|_| invalid())?,
+        )
+    }
+}
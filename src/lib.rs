@@ -0,0 +1,75 @@
+//! Sandtris: falling blocks that crumble into sand when they land. A line of one color stretching
+//! from the left wall to the right wall is cleared.
+//!
+//! The game can be embedded or driven without a window through [`game::Game`]:
+//!
+//! ```
+//! use piston_window::UpdateArgs;
+//! use sandtris::game::Game;
+//! use sandtris::input::Action;
+//! use sandtris::mode::GameMode;
+//! use sandtris::settings::Settings;
+//!
+//! let mut game = Game::headless(Settings::default());
+//! game.start(GameMode::Marathon);
+//! game.press(Action::Left);
+//! game.release(Action::Left);
+//! for _ in 0..60 {
+//!     game.update(&UpdateArgs { dt: 1.0 / 60.0 });
+//! }
+//! assert!(!game.sand().is_empty() || game.falling_block().is_some());
+//! ```
+
+pub mod assets;
+pub mod board;
+pub mod bot;
+pub mod camera;
+pub mod constants;
+pub mod coop;
+pub mod desync;
+pub mod disk;
+pub mod encoding;
+pub mod error;
+pub mod events;
+pub mod game;
+pub mod grade;
+pub mod guides;
+pub mod input;
+pub mod keymap;
+pub mod layout;
+pub mod mission;
+pub mod mixing;
+pub mod mode;
+pub mod observer;
+pub mod palette;
+pub mod pathfinding;
+pub mod physics;
+pub mod powerup;
+pub mod quality;
+pub mod randomizer;
+pub mod replay;
+pub mod rng;
+pub mod rules;
+pub mod save;
+pub mod script;
+pub mod settings;
+pub mod special;
+pub mod stats;
+pub mod versus;
+
+mod analysis;
+mod autosave;
+mod background;
+mod canvas;
+mod compositor;
+mod dashboard;
+mod editor;
+mod effects;
+mod feed;
+mod split;
+mod stress;
+mod timelapse;
+mod toast;
+mod transition;
+mod tutorial;
+mod watcher;
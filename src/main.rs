@@ -1,31 +1,353 @@
-mod canvas;
-mod constants;
-mod game;
-mod physics;
-mod pathfinding;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 use piston_window::prelude::*;
+use winit::window::UserAttentionType;
 
-use crate::constants::WINDOW_SIZE;
+use sandtris::assets;
+use sandtris::constants;
+use sandtris::coop::Coop;
+use sandtris::error::Error;
+use sandtris::game;
+use sandtris::mode::GameMode;
+use sandtris::observer::Observer;
+use sandtris::replay::{self, InputSource, Replay, ReplayPlayer};
+use sandtris::script::{InputScript, ScriptPlayer};
+use sandtris::settings::Settings;
+use sandtris::stats;
+use sandtris::versus::Versus;
 
 fn main() {
     println!("Hello, world!");
 
+    let result = run();
+    // Anything still waiting to be saved is written before the process goes away
+    sandtris::disk::shutdown();
+    if let Err(e) = result {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Error> {
+    // `--watch FILE` plays back a replay instead of starting a game, `--script FILE` plays an input
+    // script the same way, `--verify-replay FILE` checks a replay's score without opening a
+    // window, `--export-stats FILE` writes the history of runs to a CSV or JSON file, `--stress`
+    // starts the physics stress test, `--coop` starts a two player game, and `--dev` turns on the
+    // developer hotkeys. Everything else is a setting.
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+    let mut path_arg = |name: &str| match args.iter().position(|arg| arg == name) {
+        Some(i) if i + 1 < args.len() => {
+            Ok(Some(PathBuf::from(args.drain(i..=i + 1).nth(1).unwrap())))
+        }
+        Some(_) => Err(Error::Config(format!("missing value for {name}"))),
+        None => Ok(None),
+    };
+    let watch = path_arg("--watch")?;
+    let script = path_arg("--script")?;
+    let verify = path_arg("--verify-replay")?;
+    let export_stats = path_arg("--export-stats")?;
+    let mut flag = |name: &str| match args.iter().position(|arg| arg == name) {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    };
+    let stress = flag("--stress");
+    let coop = flag("--coop");
+    let dev = flag("--dev");
+    let settings = Settings::load(args.into_iter()).map_err(Error::Config)?;
+    // Assets that can't be loaded fall back to the bundled ones, and the game starts anyway
+    let asset_problems = assets::load(&settings);
+    for problem in &asset_problems {
+        eprintln!("{problem}");
+    }
+    settings.validate().map_err(Error::Config)?;
+
+    if let Some(path) = export_stats {
+        let runs = stats::export(&path).map_err(Error::Stats)?;
+        println!("exported {runs} runs to {}", path.display());
+        return Ok(());
+    }
+
+    if let Some(path) = verify {
+        let replay = Replay::load(&path).map_err(Error::Replay)?;
+        let score = replay::verify(&replay, settings).map_err(Error::Verification)?;
+        println!("{} checks out with a score of {score}", path.display());
+        return Ok(());
+    }
+
     let opengl = OpenGL::V3_2;
-    // 12 * 18 blocks
-    let mut window: PistonWindow = WindowSettings::new("sandtris_rs", WINDOW_SIZE)
-        .exit_on_esc(true)
+    let mut window_size = if coop {
+        Coop::window_size(&settings)
+    } else {
+        settings.board.window_size()
+    };
+    let mut window: PistonWindow = WindowSettings::new(constants::WINDOW_TITLE, window_size)
+        // Closing is left to the game, which may want to confirm it and save first
+        .automatic_close(false)
         .graphics_api(opengl)
         .build()
-        .unwrap();
+        .map_err(|e| Error::Window(e.to_string()))?;
+
+    let mut pacing = FramePacing::default();
+    let mut status = WindowStatus::new(&window);
+
+    if coop {
+        let mut coop = Coop::new(&mut window, settings);
+        status.match_started(&window);
+        while let Some(e) = window.next() {
+            if e.close_args().is_some() {
+                break;
+            }
+            status.handle_event(&e);
+            status.set_title(&mut window, coop.window_title());
+            coop.handle_event(&e);
+            pacing.apply(&mut window, coop.max_fps(), coop.is_idle());
+            e.update(|args| coop.update(args));
+            window.draw_2d(&e, |c, g, _| {
+                coop.render(c, g);
+            });
+        }
+        return Ok(());
+    }
 
-    let mut game = game::Game::new(&mut window);
+    // A game played from a replay or a script is only watched
+    let source: Option<Box<dyn InputSource>> = match (watch, script) {
+        (Some(path), _) => {
+            let replay = Replay::load(&path).map_err(Error::Replay)?;
+            Some(Box::new(ReplayPlayer::new(replay)))
+        }
+        (None, Some(path)) => {
+            let script = InputScript::load(&path).map_err(Error::Script)?;
+            Some(Box::new(ScriptPlayer::new(script)))
+        }
+        (None, None) => None,
+    };
 
+    let mut game = game::Game::new(&mut window, settings.clone());
+    for problem in &asset_problems {
+        game.show_error(problem);
+    }
+    if stress {
+        game.start(GameMode::Stress);
+    } else if source.is_none() {
+        game.enable_autosave();
+        game.enable_idle_pause();
+    }
+
+    if let Some(source) = source {
+        pacing.apply(&mut window, game.max_fps(), false);
+        let mut observer = Observer::new(game, source);
+        window.set_size(observer.window_size());
+        while let Some(e) = window.next() {
+            if e.close_args().is_some() {
+                break;
+            }
+            status.set_title(&mut window, observer.window_title());
+            observer.handle_event(&e);
+            e.update(|args| observer.update(args));
+            window.draw_2d(&e, |c, g, _| {
+                observer.render(c, g);
+            });
+        }
+        return Ok(());
+    }
+
+    let mut versus: Option<Versus> = None;
+    let mut speed = SimSpeed::Normal;
     while let Some(e) = window.next() {
-        game.handle_event(&e);
-        e.update(|args| game.update(args));
+        if let Some(match_) = &mut versus {
+            if e.close_args().is_some() {
+                break;
+            }
+            status.handle_event(&e);
+            status.set_title(&mut window, match_.window_title());
+            match_.handle_event(&e);
+            pacing.apply(&mut window, match_.max_fps(), match_.is_idle());
+            e.update(|args| match_.update(args));
+            window.draw_2d(&e, |c, g, _| {
+                match_.render(c, g);
+            });
+            if match_.is_finished() {
+                versus = None;
+                window.set_size(game.window_size());
+            }
+            continue;
+        }
+
+        if e.close_args().is_some() {
+            game.request_quit();
+        }
+        if game.should_quit() {
+            break;
+        }
+        status.handle_event(&e);
+        status.set_title(&mut window, game.window_title());
+        // The developer hotkeys only take keys the game has no use for
+        let hotkey =
+            |key| dev && game.settings().keymap.action(key).is_none() && !game.is_capturing_key();
+        match e.press_args() {
+            Some(Button::Keyboard(key)) if hotkey(key) && SimSpeed::from_key(key).is_some() => {
+                speed = SimSpeed::from_key(key).unwrap();
+                game.show_toast(speed.name());
+            }
+            _ => game.handle_event(&e),
+        }
+        let max_fps = match speed {
+            SimSpeed::Max => None,
+            _ => game.max_fps(),
+        };
+        pacing.apply(&mut window, max_fps, game.is_idle());
+        e.update(|args| speed.update(&mut game, args));
         window.draw_2d(&e, |c, g, _| {
             game.render(c, g);
         });
+        // Modes with a board size of their own need the window sized for it
+        if game.window_size() != window_size {
+            window_size = game.window_size();
+            window.set_size(window_size);
+        }
+        // Versus needs room for two boards, so the window grows for it and shrinks back after
+        if let Some(level) = game.take_versus_request() {
+            // Keys may have been rebound in game since the settings were loaded
+            let settings = game.settings().clone();
+            window.set_size(Versus::window_size(&settings));
+            versus = Some(Versus::new(&mut window, settings, level));
+            status.match_started(&window);
+        }
+    }
+    game.shutdown();
+
+    Ok(())
+}
+
+/// How fast the simulation runs compared to real time, picked with the developer hotkeys for
+/// reproducing bugs that depend on timing. The hotkeys give way to any of their keys that are
+/// bound to an action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SimSpeed {
+    Tenth,
+    Normal,
+    Quadruple,
+    /// As many frames as fit between drawing the window, each as long as a frame advance
+    Max,
+}
+
+impl SimSpeed {
+    fn from_key(key: Key) -> Option<Self> {
+        match key {
+            Key::F6 => Some(Self::Tenth),
+            Key::F7 => Some(Self::Normal),
+            Key::F8 => Some(Self::Quadruple),
+            Key::F10 => Some(Self::Max),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Tenth => "TENTH SPEED",
+            Self::Normal => "NORMAL SPEED",
+            Self::Quadruple => "4X SPEED",
+            Self::Max => "MAX SPEED",
+        }
+    }
+
+    /// Run the game for one update of the event loop. Faster speeds run several updates of the
+    /// usual length rather than one long one, so the game steps the same way it would normally.
+    fn update(&self, game: &mut game::Game, args: &UpdateArgs) {
+        match self {
+            Self::Tenth => game.update(&UpdateArgs { dt: args.dt * 0.1 }),
+            Self::Normal => game.update(args),
+            Self::Quadruple => {
+                for _ in 0..4 {
+                    game.update(args);
+                }
+            }
+            Self::Max => {
+                let start = Instant::now();
+                while start.elapsed() < MAX_SPEED_BUDGET {
+                    game.update(&UpdateArgs {
+                        dt: constants::FRAME_STEP,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// How long max speed spends running the game before letting the window draw it
+const MAX_SPEED_BUDGET: Duration = Duration::from_millis(30);
+
+/// Keeps the window's title up to date with the game, and asks for the player's attention when a
+/// match starts while they're in another window
+struct WindowStatus {
+    focused: bool,
+    title: String,
+    last_change: Option<Instant>,
+}
+
+impl WindowStatus {
+    fn new(window: &PistonWindow) -> Self {
+        Self {
+            focused: window.window.window.has_focus(),
+            title: constants::WINDOW_TITLE.to_string(),
+            last_change: None,
+        }
+    }
+
+    fn handle_event(&mut self, e: &Event) {
+        if let Some(focused) = e.focus_args() {
+            self.focused = focused;
+        }
+    }
+
+    /// Change the title, unless it was changed too recently. Whatever's skipped is caught up on by
+    /// the next call once enough time has passed.
+    fn set_title(&mut self, window: &mut PistonWindow, title: String) {
+        let recent = self
+            .last_change
+            .is_some_and(|t| t.elapsed().as_secs_f64() < constants::TITLE_UPDATE_INTERVAL);
+        if title == self.title || recent {
+            return;
+        }
+        window.set_title(title.clone());
+        self.title = title;
+        self.last_change = Some(Instant::now());
+    }
+
+    /// Flash the window in the taskbar if the player isn't looking at it
+    fn match_started(&self, window: &PistonWindow) {
+        if !self.focused {
+            window
+                .window
+                .window
+                .request_user_attention(Some(UserAttentionType::Informational));
+        }
+    }
+}
+
+/// How often the event loop wakes up, changed only when the game asks for something different
+#[derive(Default)]
+struct FramePacing {
+    max_fps: Option<Option<u64>>,
+    lazy: bool,
+}
+
+impl FramePacing {
+    /// Cap the frame rate, and while the game is idle, wait for input instead of updating and
+    /// drawing it on a timer
+    fn apply(&mut self, window: &mut PistonWindow, max_fps: Option<u64>, idle: bool) {
+        if self.max_fps != Some(max_fps) {
+            // The event loop always has a cap, so uncapped is as high as it goes
+            window.set_max_fps(max_fps.unwrap_or(u64::MAX));
+            self.max_fps = Some(max_fps);
+        }
+        if self.lazy != idle {
+            window.set_lazy(idle);
+            self.lazy = idle;
+        }
     }
 }
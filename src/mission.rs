@@ -0,0 +1,313 @@
+//! Missions: short objectives with their own starting boards, loaded from a text file.
+
+use std::fs;
+
+use lazy_static::lazy_static;
+
+use crate::constants::{Color, Shape, MAX_BOARD_BLOCKS};
+use crate::disk;
+use crate::events::{GameEvent, Subscriber};
+use crate::mode::Modifiers;
+
+/// Where the number of completed missions is saved
+pub const MISSION_PROGRESS_FILE: &str = "sandtris_missions.txt";
+
+lazy_static! {
+    pub static ref MISSIONS: Vec<Mission> =
+        parse_missions(include_str!("../assets/missions.txt")).unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mission {
+    pub name: String,
+    pub objective: Objective,
+    /// How many times faster than normal pieces fall
+    pub gravity: f64,
+    pub modifiers: Modifiers,
+    /// The starting board, as rows of block-sized cells from top to bottom, sitting on the floor
+    pub board: Vec<Vec<Option<Color>>>,
+    /// Pieces dealt in order before the randomizer takes over
+    pub queue: Vec<(Shape, Color)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Objective {
+    /// Clear some number of groups, optionally only counting groups of one color
+    Clear {
+        count: usize,
+        color: Option<Color>,
+    },
+    /// Don't top out for this many seconds
+    Survive(f64),
+    /// Reach a combo of this length
+    Chain(usize),
+    Score(usize),
+}
+
+impl Objective {
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Clear { count, color: None } => format!("CLEAR {count}"),
+            Self::Clear {
+                count,
+                color: Some(color),
+            } => format!("CLEAR {count} {}", color.name()),
+            Self::Survive(seconds) => format!("SURVIVE {seconds:.0}S"),
+            Self::Chain(length) => format!("CHAIN {length}"),
+            Self::Score(points) => format!("SCORE {points}"),
+        }
+    }
+}
+
+/// Tracks how far the player is towards an objective
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectiveTracker {
+    pub objective: Objective,
+    clears: usize,
+    best_chain: usize,
+    started: f64,
+    survived: f64,
+    score: usize,
+}
+
+impl ObjectiveTracker {
+    pub fn new(objective: Objective, now: f64) -> Self {
+        Self {
+            objective,
+            clears: 0,
+            best_chain: 0,
+            started: now,
+            survived: 0.0,
+            score: 0,
+        }
+    }
+
+    fn record_clear(&mut self, color: Color, combo: usize) {
+        if let Objective::Clear {
+            color: Some(wanted),
+            ..
+        } = self.objective
+        {
+            if wanted != color {
+                return;
+            }
+        }
+        self.clears += 1;
+        self.best_chain = self.best_chain.max(combo);
+    }
+
+    pub fn record_time(&mut self, now: f64, score: usize) {
+        self.survived = now - self.started;
+        self.score = score;
+    }
+
+    /// How long it's been since the tracker started, as of the last [`Self::record_time`]
+    pub fn elapsed(&self) -> f64 {
+        self.survived
+    }
+
+    pub fn is_complete(&self) -> bool {
+        match self.objective {
+            Objective::Clear { count, .. } => self.clears >= count,
+            Objective::Survive(seconds) => self.survived >= seconds,
+            Objective::Chain(length) => self.best_chain >= length,
+            Objective::Score(points) => self.score >= points,
+        }
+    }
+
+    /// A short description of the progress so far, like `1 OF 3`
+    pub fn progress(&self) -> String {
+        match self.objective {
+            Objective::Clear { count, .. } => format!("{} OF {count}", self.clears.min(count)),
+            Objective::Survive(seconds) => {
+                format!("{:.0} OF {seconds:.0}", self.survived.min(seconds))
+            }
+            Objective::Chain(length) => format!("{} OF {length}", self.best_chain.min(length)),
+            Objective::Score(points) => format!("{} OF {points}", self.score.min(points)),
+        }
+    }
+}
+
+/// Tracks how far the player is towards completing a mission's objective
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissionTracker {
+    pub index: usize,
+    goal: ObjectiveTracker,
+}
+
+impl MissionTracker {
+    pub fn new(index: usize, now: f64) -> Self {
+        Self {
+            index,
+            goal: ObjectiveTracker::new(MISSIONS[index].objective, now),
+        }
+    }
+
+    pub fn mission(&self) -> &'static Mission {
+        &MISSIONS[self.index]
+    }
+
+    pub fn record_time(&mut self, now: f64, score: usize) {
+        self.goal.record_time(now, score);
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.goal.is_complete()
+    }
+
+    /// A short description of the progress so far, like `1 OF 3`
+    pub fn progress(&self) -> String {
+        self.goal.progress()
+    }
+}
+
+/// Load how many missions have been completed
+pub fn load_progress() -> usize {
+    fs::read_to_string(MISSION_PROGRESS_FILE)
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+pub fn save_progress(completed: usize) {
+    disk::write(MISSION_PROGRESS_FILE, completed.to_string());
+}
+
+/// Parse a missions file, where each mission is a block of `key = value` lines separated by blank
+/// lines, and lines starting with `;` are comments
+pub fn parse_missions(source: &str) -> Result<Vec<Mission>, String> {
+    let mut missions = Vec::new();
+    let mut mission: Option<Mission> = None;
+    for (i, line) in source.lines().map(str::trim).enumerate() {
+        let error = |e: String| format!("line {}: {e}", i + 1);
+        if line.starts_with(';') {
+            continue;
+        }
+        if line.is_empty() {
+            missions.extend(mission.take());
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .map(|(k, v)| (k.trim(), v.trim()))
+            .ok_or_else(|| error("expected `key = value`".to_string()))?;
+        let mission = mission.get_or_insert_with(|| Mission {
+            name: String::new(),
+            objective: Objective::Score(0),
+            gravity: 1.0,
+            modifiers: Modifiers::default(),
+            board: Vec::new(),
+            queue: Vec::new(),
+        });
+        match key {
+            "name" => mission.name = value.to_string(),
+            "goal" => mission.objective = parse_objective(value).map_err(error)?,
+            "gravity" => {
+                mission.gravity = value
+                    .parse()
+                    .ok()
+                    .filter(|g| *g > 0.0)
+                    .ok_or_else(|| error(format!("invalid gravity: {value}")))?
+            }
+            "modifiers" => mission.modifiers = value.parse().map_err(error)?,
+            "row" => mission.board.push(parse_row(value).map_err(error)?),
+            "queue" => mission.queue = parse_queue(value).map_err(error)?,
+            _ => return Err(error(format!("unknown key: {key}"))),
+        }
+    }
+    missions.extend(mission);
+
+    if missions.is_empty() {
+        return Err("no missions".to_string());
+    }
+    Ok(missions)
+}
+
+pub fn parse_objective(s: &str) -> Result<Objective, String> {
+    let invalid = || format!("invalid goal: {s}");
+    let words = s.split_whitespace().collect::<Vec<_>>();
+    match words.as_slice() {
+        ["clear", count] => Ok(Objective::Clear {
+            count: count.parse().map_err(|_| invalid())?,
+            color: None,
+        }),
+        ["clear", count, color] => Ok(Objective::Clear {
+            count: count.parse().map_err(|_| invalid())?,
+            color: Some(color.parse()?),
+        }),
+        ["survive", seconds] => Ok(Objective::Survive(seconds.parse().map_err(|_| invalid())?)),
+        ["chain", length] => Ok(Objective::Chain(length.parse().map_err(|_| invalid())?)),
+        ["score", points] => Ok(Objective::Score(points.parse().map_err(|_| invalid())?)),
+        _ => Err(invalid()),
+    }
+}
+
+pub fn parse_row(s: &str) -> Result<Vec<Option<Color>>, String> {
+    if s.len() > MAX_BOARD_BLOCKS.0 {
+        return Err(format!("row is wider than the widest board: {s}"));
+    }
+    s.chars()
+        .map(|c| match c {
+            'R' => Ok(Some(Color::Red)),
+            'Y' => Ok(Some(Color::Yellow)),
+            'B' => Ok(Some(Color::Blue)),
+            'G' => Ok(Some(Color::Green)),
+            '.' => Ok(None),
+            c => Err(format!("unexpected character {c:?} in row")),
+        })
+        .collect()
+}
+
+pub fn format_row(row: &[Option<Color>]) -> String {
+    row.iter()
+        .map(|cell| match cell {
+            Some(Color::Red) => 'R',
+            Some(Color::Yellow) => 'Y',
+            Some(Color::Blue) => 'B',
+            Some(Color::Green) => 'G',
+            None => '.',
+        })
+        .collect()
+}
+
+/// Parse a comma-separated list of `shape:color` pieces, where shapes are numbered from 1 in the
+/// order they appear in the shape pack
+pub fn parse_queue(s: &str) -> Result<Vec<(Shape, Color)>, String> {
+    s.split(',')
+        .map(|piece| {
+            let (shape, color) = piece
+                .trim()
+                .split_once(':')
+                .ok_or_else(|| format!("expected `shape:color`, got {piece:?}"))?;
+            let shape = shape
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| Shape::all().nth(n.checked_sub(1)?))
+                .ok_or_else(|| format!("invalid shape: {shape}"))?;
+            Ok((shape, color.parse()?))
+        })
+        .collect()
+}
+
+pub fn format_queue(queue: &[(Shape, Color)]) -> String {
+    queue
+        .iter()
+        .map(|(shape, color)| format!("{}:{}", shape.index() + 1, color.name().to_lowercase()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl Subscriber for MissionTracker {
+    fn on_event(&mut self, event: &GameEvent) {
+        self.goal.on_event(event);
+    }
+}
+
+impl Subscriber for ObjectiveTracker {
+    fn on_event(&mut self, event: &GameEvent) {
+        if let GameEvent::SandCleared { color, combo, .. } = event {
+            self.record_clear(*color, *combo);
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! The color mixing modifier: grains of two different colors that touch for long enough blend into
+//! a third color.
+
+use crate::board::Board;
+use crate::constants::{Color, MIX_DELAY};
+
+/// Pairs of colors that blend, and what they blend into. Either order of the pair blends the same.
+pub const MIXES: [(Color, Color, Color); 2] = [
+    (Color::Blue, Color::Yellow, Color::Green),
+    (Color::Red, Color::Green, Color::Yellow),
+];
+
+/// What two touching colors blend into, if they blend at all
+pub fn mix(a: Color, b: Color) -> Option<Color> {
+    MIXES
+        .iter()
+        .find(|(first, second, _)| (*first, *second) == (a, b) || (*first, *second) == (b, a))
+        .map(|(_, _, mixed)| *mixed)
+}
+
+/// Advance each grain's contact timer by `dt`, and blend the grains that have been touching a grain
+/// they mix with for long enough. The timers belong to cells rather than grains, so a grain that
+/// moves starts over, and only settled sand blends.
+pub fn run_mixing(sand: &mut Board, dt: f64) {
+    let (width, height) = sand.dim();
+    let mut blended = Vec::new();
+    for ((x, y), grain) in sand.grains().collect::<Vec<_>>() {
+        let neighbors = [
+            (x > 0).then(|| (x - 1, y)),
+            (x + 1 < width).then_some((x + 1, y)),
+            (y > 0).then(|| (x, y - 1)),
+            (y + 1 < height).then_some((x, y + 1)),
+        ];
+        let mixed = neighbors
+            .into_iter()
+            .flatten()
+            .find_map(|pos| sand.color(pos).and_then(|other| mix(grain.color, other)));
+        let timer = sand.mix_timer_mut((x, y));
+        match mixed {
+            Some(color) => {
+                *timer += dt;
+                if *timer >= MIX_DELAY {
+                    *timer = 0.0;
+                    blended.push(((x, y), color));
+                }
+            }
+            None => *timer = 0.0,
+        }
+    }
+    // Colors change only once every grain has been checked, so both grains of a pair blend together
+    for (pos, color) in blended {
+        sand.set_color(pos, color);
+    }
+}
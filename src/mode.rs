@@ -0,0 +1,511 @@
+//! Game modes and challenge modifiers. What each mode's rules are is loaded from a text file, so
+//! a new mode that's the standard game with different rules only takes a few lines there.
+
+use std::str::FromStr;
+
+use lazy_static::lazy_static;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::constants::{
+    Color, COMBO_WINDOW, COOP_RISE_RELIEF, MAX_SCORE_GRAVITY, PARTIAL_CLEAR_BAND,
+    SCORE_GRAVITY_POINTS, SCORE_GRAVITY_RAMP, VERSUS_RISE_PUSH,
+};
+use crate::grade::GradeTable;
+use crate::layout::Layout;
+use crate::mission::{parse_objective, Objective};
+use crate::pathfinding::Connectivity;
+use crate::rules::{ClearRules, StandardRules};
+use crate::settings::{parse_bool, ClearAnimation, Settings};
+
+lazy_static! {
+    /// Every mode's rules, in the order they're listed in the mode menu
+    pub static ref MODES: Vec<ModeDef> = parse_modes(include_str!("../assets/modes.txt")).unwrap();
+}
+
+/// The rules a game is played with, chosen from the mode menu. Everything a mode's definition in
+/// [`MODES`] covers comes from there; the variants here are the modes the game knows by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameMode {
+    /// The standard game
+    Marathon,
+    /// A row of garbage sand pushes up from the bottom every so often
+    Rising,
+    /// A sequence of short objectives, each with its own starting board and modifiers
+    Missions,
+    /// Scripted lessons on how to play
+    Tutorial,
+    /// The standard game, but any placement can be undone
+    Practice,
+    /// Paint a starting board and piece queue, then try it out
+    Editor,
+    /// Two players take turns placing pieces on the same board, and each clear scores for whoever
+    /// placed the piece that set it off
+    HotSeat,
+    /// Practice mode where every piece is the same color, so every lock threatens a clear
+    SingleColor(Color),
+    /// Sand pours onto the board with no blocks or clears, to profile the physics. It's started
+    /// with `--stress` rather than from the menu.
+    Stress,
+    /// One of two boards side by side, dealt from the same piece queue, with sand rising from the
+    /// bottom like in [`GameMode::Rising`]. It's started with `--coop` rather than from the menu.
+    Coop,
+    /// One of two boards side by side, one played by the bot, with sand rising from the bottom
+    /// like in [`GameMode::Rising`]. It's started from its own entry on the menu, which picks how
+    /// well the bot plays.
+    Versus,
+    /// The standard game played with the rules of a mode that's only in [`MODES`], like sprint
+    Defined(ModeId),
+}
+
+impl GameMode {
+    /// Every mode, in the order it's listed in the mode menu
+    pub fn all() -> impl Iterator<Item = Self> {
+        (0..MODES.len())
+            .filter(|index| MODES[*index].listed)
+            .map(Self::from_def)
+    }
+
+    /// The modes the game knows by name, which need a definition in [`MODES`] like any other
+    fn built_in() -> impl Iterator<Item = Self> {
+        [
+            Self::Marathon,
+            Self::Rising,
+            Self::Missions,
+            Self::Tutorial,
+            Self::Practice,
+            Self::Editor,
+            Self::HotSeat,
+            Self::Stress,
+            Self::Coop,
+            Self::Versus,
+        ]
+        .into_iter()
+        .chain(Color::ALL.map(Self::SingleColor))
+    }
+
+    /// The mode played by the rules of the definition at `index` in [`MODES`]
+    fn from_def(index: usize) -> Self {
+        Self::built_in()
+            .find(|mode| mode.name() == MODES[index].name)
+            .unwrap_or(Self::Defined(ModeId(index)))
+    }
+
+    /// The mode's rules
+    pub fn def(&self) -> &'static ModeDef {
+        match self {
+            Self::Defined(id) => &MODES[id.0],
+            _ => {
+                let name = self.name();
+                // Loading the definitions fails if a built in mode is missing
+                MODES.iter().find(|def| def.name == name).unwrap()
+            }
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::Marathon => "MARATHON".to_string(),
+            Self::Rising => "RISING".to_string(),
+            Self::Missions => "MISSIONS".to_string(),
+            Self::Tutorial => "TUTORIAL".to_string(),
+            Self::Practice => "PRACTICE".to_string(),
+            Self::Editor => "EDITOR".to_string(),
+            Self::HotSeat => "HOT SEAT".to_string(),
+            Self::SingleColor(color) => format!("{} ONLY", color.name()),
+            Self::Stress => "STRESS TEST".to_string(),
+            Self::Coop => "COOP".to_string(),
+            Self::Versus => "VERSUS".to_string(),
+            Self::Defined(id) => MODES[id.0].name.clone(),
+        }
+    }
+
+    /// What the mode is about, for its rules card. It's shown in capitals in the pixel font, so
+    /// it sticks to letters and digits.
+    pub fn description(&self) -> String {
+        self.def().description.clone()
+    }
+
+    /// Whether rows of garbage sand push up from the bottom of the board every so often
+    pub fn rises(&self) -> bool {
+        self.def().rising
+    }
+
+    /// The modifiers a game in this mode starts with: the ones the mode always has on, and the
+    /// ones from the settings
+    pub fn modifiers(&self, settings: &Settings) -> Modifiers {
+        self.def().modifiers.union(&settings.modifiers)
+    }
+
+    /// The card shown before the mode starts, with what it's about, what's different about how it
+    /// plays and how it scores. Only the objective is written out by hand; the rest is worked out
+    /// from the mode's rules and the settings, so it stays true when either changes.
+    pub fn rules_card(&self, settings: &Settings) -> RulesCard {
+        let def = self.def();
+        let mut objective = vec![self.description()];
+        if let Some(goal) = def.goal {
+            objective.push(format!("Goal {}", goal.describe().to_lowercase()));
+        }
+        if let Some(seconds) = def.time_limit {
+            objective.push(format!("The game lasts {seconds:.0} seconds"));
+        }
+
+        let mut modifiers = Vec::new();
+        if let Some(board) = def.board {
+            modifiers.push(format!(
+                "The board is {} by {} blocks",
+                board.columns, board.rows
+            ));
+        }
+        if self.rises() {
+            modifiers.push("Sand rises from the bottom".to_string());
+        }
+        if def.garbage > 0 {
+            modifiers.push(format!(
+                "The board starts with {} rows of garbage",
+                def.garbage
+            ));
+        }
+        if let Some(color) = self.color_override() {
+            modifiers.push(format!("Every piece is {}", color.name().to_lowercase()));
+        }
+        if def.undo {
+            modifiers.push("Any placement can be undone".to_string());
+        }
+        if def.endless {
+            modifiers.push("Topping out empties the board".to_string());
+        }
+        if *self == Self::Missions {
+            modifiers.push("Each mission picks its own".to_string());
+        } else {
+            modifiers.extend(self.modifiers(settings).names().map(str::to_string));
+        }
+        let rules = self.clear_rules(settings, &settings.modifiers);
+        if rules.connectivity() == Connectivity::Diagonal {
+            modifiers.push("Grains touching at the corners connect".to_string());
+        }
+        if rules.min_grains() > 0 {
+            modifiers.push(format!("Clears need {} grains or more", rules.min_grains()));
+        }
+        if modifiers.is_empty() {
+            modifiers.push("None".to_string());
+        }
+
+        let mut scoring = Vec::new();
+        let ramp = self.score_gravity(SCORE_GRAVITY_POINTS) - 1.0;
+        scoring.push(if ramp >= SCORE_GRAVITY_RAMP {
+            "Pieces fall faster as you score".to_string()
+        } else if ramp > 0.0 {
+            "Pieces fall a little faster as you score".to_string()
+        } else {
+            "Pieces always fall at the same speed".to_string()
+        });
+        scoring.push(format!("Combos last {:.0} seconds", self.combo_window()));
+        match self {
+            Self::Coop => scoring.push(format!(
+                "Clears hold back the sand on the other board for {COOP_RISE_RELIEF:.0} seconds"
+            )),
+            Self::Versus => scoring.push(format!(
+                "Clears bring the sand on the bots board {VERSUS_RISE_PUSH:.0} seconds closer"
+            )),
+            _ => {}
+        }
+        if GradeTable::for_mode(*self).is_some() {
+            scoring.push("Runs are graded on pace efficiency and chains".to_string());
+        }
+
+        RulesCard {
+            title: self.name(),
+            sections: vec![
+                ("OBJECTIVE", objective),
+                ("MODIFIERS", modifiers),
+                ("SCORING", scoring),
+            ],
+        }
+    }
+
+    /// The color every piece spawns with, if this mode forces one
+    pub fn color_override(&self) -> Option<Color> {
+        self.def().color
+    }
+
+    /// The rules for what sand clears in this mode
+    pub fn clear_rules(&self, settings: &Settings, modifiers: &Modifiers) -> Box<dyn ClearRules> {
+        let connectivity = if settings
+            .diagonal_clears
+            .contains(&self.name().to_lowercase())
+        {
+            Connectivity::Diagonal
+        } else {
+            Connectivity::Orthogonal
+        };
+        Box::new(StandardRules {
+            connectivity,
+            min_grains: self.min_clear_grains(settings),
+            band: modifiers.partial_clears.then_some(PARTIAL_CLEAR_BAND),
+        })
+    }
+
+    /// The fewest grains a clear takes in this mode
+    pub fn min_clear_grains(&self, settings: &Settings) -> usize {
+        let name = self.name().to_lowercase();
+        settings
+            .mode_min_clear_grains
+            .iter()
+            .find(|(mode, _)| *mode == name)
+            .map_or(settings.min_clear_grains, |(_, grains)| *grains)
+    }
+
+    /// The size of the board in this mode, with as many hidden rows as the settings give it
+    pub fn layout(&self, settings: &Settings) -> Layout {
+        self.def().board.map_or(settings.board, |board| Layout {
+            hidden_rows: settings.board.hidden_rows,
+            ..board
+        })
+    }
+
+    /// How clears are animated in this mode
+    pub fn clear_animation(&self, settings: &Settings) -> ClearAnimation {
+        let name = self.name().to_lowercase();
+        settings
+            .mode_clear_animations
+            .iter()
+            .find(|(mode, _)| *mode == name)
+            .map_or(settings.clear_animation, |(_, animation)| *animation)
+    }
+
+    /// How many times faster than normal pieces fall at a score. The speed ramps up smoothly with
+    /// every point rather than in steps, until it reaches [`MAX_SCORE_GRAVITY`].
+    pub fn score_gravity(&self, score: usize) -> f64 {
+        let ramp = SCORE_GRAVITY_RAMP * self.def().ramp;
+        (1.0 + ramp * score as f64 / SCORE_GRAVITY_POINTS as f64).min(MAX_SCORE_GRAVITY)
+    }
+
+    /// How long a combo stays alive after a clear, in seconds. Another clear in that time keeps it
+    /// going, and it resets once the time runs out.
+    pub fn combo_window(&self) -> f64 {
+        COMBO_WINDOW * self.def().combo
+    }
+}
+
+impl FromStr for GameMode {
+    type Err = String;
+
+    /// Parse a mode by its name, in any case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        (0..MODES.len())
+            .map(Self::from_def)
+            .find(|mode| mode.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown mode: {s}"))
+    }
+}
+
+/// A mode that's only in [`MODES`], by where it is there. It's saved by name, so replays still
+/// find it after modes are added or moved around.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModeId(usize);
+
+impl Serialize for ModeId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        MODES[self.0].name.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ModeId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        MODES
+            .iter()
+            .position(|def| def.name == name)
+            .map(Self)
+            .ok_or_else(|| D::Error::custom(format!("unknown mode: {name}")))
+    }
+}
+
+/// A mode's rules, from `modes.txt`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModeDef {
+    pub name: String,
+    pub description: String,
+    /// Whether it's on the mode menu, rather than started some other way
+    pub listed: bool,
+    /// How many times faster than normal pieces fall
+    pub gravity: f64,
+    /// How quickly pieces speed up as the score goes up, as a multiple of [`SCORE_GRAVITY_RAMP`]
+    pub ramp: f64,
+    /// How long combos last, as a multiple of [`COMBO_WINDOW`]
+    pub combo: f64,
+    /// Whether rows of garbage sand push up from the bottom every so often
+    pub rising: bool,
+    /// Rows of garbage sand the board starts with, each as tall as a row of rising sand
+    pub garbage: usize,
+    /// The color every piece is dealt in
+    pub color: Option<Color>,
+    /// Modifiers that are always on, on top of the ones from the settings
+    pub modifiers: Modifiers,
+    /// The size of the board, in place of the board size setting
+    pub board: Option<Layout>,
+    /// Whether any placement can be undone
+    pub undo: bool,
+    /// Whether topping out empties the board rather than ending the game
+    pub endless: bool,
+    /// What ends the game once it's reached
+    pub goal: Option<Objective>,
+    /// How long the game lasts, in seconds
+    pub time_limit: Option<f64>,
+}
+
+/// Parse a modes file, where each mode is a block of `key = value` lines separated by blank lines,
+/// and lines starting with `;` are comments. Every mode the game knows by name has to be there.
+pub fn parse_modes(source: &str) -> Result<Vec<ModeDef>, String> {
+    let mut defs: Vec<ModeDef> = Vec::new();
+    let mut def: Option<ModeDef> = None;
+    for (i, line) in source.lines().map(str::trim).enumerate() {
+        let error = |e: String| format!("line {}: {e}", i + 1);
+        if line.starts_with(';') {
+            continue;
+        }
+        if line.is_empty() {
+            defs.extend(def.take());
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .map(|(k, v)| (k.trim(), v.trim()))
+            .ok_or_else(|| error("expected `key = value`".to_string()))?;
+        let def = def.get_or_insert_with(|| ModeDef {
+            name: String::new(),
+            description: String::new(),
+            listed: true,
+            gravity: 1.0,
+            ramp: 1.0,
+            combo: 1.0,
+            rising: false,
+            garbage: 0,
+            color: None,
+            modifiers: Modifiers::default(),
+            board: None,
+            undo: false,
+            endless: false,
+            goal: None,
+            time_limit: None,
+        });
+        let number = |min: f64| {
+            value
+                .parse()
+                .ok()
+                .filter(|n| *n >= min)
+                .ok_or_else(|| error(format!("invalid {key}: {value}")))
+        };
+        match key {
+            "name" => def.name = value.to_uppercase(),
+            "description" => def.description = value.to_string(),
+            "listed" => def.listed = parse_bool(value).map_err(error)?,
+            "gravity" => def.gravity = number(f64::MIN_POSITIVE)?,
+            "ramp" => def.ramp = number(0.0)?,
+            "combo" => def.combo = number(f64::MIN_POSITIVE)?,
+            "rising" => def.rising = parse_bool(value).map_err(error)?,
+            "garbage" => {
+                def.garbage = value
+                    .parse()
+                    .map_err(|_| error(format!("invalid garbage: {value}")))?
+            }
+            "color" => def.color = Some(value.parse().map_err(error)?),
+            "modifiers" => def.modifiers = value.parse().map_err(error)?,
+            "board" => def.board = Some(value.parse().map_err(error)?),
+            "undo" => def.undo = parse_bool(value).map_err(error)?,
+            "endless" => def.endless = parse_bool(value).map_err(error)?,
+            "goal" => def.goal = Some(parse_objective(value).map_err(error)?),
+            "time" => def.time_limit = Some(number(f64::MIN_POSITIVE)?),
+            _ => return Err(error(format!("unknown key: {key}"))),
+        }
+    }
+    defs.extend(def);
+
+    for (i, def) in defs.iter().enumerate() {
+        if def.name.is_empty() {
+            return Err(format!("mode {} has no name", i + 1));
+        }
+        if defs[..i].iter().any(|other| other.name == def.name) {
+            return Err(format!("mode {} is defined twice", def.name));
+        }
+    }
+    if let Some(mode) =
+        GameMode::built_in().find(|mode| !defs.iter().any(|d| d.name == mode.name()))
+    {
+        return Err(format!("mode {} isn't defined", mode.name()));
+    }
+    Ok(defs)
+}
+
+/// What a mode is about, see [`GameMode::rules_card`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RulesCard {
+    pub title: String,
+    /// Each section's heading, and its lines before they're wrapped to fit
+    pub sections: Vec<(&'static str, Vec<String>)>,
+}
+
+/// Challenge modifiers that can be layered on top of any mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    /// Settled sand fades out a few seconds after it lands, leaving only recent landings visible
+    pub invisible_sand: bool,
+    /// Grains of two different colors that touch for a while blend into a third color
+    pub color_mixing: bool,
+    /// Grains stick to touching grains of the same color, and fall as rigid clumps
+    pub sticky_sand: bool,
+    /// A clear only takes the grains near the path that crossed the board, rather than the whole
+    /// group
+    pub partial_clears: bool,
+    /// Pieces are now and then dealt as powerups, which do something special when they lock
+    pub powerups: bool,
+}
+
+impl Modifiers {
+    /// The modifiers that are on in either
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            invisible_sand: self.invisible_sand || other.invisible_sand,
+            color_mixing: self.color_mixing || other.color_mixing,
+            sticky_sand: self.sticky_sand || other.sticky_sand,
+            partial_clears: self.partial_clears || other.partial_clears,
+            powerups: self.powerups || other.powerups,
+        }
+    }
+
+    /// The names of the modifiers that are on, as they're shown in game
+    pub fn names(&self) -> impl Iterator<Item = &'static str> {
+        [
+            (self.invisible_sand, "Invisible sand"),
+            (self.color_mixing, "Colors mix"),
+            (self.sticky_sand, "Sticky sand"),
+            (self.partial_clears, "Partial clears"),
+            (self.powerups, "Powerups"),
+        ]
+        .into_iter()
+        .filter_map(|(on, name)| on.then_some(name))
+    }
+}
+
+impl FromStr for Modifiers {
+    type Err = String;
+
+    /// Parse a comma-separated list of modifier names, or `none`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = Self::default();
+        for name in s.split(',').map(str::trim) {
+            match name {
+                "none" => {}
+                "invisible" => modifiers.invisible_sand = true,
+                "mixing" => modifiers.color_mixing = true,
+                "sticky" => modifiers.sticky_sand = true,
+                "partial" => modifiers.partial_clears = true,
+                "powerups" => modifiers.powerups = true,
+                _ => return Err(format!("unknown modifier: {name}")),
+            }
+        }
+        Ok(modifiers)
+    }
+}
@@ -0,0 +1,131 @@
+//! Watching a game that's played by someone else, from a replay or a network feed.
+
+use piston_window::graphics;
+use piston_window::prelude::*;
+
+use crate::desync::{write_report, DesyncCheck};
+use crate::game::Game;
+use crate::replay::{Frame, Input, InputSource};
+
+/// How much faster than real time the fast-forward plays
+const FAST_FORWARD_RATE: f64 = 2.0;
+
+/// Renders a game driven entirely by an [`InputSource`]. The keyboard only controls playback:
+/// P pauses, F toggles fast-forward, and `.` steps forward one frame while paused.
+#[derive(Debug)]
+pub struct Observer {
+    game: Game,
+    source: Box<dyn InputSource>,
+    /// How fast playback runs, relative to real time
+    rate: f64,
+    paused: bool,
+    /// Frames to play while paused, queued by stepping
+    steps: usize,
+    /// Real time that has passed but hasn't been played yet, scaled by the rate
+    lag: f64,
+    /// The frame that plays once enough time has passed
+    next: Option<Frame>,
+    desync: DesyncCheck,
+}
+
+impl Observer {
+    pub fn new(mut game: Game, mut source: Box<dyn InputSource>) -> Self {
+        let (mode, seed) = source.setup();
+        game.set_speed(source.speed());
+        game.start_seeded(mode, seed);
+        let next = source.next_frame();
+        Self {
+            game,
+            source,
+            rate: 1.0,
+            paused: false,
+            steps: 0,
+            lag: 0.0,
+            next,
+            desync: DesyncCheck::default(),
+        }
+    }
+
+    /// The size the window needs to be for the game's board, see [`Game::window_size`]
+    pub fn window_size(&self) -> (u32, u32) {
+        self.game.window_size()
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        let Some(Button::Keyboard(key)) = event.press_args() else {
+            return;
+        };
+        match key {
+            Key::P => {
+                self.paused = !self.paused;
+                self.game
+                    .show_toast(if self.paused { "PAUSED" } else { "PLAYING" });
+            }
+            Key::F => {
+                self.rate = if self.rate == 1.0 {
+                    FAST_FORWARD_RATE
+                } else {
+                    1.0
+                };
+                self.game.show_toast(&format!("SPEED {}X", self.rate));
+            }
+            Key::Period if self.paused => {
+                self.steps += 1;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn update(&mut self, args: &UpdateArgs) {
+        if self.paused {
+            for _ in 0..std::mem::take(&mut self.steps) {
+                self.play_frame();
+            }
+            return;
+        }
+
+        self.lag += args.dt * self.rate;
+        // Poll for frames that have arrived since the last update, like from a network feed
+        if self.next.is_none() {
+            self.next = self.source.next_frame();
+        }
+        while self.next.as_ref().is_some_and(|frame| frame.dt <= self.lag) {
+            self.lag -= self.next.as_ref().unwrap().dt;
+            self.play_frame();
+        }
+        if self.next.is_none() {
+            // Caught up with the source, so don't bank time to rush through later frames with
+            self.lag = 0.0;
+        }
+    }
+
+    fn play_frame(&mut self) {
+        let Some(frame) = self.next.take() else {
+            return;
+        };
+        for input in frame.inputs {
+            match input {
+                Input::Press(action) => self.game.press(action),
+                Input::Release(action) => self.game.release(action),
+            }
+        }
+        if let Some(report) = self.desync.check(&self.game, frame.hash) {
+            let message = format!("DESYNC AT FRAME {}", report.frame);
+            if let Err(e) = write_report(report) {
+                eprintln!("{e}");
+            }
+            self.game.show_error(&message);
+        }
+        self.game.update(&UpdateArgs { dt: frame.dt });
+        self.next = self.source.next_frame();
+    }
+
+    /// What the window's title should say, which is the game's with a note that it's a replay
+    pub fn window_title(&self) -> String {
+        format!("{} (replay)", self.game.window_title())
+    }
+
+    pub fn render(&mut self, context: graphics::Context, g: &mut G2d) {
+        self.game.render(context, g);
+    }
+}
@@ -0,0 +1,132 @@
+//! Custom colors for the sand, picked on the palette screen and saved to the theme file.
+
+use std::fs;
+use std::path::Path;
+
+use enum_map::EnumMap;
+
+use crate::constants::{parse_hex_color, Color, PALETTE_STEP};
+use crate::disk;
+
+/// Where custom colors are saved. Colors it doesn't mention keep their defaults.
+pub const THEME_FILE: &str = "sandtris_theme.txt";
+
+/// What each color of sand is drawn as
+pub type Palette = EnumMap<Color, [u8; 4]>;
+
+/// The names of a color's red, green and blue channels, as shown on the palette screen
+pub const CHANNELS: [&str; 3] = ["R", "G", "B"];
+
+/// Load the theme file, or the default palette if there isn't one
+pub fn load_palette() -> Result<Palette, String> {
+    if !Path::new(THEME_FILE).exists() {
+        return Ok(Color::DEFAULT_PALETTE);
+    }
+    let source =
+        fs::read_to_string(THEME_FILE).map_err(|e| format!("could not read {THEME_FILE}: {e}"))?;
+    parse_palette(&source).map_err(|e| format!("{THEME_FILE}:{e}"))
+}
+
+/// Parse a theme file made of `color = #rrggbb` lines, where lines starting with `#` are comments
+pub fn parse_palette(source: &str) -> Result<Palette, String> {
+    let mut palette = Color::DEFAULT_PALETTE;
+    for (i, line) in source.lines().enumerate().map(|(i, l)| (i, l.trim())) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (color, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("{}: expected `color = #rrggbb`", i + 1))?;
+        let color = color.trim().parse::<Color>();
+        let value = parse_hex_color(value.trim());
+        palette[color.map_err(|e| format!("{}: {e}", i + 1))?] =
+            value.map_err(|e| format!("{}: {e}", i + 1))?.0;
+    }
+    Ok(palette)
+}
+
+pub fn save_palette(palette: &Palette) -> Result<(), String> {
+    let source = palette
+        .iter()
+        .map(|(color, [r, g, b, a])| {
+            let alpha = if *a == 255 {
+                String::new()
+            } else {
+                format!("{a:02x}")
+            };
+            format!(
+                "{} = #{r:02x}{g:02x}{b:02x}{alpha}\n",
+                color.name().to_lowercase()
+            )
+        })
+        .collect::<String>();
+    disk::write(THEME_FILE, source);
+    Ok(())
+}
+
+/// The palette screen, where each color's red, green and blue can be nudged up and down. Changes
+/// show up everywhere as they're made, and are put back if the screen is cancelled.
+#[derive(Debug, Clone)]
+pub struct PaletteEditor {
+    /// The palette from before the screen was opened
+    original: Palette,
+    pub color: Color,
+    /// Which of the color's channels is selected, as an index into [`CHANNELS`]
+    pub channel: usize,
+}
+
+impl PaletteEditor {
+    pub fn new() -> Self {
+        Self {
+            original: Color::palette(),
+            color: Color::ALL[0],
+            channel: 0,
+        }
+    }
+
+    /// Select the next color down the list, or up it if `forward` isn't set
+    pub fn cycle_color(&mut self, forward: bool) {
+        let index = Color::ALL.iter().position(|c| *c == self.color).unwrap();
+        let count = Color::ALL.len();
+        let offset = if forward { 1 } else { count - 1 };
+        self.color = Color::ALL[(index + offset) % count];
+    }
+
+    pub fn cycle_channel(&mut self) {
+        self.channel = (self.channel + 1) % CHANNELS.len();
+    }
+
+    /// Raise the selected channel a step, or lower it if `up` isn't set
+    pub fn adjust(&mut self, up: bool) {
+        let mut palette = Color::palette();
+        let value = &mut palette[self.color][self.channel];
+        *value = if up {
+            value.saturating_add(PALETTE_STEP)
+        } else {
+            value.saturating_sub(PALETTE_STEP)
+        };
+        Color::set_palette(palette);
+    }
+
+    /// Put the selected color back to how it comes by default
+    pub fn reset_color(&mut self) {
+        let mut palette = Color::palette();
+        palette[self.color] = Color::DEFAULT_PALETTE[self.color];
+        Color::set_palette(palette);
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        save_palette(&Color::palette())
+    }
+
+    /// Go back to the palette from before the screen was opened
+    pub fn cancel(&self) {
+        Color::set_palette(self.original);
+    }
+}
+
+impl Default for PaletteEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
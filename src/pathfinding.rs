@@ -1,27 +1,77 @@
+//! Finding lines of one color that can be cleared.
+//!
+//! ```
+//! use sandtris::board::Board;
+//! use sandtris::constants::{Color, Grain};
+//! use sandtris::pathfinding::{
+//!     find_connected_sand, find_spanning_group, find_spanning_path, find_spanning_paths,
+//!     Connectivity,
+//! };
+//!
+//! let grain = Grain { color: Color::Blue, landed: 0.0, piece: 1 };
+//! let mut grid = Board::new((3, 1));
+//! grid.fill([0, 0, 3, 1], Some(grain));
+//! let orthogonal = Connectivity::Orthogonal;
+//! assert_eq!(find_spanning_group(&grid, orthogonal), Some((0, 0)));
+//! assert_eq!(find_spanning_path(&grid, orthogonal), Some(vec![(0, 0), (1, 0), (2, 0)]));
+//! assert_eq!(find_spanning_paths(&grid, orthogonal).len(), 1);
+//! assert_eq!(find_connected_sand(&grid, 0, 0, orthogonal).len(), 3);
+//!
+//! // A staircase only connects through its corners
+//! let mut stairs = Board::new((3, 3));
+//! for i in 0..3 {
+//!     stairs.set((i, i), Some(grain));
+//! }
+//! assert_eq!(find_spanning_group(&stairs, orthogonal), None);
+//! assert_eq!(find_spanning_group(&stairs, Connectivity::Diagonal), Some((0, 0)));
+//! ```
+
 use std::iter;
 
-use ndarray::{s, Array, Array1, Array2, ArrayView2};
+use ndarray::{s, Array, Array1, ArrayView2};
 use pathfinding::directed::{astar::astar, bfs::bfs_reach};
 
+use crate::board::Board;
 use crate::constants::Color;
 
+/// Which grains count as touching, for finding groups
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Connectivity {
+    /// Only grains directly above, below or to either side
+    #[default]
+    Orthogonal,
+    /// Grains that touch at the corners as well
+    Diagonal,
+}
+
+/// The offsets to each neighbor of a grain, with the orthogonal ones first
+const NEIGHBORS: [(isize, isize); 8] = [(-1, 0), (0, -1), (1, 0), (0, 1), (-1, -1), (1, -1), (-1, 1), (1, 1)];
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum Node {
     StartingEdge,
     Grid(usize, usize),
 }
 
-pub fn find_spanning_group(grid: &Array2<Option<Color>>) -> Option<(usize, usize)> {
+/// Find a group of same-colored grains connecting the left wall to the right wall. Returns a grain
+/// in the group, on the left wall.
+pub fn find_spanning_group(grid: &Board, connectivity: Connectivity) -> Option<(usize, usize)> {
+    find_spanning_path(grid, connectivity).and_then(|path| path.first().copied())
+}
+
+/// Find a path of same-colored grains connecting the left wall to the right wall. Returns every
+/// grain along the path, from left to right.
+pub fn find_spanning_path(grid: &Board, connectivity: Connectivity) -> Option<Vec<(usize, usize)>> {
     astar(
         &Node::StartingEdge,
         |node| -> Box<dyn Iterator<Item = (Node, usize)>> {
             match node {
                 Node::StartingEdge => Box::new(
-                    (0..grid.dim().1).filter_map(|y| grid[[0, y]].map(|_| (Node::Grid(0, y), 1))),
+                    (0..grid.dim().1).filter_map(|y| grid.color((0, y)).map(|_| (Node::Grid(0, y), 1))),
                 ),
                 Node::Grid(x, y) => {
-                    if let Some(color) = grid[[*x, *y]] {
-                        Box::new(find_neighbors(grid, *x, *y, color).map(|(nx, ny)| (Node::Grid(nx, ny), 1)))
+                    if let Some(color) = grid.color((*x, *y)) {
+                        Box::new(find_neighbors(grid, *x, *y, color, connectivity).map(|(nx, ny)| (Node::Grid(nx, ny), 1)))
                     } else {
                         Box::new(iter::empty())
                     }
@@ -37,43 +87,95 @@ pub fn find_spanning_group(grid: &Array2<Option<Color>>) -> Option<(usize, usize
             Node::Grid(x, _) => *x == grid.dim().0 - 1,
         },
     )
-    .and_then(|path| match path.0[1] {
-        Node::StartingEdge => None,
-        Node::Grid(x, y) => Some((x, y)),
+    .map(|(path, _)| {
+        path.into_iter()
+            .filter_map(|node| match node {
+                Node::StartingEdge => None,
+                Node::Grid(x, y) => Some((x, y)),
+            })
+            .collect()
     })
 }
 
-pub fn find_connected_sand(grid: &Array2<Option<Color>>, x: usize, y: usize) -> Vec<(usize, usize)> {
+/// Find every group of same-colored grains connecting the left wall to the right wall. Returns a
+/// path across the board for each group, like [`find_spanning_path`].
+pub fn find_spanning_paths(grid: &Board, connectivity: Connectivity) -> Vec<Vec<(usize, usize)>> {
+    let Some(first) = find_spanning_path(grid, connectivity) else {
+        return Vec::new();
+    };
+    // Each group that's found is taken off a copy of the board, so the next search finds another
+    let mut grid = grid.clone();
+    let mut paths = Vec::new();
+    let mut next = Some(first);
+    while let Some(path) = next {
+        let (x, y) = path[0];
+        for pos in find_connected_sand(&grid, x, y, connectivity) {
+            grid.set(pos, None);
+        }
+        paths.push(path);
+        next = find_spanning_path(&grid, connectivity);
+    }
+    paths
+}
+
+/// Every grain connected to the one at `(x, y)` through grains of the same color, including itself
+pub fn find_connected_sand(grid: &Board, x: usize, y: usize, connectivity: Connectivity) -> Vec<(usize, usize)> {
     bfs_reach((x, y), |(x, y)| -> Box<dyn Iterator<Item=(usize, usize)>> {
-        if let Some(color) = grid[[*x, *y]] {
-            Box::new(find_neighbors(grid, *x, *y, color))
+        if let Some(color) = grid.color((*x, *y)) {
+            Box::new(find_neighbors(grid, *x, *y, color, connectivity))
         } else {
             Box::new(iter::empty())
         }
     }).collect()
 }
 
+/// Like [`find_connected_sand`], but only grains within `band` rows of `path` in each column are
+/// included, so that a clear along the path leaves the rest of the group standing
+pub fn find_connected_sand_in_band(
+    grid: &Board,
+    x: usize,
+    y: usize,
+    path: &[(usize, usize)],
+    band: usize,
+    connectivity: Connectivity,
+) -> Vec<(usize, usize)> {
+    // The highest and lowest rows the path passes through in each column
+    let mut rows = vec![None; grid.dim().0];
+    for &(px, py) in path {
+        rows[px] = Some(match rows[px] {
+            Some((top, bottom)) => (py.min(top), py.max(bottom)),
+            None => (py, py),
+        });
+    }
+    let in_band = |x: usize, y: usize| {
+        rows[x].is_some_and(|(top, bottom): (usize, usize)| y + band >= top && y <= bottom + band)
+    };
+    bfs_reach((x, y), |(x, y)| match grid.color((*x, *y)) {
+        Some(color) => find_neighbors(grid, *x, *y, color, connectivity)
+            .filter(|(nx, ny)| in_band(*nx, *ny))
+            .collect(),
+        None => Vec::new(),
+    }).collect()
+}
+
 fn find_neighbors(
-    grid: &Array2<Option<Color>>,
+    grid: &Board,
     x: usize,
     y: usize,
     color: Color,
+    connectivity: Connectivity,
 ) -> impl Iterator<Item = (usize, usize)> {
-    [
-        (x.wrapping_sub(1), y),
-        (x, y.wrapping_sub(1)),
-        (x + 1, y),
-        (x, y + 1),
-    ]
-    .map(|(nx, ny)| test_node(grid, nx, ny, color))
-    .into_iter()
-    .flatten()
+    let count = match connectivity {
+        Connectivity::Orthogonal => 4,
+        Connectivity::Diagonal => 8,
+    };
+    let mut neighbors = [None; 8];
+    for (neighbor, (dx, dy)) in neighbors.iter_mut().zip(&NEIGHBORS[..count]) {
+        *neighbor = test_node(grid, x.wrapping_add_signed(*dx), y.wrapping_add_signed(*dy), color);
+    }
+    neighbors.into_iter().flatten()
 }
 
-fn test_node(grid: &Array2<Option<Color>>, x: usize, y: usize, color: Color) -> Option<(usize, usize)> {
-    grid.get([x, y])
-        .copied()
-        .flatten()
-        .filter(|c| *c == color)
-        .map(|_| (x, y))
+fn test_node(grid: &Board, x: usize, y: usize, color: Color) -> Option<(usize, usize)> {
+    grid.color((x, y)).filter(|c| *c == color).map(|_| (x, y))
 }
@@ -1,70 +1,457 @@
-use std::iter::once;
+//! The sand simulation. Every step, each grain falls one row if it can, or slides diagonally
+//! down if the grain under it is blocked.
+//!
+//! ```
+//! use ndarray::{s, Array2};
+//! use sandtris::physics::run_rng_physics;
+//! use sandtris::rng::WyRand;
+//!
+//! let mut sand = Array2::<Option<()>>::default([3, 2]);
+//! sand[[1, 0]] = Some(());
+//! run_rng_physics(&mut WyRand::new(), &mut sand);
+//! assert_eq!(sand.slice(s![.., 1]).iter().flatten().count(), 1);
+//! ```
 
-use nanorand::{Rng, WyRand};
-use ndarray::{s, Array1, ArrayView1, ArrayView2, ArrayViewMut2};
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::ops::Range;
+use std::time::{Duration, Instant};
 
-use crate::constants::Direction;
+use ndarray::{s, Array2, ArrayView2};
 
-pub fn run_rng_physics<T>(rng: &mut WyRand, mut sand: ArrayViewMut2<Option<T>>) {
-    for i in (1..sand.dim().1).rev() {
-        for (j, m) in run_physics_line(rng, sand.slice(s![.., i - 1..=i]))
+use crate::board::Board;
+use crate::constants::{Color, Direction, Grain, PHYSICS_MAX_ROUNDS};
+use crate::pathfinding::{find_connected_sand, Connectivity};
+use crate::rng::{GameRng, WyRand};
+
+/// Something the physics can move grains around in: a [`Board`], or a plain array of cells for
+/// sand that's only for show
+pub trait Grid {
+    type Cell;
+
+    /// Every cell, `Some` where there's a grain, indexed by `[x, y]`
+    fn cells(&self) -> ArrayView2<'_, Option<Self::Cell>>;
+
+    /// Move the grain in one cell to another, replacing whatever was there
+    fn move_grain(&mut self, from: (usize, usize), to: (usize, usize));
+
+    fn remove_grain(&mut self, pos: (usize, usize));
+}
+
+impl<T> Grid for Array2<Option<T>> {
+    type Cell = T;
+
+    fn cells(&self) -> ArrayView2<'_, Option<T>> {
+        self.view()
+    }
+
+    fn move_grain(&mut self, from: (usize, usize), to: (usize, usize)) {
+        self[to] = self[from].take();
+    }
+
+    fn remove_grain(&mut self, pos: (usize, usize)) {
+        self[pos] = None;
+    }
+}
+
+/// Pinned grains stay where they are, but still hold up the grains around them
+impl Grid for Board {
+    type Cell = Color;
+
+    fn cells(&self) -> ArrayView2<'_, Option<Self::Cell>> {
+        self.colors().view()
+    }
+
+    fn move_grain(&mut self, from: (usize, usize), to: (usize, usize)) {
+        if !self.is_pinned(from) {
+            Board::move_grain(self, from, to);
+        }
+    }
+
+    fn remove_grain(&mut self, pos: (usize, usize)) {
+        if !self.is_pinned(pos) {
+            self.set(pos, None);
+        }
+    }
+}
+
+/// How much work it took to settle which grains get to move where, over one or more rows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Resolution {
+    /// Rounds of resolving conflicts, across every row
+    pub rounds: usize,
+    /// The most rounds any one row took
+    pub most_rounds: usize,
+    /// How many rows hit [`PHYSICS_MAX_ROUNDS`] and had the conflicts left in them settled by
+    /// keeping the grains where they were
+    pub capped: usize,
+}
+
+impl Resolution {
+    fn add(&mut self, other: Self) {
+        self.rounds += other.rounds;
+        self.most_rounds = self.most_rounds.max(other.most_rounds);
+        self.capped += other.capped;
+    }
+}
+
+/// Move every grain that can fall one step, and return how much work it took to settle which
+/// grains move where
+pub fn run_rng_physics(rng: &mut WyRand, sand: &mut impl Grid) -> Resolution {
+    let mut resolution = Resolution::default();
+    for i in (1..sand.cells().dim().1).rev() {
+        let (moves, line) = run_physics_line(rng, sand.cells().slice(s![.., i - 1..=i]));
+        resolution.add(line);
+        for (j, m) in moves
             .into_iter()
             .enumerate()
             .filter_map(|(j, m)| m.map(|m| (j, m)))
         {
             match m {
-                Direction::Left => {
-                    sand[[j - 1, i]] = sand[[j, i - 1]].take();
-                }
-                Direction::Right => {
-                    sand[[j + 1, i]] = sand[[j, i - 1]].take();
-                }
-                Direction::Down => {
-                    sand[[j, i]] = sand[[j, i - 1]].take();
-                }
+                Direction::Left => sand.move_grain((j, i - 1), (j - 1, i)),
+                Direction::Right => sand.move_grain((j, i - 1), (j + 1, i)),
+                Direction::Down => sand.move_grain((j, i - 1), (j, i)),
             };
         }
     }
+    resolution
+}
+
+/// The physics for the sticky sand modifier. Grains stick to touching grains of the same color,
+/// and each clump falls one row as a unit if nothing outside of it holds it up. Lower clumps move
+/// first, so clumps resting on a falling clump fall along with it. Clumps with a pinned grain in
+/// them stay put.
+pub fn run_sticky_physics(sand: &mut Board) {
+    let height = sand.dim().1;
+    let mut seen = Array2::from_elem(sand.dim(), false);
+    let mut clumps = Vec::new();
+    for ((x, y), _) in sand.grains() {
+        if !seen[[x, y]] {
+            let clump = find_connected_sand(sand, x, y, Connectivity::Orthogonal);
+            for (cx, cy) in &clump {
+                seen[[*cx, *cy]] = true;
+            }
+            clumps.push(clump);
+        }
+    }
+
+    clumps.sort_by_key(|clump| Reverse(clump.iter().map(|(_, y)| *y).max()));
+    for mut clump in clumps {
+        let members = clump.iter().copied().collect::<HashSet<_>>();
+        let supported = clump.iter().any(|(x, y)| {
+            *y + 1 == height
+                || sand.is_pinned((*x, *y))
+                || (sand.is_filled((*x, y + 1)) && !members.contains(&(*x, y + 1)))
+        });
+        if supported {
+            continue;
+        }
+        // Moving the bottom of the clump first leaves room for the grains above it
+        clump.sort_by_key(|(_, y)| Reverse(*y));
+        for (x, y) in clump {
+            sand.move_grain((x, y), (x, y + 1));
+        }
+    }
+}
+
+/// Run the physics with no floor, so that grains on the bottom row fall off the board
+pub fn run_rng_physics_open(rng: &mut WyRand, sand: &mut impl Grid) -> Resolution {
+    let (width, height) = sand.cells().dim();
+    if height == 0 {
+        return Resolution::default();
+    }
+    for x in 0..width {
+        sand.remove_grain((x, height - 1));
+    }
+    run_rng_physics(rng, sand)
+}
+
+/// Where a grain poured in from above the board at column `x` enters the top row. If the cell
+/// there is taken, the grain rolls off to the nearest free cell along the top row, preferring the
+/// left on a tie, and if the whole top row is full there's nowhere for it to go.
+pub fn inflow(sand: &impl Grid, x: usize) -> Option<(usize, usize)> {
+    let cells = sand.cells();
+    let width = cells.dim().0;
+    if cells.dim().1 == 0 || x >= width {
+        return None;
+    }
+    (0..width)
+        .flat_map(|distance| [x.checked_sub(distance), Some(x + distance)])
+        .flatten()
+        .find(|x| *x < width && cells[[*x, 0]].is_none())
+        .map(|x| (x, 0))
+}
+
+/// A source of sand that pours grains in along the top of a board at a steady rate, like the stress
+/// test's downpour. The game steps its emitters alongside the physics.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Emitter {
+    /// The columns grains are poured in at, one picked at random for each grain
+    pub columns: Range<usize>,
+    /// How many grains are poured each second
+    pub rate: f64,
+    /// The color of every grain, or `None` for a random color for each one
+    pub color: Option<Color>,
+    /// How many more grains to pour before running dry, or `None` to pour forever
+    pub remaining: Option<usize>,
+    /// The part of a grain that's due but hasn't been poured yet
+    owed: f64,
+}
+
+impl Emitter {
+    pub fn new(columns: Range<usize>, rate: f64, color: Option<Color>) -> Self {
+        Self {
+            columns,
+            rate,
+            color,
+            remaining: None,
+            owed: 0.0,
+        }
+    }
+
+    /// An emitter that pours into a single column
+    pub fn column(x: usize, rate: f64, color: Option<Color>) -> Self {
+        Self::new(x..x + 1, rate, color)
+    }
+
+    /// Run dry after pouring `grains` grains
+    pub fn limit(self, grains: usize) -> Self {
+        Self {
+            remaining: Some(grains),
+            ..self
+        }
+    }
+
+    pub fn is_dry(&self) -> bool {
+        self.remaining == Some(0)
+    }
+
+    /// Pour in the grains that are due after `dt` seconds, landing at `time`, see [`inflow`].
+    /// Grains that have nowhere to go are lost. Returns how many went in.
+    pub fn step(&mut self, rng: &mut WyRand, dt: f64, sand: &mut Board, time: f64) -> usize {
+        self.owed += self.rate * dt;
+        let mut due = self.owed as usize;
+        self.owed -= due as f64;
+        if let Some(remaining) = &mut self.remaining {
+            due = due.min(*remaining);
+            *remaining -= due;
+        }
+        let mut poured = 0;
+        for _ in 0..due {
+            if self.columns.is_empty() {
+                break;
+            }
+            let x = rng.generate_range(self.columns.clone());
+            let color = self
+                .color
+                .unwrap_or_else(|| Color::ALL[rng.generate_range(0..Color::ALL.len())]);
+            if let Some(pos) = inflow(sand, x) {
+                sand.set(
+                    pos,
+                    Some(Grain {
+                        color,
+                        landed: time,
+                        piece: 0,
+                    }),
+                );
+                poured += 1;
+            }
+        }
+        poured
+    }
+}
+
+/// Shift every grain up by `rows`, in place, leaving the bottom `rows` rows empty. Returns how many
+/// grains were pushed off the top of the board, which are gone.
+pub fn shift_up(sand: &mut impl Grid, rows: usize) -> usize {
+    let (width, height) = sand.cells().dim();
+    let rows = rows.min(height);
+    let overflowed = sand.cells().slice(s![.., ..rows]).iter().flatten().count();
+    for y in 0..height {
+        for x in 0..width {
+            if y + rows < height {
+                sand.move_grain((x, y + rows), (x, y));
+            } else {
+                sand.remove_grain((x, y));
+            }
+        }
+    }
+    overflowed
+}
+
+/// A copy of some columns of the board, to run the physics on ahead of time without touching the
+/// board or the game's random numbers. Grains can be added and followed to see where they settle.
+/// The edges of the copy act as walls, so grains that would have slid out of it pile up against
+/// them instead.
+#[derive(Debug, Clone)]
+pub struct Subgrid {
+    /// The board's coordinates of the copy's top left cell
+    origin: (usize, usize),
+    /// `Some(true)` where a grain being followed is
+    cells: Array2<Option<bool>>,
+}
+
+impl Subgrid {
+    /// Copy the columns in `columns` of the board, from row `top` down to the floor
+    pub fn new<T>(sand: ArrayView2<Option<T>>, columns: Range<usize>, top: usize) -> Self {
+        let (width, height) = sand.dim();
+        let (left, right) = (columns.start.min(width), columns.end.min(width));
+        let top = top.min(height);
+        let cells = sand
+            .slice(s![left..right, top..])
+            .map(|cell| cell.as_ref().map(|_| false));
+        Self {
+            origin: (left, top),
+            cells,
+        }
+    }
+
+    /// Put grains to follow in the given cells of the board, where they're inside the copy
+    pub fn add_grains(&mut self, grains: impl Iterator<Item = (usize, usize)>) {
+        let (left, top) = self.origin;
+        for (x, y) in grains {
+            if let Some(cell) = x
+                .checked_sub(left)
+                .zip(y.checked_sub(top))
+                .and_then(|(x, y)| self.cells.get_mut([x, y]))
+            {
+                *cell = Some(true);
+            }
+        }
+    }
+
+    /// Run up to `ticks` physics ticks, stopping early once nothing moves or `budget` runs out,
+    /// and return how many were run. One tick is always run, however small the budget.
+    pub fn simulate(&mut self, rng: &mut WyRand, ticks: usize, budget: Duration) -> usize {
+        let start = Instant::now();
+        for tick in 0..ticks {
+            if tick > 0 && start.elapsed() >= budget {
+                return tick;
+            }
+            let before = self.cells.clone();
+            run_rng_physics(rng, &mut self.cells);
+            if self.cells == before {
+                return tick + 1;
+            }
+        }
+        ticks
+    }
+
+    /// Where the grains being followed are, in the board's coordinates
+    pub fn followed(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let (left, top) = self.origin;
+        self.cells
+            .indexed_iter()
+            .filter(|(_, cell)| **cell == Some(true))
+            .map(move |((x, y), _)| (x + left, y + top))
+    }
+}
+
+/// A copy of the whole board, to run the sticky sand physics on ahead of time like [`Subgrid`] does
+/// for loose sand. A clump can reach right across the board, so none of it is left out. The grains
+/// being followed are marked as coming from a piece that no run gets to.
+#[derive(Debug, Clone)]
+pub struct StickyGrid {
+    sand: Board,
 }
 
+impl StickyGrid {
+    const FOLLOWED: usize = usize::MAX;
+
+    pub fn new(sand: Board) -> Self {
+        Self { sand }
+    }
+
+    /// Put grains of a color to follow in the given cells of the board
+    pub fn add_grains(&mut self, grains: impl Iterator<Item = (usize, usize)>, color: Color) {
+        let grain = Grain {
+            color,
+            landed: 0.0,
+            piece: Self::FOLLOWED,
+        };
+        for (x, y) in grains {
+            if x < self.sand.dim().0 && y < self.sand.dim().1 {
+                self.sand.set((x, y), Some(grain));
+            }
+        }
+    }
+
+    /// Run up to `ticks` physics ticks, the same way as [`Subgrid::simulate`]
+    pub fn simulate(&mut self, ticks: usize, budget: Duration) -> usize {
+        let start = Instant::now();
+        for tick in 0..ticks {
+            if tick > 0 && start.elapsed() >= budget {
+                return tick;
+            }
+            let before = self.sand.colors().clone();
+            run_sticky_physics(&mut self.sand);
+            if *self.sand.colors() == before {
+                return tick + 1;
+            }
+        }
+        ticks
+    }
+
+    /// Where the grains being followed are
+    pub fn followed(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.sand
+            .grains()
+            .filter(|(_, grain)| grain.piece == Self::FOLLOWED)
+            .map(|(pos, _)| pos)
+    }
+}
+
+/// Decide where each grain on the top row of a pair of rows moves, if anywhere, along with how much
+/// work it took to keep any two grains from moving into the same cell
 pub fn run_physics_line<T>(
     rng: &mut WyRand,
     sand: ArrayView2<Option<T>>,
-) -> Vec<Option<Direction>> {
+) -> (Vec<Option<Direction>>, Resolution) {
     // Figure out what each grain of sand "wants to" do
     // assume that if there is a grain of sand next to the current one, the current one cannot move
-    // diagonally in that direction
-    let mut requests = once(sand[[0, 0]].is_some().then(|| {
-        [
-            true,
-            sand[[1, 1]].is_some(),
-            sand[[2, 1]].is_some() || sand[[2, 0]].is_some(),
-        ]
-    }))
-    .chain(sand.windows([3, 2]).into_iter().map(|w| {
-        w[[1, 0]].is_some().then(|| {
-            [
-                w[[0, 1]].is_some() || w[[0, 0]].is_some(),
-                w[[1, 1]].is_some(),
-                w[[2, 1]].is_some() || w[[2, 0]].is_some(),
-            ]
+    // diagonally in that direction. The walls count as sand, so boards of any width, even a
+    // single column, are handled the same way.
+    let width = sand.dim().0;
+    let blocked = |x: Option<usize>, y: usize| {
+        x.filter(|x| *x < width)
+            .is_none_or(|x| sand[[x, y]].is_some())
+    };
+    let mut requests = (0..width)
+        .map(|x| {
+            let (left, right) = (x.checked_sub(1), Some(x + 1));
+            sand[[x, 0]].is_some().then(|| {
+                [
+                    blocked(left, 1) || blocked(left, 0),
+                    blocked(Some(x), 1),
+                    blocked(right, 1) || blocked(right, 0),
+                ]
+            })
         })
-    }))
-    .chain(once(sand[[sand.dim().0 - 1, 0]].is_some().then(|| {
-        [
-            sand[[sand.dim().0 - 2, 1]].is_some(),
-            sand[[sand.dim().0 - 1, 1]].is_some(),
-            true,
-        ]
-    })))
-    .map(|o| o.and_then(|s| decide_direction(rng, s)))
-    .collect::<Vec<_>>();
+        .map(|o| o.and_then(|s| decide_direction(rng, s)))
+        .collect::<Vec<_>>();
+
+    let resolution = resolve_conflicts(rng, &mut requests);
+    let moves = requests
+        .into_iter()
+        .map(|request| request.map(|d| d.0))
+        .collect();
+    (moves, resolution)
+}
 
+/// A move a grain wants to make, and the one it falls back on if that one is taken
+pub type Request = (Direction, Option<Direction>);
+
+/// Change the moves of a row of grains until no two of them would move into the same cell, and
+/// return how many rounds it took. Each row gets at most [`PHYSICS_MAX_ROUNDS`] rounds, and any
+/// grains still in conflict after that stay where they are.
+pub fn resolve_conflicts(rng: &mut WyRand, requests: &mut [Option<Request>]) -> Resolution {
     // Resolve conflicts, first between neighboring sand grains (one will be straight down, one
     // will be diagonal. Straight down gets priority)
+    let mut rounds = 0;
     let mut changed = true;
-    while changed {
-        // Loop until all conflicts have been resolved
+    while changed && rounds < PHYSICS_MAX_ROUNDS {
+        // Loop until all conflicts have been resolved, or for as many rounds as a tick can afford
+        rounds += 1;
         changed = false;
         // Resolve conflicts of neighboring sand grains
         for (left, right) in (1..requests.len()).map(|i| (i - 1, i)) {
@@ -95,16 +482,48 @@ pub fn run_physics_line<T>(
         }
     }
 
-    requests
-        .into_iter()
-        .map(|request| request.map(|d| d.0))
-        .collect()
+    // Anything still in conflict once the rounds run out stays where it is. Taking a move away
+    // can't start a new conflict, so one pass settles them all, and without drawing from the rng,
+    // so hitting the cap plays out the same every time.
+    let capped = changed && cancel_conflicts(requests);
+
+    Resolution {
+        rounds,
+        most_rounds: rounds,
+        capped: capped as usize,
+    }
 }
 
-fn decide_direction(
-    rng: &mut WyRand,
-    sand_under: [bool; 3],
-) -> Option<(Direction, Option<Direction>)> {
+/// Cancel the moves of any grains that would move into the same cell, and return whether there
+/// were any
+fn cancel_conflicts(requests: &mut [Option<Request>]) -> bool {
+    let mut cancelled = false;
+    for (left, right) in (1..requests.len()).map(|i| (i - 1, i)) {
+        match (requests[left], requests[right]) {
+            (Some((Direction::Right, _)), Some((Direction::Down, _))) => {
+                requests[left] = None;
+                cancelled = true;
+            }
+            (Some((Direction::Down, _)), Some((Direction::Left, _))) => {
+                requests[right] = None;
+                cancelled = true;
+            }
+            _ => {}
+        }
+    }
+    for (left, right) in (2..requests.len()).map(|i| (i - 2, i)) {
+        if let (Some((Direction::Right, _)), Some((Direction::Left, _))) =
+            (requests[left], requests[right])
+        {
+            requests[left] = None;
+            requests[right] = None;
+            cancelled = true;
+        }
+    }
+    cancelled
+}
+
+fn decide_direction(rng: &mut WyRand, sand_under: [bool; 3]) -> Option<Request> {
     use Direction as D;
     match sand_under {
         [true, true, true] => None,
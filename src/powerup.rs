@@ -0,0 +1,32 @@
+//! Powerup pieces, which do something special when they lock rather than turning into sand. With
+//! the powerups modifier, a piece is dealt as a powerup every so often, and a long enough chain of
+//! clears earns one for the next piece.
+
+use serde::{Deserialize, Serialize};
+
+use crate::constants::POWERUP_ODDS;
+use crate::rng::{GameRng, WyRand};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Powerup {
+    /// Sweeps every grain of the piece's color off the board
+    ColorClear,
+    /// Burns away every grain in the rows the piece lands in, whatever their color
+    Laser,
+    /// Blows away every grain within a few blocks of where the piece lands
+    Bomb,
+}
+
+impl Powerup {
+    pub const ALL: [Powerup; 3] = [Powerup::ColorClear, Powerup::Laser, Powerup::Bomb];
+
+    /// Any one of the powerups, picked at random
+    pub fn random(rng: &mut WyRand) -> Self {
+        Self::ALL[rng.generate_range(0..Self::ALL.len())]
+    }
+
+    /// A powerup for a piece being dealt, which is `None` most of the time
+    pub fn roll(rng: &mut WyRand) -> Option<Self> {
+        (rng.generate_range(0..POWERUP_ODDS) == 0).then(|| Self::random(rng))
+    }
+}
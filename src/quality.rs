@@ -0,0 +1,132 @@
+//! Render quality tiers, and lowering them automatically when frames take too long to draw.
+
+use std::str::FromStr;
+
+use crate::constants::{QUALITY_HEADROOM, QUALITY_WINDOW, RENDER_BUDGET};
+
+/// How much drawing work to do each frame. Each tier drops more of the extras, but never anything
+/// needed to play.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Quality {
+    /// The board is redrawn every fourth frame, fading grains snap between shown and hidden, and
+    /// there are no streaks behind dropped pieces or grid behind the sand
+    Low,
+    /// The board is redrawn every other frame, and no dust is thrown up where pieces land
+    Medium,
+    High,
+}
+
+impl Quality {
+    /// How many frames pass between redraws of the board. The falling piece and the dashboard are
+    /// drawn every frame regardless, so movement stays smooth.
+    pub fn board_interval(&self) -> usize {
+        match self {
+            Self::Low => 4,
+            Self::Medium => 2,
+            Self::High => 1,
+        }
+    }
+
+    /// Whether grains fading out with the invisible sand modifier are drawn partly faded
+    pub fn smooth_fade(&self) -> bool {
+        *self > Self::Low
+    }
+
+    /// Whether the dust thrown up where a hard-dropped piece lands is drawn
+    pub fn particles(&self) -> bool {
+        *self == Self::High
+    }
+
+    /// Whether the streak behind a hard-dropped piece is drawn
+    pub fn trails(&self) -> bool {
+        *self > Self::Low
+    }
+
+    /// Whether the grid is drawn behind the sand. The frame, spawn zone and kill line are always
+    /// drawn, since they help with playing.
+    pub fn background_grid(&self) -> bool {
+        *self > Self::Low
+    }
+
+    fn lower(self) -> Self {
+        match self {
+            Self::High => Self::Medium,
+            Self::Medium | Self::Low => Self::Low,
+        }
+    }
+
+    fn raise(self) -> Self {
+        match self {
+            Self::Low => Self::Medium,
+            Self::Medium | Self::High => Self::High,
+        }
+    }
+}
+
+impl FromStr for Quality {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "low" => Ok(Self::Low),
+            "medium" => Ok(Self::Medium),
+            "high" => Ok(Self::High),
+            other => Err(format!("invalid render quality: {other}")),
+        }
+    }
+}
+
+/// Tracks how long frames take to draw, and lowers the quality when they're consistently over
+/// budget, raising it again once there's room to spare
+#[derive(Debug, Clone)]
+pub struct FrameTimer {
+    pub quality: Quality,
+    /// The quality is fixed by the settings, rather than adjusted automatically
+    fixed: bool,
+    /// A moving average of the time spent drawing each frame, in seconds
+    average: f64,
+    /// How many frames in a row the average has been over budget, or well under it
+    over: usize,
+    under: usize,
+}
+
+impl FrameTimer {
+    /// Start at `quality` if given and keep it, or start at the highest quality and adapt
+    pub fn new(quality: Option<Quality>) -> Self {
+        Self {
+            quality: quality.unwrap_or(Quality::High),
+            fixed: quality.is_some(),
+            average: 0.0,
+            over: 0,
+            under: 0,
+        }
+    }
+
+    /// Record how long a frame took to draw, in seconds
+    pub fn record(&mut self, seconds: f64) {
+        self.average += (seconds - self.average) * 0.1;
+        if self.fixed {
+            return;
+        }
+
+        if self.average > RENDER_BUDGET {
+            self.over += 1;
+            self.under = 0;
+        } else if self.average < RENDER_BUDGET * QUALITY_HEADROOM {
+            self.under += 1;
+            self.over = 0;
+        } else {
+            self.over = 0;
+            self.under = 0;
+        }
+
+        // Quality comes back more slowly than it drops, so that it doesn't flicker between tiers
+        if self.over >= QUALITY_WINDOW {
+            self.quality = self.quality.lower();
+            self.over = 0;
+        } else if self.under >= QUALITY_WINDOW * 4 {
+            self.quality = self.quality.raise();
+            self.under = 0;
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Strategies for choosing which shape comes next.
+
+use std::collections::VecDeque;
+use std::fmt::Debug;
+
+use crate::constants::Shape;
+use crate::rng::{GameRng, WyRand};
+
+/// How many previous pieces the history randomizer remembers
+const HISTORY_LEN: usize = 4;
+/// How many times the history randomizer will reroll before accepting a repeat
+const HISTORY_REROLLS: usize = 4;
+/// The most pieces a bag can hold, once its weights are divided down as far as they go
+pub const MAX_BAG_SIZE: u32 = 1000;
+
+/// A strategy for choosing which shape comes next
+pub trait Randomizer: Debug {
+    fn next_shape(&mut self, rng: &mut WyRand) -> Shape;
+
+    /// Copy the randomizer along with its internal state, so that a game snapshot can replay the
+    /// same pieces
+    fn clone_box(&self) -> Box<dyn Randomizer>;
+}
+
+impl Clone for Box<dyn Randomizer> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// Pick an index into `weights`, where each index is chosen with probability proportional to its
+/// weight. The weights have to add up to no more than `u32::MAX`, which
+/// [`Settings::validate`](crate::settings::Settings::validate) checks.
+pub fn weighted_index(rng: &mut WyRand, weights: &[u32]) -> usize {
+    let mut choice = rng.generate_range(0..weights.iter().sum::<u32>());
+    for (i, weight) in weights.iter().enumerate() {
+        if choice < *weight {
+            return i;
+        }
+        choice -= weight;
+    }
+    unreachable!("weights must not all be zero")
+}
+
+fn weighted_shape(rng: &mut WyRand, weights: &[u32]) -> Shape {
+    Shape::all().nth(weighted_index(rng, weights)).unwrap()
+}
+
+/// The weights a bag is filled by: `weights` divided by their greatest common divisor, which deals
+/// the same mix of shapes from a smaller bag
+pub fn bag_weights(weights: &[u32]) -> Vec<u32> {
+    let divisor = weights.iter().fold(0, |a, &b| gcd(a, b)).max(1);
+    weights.iter().map(|weight| weight / divisor).collect()
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Every shape is picked independently, according to its weight
+#[derive(Debug, Clone)]
+pub struct PureRandom {
+    weights: Vec<u32>,
+}
+
+impl PureRandom {
+    pub fn new(weights: Vec<u32>) -> Self {
+        Self { weights }
+    }
+}
+
+impl Randomizer for PureRandom {
+    fn next_shape(&mut self, rng: &mut WyRand) -> Shape {
+        weighted_shape(rng, &self.weights)
+    }
+
+    fn clone_box(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Deals a shuffled bag before refilling, like the 7-bag in modern Tetris. Each shape is put in
+/// the bag as many times as its weight, after the weights are divided down by [`bag_weights`].
+#[derive(Debug, Clone)]
+pub struct Bag {
+    weights: Vec<u32>,
+    bag: Vec<Shape>,
+}
+
+impl Bag {
+    pub fn new(weights: Vec<u32>) -> Self {
+        Self {
+            weights: bag_weights(&weights),
+            bag: Vec::new(),
+        }
+    }
+}
+
+impl Randomizer for Bag {
+    fn next_shape(&mut self, rng: &mut WyRand) -> Shape {
+        if self.bag.is_empty() {
+            for (shape, weight) in Shape::all().zip(&self.weights) {
+                self.bag.extend((0..*weight).map(|_| shape));
+            }
+            rng.shuffle(&mut self.bag);
+        }
+        self.bag.pop().unwrap()
+    }
+
+    fn clone_box(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+/// TGM-style randomizer, which rerolls a shape that was one of the last few dealt, up to a limit
+#[derive(Debug, Clone)]
+pub struct History {
+    weights: Vec<u32>,
+    history: VecDeque<Shape>,
+}
+
+impl History {
+    pub fn new(weights: Vec<u32>) -> Self {
+        Self {
+            weights,
+            history: VecDeque::new(),
+        }
+    }
+}
+
+impl Randomizer for History {
+    fn next_shape(&mut self, rng: &mut WyRand) -> Shape {
+        let mut shape = weighted_shape(rng, &self.weights);
+        for _ in 0..HISTORY_REROLLS {
+            if !self.history.contains(&shape) {
+                break;
+            }
+            shape = weighted_shape(rng, &self.weights);
+        }
+
+        if self.history.len() >= HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(shape);
+        shape
+    }
+
+    fn clone_box(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
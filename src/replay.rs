@@ -0,0 +1,231 @@
+//! Recording games as the inputs that drove them, and feeding those inputs back in.
+//!
+//! A game started from the same seed, with the same settings, and given the same inputs on the
+//! same frames plays out exactly the same way, so a replay only needs the seed and the inputs.
+//! That also means the score a replay claims can be checked by playing it through again.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+
+use piston_window::UpdateArgs;
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{MAX_GAME_SPEED, MIN_GAME_SPEED};
+use crate::desync::{write_report, DesyncCheck, DESYNC_FILE};
+use crate::disk;
+use crate::game::Game;
+use crate::input::Action;
+use crate::mode::GameMode;
+use crate::save;
+use crate::settings::Settings;
+
+/// Where the last finished game's replay is written
+pub const REPLAY_FILE: &str = "sandtris_replay.json";
+/// The most key presses a replay can have in any one second of game time. Even the fastest players
+/// stay well under this, so a replay over it was made some other way than playing.
+pub const MAX_PRESSES_PER_SECOND: usize = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Input {
+    Press(Action),
+    Release(Action),
+}
+
+/// Everything that happened in one update: the inputs that came in before it, then how much time
+/// it covered
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    pub dt: f64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<Input>,
+    /// The [hash](crate::board::Board::hash) of the board once the frame's inputs were in, just
+    /// before its update, if it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub mode: GameMode,
+    pub seed: u64,
+    /// The game speed setting the game was played at, which also shows whether it was slowed down
+    #[serde(default = "normal_speed")]
+    pub speed: f64,
+    /// The settings the game was played with that change how it plays out, see
+    /// [`Settings::rules`]. Replays from before they were recorded were played by the default
+    /// rules as far as [`verify`] is concerned.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<(String, String)>,
+    pub frames: Vec<Frame>,
+    /// The score the game ended on, for [`verify`] to check
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<usize>,
+}
+
+fn normal_speed() -> f64 {
+    1.0
+}
+
+impl Replay {
+    pub fn new(mode: GameMode, seed: u64, speed: f64) -> Self {
+        Self {
+            mode,
+            seed,
+            speed,
+            rules: Vec::new(),
+            frames: Vec::new(),
+            score: None,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let source = fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {e}", path.display()))?;
+        let replay: Self =
+            save::from_str_seeded(&source).map_err(|e| format!("{}: {e}", path.display()))?;
+        replay
+            .validate()
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        Ok(replay)
+    }
+
+    /// Check that the replay is something a person could have played: the game speed is one the
+    /// settings allow, time only moves forwards, and keys aren't pressed faster than
+    /// [`MAX_PRESSES_PER_SECOND`]
+    pub fn validate(&self) -> Result<(), String> {
+        if !(MIN_GAME_SPEED..=MAX_GAME_SPEED).contains(&self.speed) {
+            return Err(format!(
+                "the game speed has to be between {MIN_GAME_SPEED} and {MAX_GAME_SPEED}, not {}",
+                self.speed
+            ));
+        }
+        if matches!(
+            self.mode,
+            GameMode::Missions
+                | GameMode::Tutorial
+                | GameMode::Editor
+                | GameMode::Coop
+                | GameMode::Versus
+        ) {
+            return Err(format!("{} games aren't recorded", self.mode.name()));
+        }
+
+        // The time of each press in the last second, oldest first
+        let mut presses = VecDeque::new();
+        let mut time = 0.0;
+        for (i, frame) in self.frames.iter().enumerate() {
+            if !(frame.dt.is_finite() && frame.dt >= 0.0) {
+                return Err(format!("frame {i} goes back in time"));
+            }
+            time += frame.dt;
+            while presses.front().is_some_and(|press| time - press >= 1.0) {
+                presses.pop_front();
+            }
+            for input in &frame.inputs {
+                if let Input::Press(_) = input {
+                    presses.push_back(time);
+                }
+            }
+            if presses.len() > MAX_PRESSES_PER_SECOND {
+                return Err(format!(
+                    "more than {MAX_PRESSES_PER_SECOND} keys pressed in a second, around frame {i}"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        disk::write(path, save::to_string(self)?);
+        Ok(())
+    }
+}
+
+/// Play a replay through without a window and check that it ends on the score it claims. It's
+/// played by the rules it recorded, whatever `settings` say, which only supply everything else,
+/// like whether to check its hashes. Returns the score.
+pub fn verify(replay: &Replay, settings: Settings) -> Result<usize, String> {
+    let Some(claimed) = replay.score else {
+        return Err("the replay doesn't say what it scored".to_string());
+    };
+    let settings = settings
+        .with_rules(&replay.rules)
+        .map_err(|e| format!("can't play the replay by its rules: {e}"))?;
+    let mut game = Game::headless(settings);
+    game.set_speed(replay.speed);
+    game.start_seeded(replay.mode, replay.seed);
+    let mut desync = DesyncCheck::default();
+    for frame in &replay.frames {
+        // Some inputs change the board as they come in, like stepping forward a frame or undoing,
+        // so the hash is checked after them
+        for input in &frame.inputs {
+            match *input {
+                Input::Press(action) => game.press(action),
+                Input::Release(action) => game.release(action),
+            }
+        }
+        if let Some(report) = desync.check(&game, frame.hash) {
+            write_report(report)?;
+            return Err(format!(
+                "the replay goes out of step on frame {}, see {DESYNC_FILE}",
+                report.frame
+            ));
+        }
+        game.update(&UpdateArgs { dt: frame.dt });
+    }
+    if game.score() == claimed {
+        Ok(claimed)
+    } else {
+        Err(format!(
+            "the replay claims {claimed} points, but plays out to {}",
+            game.score()
+        ))
+    }
+}
+
+/// Where an observed game gets its inputs from, like a replay file or a network feed
+pub trait InputSource: std::fmt::Debug {
+    /// The mode and seed the game was started with
+    fn setup(&self) -> (GameMode, u64);
+
+    /// The game speed setting the game is played at
+    fn speed(&self) -> f64 {
+        1.0
+    }
+
+    /// The next frame, or `None` if it hasn't arrived yet or the game has ended
+    fn next_frame(&mut self) -> Option<Frame>;
+}
+
+/// Plays back a recorded replay
+#[derive(Debug, Clone)]
+pub struct ReplayPlayer {
+    replay: Replay,
+    position: usize,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            position: 0,
+        }
+    }
+}
+
+impl InputSource for ReplayPlayer {
+    fn setup(&self) -> (GameMode, u64) {
+        (self.replay.mode, self.replay.seed)
+    }
+
+    fn speed(&self) -> f64 {
+        self.replay.speed
+    }
+
+    fn next_frame(&mut self) -> Option<Frame> {
+        let frame = self.replay.frames.get(self.position).cloned();
+        self.position += 1;
+        frame
+    }
+}
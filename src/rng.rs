@@ -0,0 +1,172 @@
+//! The random numbers behind everything that has to play out the same way twice: the pieces dealt,
+//! the sand physics, the garbage rows. Replays only store a seed, so every number drawn from it
+//! has to come out the same on every platform and with every version of every crate, or old
+//! replays stop playing back. The algorithm is written out here rather than borrowed, and whenever
+//! it changes, [`RNG_VERSION`] goes up so that replays made with the old one can be turned away.
+//!
+//! Version 1 is wyrand, as nanorand 0.7 implements it on 64-bit little-endian platforms, which is
+//! what every replay made before the version was recorded used:
+//!
+//! - Each step adds `0xa0761d6478bd642f` to the 64-bit state, then multiplies the state by itself
+//!   xored with `0xe7037ed1a0b428db` as 128-bit numbers, and outputs the high 64 bits xored with
+//!   the low 64 bits.
+//! - A `u64` or `usize` is one output, and a `u32` is its low 32 bits.
+//! - A `bool` is whether the output's low 8 bits are under 128.
+//! - An `f64` is the output divided by `u64::MAX`.
+//! - A number in a range is drawn with Lemire's multiply and shift method, at the width of the
+//!   range's type: `u32` ranges multiply in 64 bits and `usize` ranges in 128 bits.
+//! - A shuffle swaps each item, first to last, with one drawn from the whole slice.
+//!
+//! ```
+//! use sandtris::rng::{GameRng, WyRand};
+//!
+//! let mut rng = WyRand::new_seed(7);
+//! let first = rng.generate::<u64>();
+//! assert_eq!(WyRand::new_seed(7).generate::<u64>(), first);
+//! assert!(rng.generate_range(3..5usize) >= 3);
+//! ```
+
+use std::ops::{Bound, RangeBounds};
+
+/// The version of the algorithm in this module. Bump it whenever a change would draw different
+/// numbers from the same seed.
+pub const RNG_VERSION: u32 = 1;
+
+/// Where the game's random numbers come from
+pub trait GameRng {
+    /// The next 64 bits of output
+    fn next_u64(&mut self) -> u64;
+
+    fn generate<T: Random>(&mut self) -> T
+    where
+        Self: Sized,
+    {
+        T::random(self)
+    }
+
+    /// A number in a range, which must not be empty
+    fn generate_range<T: RandomRange>(&mut self, range: impl RangeBounds<T>) -> T
+    where
+        Self: Sized,
+    {
+        T::random_range(self, range)
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T])
+    where
+        Self: Sized,
+    {
+        for i in 0..items.len() {
+            let j = self.generate_range(0..items.len());
+            items.swap(i, j);
+        }
+    }
+}
+
+/// The wyrand generator, which is small, fast and good enough for a game
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WyRand {
+    state: u64,
+}
+
+impl WyRand {
+    /// A generator seeded from the system's source of entropy, for anything that doesn't have to
+    /// play out the same way again
+    pub fn new() -> Self {
+        Self::new_seed(nanorand::Rng::generate(&mut nanorand::WyRand::new()))
+    }
+
+    pub const fn new_seed(seed: u64) -> Self {
+        Self { state: seed }
+    }
+}
+
+impl Default for WyRand {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GameRng for WyRand {
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0xa0761d6478bd642f);
+        let t = (self.state as u128).wrapping_mul((self.state ^ 0xe7037ed1a0b428db) as u128);
+        ((t >> 64) ^ t) as u64
+    }
+}
+
+/// Something that can be drawn at random
+pub trait Random {
+    fn random(rng: &mut impl GameRng) -> Self;
+}
+
+/// A number that can be drawn from a range
+pub trait RandomRange: Random + Sized {
+    fn random_range(rng: &mut impl GameRng, range: impl RangeBounds<Self>) -> Self;
+}
+
+impl Random for u64 {
+    fn random(rng: &mut impl GameRng) -> Self {
+        rng.next_u64()
+    }
+}
+
+impl Random for usize {
+    fn random(rng: &mut impl GameRng) -> Self {
+        rng.next_u64() as usize
+    }
+}
+
+impl Random for u32 {
+    fn random(rng: &mut impl GameRng) -> Self {
+        rng.next_u64() as u32
+    }
+}
+
+impl Random for bool {
+    fn random(rng: &mut impl GameRng) -> Self {
+        (rng.next_u64() as u8) < 0x80
+    }
+}
+
+impl Random for f64 {
+    fn random(rng: &mut impl GameRng) -> Self {
+        rng.next_u64() as f64 / u64::MAX as f64
+    }
+}
+
+/// Lemire's method, at a fixed width so that `usize` ranges draw the same numbers on 32-bit
+/// platforms as on 64-bit ones
+macro_rules! random_range {
+    ($type:ty, $bits:ty, $wide:ty) => {
+        impl RandomRange for $type {
+            fn random_range(rng: &mut impl GameRng, range: impl RangeBounds<Self>) -> Self {
+                const BITS: u32 = <$bits>::BITS;
+                let lower = match range.start_bound() {
+                    Bound::Included(lower) => *lower as $bits,
+                    Bound::Excluded(lower) => (*lower as $bits).saturating_add(1),
+                    Bound::Unbounded => 0,
+                };
+                let upper = match range.end_bound() {
+                    Bound::Included(upper) => (*upper as $bits).saturating_add(1),
+                    Bound::Excluded(upper) => *upper as $bits,
+                    Bound::Unbounded => <$bits>::MAX,
+                };
+                assert!(upper > lower, "can't draw from an empty range");
+                let span = upper - lower;
+                let mut m = (span as $wide).wrapping_mul(<$bits>::random(rng) as $wide);
+                if (m as $bits) < span {
+                    // Draws that would make some numbers more likely than others are thrown away
+                    let threshold = span.wrapping_neg() % span;
+                    while (m as $bits) < threshold {
+                        m = (span as $wide).wrapping_mul(<$bits>::random(rng) as $wide);
+                    }
+                }
+                ((m >> BITS) as $bits + lower) as $type
+            }
+        }
+    };
+}
+
+random_range!(u32, u32, u64);
+random_range!(usize, u64, u128);
@@ -0,0 +1,88 @@
+//! The rules for what sand clears. The game asks its mode for a set of rules and leaves finding
+//! clears to them, so a mode can clear differently without the game needing to know about it.
+
+use std::fmt::Debug;
+
+use crate::board::Board;
+use crate::pathfinding::{
+    find_connected_sand, find_connected_sand_in_band, find_spanning_path, find_spanning_paths,
+    Connectivity,
+};
+
+/// A group of grains that spans the board and is ready to clear
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clear {
+    /// A path from wall to wall through the group
+    pub path: Vec<(usize, usize)>,
+    /// Every grain that clears with it
+    pub grains: Vec<(usize, usize)>,
+}
+
+pub trait ClearRules: Debug {
+    /// Which grains count as touching
+    fn connectivity(&self) -> Connectivity;
+
+    /// How many grains a group needs before it clears. Groups too thin to count stay on the board
+    /// until more sand joins them.
+    fn min_grains(&self) -> usize {
+        0
+    }
+
+    /// Whether every group that spans the board clears at once, or only the first one found
+    fn multiple_groups(&self) -> bool {
+        true
+    }
+
+    /// The grains that clear with the group that `path` runs through
+    fn group(&self, board: &Board, path: &[(usize, usize)]) -> Vec<(usize, usize)> {
+        let (x, y) = path[0];
+        find_connected_sand(board, x, y, self.connectivity())
+    }
+
+    /// Every group on the board that's ready to clear
+    fn find_clears(&self, board: &Board) -> Vec<Clear> {
+        let paths = if self.multiple_groups() {
+            find_spanning_paths(board, self.connectivity())
+        } else {
+            find_spanning_path(board, self.connectivity())
+                .into_iter()
+                .collect()
+        };
+        paths
+            .into_iter()
+            .map(|path| Clear {
+                grains: self.group(board, &path),
+                path,
+            })
+            .filter(|clear| clear.grains.len() >= self.min_grains())
+            .collect()
+    }
+}
+
+/// The rules every mode uses, adjusted by the settings and modifiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StandardRules {
+    pub connectivity: Connectivity,
+    pub min_grains: usize,
+    /// If set, only grains within this many rows of the spanning path clear, see
+    /// [`find_connected_sand_in_band`]
+    pub band: Option<usize>,
+}
+
+impl ClearRules for StandardRules {
+    fn connectivity(&self) -> Connectivity {
+        self.connectivity
+    }
+
+    fn min_grains(&self) -> usize {
+        self.min_grains
+    }
+
+    fn group(&self, board: &Board, path: &[(usize, usize)]) -> Vec<(usize, usize)> {
+        let (x, y) = path[0];
+        match self.band {
+            Some(band) => find_connected_sand_in_band(board, x, y, path, band, self.connectivity),
+            None => find_connected_sand(board, x, y, self.connectivity),
+        }
+    }
+}
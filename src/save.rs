@@ -0,0 +1,179 @@
+//! Serializable game state, the foundation for saves, replays and networking.
+//!
+//! Everything is written inside a versioned envelope. Files from older versions of the format can
+//! always be read, since fields added later have defaults, and files from newer versions are
+//! rejected with a clear error instead of being misread. The envelope also records which version
+//! of the [random number algorithm](crate::rng) wrote the file, for files like replays that only
+//! play back the same way with the same random numbers.
+
+use ndarray::Array2;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::constants::Color;
+use crate::game::Block;
+use crate::mode::GameMode;
+use crate::rng::RNG_VERSION;
+
+/// The current version of the save format. Bump it whenever a change would make older versions
+/// misread a save, like changing the meaning of an existing field.
+///
+/// - Version 2 added rows hidden above the top of the board to [`GameSnapshot::sand`], which
+///   older versions would take for part of the board.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// A game position: the board and pieces, without the random state or timers. Restoring one deals
+/// fresh pieces after `next_block`, and counts every grain as having just landed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameSnapshot {
+    pub mode: GameMode,
+    /// The whole board, starting with `hidden_rows` rows of grains above the top of the view
+    #[serde(with = "board")]
+    pub sand: Array2<Option<Color>>,
+    /// Saves from before the board had hidden rows have none
+    #[serde(default)]
+    pub hidden_rows: usize,
+    #[serde(default)]
+    pub falling_block: Option<Block>,
+    pub next_block: Block,
+    #[serde(default)]
+    pub held_block: Option<Block>,
+    #[serde(default)]
+    pub hold_used: bool,
+    #[serde(default)]
+    pub score: usize,
+    #[serde(default)]
+    pub combo: usize,
+}
+
+impl GameSnapshot {
+    /// The same position on a board with `rows` rows of grains hidden above the view, moving the
+    /// sand and the falling piece down or up to make room for them or take them away. Sand or a
+    /// piece that would end up above the board is an error.
+    pub fn with_hidden_rows(mut self, rows: usize) -> Result<Self, String> {
+        let (width, height) = self.sand.dim();
+        let visible = height
+            .checked_sub(self.hidden_rows)
+            .ok_or("saved board is shorter than its hidden rows")?;
+        let mut sand = Array2::default((width, visible + rows));
+        for ((x, y), color) in self.sand.indexed_iter() {
+            if color.is_some() {
+                let y = (y + rows)
+                    .checked_sub(self.hidden_rows)
+                    .ok_or("saved sand doesn't fit above the board")?;
+                sand[(x, y)] = *color;
+            }
+        }
+        if let Some(block) = &mut self.falling_block {
+            block.y = (block.y + rows)
+                .checked_sub(self.hidden_rows)
+                .ok_or("saved piece doesn't fit above the board")?;
+        }
+        self.sand = sand;
+        self.hidden_rows = rows;
+        Ok(self)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    version: u32,
+    #[serde(default = "first_rng_version")]
+    rng: u32,
+    data: T,
+}
+
+/// Just the versions, read first so that newer formats are never parsed as the current one
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+    #[serde(default = "first_rng_version")]
+    rng: u32,
+}
+
+/// Files from before the RNG version was recorded were all written with the first one
+fn first_rng_version() -> u32 {
+    1
+}
+
+/// Serialize a value as JSON, inside a versioned envelope
+pub fn to_string<T: Serialize>(data: &T) -> Result<String, String> {
+    serde_json::to_string(&Envelope {
+        version: FORMAT_VERSION,
+        rng: RNG_VERSION,
+        data,
+    })
+    .map_err(|e| format!("could not serialize save: {e}"))
+}
+
+/// Read a value written by [`to_string`], by this version or an older one
+pub fn from_str<T: DeserializeOwned>(source: &str) -> Result<T, String> {
+    read_header(source)?;
+    read_data(source)
+}
+
+/// Read a value written by [`to_string`] that only plays back the same way with the same random
+/// numbers, like a replay. One written with a different version of the random number algorithm is
+/// turned away, since it would play out differently.
+pub fn from_str_seeded<T: DeserializeOwned>(source: &str) -> Result<T, String> {
+    let header = read_header(source)?;
+    if header.rng != RNG_VERSION {
+        return Err(format!(
+            "save was made with version {} of the random number algorithm, and this version of \
+             sandtris only plays back version {RNG_VERSION}",
+            header.rng
+        ));
+    }
+    read_data(source)
+}
+
+fn read_header(source: &str) -> Result<Header, String> {
+    let header: Header = serde_json::from_str(source).map_err(|e| format!("invalid save: {e}"))?;
+    if header.version > FORMAT_VERSION {
+        return Err(format!(
+            "save is from a newer version of sandtris (format {}, only {FORMAT_VERSION} and \
+             older are supported)",
+            header.version
+        ));
+    }
+    Ok(header)
+}
+
+fn read_data<T: DeserializeOwned>(source: &str) -> Result<T, String> {
+    serde_json::from_str::<Envelope<T>>(source)
+        .map(|envelope| envelope.data)
+        .map_err(|e| format!("invalid save: {e}"))
+}
+
+/// Boards in the compact [encoding](crate::encoding), written as a hex string. Use with
+/// `#[serde(with = "board")]`.
+pub mod board {
+    use super::*;
+    use crate::encoding::{decode_board, encode_board};
+
+    pub fn serialize<S: Serializer>(
+        board: &Array2<Option<Color>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        encode_board(board)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Array2<Option<Color>>, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                hex.get(i..i + 2)
+                    .and_then(|h| u8::from_str_radix(h, 16).ok())
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| serde::de::Error::custom("board isn't a hex string"))?;
+        decode_board(&bytes).map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,189 @@
+//! Input scripts: hand-written lists of what to press on which frame, for tool-assisted runs and
+//! for setting up exact situations when debugging the physics. A script is played like a replay,
+//! so it can be paused and stepped through a frame at a time.
+//!
+//! Each line is a setting or a frame number followed by what happens on that frame. Frames are
+//! counted from 0, every frame is the same length, and the inputs on a frame come in before it
+//! plays. `tap` presses on the frame and releases on the next one.
+//!
+//! ```
+//! use sandtris::replay::{Input, InputSource};
+//! use sandtris::input::Action;
+//! use sandtris::script::{InputScript, ScriptPlayer};
+//!
+//! let script: InputScript = "
+//!     mode marathon
+//!     seed 7
+//!     30 tap left  # Move the first piece over
+//!     60 tap drop  # and drop it
+//!     frames 120
+//! "
+//! .parse()
+//! .unwrap();
+//! let mut player = ScriptPlayer::new(script);
+//! let frames = std::iter::from_fn(|| player.next_frame()).collect::<Vec<_>>();
+//! assert_eq!(frames.len(), 120);
+//! assert_eq!(frames[30].inputs, vec![Input::Press(Action::Left)]);
+//! assert_eq!(frames[31].inputs, vec![Input::Release(Action::Left)]);
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::constants::FRAME_STEP;
+use crate::input::Action;
+use crate::mode::GameMode;
+use crate::replay::{Frame, Input, InputSource};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputScript {
+    pub mode: GameMode,
+    pub seed: u64,
+    /// The game speed setting to play at
+    pub speed: f64,
+    /// How long each frame is, in seconds
+    pub step: f64,
+    /// The inputs on each frame that has any
+    pub inputs: BTreeMap<usize, Vec<Input>>,
+    /// How many frames the script runs for
+    pub frames: usize,
+}
+
+impl InputScript {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {e}", path.display()))?
+            .parse()
+            .map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+impl FromStr for InputScript {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mode = GameMode::Marathon;
+        let mut seed = 0;
+        let mut speed = 1.0;
+        let mut step = FRAME_STEP;
+        let mut frames = None;
+        let mut inputs = BTreeMap::<usize, Vec<Input>>::new();
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: String| format!("line {}: {message}", i + 1);
+            let (key, value) = line
+                .split_once(char::is_whitespace)
+                .map(|(key, value)| (key, value.trim()))
+                .ok_or_else(|| error(format!("missing value for {line}")))?;
+            let number = |value: &str| {
+                value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|n| n.is_finite() && *n > 0.0)
+                    .ok_or_else(|| error(format!("invalid {key}: {value}")))
+            };
+            match key {
+                "mode" => mode = value.parse().map_err(error)?,
+                "seed" => {
+                    seed = value
+                        .parse()
+                        .map_err(|_| error(format!("invalid seed: {value}")))?
+                }
+                "speed" => speed = number(value)?,
+                "step" => step = number(value)?,
+                "frames" => {
+                    frames = Some(
+                        value
+                            .parse()
+                            .map_err(|_| error(format!("invalid frame count: {value}")))?,
+                    )
+                }
+                frame => {
+                    let frame = frame
+                        .parse::<usize>()
+                        .map_err(|_| error(format!("unknown setting: {frame}")))?;
+                    let (kind, action) = value
+                        .split_once(char::is_whitespace)
+                        .ok_or_else(|| error(format!("missing action on frame {frame}")))?;
+                    let action = action.trim().parse::<Action>().map_err(error)?;
+                    match kind {
+                        "press" => inputs.entry(frame).or_default().push(Input::Press(action)),
+                        "release" => inputs
+                            .entry(frame)
+                            .or_default()
+                            .push(Input::Release(action)),
+                        "tap" => {
+                            inputs.entry(frame).or_default().push(Input::Press(action));
+                            inputs
+                                .entry(frame + 1)
+                                .or_default()
+                                .push(Input::Release(action));
+                        }
+                        other => return Err(error(format!("invalid input: {other}"))),
+                    }
+                }
+            }
+        }
+
+        // Without a length, the script ends on the frame after its last input
+        let last = inputs.keys().next_back().map_or(0, |frame| frame + 1);
+        Ok(Self {
+            mode,
+            seed,
+            speed,
+            step,
+            frames: frames.unwrap_or(last),
+            inputs,
+        })
+    }
+}
+
+/// Plays an input script, one frame at a time
+#[derive(Debug, Clone)]
+pub struct ScriptPlayer {
+    script: InputScript,
+    position: usize,
+}
+
+impl ScriptPlayer {
+    pub fn new(script: InputScript) -> Self {
+        Self {
+            script,
+            position: 0,
+        }
+    }
+}
+
+impl InputSource for ScriptPlayer {
+    fn setup(&self) -> (GameMode, u64) {
+        (self.script.mode, self.script.seed)
+    }
+
+    fn speed(&self) -> f64 {
+        self.script.speed
+    }
+
+    fn next_frame(&mut self) -> Option<Frame> {
+        if self.position >= self.script.frames {
+            return None;
+        }
+        let frame = Frame {
+            dt: self.script.step,
+            inputs: self
+                .script
+                .inputs
+                .get(&self.position)
+                .cloned()
+                .unwrap_or_default(),
+            hash: None,
+        };
+        self.position += 1;
+        Some(frame)
+    }
+}
@@ -0,0 +1,817 @@
+//! Settings, loaded from the config file and command line.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use enum_map::EnumMap;
+use ndarray::Array2;
+
+use crate::camera::CameraMode;
+use crate::constants::{
+    parse_shapes, Color, Shape, AUTOSAVE_INTERVAL, CLEAR_DURATION, CLEAR_FLASHES, DROP_LOCKOUT,
+    IDLE_PAUSE_DELAY, MAX_CLEAR_DURATION, MAX_GAME_SPEED, MIN_GAME_SPEED, PENTOMINO_SHAPES,
+    SAND_BLOCK_SIZE, TETROMINO_SHAPES, TIMELAPSE_INTERVAL,
+};
+use crate::guides::{parse_guide_color, Guides};
+use crate::input::ControlScheme;
+use crate::keymap::{parse_keys, KeyPreset, Keymap, KEYS_FILE};
+use crate::layout::Layout;
+use crate::mode::{GameMode, Modifiers};
+use crate::quality::Quality;
+use crate::randomizer::{bag_weights, Bag, History, PureRandom, Randomizer, MAX_BAG_SIZE};
+use crate::special::Special;
+
+/// The config file that is loaded on startup if it exists
+pub const CONFIG_FILE: &str = "sandtris.cfg";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub spawn_position: SpawnPosition,
+    /// What happens to sand pushed up past the top of the board
+    pub overflow: Overflow,
+    pub randomizer: RandomizerKind,
+    pub shape_pack: ShapePack,
+    /// The layout file of a font to draw text with in place of the bundled one, see
+    /// [`crate::assets::FontLayout`]
+    pub font: Option<PathBuf>,
+    /// Relative spawn weights for each shape in the shape pack, in order. `None` means every shape
+    /// is equally likely.
+    pub shape_weights: Option<Vec<u32>>,
+    /// Relative spawn weights for each color
+    pub color_weights: EnumMap<Color, u32>,
+    pub modifiers: Modifiers,
+    /// The fewest grains a group that spans the board has to clear for it to count
+    pub min_clear_grains: usize,
+    /// The fewest grains to clear for particular modes in place of `min_clear_grains`, by
+    /// lowercase mode name
+    pub mode_min_clear_grains: Vec<(String, usize)>,
+    /// The modes where grains touching at the corners count as connected for clears, by lowercase
+    /// mode name
+    pub diagonal_clears: Vec<String>,
+    /// How clears are animated as their grains are taken away
+    pub clear_animation: ClearAnimation,
+    /// The clear animation for particular modes in place of `clear_animation`, by lowercase mode
+    /// name
+    pub mode_clear_animations: Vec<(String, ClearAnimation)>,
+    /// The size of the board, which also sets the size of the window, and how many rows are hidden
+    /// above it
+    pub board: Layout,
+    /// Whether the board is always shown whole, or zoomed in on the falling piece
+    pub camera: CameraMode,
+    pub guides: Guides,
+    pub controls: ControlScheme,
+    pub keymap: Keymap,
+    /// The keymap from the config file, before any keys rebound on the key binding screen
+    pub default_keymap: Keymap,
+    /// Hard drops pressed less than this many seconds after a piece spawns are ignored
+    pub drop_lockout: f64,
+    /// Hard drop only once the key has been held for a moment, rather than on a tap
+    pub hold_to_drop: bool,
+    /// Give pieces a moment after landing where they can be nudged a cell to either side, to
+    /// forgive drops that were just off
+    pub lock_nudge: bool,
+    /// Mark where the greedy bot would drop the falling piece
+    pub hint: bool,
+    /// Outline the pile the falling piece would slump into if it were dropped now
+    pub pile_preview: bool,
+    /// Show a card with a mode's rules before it starts
+    pub rules_cards: bool,
+    /// Show the next piece a second time as the grains of sand it turns into
+    pub sand_preview: bool,
+    /// Show how many of each shape have been dealt and how many clears each took part in, in place
+    /// of the scoring feed when there's room for them on the dashboard. They're always on the game
+    /// over analysis.
+    pub piece_stats: bool,
+    /// Leave out effects that are only there for show, like the streak behind a hard-dropped
+    /// piece and the dust it kicks up
+    pub reduce_motion: bool,
+    /// What the special key does once the special meter is full, or `None` for no special meter
+    pub special: Option<Special>,
+    /// A fixed render quality, or `None` to adjust it to how fast frames are drawn
+    pub render_quality: Option<Quality>,
+    /// The most frames drawn each second, or `None` for as many as possible
+    pub max_fps: Option<u64>,
+    /// How many pieces lock between snapshots of the board for the time-lapse, or 0 for none
+    pub timelapse_interval: usize,
+    /// How often the run in progress is saved in case the game crashes, in seconds, or 0 for never
+    pub autosave_interval: f64,
+    /// How long a game can go without any input before it dims the screen and pauses itself, in
+    /// seconds, or 0 for never
+    pub idle_pause: f64,
+    /// How fast the game runs, as a multiple of normal speed. Gravity, the physics and held keys
+    /// all speed up or slow down together.
+    pub game_speed: f64,
+    /// Print every game event to stdout
+    pub log_events: bool,
+    /// Record a hash of the board on every frame of a replay, so that playing it back can tell
+    /// exactly where it went out of step. On by default in debug builds.
+    pub replay_hashes: bool,
+    /// The command line arguments the settings were loaded with, so they can be reapplied when the
+    /// config file is reloaded
+    args: Vec<String>,
+    /// Every setting applied that changes how a game plays out, as `key = value` pairs in the order
+    /// they were applied, for replays to record
+    rules: Vec<(String, String)>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            spawn_position: SpawnPosition::Centered,
+            overflow: Overflow::GameOver,
+            randomizer: RandomizerKind::Random,
+            shape_pack: ShapePack::Tetromino,
+            font: None,
+            shape_weights: None,
+            color_weights: EnumMap::from_fn(|_| 1),
+            modifiers: Modifiers::default(),
+            min_clear_grains: 0,
+            mode_min_clear_grains: Vec::new(),
+            diagonal_clears: Vec::new(),
+            clear_animation: ClearAnimation::default(),
+            mode_clear_animations: Vec::new(),
+            board: Layout::default(),
+            camera: CameraMode::Fit,
+            guides: Guides::default(),
+            controls: ControlScheme::Keyboard,
+            keymap: Keymap::default(),
+            default_keymap: Keymap::default(),
+            drop_lockout: DROP_LOCKOUT,
+            hold_to_drop: false,
+            lock_nudge: false,
+            hint: false,
+            pile_preview: false,
+            rules_cards: true,
+            sand_preview: false,
+            piece_stats: false,
+            reduce_motion: false,
+            special: None,
+            render_quality: None,
+            max_fps: Some(60),
+            timelapse_interval: TIMELAPSE_INTERVAL,
+            autosave_interval: AUTOSAVE_INTERVAL,
+            idle_pause: IDLE_PAUSE_DELAY,
+            game_speed: 1.0,
+            log_events: false,
+            replay_hashes: cfg!(debug_assertions),
+            args: Vec::new(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// Build settings from the config file (if there is one), then the keys rebound in game, and
+    /// then command line arguments, falling back to the defaults for anything not specified. Every
+    /// config file key can also be given as a `--key value` argument.
+    pub fn load(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut settings = Self {
+            args: args.collect(),
+            ..Self::default()
+        };
+        if Path::new(CONFIG_FILE).exists() {
+            settings.apply_file(Path::new(CONFIG_FILE))?;
+        }
+        settings.default_keymap = settings.keymap.clone();
+        if Path::new(KEYS_FILE).exists() {
+            settings.apply_file(Path::new(KEYS_FILE))?;
+        }
+        settings.apply_args(settings.args.clone().into_iter())?;
+        Ok(settings)
+    }
+
+    /// Load the settings again from the same config files and arguments. The shape pack and font
+    /// can't be swapped out while the game is running, and the window is sized for the board, so
+    /// changing any of them is an error.
+    pub fn reload(&self) -> Result<Self, String> {
+        let settings = Self::load(self.args.clone().into_iter())?;
+        if settings.shape_pack != self.shape_pack {
+            return Err("changing the shape pack needs a restart".to_string());
+        }
+        if settings.font != self.font {
+            return Err("changing the font needs a restart".to_string());
+        }
+        if settings.board != self.board {
+            return Err("changing the board size needs a restart".to_string());
+        }
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Every config file the settings are read from
+    pub fn config_files(&self) -> Vec<PathBuf> {
+        let mut files = vec![PathBuf::from(CONFIG_FILE)];
+        let mut args = self.args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--config" {
+                files.extend(args.next().map(PathBuf::from));
+            }
+        }
+        files
+    }
+
+    fn apply_args(&mut self, mut args: impl Iterator<Item = String>) -> Result<(), String> {
+        while let Some(arg) = args.next() {
+            let Some(key) = arg.strip_prefix("--") else {
+                return Err(format!("unknown argument: {arg}"));
+            };
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value for {arg}"))?;
+            match key {
+                "config" => self.apply_file(Path::new(&value))?,
+                key => self.set(key, &value)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply a config file made of `key = value` lines, where lines starting with `#` are comments
+    fn apply_file(&mut self, path: &Path) -> Result<(), String> {
+        let source = fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {e}", path.display()))?;
+        for (i, line) in source.lines().enumerate().map(|(i, l)| (i, l.trim())) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            line.split_once('=')
+                .ok_or_else(|| "expected `key = value`".to_string())
+                .and_then(|(key, value)| self.set(key.trim(), value.trim()))
+                .map_err(|e| format!("{}:{}: {e}", path.display(), i + 1))?;
+        }
+        Ok(())
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "spawn" => self.spawn_position = value.parse()?,
+            "overflow" => self.overflow = value.parse()?,
+            "randomizer" => self.randomizer = value.parse()?,
+            "shapes" => self.shape_pack = value.parse()?,
+            "font" => {
+                self.font = match value {
+                    "bundled" => None,
+                    path => Some(path.into()),
+                }
+            }
+            "shape_weights" => {
+                self.shape_weights = Some(
+                    value
+                        .split(',')
+                        .map(|w| parse_weight(w.trim()))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            "color_weights" => {
+                for entry in value.split(',') {
+                    let (color, weight) = entry
+                        .split_once(':')
+                        .ok_or_else(|| format!("expected `color:weight`, got {entry:?}"))?;
+                    self.color_weights[color.trim().parse::<Color>()?] =
+                        parse_weight(weight.trim())?;
+                }
+            }
+            "modifiers" => self.modifiers = value.parse()?,
+            "min_clear_grains" => self.min_clear_grains = parse_grain_count(value)?,
+            "diagonal_clears" => self.diagonal_clears = parse_modes(value)?,
+            "clear_animation" => self.clear_animation = value.parse()?,
+            "board_size" => {
+                let hidden_rows = self.board.hidden_rows;
+                self.board = value.parse::<Layout>()?.with_hidden_rows(hidden_rows)?;
+            }
+            "hidden_rows" => {
+                let rows = value
+                    .parse()
+                    .map_err(|_| format!("invalid row count: {value}"))?;
+                self.board = self.board.with_hidden_rows(rows)?;
+            }
+            "camera" => self.camera = value.parse()?,
+            "guides" => {
+                self.guides = match parse_bool(value)? {
+                    true => Guides::standard(),
+                    false => Guides::default(),
+                }
+            }
+            "guide_frame" => self.guides.frame = parse_guide_color(value)?,
+            "guide_grid" => self.guides.grid = parse_guide_color(value)?,
+            "guide_spawn_zone" => self.guides.spawn_zone = parse_guide_color(value)?,
+            "guide_kill_line" => self.guides.kill_line = parse_guide_color(value)?,
+            "controls" => self.controls = value.parse()?,
+            // Choosing a preset starts the keys over, so it goes before any `bind_` lines
+            "keys" => self.keymap = Keymap::preset(value.parse::<KeyPreset>()?),
+            key if key.starts_with("bind_") => {
+                let action = key["bind_".len()..].parse()?;
+                self.keymap.bind(action, parse_keys(value)?);
+            }
+            "drop_lockout" => {
+                self.drop_lockout = value
+                    .parse()
+                    .ok()
+                    .filter(|lockout: &f64| *lockout >= 0.0)
+                    .ok_or_else(|| format!("invalid drop lockout: {value}"))?
+            }
+            "hold_to_drop" => self.hold_to_drop = parse_bool(value)?,
+            "lock_nudge" => self.lock_nudge = parse_bool(value)?,
+            "hint" => self.hint = parse_bool(value)?,
+            "pile_preview" => self.pile_preview = parse_bool(value)?,
+            "rules_cards" => self.rules_cards = parse_bool(value)?,
+            "sand_preview" => self.sand_preview = parse_bool(value)?,
+            "piece_stats" => self.piece_stats = parse_bool(value)?,
+            "reduce_motion" => self.reduce_motion = parse_bool(value)?,
+            "special" => {
+                self.special = match value {
+                    "none" => None,
+                    value => Some(value.parse()?),
+                }
+            }
+            "render_quality" => {
+                self.render_quality = match value {
+                    "auto" => None,
+                    value => Some(value.parse()?),
+                }
+            }
+            "max_fps" => {
+                self.max_fps = match value {
+                    "uncapped" => None,
+                    value => Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|fps| *fps > 0)
+                            .ok_or_else(|| format!("invalid frame rate: {value}"))?,
+                    ),
+                }
+            }
+            "timelapse_interval" => {
+                self.timelapse_interval = value
+                    .parse()
+                    .map_err(|_| format!("invalid time-lapse interval: {value}"))?
+            }
+            "autosave_interval" => {
+                self.autosave_interval = value
+                    .parse()
+                    .ok()
+                    .filter(|interval: &f64| *interval >= 0.0)
+                    .ok_or_else(|| format!("invalid autosave interval: {value}"))?
+            }
+            "idle_pause" => {
+                self.idle_pause = value
+                    .parse()
+                    .ok()
+                    .filter(|delay: &f64| *delay >= 0.0)
+                    .ok_or_else(|| format!("invalid idle pause delay: {value}"))?
+            }
+            "game_speed" => {
+                self.game_speed = value
+                    .parse()
+                    .ok()
+                    .filter(|speed| (MIN_GAME_SPEED..=MAX_GAME_SPEED).contains(speed))
+                    .ok_or_else(|| {
+                        format!(
+                            "the game speed has to be between {MIN_GAME_SPEED} and \
+                             {MAX_GAME_SPEED}, not {value}"
+                        )
+                    })?
+            }
+            "log_events" => self.log_events = parse_bool(value)?,
+            "replay_hashes" => self.replay_hashes = parse_bool(value)?,
+            key if key.starts_with("clear_animation.") => {
+                let animation = value.parse::<ClearAnimation>()?;
+                for mode in parse_modes(&key["clear_animation.".len()..])? {
+                    self.mode_clear_animations.retain(|(name, _)| *name != mode);
+                    self.mode_clear_animations.push((mode, animation));
+                }
+            }
+            key if key.starts_with("min_clear_grains.") => {
+                let grains = parse_grain_count(value)?;
+                for mode in parse_modes(&key["min_clear_grains.".len()..])? {
+                    self.mode_min_clear_grains.retain(|(name, _)| *name != mode);
+                    self.mode_min_clear_grains.push((mode, grains));
+                }
+            }
+            _ => return Err(format!("unknown setting: {key}")),
+        }
+        if is_rule(key) {
+            self.rules.push((key.to_string(), value.to_string()));
+        }
+        Ok(())
+    }
+
+    /// The settings applied that change how a game plays out, as `key = value` pairs
+    pub fn rules(&self) -> &[(String, String)] {
+        &self.rules
+    }
+
+    /// These settings, but with the ones that change how a game plays out set by `rules` in place
+    /// of whatever they were, like [`Settings::rules`] from another copy of the game. The shape
+    /// pack is loaded once at startup, so rules that ask for a different one are an error.
+    pub fn with_rules(&self, rules: &[(String, String)]) -> Result<Self, String> {
+        let defaults = Self::default();
+        let mut settings = Self {
+            spawn_position: defaults.spawn_position,
+            overflow: defaults.overflow,
+            randomizer: defaults.randomizer,
+            shape_pack: defaults.shape_pack,
+            shape_weights: defaults.shape_weights,
+            color_weights: defaults.color_weights,
+            modifiers: defaults.modifiers,
+            min_clear_grains: defaults.min_clear_grains,
+            mode_min_clear_grains: defaults.mode_min_clear_grains,
+            diagonal_clears: defaults.diagonal_clears,
+            clear_animation: defaults.clear_animation,
+            mode_clear_animations: defaults.mode_clear_animations,
+            board: defaults.board,
+            drop_lockout: defaults.drop_lockout,
+            hold_to_drop: defaults.hold_to_drop,
+            lock_nudge: defaults.lock_nudge,
+            special: defaults.special,
+            rules: Vec::new(),
+            ..self.clone()
+        };
+        for (key, value) in rules {
+            if !is_rule(key) {
+                return Err(format!("{key} isn't a rule"));
+            }
+            settings.set(key, value)?;
+        }
+        if settings.shape_pack != self.shape_pack {
+            return Err("the rules need a different shape pack, which needs a restart".to_string());
+        }
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Check the settings that can only be checked once the shape pack is loaded
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(weights) = &self.shape_weights {
+            let shapes = Shape::all().count();
+            if weights.len() != shapes {
+                return Err(format!(
+                    "shape_weights has {} weights, but the shape pack has {shapes} shapes",
+                    weights.len()
+                ));
+            }
+            if weights.iter().all(|w| *w == 0) {
+                return Err("shape_weights must not all be zero".to_string());
+            }
+            if total_weight(weights.iter()).is_none() {
+                return Err(format!("shape_weights must add up to at most {}", u32::MAX));
+            }
+            // The bag holds every piece it'll deal until it's refilled
+            if self.randomizer == RandomizerKind::Bag
+                && bag_weights(weights).iter().sum::<u32>() > MAX_BAG_SIZE
+            {
+                return Err(format!(
+                    "with the bag randomizer, shape_weights divided by their greatest common \
+                     divisor must add up to at most {MAX_BAG_SIZE}"
+                ));
+            }
+        }
+        if self.color_weights.values().all(|w| *w == 0) {
+            return Err("color_weights must not all be zero".to_string());
+        }
+        if total_weight(self.color_weights.values()).is_none() {
+            return Err(format!("color_weights must add up to at most {}", u32::MAX));
+        }
+        Ok(())
+    }
+
+    pub fn shape_weights(&self) -> Vec<u32> {
+        self.shape_weights
+            .clone()
+            .unwrap_or_else(|| vec![1; Shape::all().count()])
+    }
+}
+
+pub fn parse_bool(s: &str) -> Result<bool, String> {
+    match s {
+        "true" | "yes" | "on" => Ok(true),
+        "false" | "no" | "off" => Ok(false),
+        _ => Err(format!("invalid boolean: {s}")),
+    }
+}
+
+fn parse_grain_count(s: &str) -> Result<usize, String> {
+    s.parse().map_err(|_| format!("invalid grain count: {s}"))
+}
+
+/// Parse a comma-separated list of mode names, or `all` or `none`
+fn parse_modes(s: &str) -> Result<Vec<String>, String> {
+    let modes = GameMode::all()
+        .chain([GameMode::Coop, GameMode::Versus])
+        .map(|mode| mode.name().to_lowercase())
+        .collect::<Vec<_>>();
+    match s {
+        "all" => Ok(modes),
+        "none" => Ok(Vec::new()),
+        s => s
+            .split(',')
+            .map(|name| {
+                let name = name.trim().to_lowercase();
+                if modes.contains(&name) {
+                    Ok(name)
+                } else {
+                    Err(format!("unknown mode: {name}"))
+                }
+            })
+            .collect(),
+    }
+}
+
+/// The weights added up, or `None` if they add up to more than a `u32` can hold
+fn total_weight<'a>(mut weights: impl Iterator<Item = &'a u32>) -> Option<u32> {
+    weights.try_fold(0u32, |total, weight| total.checked_add(*weight))
+}
+
+/// Whether a setting changes how a game plays out, so that a game can only be played back the same
+/// way with it set the same
+fn is_rule(key: &str) -> bool {
+    matches!(
+        key,
+        "spawn"
+            | "overflow"
+            | "randomizer"
+            | "shapes"
+            | "shape_weights"
+            | "color_weights"
+            | "modifiers"
+            | "min_clear_grains"
+            | "diagonal_clears"
+            | "clear_animation"
+            | "board_size"
+            | "hidden_rows"
+            | "drop_lockout"
+            | "hold_to_drop"
+            | "lock_nudge"
+            | "special"
+    ) || key.starts_with("clear_animation.")
+        || key.starts_with("min_clear_grains.")
+}
+
+fn parse_weight(s: &str) -> Result<u32, String> {
+    s.parse().map_err(|_| format!("invalid weight: {s}"))
+}
+
+/// Where a new piece appears horizontally when it spawns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnPosition {
+    /// Spawn in the middle of the board
+    Centered,
+    /// Spawn at the horizontal position the previous piece was locked at
+    Remembered,
+    /// Spawn at a fixed column, measured in blocks from the left edge
+    Column(usize),
+}
+
+impl SpawnPosition {
+    /// Get the x position (in sand pixels) for a block `block_width` blocks wide on a board
+    /// `board_width` sand pixels wide. `last_x` is where the previous piece was locked, if any.
+    pub fn spawn_x(&self, board_width: usize, block_width: usize, last_x: Option<usize>) -> usize {
+        let centered = board_width / 2 - block_width * SAND_BLOCK_SIZE / 2;
+        let x = match self {
+            Self::Centered => centered,
+            Self::Remembered => last_x.unwrap_or(centered),
+            Self::Column(column) => column * SAND_BLOCK_SIZE,
+        };
+        x.min(board_width - block_width * SAND_BLOCK_SIZE)
+    }
+}
+
+impl FromStr for SpawnPosition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "center" | "centered" => Ok(Self::Centered),
+            "remember" | "remembered" => Ok(Self::Remembered),
+            column => column
+                .parse()
+                .map(Self::Column)
+                .map_err(|_| format!("invalid spawn position: {column}")),
+        }
+    }
+}
+
+/// What happens when rising sand pushes grains up past the top of the board, hidden rows and all
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// The game is over
+    GameOver,
+    /// The grains spill off the top and are lost, and their points are taken off the score
+    Spill,
+}
+
+impl FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "game_over" => Ok(Self::GameOver),
+            "spill" => Ok(Self::Spill),
+            other => Err(format!("invalid overflow: {other}")),
+        }
+    }
+}
+
+/// Which strategy is used to pick the next shape
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RandomizerKind {
+    Random,
+    Bag,
+    History,
+}
+
+impl RandomizerKind {
+    pub fn build(&self, weights: Vec<u32>) -> Box<dyn Randomizer> {
+        match self {
+            Self::Random => Box::new(PureRandom::new(weights)),
+            Self::Bag => Box::new(Bag::new(weights)),
+            Self::History => Box::new(History::new(weights)),
+        }
+    }
+}
+
+impl FromStr for RandomizerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(Self::Random),
+            "bag" => Ok(Self::Bag),
+            "history" | "tgm" => Ok(Self::History),
+            other => Err(format!("invalid randomizer: {other}")),
+        }
+    }
+}
+
+/// How the grains in a clear leave the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClearStyle {
+    /// The grains flash on and off
+    Flash,
+    /// The grains fade out together
+    Fade,
+    /// The grains disappear a few at a time, in a scattered order
+    Dissolve,
+    /// The grains are gone straight away
+    Instant,
+}
+
+impl FromStr for ClearStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flash" => Ok(Self::Flash),
+            "fade" => Ok(Self::Fade),
+            "dissolve" => Ok(Self::Dissolve),
+            "instant" | "none" => Ok(Self::Instant),
+            other => Err(format!("invalid clear style: {other}")),
+        }
+    }
+}
+
+/// The animation played while a clear's grains are taken away. The game carries on around it, but
+/// the grains stay pinned in place until it's done. It's written as the style, optionally followed
+/// by how long it lasts in seconds and how many times the grains flash, like `flash 0.5 1`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClearAnimation {
+    pub style: ClearStyle,
+    duration: f64,
+    /// Only used by [`ClearStyle::Flash`]
+    flashes: usize,
+}
+
+impl ClearAnimation {
+    /// How long the animation lasts, in seconds
+    pub fn duration(&self) -> f64 {
+        match self.style {
+            ClearStyle::Instant => 0.0,
+            _ => self.duration,
+        }
+    }
+
+    /// How visible the cleared grains are `time` seconds in, from 0 to 1. Dissolving grains go
+    /// from fully visible to gone each at their own point, see [`ClearAnimation::is_dissolved`].
+    pub fn visibility(&self, time: f64) -> f64 {
+        if self.duration() <= 0.0 {
+            return 0.0;
+        }
+        let progress = (time / self.duration).clamp(0.0, 1.0);
+        match self.style {
+            // Each flash starts with the grains hidden
+            ClearStyle::Flash if (progress * self.flashes as f64).fract() < 0.5 => 0.0,
+            ClearStyle::Flash => 1.0,
+            ClearStyle::Fade | ClearStyle::Dissolve => 1.0 - progress,
+            ClearStyle::Instant => 0.0,
+        }
+    }
+
+    /// Whether a dissolving grain is gone yet, `time` seconds in
+    pub fn is_dissolved(&self, time: f64, (x, y): (usize, usize)) -> bool {
+        // A scattered but steady point from 0 to 1 for each cell, for when its grain disappears
+        let hash = ((x as u64).wrapping_mul(0x9e3779b97f4a7c15) ^ y as u64)
+            .wrapping_mul(0xc2b2ae3d27d4eb4f);
+        let point = (hash >> 11) as f64 / (1u64 << 53) as f64;
+        point >= self.visibility(time)
+    }
+
+    /// Whether the path that set the clear off is outlined `time` seconds in. It's shown for the
+    /// first flash, or the first half of the other animations.
+    pub fn shows_path(&self, time: f64) -> bool {
+        match self.style {
+            ClearStyle::Flash => time < self.duration / self.flashes as f64,
+            _ => time < self.duration() / 2.0,
+        }
+    }
+}
+
+impl Default for ClearAnimation {
+    fn default() -> Self {
+        Self {
+            style: ClearStyle::Flash,
+            duration: CLEAR_DURATION,
+            flashes: CLEAR_FLASHES,
+        }
+    }
+}
+
+impl FromStr for ClearAnimation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let mut animation = Self {
+            style: parts.next().unwrap_or_default().parse()?,
+            ..Self::default()
+        };
+        if let Some(duration) = parts.next() {
+            animation.duration = duration
+                .parse()
+                .ok()
+                .filter(|duration: &f64| (0.0..=MAX_CLEAR_DURATION).contains(duration))
+                .ok_or_else(|| {
+                    format!(
+                        "the clear duration has to be between 0 and {MAX_CLEAR_DURATION} seconds, \
+                         not {duration}"
+                    )
+                })?;
+        }
+        if let Some(flashes) = parts.next() {
+            animation.flashes = flashes
+                .parse()
+                .ok()
+                .filter(|flashes| *flashes > 0)
+                .ok_or_else(|| format!("invalid flash count: {flashes}"))?;
+        }
+        match parts.next() {
+            Some(extra) => Err(format!("unexpected {extra} after the clear animation")),
+            None => Ok(animation),
+        }
+    }
+}
+
+/// Which set of shapes pieces are drawn from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShapePack {
+    Tetromino,
+    Pentomino,
+    /// Both the tetrominoes and the pentominoes
+    Mixed,
+    /// A shape pack loaded from a file, in the same format as the bundled packs
+    File(PathBuf),
+}
+
+impl ShapePack {
+    pub fn load(&self) -> Result<Vec<Array2<bool>>, String> {
+        match self {
+            Self::Tetromino => parse_shapes(TETROMINO_SHAPES),
+            Self::Pentomino => parse_shapes(PENTOMINO_SHAPES),
+            Self::Mixed => Ok([
+                parse_shapes(TETROMINO_SHAPES)?,
+                parse_shapes(PENTOMINO_SHAPES)?,
+            ]
+            .concat()),
+            Self::File(path) => fs::read_to_string(path)
+                .map_err(|e| format!("could not read {}: {e}", path.display()))
+                .and_then(|source| parse_shapes(&source))
+                .map_err(|e| format!("invalid shape pack {}: {e}", path.display())),
+        }
+    }
+}
+
+impl FromStr for ShapePack {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tetromino" => Ok(Self::Tetromino),
+            "pentomino" => Ok(Self::Pentomino),
+            "mixed" => Ok(Self::Mixed),
+            path => Ok(Self::File(path.into())),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! The special meter, which fills up with clears and chains. Once it's full, the special key sets
+//! off whichever special effect is chosen in the settings, and the meter starts over.
+
+use std::str::FromStr;
+
+use crate::constants::{SPECIAL_CHAIN_CHARGE, SPECIAL_CHARGE, SPECIAL_CLEAR_CHARGE};
+use crate::events::{GameEvent, Subscriber};
+use crate::powerup::Powerup;
+
+/// What the special key does once the meter is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Special {
+    /// Turns the falling piece into a bomb
+    Bomb,
+    /// Turns the falling piece into a color clear
+    ColorClear,
+    /// Slows the falling piece down for a while
+    SlowTime,
+}
+
+impl Special {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Bomb => "BOMB",
+            Self::ColorClear => "COLOR CLEAR",
+            Self::SlowTime => "SLOW TIME",
+        }
+    }
+
+    /// The powerup the falling piece becomes, for the specials that work that way
+    pub fn powerup(&self) -> Option<Powerup> {
+        match self {
+            Self::Bomb => Some(Powerup::Bomb),
+            Self::ColorClear => Some(Powerup::ColorClear),
+            Self::SlowTime => None,
+        }
+    }
+}
+
+impl FromStr for Special {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bomb" => Ok(Self::Bomb),
+            "color_clear" => Ok(Self::ColorClear),
+            "slow_time" => Ok(Self::SlowTime),
+            other => Err(format!("invalid special: {other}")),
+        }
+    }
+}
+
+/// How full the special meter is, charged by the clears and chains it hears about
+#[derive(Debug, Clone, Default)]
+pub struct SpecialMeter {
+    charge: usize,
+}
+
+impl SpecialMeter {
+    /// How full the meter is, from 0 to 1
+    pub fn fill(&self) -> f64 {
+        self.charge as f64 / SPECIAL_CHARGE as f64
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.charge >= SPECIAL_CHARGE
+    }
+
+    /// Use up the charge, once the special has been set off
+    pub fn drain(&mut self) {
+        self.charge = 0;
+    }
+
+    fn add(&mut self, charge: usize) {
+        self.charge = (self.charge + charge).min(SPECIAL_CHARGE);
+    }
+}
+
+impl Subscriber for SpecialMeter {
+    fn on_event(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::SandCleared { .. } => self.add(SPECIAL_CLEAR_CHARGE),
+            GameEvent::Chain { chain } => {
+                self.add(SPECIAL_CHAIN_CHARGE * (chain - 1));
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Two boards side by side in one window, as co-op and versus both play out. The modes decide what
+//! the boards do to each other; this keeps them updated, drawn and laid out.
+
+use piston_window::graphics::{self, Transformed};
+use piston_window::prelude::*;
+
+use crate::game::Game;
+use crate::input::Action;
+use crate::mode::GameMode;
+use crate::settings::Settings;
+
+/// Two boards, the first drawn on the left and the second on the right
+#[derive(Debug)]
+pub(crate) struct SplitScreen {
+    settings: Settings,
+    pub boards: [Game; 2],
+}
+
+impl SplitScreen {
+    /// Two boards for the window, played with the settings given for each. The first board's
+    /// settings decide the layout and frame rate.
+    pub fn new(window: &mut PistonWindow, settings: [Settings; 2]) -> Self {
+        let [left, right] = settings;
+        Self {
+            boards: [Game::new(window, left.clone()), Game::new(window, right)],
+            settings: left,
+        }
+    }
+
+    /// The window size that fits two boards of a mode side by side
+    pub fn window_size(mode: GameMode, settings: &Settings) -> (u32, u32) {
+        let (width, height) = mode.layout(settings).window_size();
+        (width * 2, height)
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Press an action on both boards, for the ones like pausing that they have to share
+    pub fn press_both(&mut self, action: Action) {
+        for board in &mut self.boards {
+            board.press(action);
+        }
+    }
+
+    pub fn release_both(&mut self, action: Action) {
+        for board in &mut self.boards {
+            board.release(action);
+        }
+    }
+
+    /// Whether both boards are idle, see [`Game::is_idle`]
+    pub fn is_idle(&self) -> bool {
+        self.boards.iter().all(Game::is_idle)
+    }
+
+    pub fn max_fps(&self) -> Option<u64> {
+        self.settings.max_fps
+    }
+
+    /// Update both boards, and hand the other board to `on_clear` whenever one of them scores
+    pub fn update(&mut self, args: &UpdateArgs, mut on_clear: impl FnMut(&mut Game)) {
+        for i in 0..2 {
+            let score = self.boards[i].score();
+            self.boards[i].update(args);
+            if self.boards[i].score() > score {
+                on_clear(&mut self.boards[1 - i]);
+            }
+        }
+    }
+
+    pub fn render(&mut self, context: graphics::Context, g: &mut G2d) {
+        let width = self.boards[0].window_size().0 as f64;
+        let [left, right] = &mut self.boards;
+        left.render(context, g);
+        right.render(context.trans(width, 0.0), g);
+    }
+}
@@ -0,0 +1,173 @@
+//! A history of every finished run, kept so players can chart how they're doing over time. Each
+//! run is added as a line of JSON to the stats file when it ends, and the whole history can be
+//! exported as CSV, or as JSON with lifetime totals, for a spreadsheet or plotting tool.
+
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::disk;
+
+/// Where the history of finished runs is saved, one run per line
+pub const STATS_FILE: &str = "sandtris_stats.txt";
+
+/// How a finished run went
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+    /// The mode's name, in lowercase
+    pub mode: String,
+    pub score: usize,
+    pub pieces: usize,
+    pub clears: usize,
+    pub grains_cleared: usize,
+    /// How long the run lasted, in seconds
+    pub seconds: f64,
+    #[serde(default)]
+    pub grade: Option<String>,
+    /// Whether the run reached its mode's goal rather than topping out
+    #[serde(default)]
+    pub finished: bool,
+    /// Whether the game was slowed down or hints were shown during the run
+    #[serde(default)]
+    pub assisted: bool,
+}
+
+/// Totals across every run in the history
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LifetimeStats {
+    pub runs: usize,
+    pub pieces: usize,
+    pub clears: usize,
+    pub grains_cleared: usize,
+    pub seconds: f64,
+    /// The highest score in each mode, by lowercase mode name
+    pub best_scores: BTreeMap<String, usize>,
+}
+
+impl LifetimeStats {
+    pub fn new(runs: &[RunRecord]) -> Self {
+        let mut stats = Self::default();
+        for run in runs {
+            stats.runs += 1;
+            stats.pieces += run.pieces;
+            stats.clears += run.clears;
+            stats.grains_cleared += run.grains_cleared;
+            stats.seconds += run.seconds;
+            let best = stats.best_scores.entry(run.mode.clone()).or_default();
+            *best = (*best).max(run.score);
+        }
+        stats
+    }
+}
+
+/// Add a finished run to the end of the history
+pub fn record_run(run: &RunRecord) {
+    let line = match serde_json::to_string(run) {
+        Ok(line) => line + "\n",
+        Err(e) => {
+            eprintln!("could not save the run's stats: {e}");
+            return;
+        }
+    };
+    disk::run(move || {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(STATS_FILE)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .map_err(|e| format!("could not save {STATS_FILE}: {e}"))
+    });
+}
+
+/// Every run in the history, oldest first. There's no history before the first run ends.
+pub fn load_runs() -> Result<Vec<RunRecord>, String> {
+    let source = match fs::read_to_string(STATS_FILE) {
+        Ok(source) => source,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("could not read {STATS_FILE}: {e}")),
+    };
+    parse_runs(&source)
+}
+
+/// Parse a history made of one run per line, as JSON
+pub fn parse_runs(source: &str) -> Result<Vec<RunRecord>, String> {
+    source
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| format!("invalid stats on line {}: {e}", i + 1))
+        })
+        .collect()
+}
+
+/// The history as CSV, one row per run after a header row. Only the JSON export has the lifetime
+/// totals, so that every row of the CSV is a run.
+pub fn to_csv(runs: &[RunRecord]) -> String {
+    let mut csv = String::from(
+        "run,mode,score,pieces,clears,grains_cleared,seconds,grade,finished,assisted\n",
+    );
+    for (i, run) in runs.iter().enumerate() {
+        csv += &format!(
+            "{},{},{},{},{},{},{:.1},{},{},{}\n",
+            i + 1,
+            csv_field(&run.mode),
+            run.score,
+            run.pieces,
+            run.clears,
+            run.grains_cleared,
+            run.seconds,
+            run.grade.as_deref().map(csv_field).unwrap_or_default(),
+            run.finished,
+            run.assisted,
+        );
+    }
+    csv
+}
+
+/// Quote a field if it has anything in it that would break up the row
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
+
+/// The history as JSON, with the lifetime totals alongside every run
+pub fn to_json(runs: &[RunRecord]) -> Result<String, String> {
+    #[derive(Serialize)]
+    struct Export<'a> {
+        lifetime: LifetimeStats,
+        runs: &'a [RunRecord],
+    }
+    serde_json::to_string_pretty(&Export {
+        lifetime: LifetimeStats::new(runs),
+        runs,
+    })
+    .map_err(|e| e.to_string())
+}
+
+/// Write the whole history to a file, as CSV or JSON going by its extension, and return how many
+/// runs were in it
+pub fn export(path: &Path) -> Result<usize, String> {
+    let runs = load_runs()?;
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase);
+    let contents = match extension.as_deref() {
+        Some("csv") => to_csv(&runs),
+        Some("json") => to_json(&runs)?,
+        _ => {
+            return Err(format!(
+                "can't export stats to {}: the file has to end in .csv or .json",
+                path.display()
+            ))
+        }
+    };
+    fs::write(path, contents).map_err(|e| format!("could not write {}: {e}", path.display()))?;
+    Ok(runs.len())
+}
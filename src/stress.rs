@@ -0,0 +1,78 @@
+//! A developer mode for profiling the sand physics: sand pours onto the board from the top, with no
+//! blocks and no clears, until the board is full, and the time each physics tick takes is tracked.
+
+use std::time::Duration;
+
+use crate::constants::{PHYSICS_DELAY, STRESS_POUR_GRAINS};
+use crate::physics::{Emitter, Resolution};
+
+#[derive(Debug, Clone, Default)]
+pub struct StressTest {
+    ticks: u32,
+    total: Duration,
+    last: Duration,
+    worst: Duration,
+    grains: usize,
+    /// How much work settling conflicts between grains took in the last tick
+    resolution: Resolution,
+    /// The most rounds any row has taken to settle
+    most_rounds: usize,
+    /// How many rows have hit the cap on rounds
+    capped: usize,
+}
+
+impl StressTest {
+    /// Pours grains of random colors in at random columns along the top, a few each physics tick
+    pub fn emitter(width: usize) -> Emitter {
+        Emitter::new(0..width, STRESS_POUR_GRAINS as f64 / PHYSICS_DELAY, None)
+    }
+
+    /// Record how long a physics tick took, how many grains there were and how much work settling
+    /// where they moved took
+    pub fn record(&mut self, tick: Duration, grains: usize, resolution: Resolution) {
+        self.ticks += 1;
+        self.total += tick;
+        self.last = tick;
+        self.worst = self.worst.max(tick);
+        self.grains = grains;
+        self.resolution = resolution;
+        self.most_rounds = self.most_rounds.max(resolution.most_rounds);
+        self.capped += resolution.capped;
+    }
+
+    fn average(&self) -> Duration {
+        self.total.checked_div(self.ticks).unwrap_or_default()
+    }
+
+    /// The stats for the dashboard, in microseconds
+    pub fn lines(&self) -> Vec<String> {
+        vec![
+            format!("TICK {}US", self.last.as_micros()),
+            format!("AVG {}US", self.average().as_micros()),
+            format!("MAX {}US", self.worst.as_micros()),
+            format!("GRAINS {}", self.grains),
+            format!("ROUNDS {}", self.resolution.rounds),
+            format!("MOST ROUNDS {}", self.most_rounds),
+            format!("CAPPED {}", self.capped),
+        ]
+    }
+
+    /// Describe the ticks since the last report, and start counting again
+    pub fn report(&mut self) -> String {
+        let report = format!(
+            "stress test: {} grains, {} physics ticks averaging {:?}, worst {:?}, at most {} \
+             rounds in a row, {} rows capped",
+            self.grains,
+            self.ticks,
+            self.average(),
+            self.worst,
+            self.most_rounds,
+            self.capped
+        );
+        *self = Self {
+            grains: self.grains,
+            ..Self::default()
+        };
+        report
+    }
+}
@@ -0,0 +1,111 @@
+//! Snapshots of the board taken every few pieces over a run, which can be exported as a strip of
+//! pictures or an animated GIF that shows the run build up.
+
+use std::fs::File;
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{imageops, Delay, Frame, Rgba, RgbaImage};
+use ndarray::Array2;
+
+use crate::board::Board;
+use crate::constants::{
+    Color, TIMELAPSE_COLUMNS, TIMELAPSE_FRAME_MS, TIMELAPSE_GAP, TIMELAPSE_SCALE,
+};
+use crate::disk;
+
+/// Where the strip of snapshots is exported
+pub const TIMELAPSE_STRIP_FILE: &str = "sandtris_timelapse.png";
+/// Where the animated version is exported
+pub const TIMELAPSE_GIF_FILE: &str = "sandtris_timelapse.gif";
+
+#[derive(Debug, Clone, Default)]
+pub struct TimeLapse {
+    /// Only the color of each grain is kept, which is all the pictures need
+    frames: Vec<Array2<Option<Color>>>,
+    pieces: usize,
+}
+
+impl TimeLapse {
+    /// Count a piece that locked, and take a snapshot if it's been `interval` pieces since the last
+    /// one. An interval of 0 turns snapshots off.
+    pub fn on_piece_locked(&mut self, sand: &Board, interval: usize) {
+        self.pieces += 1;
+        if interval > 0 && self.pieces.is_multiple_of(interval) {
+            self.capture(sand);
+        }
+    }
+
+    pub fn capture(&mut self, sand: &Board) {
+        self.frames.push(sand.colors().clone());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Queue the snapshots to be saved in the background, as a strip of pictures read left to
+    /// right and top to bottom, and as an animated GIF that holds on the last one
+    pub fn export(&self) -> Result<(), String> {
+        if self.frames.is_empty() {
+            return Err("there is nothing to export yet".to_string());
+        }
+        let frames = self.frames.clone();
+        disk::run(move || export_frames(&frames));
+        Ok(())
+    }
+}
+
+fn export_frames(frames: &[Array2<Option<Color>>]) -> Result<(), String> {
+    let images = frames.iter().map(frame_image).collect::<Vec<_>>();
+    let (width, height) = images[0].dimensions();
+    let columns = images.len().min(TIMELAPSE_COLUMNS) as u32;
+    let rows = images.len().div_ceil(TIMELAPSE_COLUMNS) as u32;
+    let mut strip = RgbaImage::from_pixel(
+        columns * (width + TIMELAPSE_GAP) + TIMELAPSE_GAP,
+        rows * (height + TIMELAPSE_GAP) + TIMELAPSE_GAP,
+        Rgba([89, 92, 102, 255]),
+    );
+    for (i, image) in images.iter().enumerate() {
+        let (column, row) = (i as u32 % columns, i as u32 / columns);
+        imageops::replace(
+            &mut strip,
+            image,
+            (TIMELAPSE_GAP + column * (width + TIMELAPSE_GAP)) as i64,
+            (TIMELAPSE_GAP + row * (height + TIMELAPSE_GAP)) as i64,
+        );
+    }
+    strip
+        .save(TIMELAPSE_STRIP_FILE)
+        .map_err(|e| format!("could not save {TIMELAPSE_STRIP_FILE}: {e}"))?;
+
+    let file = File::create(TIMELAPSE_GIF_FILE)
+        .map_err(|e| format!("could not save {TIMELAPSE_GIF_FILE}: {e}"))?;
+    let mut encoder = GifEncoder::new(file);
+    let last = images.len() - 1;
+    let frames = images.into_iter().enumerate().map(|(i, image)| {
+        let ms = if i == last {
+            TIMELAPSE_FRAME_MS * 4
+        } else {
+            TIMELAPSE_FRAME_MS
+        };
+        Frame::from_parts(image, 0, 0, Delay::from_numer_denom_ms(ms, 1))
+    });
+    encoder
+        .set_repeat(Repeat::Infinite)
+        .and_then(|_| encoder.encode_frames(frames))
+        .map_err(|e| format!("could not save {TIMELAPSE_GIF_FILE}: {e}"))
+}
+
+/// Draw a snapshot with each grain [`TIMELAPSE_SCALE`] pixels across
+fn frame_image(frame: &Array2<Option<Color>>) -> RgbaImage {
+    let (width, height) = frame.dim();
+    let scale = TIMELAPSE_SCALE as usize;
+    RgbaImage::from_fn(
+        (width * scale) as u32,
+        (height * scale) as u32,
+        |x, y| match frame[[x as usize / scale, y as usize / scale]] {
+            Some(color) => color.pixel_color(),
+            None => Rgba([255, 255, 255, 255]),
+        },
+    )
+}
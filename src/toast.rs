@@ -0,0 +1,110 @@
+//! Short messages shown over the board: settings being reloaded, a save that failed, a replay
+//! going out of step. Each one slides in, stays for a few seconds and fades out, and any that come
+//! in meanwhile wait their turn, so one message never hides another.
+
+use std::collections::VecDeque;
+
+use crate::constants::{
+    TextTextures, TOAST_DURATION, TOAST_FADE, TOAST_LINE_LENGTH, TOAST_QUEUED_DURATION,
+    TOAST_QUEUE_LENGTH, TOAST_SLIDE, TOAST_SLIDE_DISTANCE,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToastKind {
+    Info,
+    /// Something went wrong, like a file that couldn't be saved
+    Error,
+}
+
+#[derive(Debug, Clone)]
+struct Toast {
+    message: String,
+    lines: Vec<String>,
+    kind: ToastKind,
+    /// How long it's been shown for, in seconds
+    age: f64,
+    /// How old it is when it's gone
+    end: f64,
+}
+
+/// A toast as it's drawn this frame
+#[derive(Debug, Clone, Copy)]
+pub struct ShownToast<'a> {
+    pub lines: &'a [String],
+    pub kind: ToastKind,
+    /// How far below where it rests it is, in pixels, while sliding in
+    pub offset: f64,
+    pub opacity: f32,
+}
+
+/// The toast on screen, and the ones waiting to be shown after it
+#[derive(Debug, Default)]
+pub struct Toasts {
+    showing: Option<Toast>,
+    waiting: VecDeque<Toast>,
+}
+
+impl Toasts {
+    /// Queue a message to be shown once the ones before it are gone. A message that's already on
+    /// screen or waiting isn't queued again.
+    pub fn push(&mut self, message: &str, kind: ToastKind) {
+        let queued = |toast: &Toast| toast.message == message && toast.kind == kind;
+        if self.showing.iter().chain(&self.waiting).any(queued) {
+            return;
+        }
+        if self.waiting.len() == TOAST_QUEUE_LENGTH {
+            self.waiting.pop_front();
+        }
+        self.waiting.push_back(Toast {
+            message: message.to_string(),
+            lines: TextTextures::wrap(message, TOAST_LINE_LENGTH),
+            kind,
+            age: 0.0,
+            end: TOAST_DURATION,
+        });
+        self.advance();
+    }
+
+    pub fn tick(&mut self, dt: f64) {
+        if let Some(toast) = &mut self.showing {
+            toast.age += dt;
+            if toast.age >= toast.end {
+                self.showing = None;
+            }
+        }
+        self.advance();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.showing.is_none()
+    }
+
+    /// The toast on screen, if there is one
+    pub fn current(&self) -> Option<ShownToast<'_>> {
+        let toast = self.showing.as_ref()?;
+        // Eased out, so it slows down as it comes to rest
+        let slide = (1.0 - toast.age / TOAST_SLIDE).max(0.0);
+        Some(ShownToast {
+            lines: &toast.lines,
+            kind: toast.kind,
+            offset: TOAST_SLIDE_DISTANCE * slide * slide,
+            opacity: ((toast.end - toast.age) / TOAST_FADE).min(1.0) as f32,
+        })
+    }
+
+    /// Show the next toast if nothing is on screen, and cut the one that is short while others
+    /// are waiting
+    fn advance(&mut self) {
+        if self.showing.is_none() {
+            self.showing = self.waiting.pop_front();
+        }
+        if let Some(toast) = &mut self.showing {
+            if !self.waiting.is_empty() {
+                // One that's already fading out carries on as it was
+                toast.end = toast
+                    .end
+                    .min(TOAST_QUEUED_DURATION.max(toast.age + TOAST_FADE));
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Sand animations that play around the start and end of a game, on top of whatever the game is
+//! doing. They have their own random state, so they never change how a game plays out.
+
+use ndarray::Array2;
+
+use crate::board::Board;
+use crate::constants::{
+    Color, COLLAPSE_DELAY, DASHBOARD_WIDTH, INTRO_STEPS_PER_TICK, PHYSICS_DELAY, SAND_SIZE,
+};
+use crate::layout::Layout;
+use crate::physics::run_rng_physics_open;
+use crate::rng::{GameRng, WyRand};
+
+#[derive(Debug, Clone)]
+enum Kind {
+    /// The pile loses cohesion and drains out the bottom of the board
+    Collapse,
+    /// Sand covering the dashboard drains away to reveal it
+    Intro { curtain: Array2<Option<Color>> },
+}
+
+#[derive(Debug, Clone)]
+pub struct Transition {
+    kind: Kind,
+    rng: WyRand,
+    /// How long to wait before starting, in seconds
+    wait: f64,
+    /// Time since the last physics tick
+    time: f64,
+}
+
+impl Transition {
+    /// Drain the board after a game over, once the player has had a moment to see it. It's seeded
+    /// so that replays of the game collapse the same way.
+    pub fn collapse(seed: u64) -> Self {
+        Self {
+            kind: Kind::Collapse,
+            rng: WyRand::new_seed(seed),
+            wait: COLLAPSE_DELAY,
+            time: 0.0,
+        }
+    }
+
+    /// Cover the dashboard in sand and let it pour away, at the start of a game
+    pub fn intro(layout: Layout) -> Self {
+        let mut rng = WyRand::new();
+        let dims = [
+            DASHBOARD_WIDTH as usize / SAND_SIZE,
+            layout.window_size().1 as usize / SAND_SIZE,
+        ];
+        let curtain = Array2::from_shape_simple_fn(dims, || {
+            Some(Color::ALL[rng.generate_range(0..Color::ALL.len())])
+        });
+        Self {
+            kind: Kind::Intro { curtain },
+            rng,
+            wait: 0.0,
+            time: 0.0,
+        }
+    }
+
+    /// Advance the animation, returning whether it's still going
+    pub fn update(&mut self, dt: f64, sand: &mut Board) -> bool {
+        if self.wait > 0.0 {
+            self.wait -= dt;
+            return true;
+        }
+        self.time += dt;
+        while self.time >= PHYSICS_DELAY {
+            self.time -= PHYSICS_DELAY;
+            match &mut self.kind {
+                Kind::Collapse => {
+                    run_rng_physics_open(&mut self.rng, sand);
+                }
+                Kind::Intro { curtain } => {
+                    for _ in 0..INTRO_STEPS_PER_TICK {
+                        run_rng_physics_open(&mut self.rng, curtain);
+                    }
+                }
+            }
+        }
+        match &self.kind {
+            Kind::Collapse => !sand.is_empty(),
+            Kind::Intro { curtain } => curtain.iter().any(Option::is_some),
+        }
+    }
+
+    /// The sand covering the dashboard, in dashboard coordinates, if there is any
+    pub fn curtain(&self) -> Option<&Array2<Option<Color>>> {
+        match &self.kind {
+            Kind::Intro { curtain } => Some(curtain),
+            Kind::Collapse => None,
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use crate::constants::Color;
+use crate::events::{GameEvent, Subscriber};
+use crate::input::Action;
+
+/// One scripted stage of the tutorial
+#[derive(Debug)]
+pub struct TutorialStep {
+    pub text: &'static [&'static str],
+    /// The starting board, in the same format as mission boards
+    pub board: &'static [&'static str],
+    /// The color every piece spawns with during this step
+    pub color: Option<Color>,
+    /// The gameplay actions the player can use during this step
+    pub allowed: &'static [Action],
+    pub goal: Goal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Goal {
+    /// Move a piece both left and right
+    MoveBothWays,
+    Drop,
+    Clear,
+    /// Just read the text and press enter
+    Confirm,
+}
+
+const MOVEMENT: &[Action] = &[Action::Left, Action::Right, Action::Down, Action::Rotate];
+const MOVEMENT_AND_DROP: &[Action] = &[
+    Action::Left,
+    Action::Right,
+    Action::Down,
+    Action::Rotate,
+    Action::Drop,
+];
+
+pub const STEPS: [TutorialStep; 5] = [
+    TutorialStep {
+        text: &["USE LEFT AND RIGHT", "TO MOVE THE PIECE", "UP ROTATES IT"],
+        board: &[],
+        color: None,
+        allowed: MOVEMENT,
+        goal: Goal::MoveBothWays,
+    },
+    TutorialStep {
+        text: &["PRESS SPACE TO DROP", "THE PIECE STRAIGHT DOWN"],
+        board: &[],
+        color: None,
+        allowed: MOVEMENT_AND_DROP,
+        goal: Goal::Drop,
+    },
+    TutorialStep {
+        text: &[
+            "PIECES TURN INTO SAND",
+            "CONNECT ONE COLOR FROM THE",
+            "LEFT WALL TO THE RIGHT WALL",
+            "TO CLEAR IT",
+        ],
+        board: &["BBBBB..BBBBB"],
+        color: Some(Color::Blue),
+        allowed: MOVEMENT_AND_DROP,
+        goal: Goal::Clear,
+    },
+    TutorialStep {
+        text: &[
+            "CLEARING AGAIN BEFORE",
+            "YOUR NEXT PIECE LANDS IS A",
+            "COMBO AND MULTIPLIES",
+            "YOUR POINTS",
+            "",
+            "PRESS ENTER",
+        ],
+        board: &[],
+        color: None,
+        allowed: &[],
+        goal: Goal::Confirm,
+    },
+    TutorialStep {
+        text: &["THAT IS ALL", "GOOD LUCK", "", "PRESS ENTER"],
+        board: &[],
+        color: None,
+        allowed: &[],
+        goal: Goal::Confirm,
+    },
+];
+
+/// Tracks which tutorial step the player is on and whether they've done what it asks
+#[derive(Debug, Default)]
+pub struct Tutorial {
+    step: usize,
+    moved_left: bool,
+    moved_right: bool,
+    dropped: bool,
+    cleared: bool,
+    confirmed: bool,
+}
+
+impl Tutorial {
+    pub fn step(&self) -> &'static TutorialStep {
+        &STEPS[self.step]
+    }
+
+    /// Whether the player is allowed to use this action right now
+    pub fn allows(&self, action: Action) -> bool {
+        matches!(action, Action::Pause | Action::Menu | Action::Confirm)
+            || self.step().allowed.contains(&action)
+    }
+
+    pub fn on_action(&mut self, action: Action) {
+        match action {
+            Action::Left => self.moved_left = true,
+            Action::Right => self.moved_right = true,
+            Action::Drop => self.dropped = true,
+            Action::Confirm => self.confirmed = true,
+            _ => {}
+        }
+    }
+
+    pub fn is_step_complete(&self) -> bool {
+        match self.step().goal {
+            Goal::MoveBothWays => self.moved_left && self.moved_right,
+            Goal::Drop => self.dropped,
+            Goal::Clear => self.cleared,
+            Goal::Confirm => self.confirmed,
+        }
+    }
+
+    /// Move on to the next step, returning false if the tutorial is over
+    pub fn advance(&mut self) -> bool {
+        if self.step + 1 >= STEPS.len() {
+            return false;
+        }
+        *self = Self {
+            step: self.step + 1,
+            ..Self::default()
+        };
+        true
+    }
+}
+
+impl Subscriber for Tutorial {
+    fn on_event(&mut self, event: &GameEvent) {
+        if let GameEvent::SandCleared { .. } = event {
+            self.cleared = true;
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Versus: the player against the bot, on two boards side by side dealt the same pieces. Clears on
+//! one board bring the rising sand on the other closer, and whoever fills up first loses.
+
+use piston_window::graphics;
+use piston_window::prelude::*;
+
+use crate::bot::{Bot, BotLevel};
+use crate::constants::{VERSUS_RISE_PUSH, WINDOW_TITLE};
+use crate::input::Action;
+use crate::mode::GameMode;
+use crate::rng::{GameRng, WyRand};
+use crate::settings::Settings;
+use crate::split::SplitScreen;
+
+/// A game against the bot. The player's board is on the left and takes the usual keys, except
+/// that pausing and restarting apply to both boards and the menu key ends the match.
+#[derive(Debug)]
+pub struct Versus {
+    rng: WyRand,
+    level: BotLevel,
+    bot: Bot,
+    split: SplitScreen,
+    /// Set once the player has left for the menu
+    finished: bool,
+}
+
+impl Versus {
+    pub fn new(window: &mut PistonWindow, settings: Settings, level: BotLevel) -> Self {
+        // The bot taps its keys, so it can't be made to hold them
+        let mut bot_settings = settings.clone();
+        bot_settings.hold_to_drop = false;
+        bot_settings.hint = false;
+        let mut rng = WyRand::new();
+        let bot = Bot::new(level.skill(), rng.generate());
+        let mut versus = Self {
+            rng,
+            level,
+            bot,
+            split: SplitScreen::new(window, [settings, bot_settings]),
+            finished: false,
+        };
+        versus.restart();
+        versus
+    }
+
+    /// The window size that fits both boards side by side
+    pub fn window_size(settings: &Settings) -> (u32, u32) {
+        SplitScreen::window_size(GameMode::Versus, settings)
+    }
+
+    /// Start a new match, with both boards dealt the same pieces
+    fn restart(&mut self) {
+        let seed = self.rng.generate();
+        for board in &mut self.split.boards {
+            board.start_seeded(GameMode::Versus, seed);
+        }
+        self.bot = Bot::new(self.level.skill(), self.rng.generate());
+    }
+
+    /// Whether the player has left the match for the menu
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        if let Some(Button::Keyboard(key)) = event.press_args() {
+            match self.split.settings().keymap.action(key) {
+                Some(Action::Pause) => self.split.press_both(Action::Pause),
+                Some(Action::Restart | Action::Menu) => {}
+                Some(action) => self.split.boards[0].press(action),
+                None => {}
+            }
+        }
+        if let Some(Button::Keyboard(key)) = event.release_args() {
+            match self.split.settings().keymap.action(key) {
+                Some(Action::Pause) => self.split.release_both(Action::Pause),
+                Some(Action::Restart) => self.restart(),
+                Some(Action::Menu) => self.finished = true,
+                Some(action) => self.split.boards[0].release(action),
+                None => {}
+            }
+        }
+    }
+
+    /// Whether both boards are idle, see [`Game::is_idle`](crate::game::Game::is_idle)
+    pub fn is_idle(&self) -> bool {
+        self.split.is_idle()
+    }
+
+    /// What the window's title should say, with the player's score and then the bot's
+    pub fn window_title(&self) -> String {
+        let [player, bot] = &self.split.boards;
+        format!(
+            "{WINDOW_TITLE} - VS BOT {} - {} to {}",
+            self.level.name(),
+            player.score(),
+            bot.score()
+        )
+    }
+
+    pub fn max_fps(&self) -> Option<u64> {
+        self.split.max_fps()
+    }
+
+    pub fn update(&mut self, args: &UpdateArgs) {
+        self.bot.update(&mut self.split.boards[1], args.dt);
+        // Clears on one board put the pressure on the other
+        self.split
+            .update(args, |other| other.hasten_rise(VERSUS_RISE_PUSH));
+
+        let [player, bot] = &mut self.split.boards;
+        if player.is_game_over() != bot.is_game_over() {
+            let (winner, loser) = match player.is_game_over() {
+                true => (bot, player),
+                false => (player, bot),
+            };
+            winner.end();
+            winner.show_toast("WINNER");
+            loser.show_toast("OUT FIRST");
+        }
+    }
+
+    pub fn render(&mut self, context: graphics::Context, g: &mut G2d) {
+        self.split.render(context, g);
+    }
+}
@@ -0,0 +1,41 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Notices when files change on disk, by polling their modification times. The files watched are a
+/// handful of small config files, so checking a couple of times a second is cheap and saves
+/// pulling in a file notification library.
+#[derive(Debug)]
+pub struct FileWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl FileWatcher {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let files = paths
+            .into_iter()
+            .map(|path| {
+                let modified = Self::modified(&path);
+                (path, modified)
+            })
+            .collect();
+        Self { files }
+    }
+
+    fn modified(path: &PathBuf) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    /// Check whether any of the files were modified, created or deleted since the last poll
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        for (path, modified) in &mut self.files {
+            let now = Self::modified(path);
+            if now != *modified {
+                *modified = now;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
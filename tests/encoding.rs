@@ -0,0 +1,84 @@
+use ndarray::Array2;
+use sandtris::constants::Color;
+use sandtris::encoding::{apply_diff, decode_board, encode_board, encode_diff};
+use sandtris::rng::{GameRng, WyRand};
+
+const WIDTH: usize = 96;
+const HEIGHT: usize = 144;
+
+fn round_trip(board: &Array2<Option<Color>>) -> Vec<u8> {
+    let bytes = encode_board(board);
+    assert_eq!(decode_board(&bytes).as_ref(), Ok(board));
+    bytes
+}
+
+#[test]
+fn empty_board() {
+    let bytes = round_trip(&Array2::default([WIDTH, HEIGHT]));
+    // One run byte per 32 cells
+    assert_eq!(bytes.len(), 5 + WIDTH * HEIGHT / 32);
+}
+
+#[test]
+fn stacked_columns() {
+    let mut board = Array2::default([WIDTH, HEIGHT]);
+    for x in 0..WIDTH {
+        for y in HEIGHT / 2..HEIGHT {
+            board[[x, y]] = Some(Color::ALL[x / 8 % Color::ALL.len()]);
+        }
+    }
+    let bytes = round_trip(&board);
+    assert!(bytes.len() < WIDTH * HEIGHT / 8);
+}
+
+#[test]
+fn noise_is_at_most_three_bits_per_cell() {
+    let mut rng = WyRand::new_seed(7);
+    let board = Array2::from_shape_simple_fn([WIDTH, HEIGHT], || {
+        let code = rng.generate_range(0..=Color::ALL.len());
+        (code > 0).then(|| Color::ALL[code - 1])
+    });
+    let bytes = round_trip(&board);
+    assert_eq!(bytes.len(), 5 + (WIDTH * HEIGHT * 3).div_ceil(8));
+}
+
+#[test]
+fn odd_sizes() {
+    for (width, height) in [(0, 0), (1, 1), (3, 5), (7, 1)] {
+        let board = Array2::from_shape_fn([width, height], |(x, y)| {
+            ((x + y) % 3 != 0).then_some(Color::ALL[(x * y) % Color::ALL.len()])
+        });
+        round_trip(&board);
+    }
+}
+
+#[test]
+fn rejects_corrupt_data() {
+    let mut board = Array2::default([4, 4]);
+    board[[1, 1]] = Some(Color::Green);
+    let bytes = encode_board(&board);
+
+    assert!(decode_board(&bytes[..3]).is_err());
+    assert!(decode_board(&bytes[..bytes.len() - 1]).is_err());
+
+    let mut unknown_tag = bytes.clone();
+    unknown_tag[0] = 9;
+    assert!(decode_board(&unknown_tag).is_err());
+
+    // A run of the invalid cell value 7
+    assert!(decode_board(&[1, 1, 0, 1, 0, 0xe0]).is_err());
+}
+
+#[test]
+fn diffs_need_boards_the_same_size() {
+    let old = Array2::default([4, 4]);
+    let mut new = Array2::default([4, 4]);
+    new[[2, 3]] = Some(Color::Red);
+    let diff = encode_diff(&old, &new).unwrap();
+    let mut copy = old.clone();
+    apply_diff(&mut copy, &diff).unwrap();
+    assert_eq!(copy, new);
+
+    assert!(encode_diff(&Array2::default([4, 5]), &new).is_err());
+    assert!(encode_diff(&Array2::default([5, 4]), &new).is_err());
+}
@@ -0,0 +1,156 @@
+//! Golden-file tests: seeded scenarios run for a set number of ticks, with the hash of the board
+//! they end on checked against the values in `golden.txt`. Any change to the physics that moves
+//! even one grain differently shows up here.
+//!
+//! When a change is meant to move grains differently, run these with `SANDTRIS_BLESS=1` to write
+//! the new hashes, and check the golden file in along with it.
+
+use std::collections::BTreeMap;
+use std::fs;
+
+use ndarray::Array2;
+use piston_window::UpdateArgs;
+use sandtris::board::Board;
+use sandtris::constants::Color;
+use sandtris::game::Game;
+use sandtris::input::Action;
+use sandtris::mode::GameMode;
+use sandtris::physics::{run_rng_physics, run_rng_physics_open, run_sticky_physics};
+use sandtris::rng::{GameRng, WyRand};
+use sandtris::settings::Settings;
+
+const GOLDEN_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden.txt");
+const WIDTH: usize = 48;
+const HEIGHT: usize = 72;
+const DT: f64 = 1.0 / 30.0;
+
+/// A board with the top `rows` rows filled at random, about half full and in every color
+fn noise(seed: u64, rows: usize) -> Board {
+    let mut rng = WyRand::new_seed(seed);
+    let colors = Array2::from_shape_fn([WIDTH, HEIGHT], |(_, y)| {
+        let code = rng.generate_range(0..Color::ALL.len() * 2);
+        (y < rows && code < Color::ALL.len()).then(|| Color::ALL[code])
+    });
+    Board::from_colors(colors, 0.0)
+}
+
+/// Columns a cell wide with a cell between each, standing on nothing. As they fall and spread,
+/// neighbouring grains keep wanting the same cell, which is the part of the physics most likely
+/// to change by accident.
+fn comb() -> Board {
+    let colors = Array2::from_shape_fn([WIDTH, HEIGHT], |(x, y)| {
+        (x % 2 == 0 && y < HEIGHT / 2).then_some(Color::ALL[x / 2 % Color::ALL.len()])
+    });
+    Board::from_colors(colors, 0.0)
+}
+
+fn physics(mut sand: Board, seed: u64, ticks: usize) -> u64 {
+    let mut rng = WyRand::new_seed(seed);
+    for _ in 0..ticks {
+        run_rng_physics(&mut rng, &mut sand);
+    }
+    sand.hash()
+}
+
+fn open_physics(mut sand: Board, seed: u64, ticks: usize) -> u64 {
+    let mut rng = WyRand::new_seed(seed);
+    for _ in 0..ticks {
+        run_rng_physics_open(&mut rng, &mut sand);
+    }
+    sand.hash()
+}
+
+fn sticky_physics(mut sand: Board, ticks: usize) -> u64 {
+    for _ in 0..ticks {
+        run_sticky_physics(&mut sand);
+    }
+    sand.hash()
+}
+
+/// A whole game played for `frames` frames, with the same inputs as the state tests: each piece
+/// moved a different distance to one side and dropped
+fn game(mode: GameMode, seed: u64, frames: usize) -> u64 {
+    let mut game = Game::headless(Settings::default());
+    game.start_seeded(mode, seed);
+    for frame in 0..frames {
+        let piece = frame / 30;
+        let direction = if piece % 2 == 0 {
+            Action::Left
+        } else {
+            Action::Right
+        };
+        match frame % 30 {
+            1 => game.press(direction),
+            25 => {
+                game.press(Action::Drop);
+                game.release(Action::Drop);
+            }
+            f if f == 2 + piece * 7 % 11 => game.release(direction),
+            _ => {}
+        }
+        game.update(&UpdateArgs { dt: DT });
+    }
+    game.sand().hash()
+}
+
+/// Every scenario, by name
+fn scenarios() -> BTreeMap<&'static str, u64> {
+    BTreeMap::from([
+        (
+            "noise_1_settled",
+            physics(noise(1, HEIGHT / 2), 1, HEIGHT * 2),
+        ),
+        (
+            "noise_2_halfway",
+            physics(noise(2, HEIGHT / 2), 2, HEIGHT / 4),
+        ),
+        ("noise_3_full", physics(noise(3, HEIGHT), 3, HEIGHT)),
+        ("comb_settled", physics(comb(), 4, HEIGHT * 2)),
+        ("comb_halfway", physics(comb(), 5, HEIGHT / 3)),
+        ("open_floor", open_physics(noise(6, HEIGHT / 2), 6, HEIGHT)),
+        ("sticky", sticky_physics(noise(7, HEIGHT / 3), HEIGHT)),
+        ("marathon", game(GameMode::Marathon, 42, 900)),
+        ("rising", game(GameMode::Rising, 42, 900)),
+    ])
+}
+
+fn load_golden() -> BTreeMap<String, u64> {
+    let source = fs::read_to_string(GOLDEN_FILE).unwrap_or_default();
+    source
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(name, hash)| {
+            let hash = u64::from_str_radix(hash, 16)
+                .unwrap_or_else(|_| panic!("invalid hash for {name} in {GOLDEN_FILE}: {hash}"));
+            (name.to_string(), hash)
+        })
+        .collect()
+}
+
+#[test]
+fn boards_match_the_golden_file() {
+    let actual = scenarios();
+    if std::env::var_os("SANDTRIS_BLESS").is_some() {
+        let lines = actual
+            .iter()
+            .map(|(name, hash)| format!("{name} {hash:016x}\n"))
+            .collect::<String>();
+        fs::write(GOLDEN_FILE, lines).unwrap();
+        return;
+    }
+
+    let golden = load_golden();
+    let mismatches = actual
+        .iter()
+        .filter(|(name, hash)| golden.get(**name) != Some(hash))
+        .map(|(name, hash)| match golden.get(*name) {
+            Some(expected) => format!("{name}: expected {expected:016x}, got {hash:016x}"),
+            None => format!("{name}: missing from the golden file, got {hash:016x}"),
+        })
+        .collect::<Vec<_>>();
+    assert!(
+        mismatches.is_empty(),
+        "boards differ from {GOLDEN_FILE}, rerun with SANDTRIS_BLESS=1 if that's intended:\n{}",
+        mismatches.join("\n")
+    );
+}
@@ -0,0 +1,259 @@
+use std::time::Duration;
+
+use ndarray::Array2;
+use sandtris::board::Board;
+use sandtris::constants::{Color, Direction, PHYSICS_MAX_ROUNDS};
+use sandtris::physics::{
+    inflow, resolve_conflicts, run_rng_physics, run_rng_physics_open, Request, StickyGrid, Subgrid,
+};
+use sandtris::rng::WyRand;
+
+/// Parse a board drawn as rows from top to bottom, with `#` for a grain and `.` for an empty cell
+fn board(rows: &[&str]) -> Array2<Option<()>> {
+    let mut sand = Array2::default([rows[0].len(), rows.len()]);
+    for (y, row) in rows.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            sand[[x, y]] = (c == '#').then_some(());
+        }
+    }
+    sand
+}
+
+/// Run the physics until nothing moves
+fn settle(sand: &mut Array2<Option<()>>) {
+    let mut rng = WyRand::new_seed(7);
+    for _ in 0..sand.dim().1 * 2 {
+        run_rng_physics(&mut rng, sand);
+    }
+}
+
+fn column_heights(sand: &Array2<Option<()>>) -> Vec<usize> {
+    sand.outer_iter()
+        .map(|column| column.iter().flatten().count())
+        .collect()
+}
+
+#[test]
+fn one_column_stacks() {
+    let mut sand = board(&["#", ".", "#", ".", "."]);
+    settle(&mut sand);
+    assert_eq!(sand, board(&[".", ".", ".", "#", "#"]));
+}
+
+#[test]
+fn one_column_full() {
+    let mut sand = board(&["#", "#", "#"]);
+    settle(&mut sand);
+    assert_eq!(sand, board(&["#", "#", "#"]));
+}
+
+#[test]
+fn two_columns_slide_off() {
+    let mut sand = board(&["#.", "#.", ".."]);
+    settle(&mut sand);
+    assert_eq!(sand, board(&["..", "..", "##"]));
+}
+
+#[test]
+fn two_columns_fall_straight() {
+    let mut sand = board(&[".#", "..", "..", "#."]);
+    settle(&mut sand);
+    assert_eq!(sand, board(&["..", "..", "..", "##"]));
+}
+
+#[test]
+fn three_columns_spread() {
+    let mut sand = board(&[".#.", ".#.", ".#.", "..."]);
+    settle(&mut sand);
+    assert_eq!(sand.iter().flatten().count(), 3);
+    assert_eq!(column_heights(&sand), vec![1, 1, 1]);
+}
+
+#[test]
+fn three_columns_hold_a_pile() {
+    let mut sand = board(&["...", ".#.", "###"]);
+    settle(&mut sand);
+    assert_eq!(sand, board(&["...", ".#.", "###"]));
+}
+
+#[test]
+fn open_floor_drains() {
+    let mut rng = WyRand::new_seed(7);
+    for width in 1..=3 {
+        let mut sand = Array2::from_elem([width, 4], Some(()));
+        for _ in 0..8 {
+            run_rng_physics_open(&mut rng, &mut sand);
+        }
+        assert!(sand.iter().all(Option::is_none), "width {width}");
+    }
+}
+
+#[test]
+fn inflow_takes_the_column() {
+    assert_eq!(inflow(&board(&["."]), 0), Some((0, 0)));
+    assert_eq!(inflow(&board(&["...", "..."]), 2), Some((2, 0)));
+}
+
+#[test]
+fn inflow_rolls_to_the_nearest_free_cell() {
+    assert_eq!(inflow(&board(&["#.", ".."]), 0), Some((1, 0)));
+    assert_eq!(inflow(&board(&[".#."]), 1), Some((0, 0)));
+    assert_eq!(inflow(&board(&["##."]), 0), Some((2, 0)));
+}
+
+#[test]
+fn inflow_into_a_full_top_row() {
+    assert_eq!(inflow(&board(&["#"]), 0), None);
+    assert_eq!(inflow(&board(&["##", ".."]), 1), None);
+    assert_eq!(inflow(&board(&["###"]), 5), None);
+}
+
+/// The cells the grains on the upper row of a pair would move into
+fn targets(requests: &[Option<Request>]) -> Vec<usize> {
+    requests
+        .iter()
+        .enumerate()
+        .filter_map(|(x, request)| {
+            request.map(|(direction, _)| match direction {
+                Direction::Left => x - 1,
+                Direction::Down => x,
+                Direction::Right => x + 1,
+            })
+        })
+        .collect()
+}
+
+fn assert_no_collisions(requests: &[Option<Request>]) {
+    let mut targets = targets(requests);
+    let moving = targets.len();
+    targets.sort();
+    targets.dedup();
+    assert_eq!(targets.len(), moving, "two grains move into the same cell");
+}
+
+/// Every grain wants to slide right and falls back on going straight down, except the last, which
+/// goes straight down. Each round only settles one more grain, right to left.
+fn cascade(width: usize) -> Vec<Option<Request>> {
+    let mut requests = vec![Some((Direction::Right, Some(Direction::Down))); width];
+    requests[width - 1] = Some((Direction::Down, None));
+    requests
+}
+
+#[test]
+fn short_cascade_settles_normally() {
+    let mut requests = cascade(4);
+    let resolution = resolve_conflicts(&mut WyRand::new_seed(7), &mut requests);
+    assert_eq!(resolution.capped, 0);
+    assert!(requests
+        .iter()
+        .all(|request| *request == Some((Direction::Down, None))));
+}
+
+#[test]
+fn long_cascade_is_capped() {
+    let mut requests = cascade(500);
+    let resolution = resolve_conflicts(&mut WyRand::new_seed(7), &mut requests);
+    assert_eq!(resolution.rounds, PHYSICS_MAX_ROUNDS);
+    assert_eq!(resolution.capped, 1);
+    assert_no_collisions(&requests);
+}
+
+#[test]
+fn capped_rows_settle_the_same_every_time() {
+    let rows = [1, 2, 3, 99].map(|seed| {
+        let mut requests = cascade(300);
+        resolve_conflicts(&mut WyRand::new_seed(seed), &mut requests);
+        requests
+    });
+    assert!(rows.iter().all(|row| *row == rows[0]));
+}
+
+#[test]
+fn crossing_slides_never_collide() {
+    // Grains two apart sliding towards each other over one falling straight down, with fallbacks
+    // that run into the grains falling on either side
+    let pattern = [
+        Some((Direction::Down, None)),
+        Some((Direction::Right, Some(Direction::Left))),
+        Some((Direction::Down, None)),
+        Some((Direction::Left, Some(Direction::Right))),
+    ];
+    let mut rng = WyRand::new_seed(7);
+    for width in [5, 9, 101, 1001] {
+        let mut requests = pattern
+            .iter()
+            .cycle()
+            .take(width)
+            .copied()
+            .collect::<Vec<_>>();
+        let resolution = resolve_conflicts(&mut rng, &mut requests);
+        assert!(resolution.rounds <= PHYSICS_MAX_ROUNDS, "width {width}");
+        assert_no_collisions(&requests);
+    }
+}
+
+#[test]
+fn wide_boards_stay_within_the_cap() {
+    let mut rng = WyRand::new_seed(7);
+    let mut sand =
+        Array2::from_shape_fn([1000, 20], |(x, y)| ((x * 7 + y * 3) % 5 < 3).then_some(()));
+    let grains = sand.iter().flatten().count();
+    for _ in 0..40 {
+        let resolution = run_rng_physics(&mut rng, &mut sand);
+        assert!(resolution.most_rounds <= PHYSICS_MAX_ROUNDS);
+    }
+    assert_eq!(sand.iter().flatten().count(), grains);
+}
+
+#[test]
+fn subgrid_follows_added_grains_to_the_floor() {
+    let sand = board(&["...", "...", "...", "#.."]);
+    let mut grid = Subgrid::new(sand.view(), 0..3, 1);
+    grid.add_grains([(1, 1)].into_iter());
+    grid.simulate(&mut WyRand::new_seed(7), 10, Duration::from_secs(1));
+    assert_eq!(grid.followed().collect::<Vec<_>>(), vec![(1, 3)]);
+    // The board itself is left alone
+    assert_eq!(sand, board(&["...", "...", "...", "#.."]));
+}
+
+#[test]
+fn subgrid_edges_are_walls() {
+    let sand = board(&["....", "....", ".#.."]);
+    let mut grid = Subgrid::new(sand.view(), 1..2, 0);
+    grid.add_grains([(1, 0)].into_iter());
+    grid.simulate(&mut WyRand::new_seed(7), 10, Duration::from_secs(1));
+    assert_eq!(grid.followed().collect::<Vec<_>>(), vec![(1, 1)]);
+}
+
+#[test]
+fn subgrid_stops_when_settled_or_out_of_time() {
+    let sand = board(&["..", "..", ".."]);
+    let mut grid = Subgrid::new(sand.view(), 0..2, 0);
+    grid.add_grains([(0, 0)].into_iter());
+    assert_eq!(
+        grid.simulate(&mut WyRand::new_seed(7), 100, Duration::from_secs(1)),
+        3
+    );
+    let mut grid = Subgrid::new(sand.view(), 0..2, 0);
+    grid.add_grains([(0, 0)].into_iter());
+    assert_eq!(
+        grid.simulate(&mut WyRand::new_seed(7), 100, Duration::ZERO),
+        1
+    );
+}
+
+#[test]
+fn sticky_grid_keeps_clumps_together() {
+    let sand = board(&["...", "#..", "#..", "#.."]);
+    let sand = Board::from_colors(sand.map(|grain| grain.map(|_| Color::Red)), 0.0);
+    // A grain stuck to a standing column of its own color stays up with it
+    let mut grid = StickyGrid::new(sand.clone());
+    grid.add_grains([(1, 1)].into_iter(), Color::Red);
+    grid.simulate(10, Duration::from_secs(1));
+    assert_eq!(grid.followed().collect::<Vec<_>>(), vec![(1, 1)]);
+    // One of another color falls on its own
+    let mut grid = StickyGrid::new(sand);
+    grid.add_grains([(1, 1)].into_iter(), Color::Blue);
+    grid.simulate(10, Duration::from_secs(1));
+    assert_eq!(grid.followed().collect::<Vec<_>>(), vec![(1, 3)]);
+}
@@ -0,0 +1,142 @@
+use piston_window::UpdateArgs;
+use sandtris::game::Game;
+use sandtris::input::Action;
+use sandtris::mode::GameMode;
+use sandtris::replay::{verify, Input, InputSource, Replay};
+use sandtris::script::{InputScript, ScriptPlayer};
+use sandtris::settings::Settings;
+
+const DT: f64 = 1.0 / 30.0;
+
+fn settings() -> Settings {
+    let mut settings = Settings::default();
+    settings.replay_hashes = true;
+    settings
+}
+
+fn tap(game: &mut Game, action: Action) {
+    game.press(action);
+    game.release(action);
+}
+
+/// Play a practice game that drops a few pieces, steps through some of it a frame at a time while
+/// paused, and undoes a placement, then verify the replay it recorded
+#[test]
+fn replays_with_frame_advance_and_undo_verify() {
+    let mut game = Game::headless(settings());
+    game.start(GameMode::Practice);
+    for frame in 0..400 {
+        match frame {
+            10 | 120 | 220 => tap(&mut game, Action::Drop),
+            60 | 100 | 160 | 180 => tap(&mut game, Action::Pause),
+            61..=90 | 161..=170 => tap(&mut game, Action::Advance),
+            250 => tap(&mut game, Action::Undo),
+            f if f % 40 == 5 => tap(&mut game, Action::Left),
+            _ => {}
+        }
+        game.update(&UpdateArgs { dt: DT });
+    }
+
+    let mut replay = game
+        .recording()
+        .expect("practice games are recorded")
+        .clone();
+    replay.score = Some(game.score());
+    assert_eq!(verify(&replay, settings()), Ok(game.score()));
+}
+
+/// Play an input script that steps through most of a zen game a frame at a time, and check that
+/// the replay recorded from it plays back the same. Zen games can't end, so the recording doesn't
+/// stop early whatever pieces are dealt.
+#[test]
+fn scripted_frame_advance_round_trips() {
+    let zen = "zen".parse::<GameMode>().unwrap();
+    let mut script = String::from("mode zen\n0 tap pause\n");
+    for frame in (2..600).step_by(2) {
+        let action = match frame % 50 {
+            10 => "drop",
+            20 => "left",
+            _ => "advance",
+        };
+        script += &format!("{frame} tap {action}\n");
+    }
+    script += "600 tap pause\nframes 700\n";
+    let mut player = ScriptPlayer::new(script.parse::<InputScript>().unwrap());
+
+    let mut game = Game::headless(settings());
+    game.start(zen);
+    while let Some(frame) = player.next_frame() {
+        for input in frame.inputs {
+            match input {
+                Input::Press(action) => game.press(action),
+                Input::Release(action) => game.release(action),
+            }
+        }
+        game.update(&UpdateArgs { dt: frame.dt });
+    }
+
+    let mut replay = game
+        .recording()
+        .expect("zen games are recorded")
+        .clone();
+    assert!(replay.frames.iter().all(|frame| frame.hash.is_some()));
+    replay.score = Some(game.score());
+    assert_eq!(verify(&replay, settings()), Ok(game.score()));
+}
+
+#[test]
+fn replays_at_speeds_the_settings_wont_allow_are_rejected() {
+    let mut replay = Replay::new(GameMode::Marathon, 7, 1.0);
+    assert_eq!(replay.validate(), Ok(()));
+    for speed in [0.1, 5.0, f64::NAN, f64::INFINITY] {
+        replay.speed = speed;
+        assert!(replay.validate().is_err(), "speed {speed}");
+    }
+}
+
+#[test]
+fn recording_stops_when_the_game_ends() {
+    let mut game = Game::headless(settings());
+    game.start(GameMode::Marathon);
+    let mut frames = 0;
+    while !game.is_game_over() {
+        tap(&mut game, Action::Drop);
+        game.update(&UpdateArgs { dt: DT });
+        frames += 1;
+        assert!(frames < 10_000, "the board never filled up");
+    }
+    assert!(game.recording().is_none());
+}
+
+/// Record a game on a smaller board than the default, and check that it verifies by its own rules
+/// rather than the ones it's verified with
+#[test]
+fn replays_verify_by_the_rules_they_were_recorded_with() {
+    let args = ["--board_size", "8x12", "--replay_hashes", "true"];
+    let recorded_with = Settings::load(args.map(String::from).into_iter()).unwrap();
+    let mut game = Game::headless(recorded_with);
+    game.start(GameMode::Marathon);
+    // Few enough pieces that the small board can't fill up, whatever they are
+    for frame in 0..150 {
+        match frame % 30 {
+            0 => tap(&mut game, Action::Drop),
+            10 => tap(&mut game, Action::Left),
+            _ => {}
+        }
+        game.update(&UpdateArgs { dt: DT });
+    }
+
+    let mut replay = game
+        .recording()
+        .expect("marathon games are recorded")
+        .clone();
+    assert_eq!(
+        replay.rules,
+        [("board_size".to_string(), "8x12".to_string())]
+    );
+    replay.score = Some(game.score());
+    assert_eq!(verify(&replay, settings()), Ok(game.score()));
+
+    replay.rules.push(("hint".to_string(), "true".to_string()));
+    assert!(verify(&replay, settings()).is_err());
+}
@@ -0,0 +1,48 @@
+//! The random numbers drawn from a seed are part of the replay format, so they're pinned here.
+//! If one of these changes, old replays no longer play back, and `RNG_VERSION` has to go up.
+
+use sandtris::rng::{GameRng, WyRand, RNG_VERSION};
+use sandtris::save;
+
+#[test]
+fn seeded_numbers_never_change() {
+    let mut rng = WyRand::new_seed(7);
+    assert_eq!(rng.generate::<u64>(), 16292765481176799425);
+    assert_eq!(rng.generate::<u64>(), 15778304849968482104);
+    assert_eq!(rng.generate_range(0..10usize), 3);
+    assert_eq!(rng.generate_range(0..1000u32), 342);
+    assert!(rng.generate::<bool>());
+}
+
+#[test]
+fn ranges_stay_in_bounds() {
+    let mut rng = WyRand::new_seed(7);
+    for _ in 0..1000 {
+        assert!((3..5).contains(&rng.generate_range(3..5usize)));
+        assert!((0..=4).contains(&rng.generate_range(0..=4u32)));
+        assert!((0.0..=1.0).contains(&rng.generate::<f64>()));
+    }
+}
+
+#[test]
+fn saves_record_the_rng_version() {
+    let source = save::to_string(&5).unwrap();
+    assert!(source.contains(&format!("\"rng\":{RNG_VERSION}")));
+    assert_eq!(save::from_str_seeded::<i32>(&source), Ok(5));
+}
+
+#[test]
+fn saves_from_before_the_rng_version_are_version_one() {
+    assert_eq!(
+        save::from_str_seeded::<i32>(r#"{"version":1,"data":5}"#),
+        Ok(5)
+    );
+}
+
+#[test]
+fn seeded_saves_from_another_rng_are_refused() {
+    let source = format!(r#"{{"version":1,"rng":{},"data":5}}"#, RNG_VERSION + 1);
+    assert!(save::from_str_seeded::<i32>(&source).is_err());
+    // Files that don't depend on the random numbers still load
+    assert_eq!(save::from_str::<i32>(&source), Ok(5));
+}
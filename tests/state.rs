@@ -0,0 +1,187 @@
+use piston_window::UpdateArgs;
+use sandtris::constants::Color;
+use sandtris::game::Game;
+use sandtris::input::Action;
+use sandtris::mode::GameMode;
+use sandtris::save::{self, GameSnapshot};
+use sandtris::settings::Settings;
+use std::time::{Duration, Instant};
+
+const DT: f64 = 1.0 / 30.0;
+
+fn new_game(mode: GameMode) -> Game {
+    let mut game = Game::headless(Settings::default());
+    game.start_seeded(mode, 42);
+    game
+}
+
+/// Play frames `start..end`, with inputs that depend only on the frame number so that every run is
+/// the same. Each piece is moved a different distance to one side and dropped, which spreads them
+/// out enough to clear sand before topping out.
+fn play(game: &mut Game, start: usize, end: usize) {
+    for frame in start..end {
+        let piece = frame / 30;
+        let direction = if piece % 2 == 0 {
+            Action::Left
+        } else {
+            Action::Right
+        };
+        match frame % 30 {
+            1 => game.press(direction),
+            25 => {
+                game.press(Action::Drop);
+                game.release(Action::Drop);
+            }
+            f if f == 2 + piece * 7 % 11 => game.release(direction),
+            _ => {}
+        }
+        game.update(&UpdateArgs { dt: DT });
+    }
+}
+
+#[test]
+fn replaying_from_a_saved_state_is_deterministic() {
+    for mode in [GameMode::Marathon, GameMode::Rising] {
+        let mut game = new_game(mode);
+        play(&mut game, 0, 300);
+        let state = game.save_state();
+
+        play(&mut game, 300, 900);
+        // Make sure the run covers clearing sand, and isn't just comparing two topped out boards
+        assert!(game.score() > 0, "{mode:?}");
+        assert!(!game.is_game_over(), "{mode:?}");
+        let first = game.snapshot();
+
+        game.load_state(&state);
+        play(&mut game, 300, 900);
+        assert_eq!(game.snapshot(), first, "{mode:?}");
+    }
+}
+
+#[test]
+fn states_can_be_loaded_into_another_game() {
+    let mut game = new_game(GameMode::Marathon);
+    play(&mut game, 0, 400);
+    let state = game.save_state();
+    play(&mut game, 400, 800);
+
+    let mut other = new_game(GameMode::Rising);
+    other.load_state(&state);
+    play(&mut other, 400, 800);
+    assert_eq!(other.snapshot(), game.snapshot());
+}
+
+#[test]
+fn loading_a_state_rewinds_the_game() {
+    let mut game = new_game(GameMode::Marathon);
+    play(&mut game, 0, 200);
+    let state = game.save_state();
+    let before = game.snapshot();
+
+    play(&mut game, 200, 800);
+    assert_ne!(game.snapshot(), before);
+    game.load_state(&state);
+    assert_eq!(game.snapshot(), before);
+}
+
+/// Rollback saves and loads a state every frame, so both have to stay cheap even with the board
+/// full of sand. Timings only mean anything in a release build, so this is run by hand with
+/// `cargo test --release -- --ignored`.
+#[test]
+#[ignore]
+fn saving_and_loading_a_full_board_is_fast() {
+    let mut game = new_game(GameMode::Marathon);
+    let mut snapshot = game.snapshot();
+    snapshot.falling_block = None;
+    for ((x, y), grain) in snapshot.sand.indexed_iter_mut() {
+        if y >= snapshot.hidden_rows {
+            *grain = Some(Color::ALL[x % Color::ALL.len()]);
+        }
+    }
+    game.restore(snapshot).unwrap();
+
+    const RUNS: u32 = 1000;
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        std::hint::black_box(game.save_state());
+    }
+    let save = start.elapsed() / RUNS;
+    let state = game.save_state();
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        game.load_state(std::hint::black_box(&state));
+    }
+    let load = start.elapsed() / RUNS;
+    let (width, height) = game.sand().dim();
+    println!("{width}x{height} grains: save_state {save:?}, load_state {load:?}");
+    // A frame at 60 FPS is over 16000µs, so this leaves plenty of it for a rollback of a few frames
+    assert!(
+        save < Duration::from_micros(500),
+        "save_state took {save:?}"
+    );
+    assert!(
+        load < Duration::from_micros(500),
+        "load_state took {load:?}"
+    );
+}
+
+/// A save from before the board had hidden rows has its sand moved down below them when it's
+/// restored
+#[test]
+fn saves_without_hidden_rows_are_restored_below_them() {
+    let mut game = new_game(GameMode::Marathon);
+    play(&mut game, 0, 200);
+    let mut snapshot = game.snapshot();
+    assert!(snapshot.hidden_rows > 0);
+    // Pieces spawn in the hidden rows, so one could only be saved by a version that had them
+    snapshot.falling_block = None;
+
+    let old = snapshot.clone().with_hidden_rows(0).unwrap();
+    assert_eq!(
+        old.sand.dim().1,
+        snapshot.sand.dim().1 - snapshot.hidden_rows
+    );
+    let source = save::to_string(&old)
+        .unwrap()
+        .replace("\"version\":2", "\"version\":1")
+        .replace(",\"hidden_rows\":0", "");
+    assert!(!source.contains("hidden_rows"));
+
+    let mut other = new_game(GameMode::Rising);
+    other
+        .restore(save::from_str::<GameSnapshot>(&source).unwrap())
+        .unwrap();
+    assert_eq!(other.snapshot(), snapshot);
+}
+
+#[test]
+fn restored_combos_run_out() {
+    let mut game = new_game(GameMode::Marathon);
+    let mut snapshot = game.snapshot();
+    snapshot.combo = 3;
+    game.restore(snapshot).unwrap();
+    assert_eq!(game.snapshot().combo, 3);
+
+    let window = GameMode::Marathon.combo_window();
+    assert!(window > 0.0);
+    for _ in 0..=(window / DT) as usize + 1 {
+        game.update(&UpdateArgs { dt: DT });
+    }
+    assert_eq!(game.snapshot().combo, 0);
+}
+
+#[test]
+fn modes_with_a_board_size_of_their_own_play_on_it() {
+    let settings = Settings::default();
+    let narrow = "narrow".parse::<GameMode>().unwrap();
+    let mut game = new_game(narrow);
+    assert_eq!(game.sand().dim(), narrow.layout(&settings).sand_dims());
+    assert!(game.sand().dim().0 < settings.board.sand_dims().0);
+    assert!(game.window_size().0 < settings.board.window_size().0);
+    play(&mut game, 0, 300);
+
+    // The next mode goes back to the board size setting
+    game.start_seeded(GameMode::Marathon, 42);
+    assert_eq!(game.sand().dim(), settings.board.sand_dims());
+    assert_eq!(game.window_size(), settings.board.window_size());
+}